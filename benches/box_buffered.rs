@@ -0,0 +1,51 @@
+//! Compares splitting a stream of large items with and without
+//! `SplitBuilder::box_buffered`. Run with `cargo bench`
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use futures::{executor::block_on, StreamExt};
+use split_stream_by::SplitBuilder;
+
+/// An item of about 4 KiB
+type Item = [u64; 512];
+
+const ITEMS: u64 = 20_000;
+const BUFFER: usize = 256;
+const RUNS: u32 = 10;
+
+fn split(boxed: bool) -> Duration {
+    let start = Instant::now();
+    let builder = SplitBuilder::new(futures::stream::iter(0..ITEMS).map(|n| [n; 512]))
+        // Sends runs of items to each side, so that the buffers fill up
+        .predicate(|item: &Item| item[0] & 64 == 0)
+        .buffer(BUFFER);
+    let builder = if boxed {
+        builder.box_buffered()
+    } else {
+        builder
+    };
+    let (matches, rest) = builder.build().into_tuple();
+    let (matches, rest) = block_on(async {
+        futures::join!(
+            matches.fold(0, |sum, item| async move { sum + black_box(item)[511] }),
+            rest.fold(0, |sum, item| async move { sum + black_box(item)[511] })
+        )
+    });
+    black_box((matches, rest));
+    start.elapsed()
+}
+
+fn main() {
+    for (name, boxed) in [("inline", false), ("boxed", true)] {
+        // The first run warms up the allocator
+        split(boxed);
+        let total: Duration = (0..RUNS).map(|_| split(boxed)).sum();
+        println!(
+            "{name:>6}: {:?} per run of {ITEMS} items of {} bytes",
+            total / RUNS,
+            core::mem::size_of::<Item>()
+        );
+    }
+}
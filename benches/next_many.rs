@@ -0,0 +1,70 @@
+//! Compares a consumer that takes the items of its stream one at a time with
+//! `next` against one that takes them in batches with `next_many`. Run with
+//! `cargo bench`
+use std::{
+    hint::black_box,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::{task::noop_waker, Stream};
+use split_stream_by::SplitStreamByExt;
+
+const ITEMS: u64 = 1_000_000;
+const BUFFER: usize = 256;
+const BATCH: usize = 64;
+const RUNS: u32 = 10;
+
+fn split(batched: bool) -> Duration {
+    let start = Instant::now();
+    // Most items go to the `false` stream, which the `true` stream fills the buffer of while it
+    // runs ahead
+    let (mut matches, mut rest) = futures::stream::iter(0..ITEMS)
+        .split_by_buffered::<BUFFER>(|&n| n % 4 == 0)
+        .into_tuple();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let (mut items, mut sum) = (Vec::with_capacity(BATCH), 0);
+    let mut ended = false;
+    while !ended {
+        // The `true` stream runs until the buffer of the `false` stream is full, and then the
+        // `false` stream catches up until it has to wait
+        while !matches.would_block_on_other() {
+            match Pin::new(&mut matches).poll_next(&mut cx) {
+                Poll::Ready(Some(n)) => sum += black_box(n),
+                Poll::Ready(None) => break,
+                Poll::Pending => {}
+            }
+        }
+        loop {
+            let next = if batched {
+                rest.poll_next_many(&mut cx, &mut items, BATCH)
+                    .map(|count| (count > 0).then_some(()))
+            } else {
+                Pin::new(&mut rest)
+                    .poll_next(&mut cx)
+                    .map(|n| n.map(|n| items.push(n)))
+            };
+            match next {
+                Poll::Ready(Some(())) => sum += items.drain(..).map(black_box).sum::<u64>(),
+                Poll::Ready(None) => {
+                    ended = true;
+                    break;
+                }
+                Poll::Pending => break,
+            }
+        }
+    }
+    black_box(sum);
+    start.elapsed()
+}
+
+fn main() {
+    for (name, batched) in [("next", false), ("next_many", true)] {
+        // The first run warms up the allocator
+        split(batched);
+        let total: Duration = (0..RUNS).map(|_| split(batched)).sum();
+        println!("{name:>9}: {:?} per run of {ITEMS} items", total / RUNS);
+    }
+}
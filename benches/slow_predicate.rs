@@ -0,0 +1,64 @@
+//! Splits a stream with a predicate that takes a while, consuming both sides
+//! on one task and then each side on its own thread. The predicate runs
+//! without holding the lock of the split, so neither side spins on the lock
+//! while the other classifies an item. Run with `cargo bench`
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::{executor::block_on, StreamExt};
+use split_stream_by::SplitStreamByExt;
+
+const ITEMS: u64 = 2_000;
+const BUFFER: usize = 64;
+const RUNS: u32 = 5;
+/// How long the predicate spins for each item
+const COST: Duration = Duration::from_micros(50);
+
+fn slow_predicate(n: &u64) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < COST {
+        core::hint::spin_loop();
+    }
+    // Sends runs of items to each side, so that the buffers fill up
+    n & 32 == 0
+}
+
+fn split(threads: bool) -> Duration {
+    let start = Instant::now();
+    let (matches, rest) = futures::stream::iter(0..ITEMS)
+        .split_by_buffered::<BUFFER>(slow_predicate)
+        .into_tuple();
+    let sum = |side: futures::stream::BoxStream<'static, u64>| {
+        block_on(side.fold(0, |sum, n| async move { sum + black_box(n) }))
+    };
+    let (matches, rest) = (matches.boxed(), rest.boxed());
+    let sums = if threads {
+        let matches = thread::spawn(move || sum(matches));
+        let rest = sum(rest);
+        (matches.join().unwrap(), rest)
+    } else {
+        block_on(async {
+            futures::join!(
+                matches.fold(0, |sum, n| async move { sum + black_box(n) }),
+                rest.fold(0, |sum, n| async move { sum + black_box(n) })
+            )
+        })
+    };
+    black_box(sums);
+    start.elapsed()
+}
+
+fn main() {
+    for (name, threads) in [("joined", false), ("threads", true)] {
+        split(threads);
+        let total: Duration = (0..RUNS).map(|_| split(threads)).sum();
+        println!(
+            "{name:>7}: {:?} per run of {ITEMS} items, {:?} of which is spent in the predicate",
+            total / RUNS,
+            COST * ITEMS as u32
+        );
+    }
+}
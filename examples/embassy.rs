@@ -0,0 +1,89 @@
+//! Splits a stream on an Embassy executor, with the split in a `static` and
+//! each of its streams consumed by its own task. Runs on the host with
+//!
+//! ```text
+//! cargo run --example embassy --features embassy
+//! ```
+//!
+//! and builds for a Cortex-M target with
+//!
+//! ```text
+//! cargo check --example embassy --no-default-features --features embassy,spin --target thumbv7em-none-eabihf
+//! ```
+#![cfg_attr(target_os = "none", no_std, no_main)]
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use embassy_executor::Spawner;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use futures_util::stream::{self, Iter, StreamExt};
+#[cfg(target_os = "none")]
+use panic_halt as _;
+use split_stream_by::{EmbassySplit, FalseEmbassySplit, TrueEmbassySplit};
+use static_cell::StaticCell;
+
+const BUFFER: usize = 4;
+
+/// This crate links `alloc`, so there has to be a global allocator, but an
+/// `EmbassySplit` never allocates, so the heap is left empty
+#[cfg(target_os = "none")]
+#[global_allocator]
+static HEAP: embedded_alloc::LlffHeap = embedded_alloc::LlffHeap::empty();
+
+type Source = Iter<Range<i32>>;
+type Predicate = fn(&i32) -> bool;
+type Evens = TrueEmbassySplit<'static, CriticalSectionRawMutex, i32, Source, Predicate, BUFFER>;
+type Odds = FalseEmbassySplit<'static, CriticalSectionRawMutex, i32, Source, Predicate, BUFFER>;
+
+static SPLIT: StaticCell<EmbassySplit<CriticalSectionRawMutex, i32, Source, Predicate, BUFFER>> =
+    StaticCell::new();
+static SUM_EVENS: AtomicI32 = AtomicI32::new(0);
+static SUM_ODDS: AtomicI32 = AtomicI32::new(0);
+static FINISHED: AtomicI32 = AtomicI32::new(0);
+
+fn is_even(n: &i32) -> bool {
+    n % 2 == 0
+}
+
+#[embassy_executor::task]
+async fn sum_evens(mut evens: Evens) {
+    while let Some(n) = evens.next().await {
+        SUM_EVENS.fetch_add(n, Ordering::Relaxed);
+    }
+    finished();
+}
+
+#[embassy_executor::task]
+async fn sum_odds(mut odds: Odds) {
+    while let Some(n) = odds.next().await {
+        SUM_ODDS.fetch_add(n, Ordering::Relaxed);
+    }
+    finished();
+}
+
+/// Called by each task once its stream ends
+fn finished() {
+    if FINISHED.fetch_add(1, Ordering::Relaxed) == 1 {
+        let (evens, odds) = (
+            SUM_EVENS.load(Ordering::Relaxed),
+            SUM_ODDS.load(Ordering::Relaxed),
+        );
+        assert_eq!((2450, 2500), (evens, odds));
+        #[cfg(not(target_os = "none"))]
+        {
+            println!("evens add up to {}, odds to {}", evens, odds);
+            std::process::exit(0);
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let split = SPLIT.init(EmbassySplit::new(stream::iter(0..100), is_even));
+    let (evens, odds) = split.split().into_tuple();
+    spawner.must_spawn(sum_evens(evens));
+    spawner.must_spawn(sum_odds(odds));
+}
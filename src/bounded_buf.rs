@@ -0,0 +1,210 @@
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use crate::{buffer_age::Stamps, Overflow};
+
+/// A queue with a capacity chosen at runtime, used where `RingBuf` can't be
+/// because the capacity isn't known at compile time
+pub(crate) struct BoundedBuf<T> {
+    items: Items<T>,
+    capacity: usize,
+    stamps: Stamps,
+}
+
+/// What `BoundedBuf::push_overflowing` did with an item
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pushed {
+    /// The item was added to the back of the buffer
+    Kept,
+    /// The item was added to the back of the buffer after the oldest item was
+    /// dropped
+    ReplacedOldest,
+    /// The item was dropped
+    Dropped,
+}
+
+/// Where the items of a `BoundedBuf` are kept. Boxed items cost an allocation
+/// each, but moving them around the queue only moves a pointer
+enum Items<T> {
+    Inline(VecDeque<T>),
+    Boxed(VecDeque<Box<T>>),
+}
+
+impl<T> BoundedBuf<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            items: Items::Inline(VecDeque::new()),
+            capacity,
+            stamps: Stamps::default(),
+        }
+    }
+
+    /// Creates a buffer that keeps each item in its own box, for items that
+    /// are expensive to move
+    pub(crate) fn new_boxed(capacity: usize) -> Self {
+        Self {
+            items: Items::Boxed(VecDeque::new()),
+            capacity,
+            stamps: Stamps::default(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.items {
+            Items::Inline(items) => items.len(),
+            Items::Boxed(items) => items.len(),
+        }
+    }
+
+    /// Records when each item is buffered from now on, with `stamps` that
+    /// match the items already in the buffer
+    pub(crate) fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// How long the oldest item has been buffered, or `None` if the buffer
+    /// is empty or doesn't record when items are buffered
+    pub(crate) fn front_age(&self) -> Option<Duration> {
+        self.stamps.oldest()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many more items fit. This is 0 while the buffer holds more items
+    /// than its capacity, which happens when it's shrunk
+    pub(crate) fn remaining(&self) -> usize {
+        self.capacity.saturating_sub(self.len())
+    }
+
+    /// Changes the capacity. If the buffer holds more items than the new
+    /// capacity, none of them are dropped, but no more items fit until enough
+    /// of them have been taken out
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Puts `items` in the buffer, even if it goes over capacity, except for
+    /// the first item if it doesn't fit, which is returned instead
+    pub(crate) fn fill(&mut self, items: Vec<T>) -> Option<T> {
+        let mut items = items.into_iter();
+        let first = if items.len() > self.remaining() {
+            items.next()
+        } else {
+            None
+        };
+        for _ in 0..items.len() {
+            self.stamps.pushed();
+        }
+        match &mut self.items {
+            Items::Inline(buffered) => buffered.extend(items),
+            Items::Boxed(buffered) => buffered.extend(items.map(Box::new)),
+        }
+        first
+    }
+
+    pub(crate) fn push_back(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            match &mut self.items {
+                Items::Inline(items) => items.push_back(item),
+                Items::Boxed(items) => items.push_back(Box::new(item)),
+            }
+            self.stamps.pushed();
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    pub(crate) fn push_front(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            match &mut self.items {
+                Items::Inline(items) => items.push_front(item),
+                Items::Boxed(items) => items.push_front(Box::new(item)),
+            }
+            self.stamps.pushed_front();
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        match &self.items {
+            Items::Inline(items) => items.get(index),
+            Items::Boxed(items) => items.get(index).map(|item| &**item),
+        }
+    }
+
+    /// Pushes an item, making room for it if the buffer is full by dropping
+    /// whichever item `overflow` says to. `Overflow::Wait` drops the new item,
+    /// but that policy makes sure the buffer has room before pulling an item
+    pub(crate) fn push_overflowing(&mut self, item: T, overflow: Overflow) -> Pushed {
+        match self.push_back(item) {
+            None => Pushed::Kept,
+            Some(item) if overflow == Overflow::DropOldest && self.capacity > 0 => {
+                self.stamps.popped();
+                self.stamps.pushed();
+                match &mut self.items {
+                    Items::Inline(items) => {
+                        items.pop_front();
+                        items.push_back(item);
+                    }
+                    Items::Boxed(items) => {
+                        // The box of the oldest item is reused for the new one
+                        if let Some(mut oldest) = items.pop_front() {
+                            *oldest = item;
+                            items.push_back(oldest);
+                        }
+                    }
+                }
+                Pushed::ReplacedOldest
+            }
+            Some(_) => Pushed::Dropped,
+        }
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<T> {
+        let item = match &mut self.items {
+            Items::Inline(items) => items.pop_front(),
+            Items::Boxed(items) => items.pop_front().map(|item| *item),
+        };
+        if item.is_some() {
+            self.stamps.popped();
+        }
+        item
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer;
+
+    #[test]
+    fn test_push_overflowing() {
+        let mut buf = BoundedBuf::new(2);
+        buf.push_overflowing(1, Overflow::DropOldest);
+        buf.push_overflowing(2, Overflow::DropOldest);
+        buf.push_overflowing(3, Overflow::DropOldest);
+        buf.push_overflowing(4, Overflow::DropNewest);
+        assert_eq!(vec![2, 3], buffer::into_vec(buf));
+
+        let mut buf = BoundedBuf::new(0);
+        buf.push_overflowing(1, Overflow::DropOldest);
+        assert_eq!(0, buf.len());
+    }
+
+    #[test]
+    fn test_boxed() {
+        let mut buf = BoundedBuf::new_boxed(3);
+        assert_eq!(Some(0), buf.fill(vec![0, 1, 2, 3]));
+        buf.push_overflowing(4, Overflow::DropOldest);
+        assert_eq!(vec![&2, &3, &4], buffer::iter(&buf).collect::<Vec<_>>());
+        assert_eq!(Some(2), buf.pop_front());
+        assert_eq!(None, buf.push_back(5));
+        assert_eq!(Some(6), buf.push_back(6));
+        assert_eq!(vec![3, 4, 5], buffer::into_vec(buf));
+    }
+}
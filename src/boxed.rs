@@ -0,0 +1,237 @@
+//! Type aliases for splits whose underlying stream and predicate are boxed,
+//! so the streams can be named in struct fields and trait objects
+use alloc::boxed::Box;
+
+use futures_core::{stream::BoxStream, Stream};
+use futures_util::future::Either;
+
+use crate::{
+    FalseSplitBy, FalseSplitByBuffered, LeftSplitByMap, LeftSplitByMapBuffered, RightSplitByMap,
+    RightSplitByMapBuffered, Split, SplitMap, SplitStreamByExt, SplitStreamByMapExt, TrueSplitBy,
+    TrueSplitByBuffered,
+};
+
+/// A boxed predicate for `split_by` and `split_by_buffered`
+pub type BoxedPredicate<I> = Box<dyn Fn(&I) -> bool + Send>;
+
+/// A boxed predicate for `split_by_map` and `split_by_map_buffered`
+pub type BoxedMapPredicate<I, L, R> = Box<dyn Fn(I) -> Either<L, R> + Send>;
+
+/// The `true` stream of a `BoxedSplit`
+pub type BoxedTrueSplit<I> = TrueSplitBy<I, BoxStream<'static, I>, BoxedPredicate<I>>;
+
+/// The `false` stream of a `BoxedSplit`
+pub type BoxedFalseSplit<I> = FalseSplitBy<I, BoxStream<'static, I>, BoxedPredicate<I>>;
+
+/// A split made by `split_by` with a boxed stream and predicate. Created by
+/// `BoxedSplit::new`
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{BoxedFalseSplit, BoxedSplit, BoxedTrueSplit};
+///
+/// struct Numbers {
+///     evens: BoxedTrueSplit<u32>,
+///     odds: BoxedFalseSplit<u32>,
+/// }
+///
+/// let split = BoxedSplit::new(futures::stream::iter(0..6), |&n| n % 2 == 0);
+/// let numbers = Numbers {
+///     evens: split.matches,
+///     odds: split.rest,
+/// };
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let (evens, odds) = futures::join!(
+///         numbers.evens.collect::<Vec<_>>(),
+///         numbers.odds.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3, 5], odds);
+/// })
+/// ```
+pub type BoxedSplit<I> = Split<BoxedTrueSplit<I>, BoxedFalseSplit<I>>;
+
+/// The `true` stream of a `BoxedSplitBuffered`
+pub type BoxedTrueSplitBuffered<I, const N: usize> =
+    TrueSplitByBuffered<I, BoxStream<'static, I>, BoxedPredicate<I>, N>;
+
+/// The `false` stream of a `BoxedSplitBuffered`
+pub type BoxedFalseSplitBuffered<I, const N: usize> =
+    FalseSplitByBuffered<I, BoxStream<'static, I>, BoxedPredicate<I>, N>;
+
+/// A split made by `split_by_buffered` with a boxed stream and predicate.
+/// Created by `BoxedSplitBuffered::new`
+pub type BoxedSplitBuffered<I, const N: usize> =
+    Split<BoxedTrueSplitBuffered<I, N>, BoxedFalseSplitBuffered<I, N>>;
+
+/// The left stream of a `BoxedSplitMap`
+pub type BoxedLeftSplitMap<I, L, R> =
+    LeftSplitByMap<I, L, R, BoxStream<'static, I>, BoxedMapPredicate<I, L, R>>;
+
+/// The right stream of a `BoxedSplitMap`
+pub type BoxedRightSplitMap<I, L, R> =
+    RightSplitByMap<I, L, R, BoxStream<'static, I>, BoxedMapPredicate<I, L, R>>;
+
+/// A split made by `split_by_map` with a boxed stream and predicate. Created
+/// by `BoxedSplitMap::new`
+pub type BoxedSplitMap<I, L, R> = SplitMap<BoxedLeftSplitMap<I, L, R>, BoxedRightSplitMap<I, L, R>>;
+
+/// The left stream of a `BoxedSplitMapBuffered`
+pub type BoxedLeftSplitMapBuffered<I, L, R, const N: usize> =
+    LeftSplitByMapBuffered<I, L, R, BoxStream<'static, I>, BoxedMapPredicate<I, L, R>, N>;
+
+/// The right stream of a `BoxedSplitMapBuffered`
+pub type BoxedRightSplitMapBuffered<I, L, R, const N: usize> =
+    RightSplitByMapBuffered<I, L, R, BoxStream<'static, I>, BoxedMapPredicate<I, L, R>, N>;
+
+/// A split made by `split_by_map_buffered` with a boxed stream and predicate.
+/// Created by `BoxedSplitMapBuffered::new`
+pub type BoxedSplitMapBuffered<I, L, R, const N: usize> =
+    SplitMap<BoxedLeftSplitMapBuffered<I, L, R, N>, BoxedRightSplitMapBuffered<I, L, R, N>>;
+
+impl<I> BoxedSplit<I> {
+    /// Boxes `stream` and `predicate` and splits the stream with `split_by`
+    pub fn new<S, P>(stream: S, predicate: P) -> Self
+    where
+        S: Stream<Item = I> + Send + 'static,
+        P: Fn(&I) -> bool + Send + 'static,
+    {
+        let predicate: BoxedPredicate<I> = Box::new(predicate);
+        let stream: BoxStream<'static, I> = Box::pin(stream);
+        stream.split_by(predicate)
+    }
+}
+
+impl<I, const N: usize> BoxedSplitBuffered<I, N> {
+    /// Boxes `stream` and `predicate` and splits the stream with
+    /// `split_by_buffered`
+    pub fn new<S, P>(stream: S, predicate: P) -> Self
+    where
+        S: Stream<Item = I> + Send + 'static,
+        P: Fn(&I) -> bool + Send + 'static,
+    {
+        let predicate: BoxedPredicate<I> = Box::new(predicate);
+        let stream: BoxStream<'static, I> = Box::pin(stream);
+        stream.split_by_buffered::<N>(predicate)
+    }
+}
+
+impl<I, L, R> BoxedSplitMap<I, L, R> {
+    /// Boxes `stream` and `predicate` and splits the stream with
+    /// `split_by_map`
+    pub fn new<S, P>(stream: S, predicate: P) -> Self
+    where
+        S: Stream<Item = I> + Send + 'static,
+        P: Fn(I) -> Either<L, R> + Send + 'static,
+    {
+        let predicate: BoxedMapPredicate<I, L, R> = Box::new(predicate);
+        let stream: BoxStream<'static, I> = Box::pin(stream);
+        stream.split_by_map(predicate)
+    }
+}
+
+impl<I, L, R, const N: usize> BoxedSplitMapBuffered<I, L, R, N> {
+    /// Boxes `stream` and `predicate` and splits the stream with
+    /// `split_by_map_buffered`
+    pub fn new<S, P>(stream: S, predicate: P) -> Self
+    where
+        S: Stream<Item = I> + Send + 'static,
+        P: Fn(I) -> Either<L, R> + Send + 'static,
+    {
+        let predicate: BoxedMapPredicate<I, L, R> = Box::new(predicate);
+        let stream: BoxStream<'static, I> = Box::pin(stream);
+        stream.split_by_map_buffered::<N>(predicate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use futures::{
+        stream::{self, BoxStream, LocalBoxStream},
+        StreamExt,
+    };
+
+    use crate::{
+        BoxedPredicate, BoxedSplitMap, Either, FalseSplitByBuffered, SplitStreamByExt,
+        TrueSplitByBuffered,
+    };
+
+    /// Holds both streams of a split without the bounds that are only needed
+    /// to poll them
+    struct Pipeline<S, P> {
+        matches: TrueSplitByBuffered<i32, S, P, 16>,
+        rest: FalseSplitByBuffered<i32, S, P, 16>,
+    }
+
+    impl<S, P> Pipeline<S, P> {
+        fn is_running(&self) -> bool {
+            !self.matches.is_source_done() && !self.rest.other_side_closed()
+        }
+    }
+
+    impl<S, P> Pipeline<S, P>
+    where
+        P: Fn(&i32) -> bool,
+    {
+        fn reroute(&self, predicate: P) {
+            self.matches.set_predicate_reclassify(predicate);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_boxed_streams() {
+        let split = stream::iter(0..6).split_by(|&n| n % 3 == 0);
+        let streams: Vec<BoxStream<'static, i32>> = vec![split.matches.boxed(), split.rest.boxed()];
+        let mut items = stream::select_all(streams).collect::<Vec<_>>().await;
+        items.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], items);
+    }
+
+    #[tokio::test]
+    async fn test_boxed_local_streams() {
+        let split = stream::iter(0..4).map(Rc::new).split_by(|n| **n < 2);
+        let matches: LocalBoxStream<'static, Rc<i32>> = split.matches.boxed_local();
+        let rest: LocalBoxStream<'static, Rc<i32>> = split.rest.boxed_local();
+        let (matches, rest) = futures::join!(matches.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+        assert_eq!(vec![Rc::new(0), Rc::new(1)], matches);
+        assert_eq!(vec![Rc::new(2), Rc::new(3)], rest);
+    }
+
+    #[tokio::test]
+    async fn test_streams_in_user_structs() {
+        let predicate: BoxedPredicate<i32> = Box::new(|&n| n % 2 == 0);
+        let split = stream::iter(0..6).boxed().split_by_buffered(predicate);
+        let pipeline: Pipeline<BoxStream<'static, i32>, BoxedPredicate<i32>> = Pipeline {
+            matches: split.matches,
+            rest: split.rest,
+        };
+        assert!(pipeline.is_running());
+        pipeline.reroute(Box::new(|&n| n < 3));
+        let (matches, rest) = futures::join!(
+            pipeline.matches.collect::<Vec<_>>(),
+            pipeline.rest.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 1, 2], matches);
+        assert_eq!(vec![3, 4, 5], rest);
+    }
+
+    #[tokio::test]
+    async fn test_boxed_split_map() {
+        let split = BoxedSplitMap::new(stream::iter(0..4), |n: i32| {
+            if n % 2 == 0 {
+                Either::Left(n)
+            } else {
+                Either::Right(n.to_string())
+            }
+        });
+        let (left, right) = futures::join!(
+            split.left.collect::<Vec<_>>(),
+            split.right.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 2], left);
+        assert_eq!(vec!["1".to_owned(), "3".to_owned()], right);
+    }
+}
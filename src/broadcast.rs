@@ -0,0 +1,341 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    side_waker::{RoomWaker, SideWaker},
+    sync::Mutex,
+    Error, Overflow,
+};
+
+/// The buffer of one subscriber
+struct Subscription<I> {
+    id: u64,
+    buf: VecDeque<I>,
+    cap: usize,
+    overflow: Overflow,
+    /// How many items were dropped since the subscriber last took one
+    lagged: u64,
+    waker: SideWaker,
+}
+
+/// What a stream of a split shares with its subscribers
+struct Subscribers<I> {
+    subscriptions: Vec<Subscription<I>>,
+    /// Clones an item for each subscriber. Kept here so that the stream can
+    /// send to its subscribers without requiring `I: Clone` itself
+    clone: fn(&I) -> I,
+    next_id: u64,
+    /// The stream, if it waits for room in the buffer of a subscriber
+    waker: RoomWaker,
+    /// Whether the stream has ended or was dropped
+    ended: bool,
+}
+
+/// The subscribers of one stream of a split, which get a clone of every item
+/// the stream returns. Nothing is allocated until the first subscription
+pub(crate) struct Broadcast<I> {
+    subscribers: Option<Arc<Mutex<Subscribers<I>>>>,
+}
+
+impl<I> Default for Broadcast<I> {
+    fn default() -> Self {
+        Self { subscribers: None }
+    }
+}
+
+impl<I> Broadcast<I> {
+    pub(crate) fn subscribe(&mut self, cap: usize, overflow: Overflow) -> Subscriber<I>
+    where
+        I: Clone,
+    {
+        assert!(cap > 0, "a subscriber has to be able to buffer an item");
+        let subscribers = self.subscribers.get_or_insert_with(|| {
+            Arc::new(Mutex::new(Subscribers {
+                subscriptions: Vec::new(),
+                clone: I::clone,
+                next_id: 0,
+                waker: RoomWaker::default(),
+                ended: false,
+            }))
+        });
+        let id = {
+            let mut state = subscribers.lock();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.subscriptions.push(Subscription {
+                id,
+                buf: VecDeque::new(),
+                cap,
+                overflow,
+                lagged: 0,
+                waker: SideWaker::default(),
+            });
+            id
+        };
+        Subscriber {
+            subscribers: subscribers.clone(),
+            id,
+            terminated: false,
+        }
+    }
+
+    /// How many more items every subscriber with `Overflow::Wait` has room
+    /// for. Otherwise the waker of `cx` is woken once one of them takes an
+    /// item
+    pub(crate) fn poll_room(&self, cx: &mut Context<'_>) -> Poll<usize> {
+        let subscribers = match &self.subscribers {
+            Some(subscribers) => subscribers,
+            None => return Poll::Ready(usize::MAX),
+        };
+        let mut state = subscribers.lock();
+        let room = state
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.overflow == Overflow::Wait)
+            .map(|subscription| subscription.cap - subscription.buf.len())
+            .min()
+            .unwrap_or(usize::MAX);
+        if room == 0 {
+            state.waker.register(cx.waker());
+            return Poll::Pending;
+        }
+        Poll::Ready(room)
+    }
+
+    /// Gives each subscriber a clone of `items`. The ones with
+    /// `Overflow::Wait` must have room for them, as checked with `poll_room`
+    pub(crate) fn send(&self, items: &[I]) {
+        let subscribers = match &self.subscribers {
+            Some(subscribers) if !items.is_empty() => subscribers,
+            _ => return,
+        };
+        let mut state = subscribers.lock();
+        let clone = state.clone;
+        for subscription in &mut state.subscriptions {
+            for item in items {
+                if subscription.buf.len() == subscription.cap {
+                    subscription.lagged += 1;
+                    match subscription.overflow {
+                        Overflow::Wait => unreachable!("checked for room before sending"),
+                        Overflow::DropOldest => {
+                            subscription.buf.pop_front();
+                        }
+                        Overflow::DropNewest => continue,
+                    }
+                }
+                subscription.buf.push_back(clone(item));
+            }
+            subscription.waker.wake_waiting();
+        }
+    }
+
+    /// Ends the subscriptions once their subscribers took what's left
+    pub(crate) fn end(&self) {
+        if let Some(subscribers) = &self.subscribers {
+            let mut state = subscribers.lock();
+            state.ended = true;
+            for subscription in &mut state.subscriptions {
+                subscription.waker.wake_waiting();
+            }
+        }
+    }
+}
+
+impl<I> Drop for Broadcast<I> {
+    /// A stream that is dropped won't return any more items
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// A stream that gets a clone of every item that a stream of a split returns
+/// from when it subscribed, with a buffer of its own. Returns
+/// `Err(Error::Lagged)` with how many items were dropped when it fell behind
+/// with `Overflow::DropOldest` or `Overflow::DropNewest`, and ends once the
+/// stream it subscribed to has ended or was dropped. Dropping it never holds
+/// up that stream. Created by `subscribe`
+#[must_use = "streams do nothing unless polled"]
+pub struct Subscriber<I> {
+    subscribers: Arc<Mutex<Subscribers<I>>>,
+    id: u64,
+    terminated: bool,
+}
+
+impl<I> Subscriber<I> {
+    /// The number of items that are waiting for this subscriber
+    pub fn len(&self) -> usize {
+        let state = self.subscribers.lock();
+        state
+            .subscriptions
+            .iter()
+            .find(|subscription| subscription.id == self.id)
+            .map_or(0, |subscription| subscription.buf.len())
+    }
+
+    /// Whether no items are waiting for this subscriber
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<I> Unpin for Subscriber<I> {}
+
+impl<I> Stream for Subscriber<I> {
+    type Item = Result<I, Error<I>>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let this = &mut *self;
+        let mut guard = this.subscribers.lock();
+        let state = &mut *guard;
+        let subscription = state
+            .subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.id == this.id)
+            .expect("a subscription is only removed by its subscriber");
+        if subscription.lagged > 0 {
+            let lagged = core::mem::take(&mut subscription.lagged);
+            return Poll::Ready(Some(Err(Error::Lagged(lagged))));
+        }
+        if let Some(item) = subscription.buf.pop_front() {
+            subscription.waker.ready();
+            if subscription.overflow == Overflow::Wait {
+                // The stream might be waiting for room in this buffer
+                state.waker.wake();
+            }
+            return Poll::Ready(Some(Ok(item)));
+        }
+        if state.ended {
+            this.terminated = true;
+            return Poll::Ready(None);
+        }
+        subscription.waker.register(cx.waker(), "Subscriber");
+        Poll::Pending
+    }
+}
+
+impl<I> FusedStream for Subscriber<I> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I> Drop for Subscriber<I> {
+    fn drop(&mut self) {
+        let mut state = self.subscribers.lock();
+        state
+            .subscriptions
+            .retain(|subscription| subscription.id != self.id);
+        // The stream might have been waiting for room in the buffer of this one
+        state.waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker},
+        Error, Overflow, SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_subscribers_with_different_rates() {
+        let (mut small, _big) = futures::stream::iter(0..6)
+            .split_by_buffered::<16>(|&n| n < 6)
+            .into_tuple();
+        let mut fast = small.subscribe(2, Overflow::Wait);
+        let mut slow = small.subscribe(2, Overflow::DropOldest);
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut small, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut small, &waker.waker()));
+        // `fast` has no room left, so `small` waits for it
+        assert_eq!(Poll::Pending, poll_once(&mut small, &waker.waker()));
+        let wakes = waker.count();
+        assert_eq!(
+            Poll::Ready(Some(Ok(0))),
+            poll_once(&mut fast, &waker.waker())
+        );
+        assert_eq!(wakes + 1, waker.count());
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut small, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(Ok(1))),
+            poll_once(&mut fast, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(Ok(2))),
+            poll_once(&mut fast, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut small, &waker.waker()));
+        // `slow` kept the two newest items and dropped the rest
+        assert_eq!(2, slow.len());
+        assert_eq!(
+            Poll::Ready(Some(Err(Error::Lagged(2)))),
+            poll_once(&mut slow, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(Ok(2))),
+            poll_once(&mut slow, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(Ok(3))),
+            poll_once(&mut slow, &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut slow, &waker.waker()));
+        let rest = futures::executor::block_on(async {
+            let (small, fast, slow) = futures::join!(
+                small.collect::<Vec<_>>(),
+                fast.collect::<Vec<_>>(),
+                slow.collect::<Vec<_>>()
+            );
+            (small, fast, slow)
+        });
+        assert_eq!(
+            (vec![4, 5], vec![Ok(3), Ok(4), Ok(5)], vec![Ok(4), Ok(5)]),
+            rest
+        );
+    }
+
+    #[test]
+    fn test_dropped_subscriber_doesnt_stall_stream() {
+        let (mut small, _big) = futures::stream::iter(0..6)
+            .split_by_buffered::<16>(|&n| n < 6)
+            .into_tuple();
+        let subscriber = small.subscribe(1, Overflow::Wait);
+        let mut late = small.subscribe(8, Overflow::Wait);
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut small, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut small, &waker.waker()));
+        let wakes = waker.count();
+        drop(subscriber);
+        assert_eq!(wakes + 1, waker.count());
+        assert_eq!(
+            vec![1, 2, 3, 4, 5],
+            futures::executor::block_on(small.by_ref().collect::<Vec<_>>())
+        );
+        assert_eq!(
+            (0..6).map(Ok).collect::<Vec<_>>(),
+            futures::executor::block_on(late.by_ref().collect::<Vec<_>>())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_ends_when_stream_dropped() {
+        let (mut small, _big) = futures::stream::iter(0..6)
+            .split_by_buffered::<16>(|&n| n < 6)
+            .into_tuple();
+        let subscriber = small.subscribe(8, Overflow::DropNewest);
+        assert_eq!(Some(0), small.next().await);
+        drop(small);
+        assert_eq!(vec![Ok(0)], subscriber.collect::<Vec<_>>().await);
+    }
+}
@@ -0,0 +1,158 @@
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{bounded_buf::BoundedBuf, ring::RingBuf};
+
+/// The storage of the buffers of a split made with `split_by_with_buffers`.
+/// Each stream of the split gets its own buffer, which holds the items the
+/// underlying stream returned for that stream before it asked for them.
+/// Once the buffer of one stream is full, the other stream stops pulling
+/// items until there's room again.
+///
+/// `RingBuf<T, N>`, which `split_by_buffered` uses, holds up to `N` items
+/// inline. A `VecDeque<T>` is never full, so the streams never wait for each
+/// other, at the cost of unbounded memory. Other storage, such as a queue
+/// backed by an arena or a pool, or one that limits the total size of its
+/// items rather than their number, can implement this trait to be used the
+/// same way
+pub trait Buffer<T> {
+    /// The number of items in the buffer
+    fn len(&self) -> usize;
+
+    /// How many more items fit in the buffer. Once this is 0, `push_back` and
+    /// `push_front` hand their item back
+    fn remaining(&self) -> usize;
+
+    /// Adds an item to the back of the buffer, or hands it back if the
+    /// buffer is full
+    fn push_back(&mut self, item: T) -> Option<T>;
+
+    /// Adds an item to the front of the buffer, so it's the next one
+    /// `pop_front` returns, or hands it back if the buffer is full
+    fn push_front(&mut self, item: T) -> Option<T>;
+
+    /// Takes the item at the front out of the buffer
+    fn pop_front(&mut self) -> Option<T>;
+
+    /// The item `index` places from the front of the buffer
+    fn get(&self, index: usize) -> Option<&T>;
+
+    /// The item at the back of the buffer, which `push_back` added last
+    fn back(&self) -> Option<&T> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Whether the buffer holds no items
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many items the buffer holds once it's full
+    fn capacity(&self) -> usize {
+        self.len().saturating_add(self.remaining())
+    }
+}
+
+/// Iterates over the items in `buf`, front first
+pub(crate) fn iter<'a, T, B>(buf: &'a B) -> impl Iterator<Item = &'a T> + Clone
+where
+    T: 'a,
+    B: Buffer<T>,
+{
+    (0..buf.len()).filter_map(move |index| buf.get(index))
+}
+
+/// Takes all the items out of `buf`, front first
+pub(crate) fn into_vec<T, B>(mut buf: B) -> Vec<T>
+where
+    B: Buffer<T>,
+{
+    let mut items = Vec::with_capacity(buf.len());
+    while let Some(item) = buf.pop_front() {
+        items.push(item);
+    }
+    items
+}
+
+impl<T, const N: usize> Buffer<T> for RingBuf<T, N> {
+    fn len(&self) -> usize {
+        RingBuf::len(self)
+    }
+
+    fn remaining(&self) -> usize {
+        RingBuf::remaining(self)
+    }
+
+    fn push_back(&mut self, item: T) -> Option<T> {
+        RingBuf::push_back(self, item)
+    }
+
+    fn push_front(&mut self, item: T) -> Option<T> {
+        RingBuf::push_front(self, item)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        RingBuf::pop_front(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        RingBuf::get(self, index)
+    }
+}
+
+impl<T> Buffer<T> for VecDeque<T> {
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn remaining(&self) -> usize {
+        usize::MAX - VecDeque::len(self)
+    }
+
+    fn push_back(&mut self, item: T) -> Option<T> {
+        VecDeque::push_back(self, item);
+        None
+    }
+
+    fn push_front(&mut self, item: T) -> Option<T> {
+        VecDeque::push_front(self, item);
+        None
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        VecDeque::pop_front(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        VecDeque::get(self, index)
+    }
+}
+
+impl<T> Buffer<T> for BoundedBuf<T> {
+    fn len(&self) -> usize {
+        BoundedBuf::len(self)
+    }
+
+    fn remaining(&self) -> usize {
+        BoundedBuf::remaining(self)
+    }
+
+    fn push_back(&mut self, item: T) -> Option<T> {
+        BoundedBuf::push_back(self, item)
+    }
+
+    fn push_front(&mut self, item: T) -> Option<T> {
+        BoundedBuf::push_front(self, item)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        BoundedBuf::pop_front(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        BoundedBuf::get(self, index)
+    }
+
+    fn capacity(&self) -> usize {
+        BoundedBuf::capacity(self)
+    }
+}
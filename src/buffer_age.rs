@@ -0,0 +1,239 @@
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use core::{convert::TryFrom, time::Duration};
+
+use crate::{bounded_buf::BoundedBuf, timer::Clock, Side};
+
+/// The clock the ages of buffered items are measured on, shared by both
+/// buffers
+type SharedClock = Arc<dyn Clock + Send + Sync>;
+
+/// When each item in a buffer was buffered, oldest first, on the clock the
+/// ages are measured on. Nothing is kept unless the ages of the buffered
+/// items are tracked
+#[derive(Default)]
+pub(crate) struct Stamps {
+    stamps: Option<(SharedClock, VecDeque<Duration>)>,
+}
+
+impl Stamps {
+    /// Records that an item was added to the back of the buffer
+    #[inline]
+    pub(crate) fn pushed(&mut self) {
+        if let Some((clock, stamps)) = &mut self.stamps {
+            stamps.push_back(clock.now());
+        }
+    }
+
+    /// Records that an item was added to the front of the buffer
+    #[inline]
+    pub(crate) fn pushed_front(&mut self) {
+        if let Some((clock, stamps)) = &mut self.stamps {
+            stamps.push_front(clock.now());
+        }
+    }
+
+    /// Records that the item at the front of the buffer was taken out
+    #[inline]
+    pub(crate) fn popped(&mut self) {
+        if let Some((_, stamps)) = &mut self.stamps {
+            stamps.pop_front();
+        }
+    }
+
+    /// How long the item at the front of the buffer has been in it, or `None`
+    /// if the buffer is empty or ages aren't tracked
+    #[inline]
+    pub(crate) fn oldest(&self) -> Option<Duration> {
+        let (clock, stamps) = self.stamps.as_ref()?;
+        let stamp = stamps.front()?;
+        Some(clock.now().saturating_sub(*stamp))
+    }
+}
+
+/// Tracks how long items wait in the buffers of a split before their stream
+/// takes them. Nothing is tracked unless it was turned on
+#[derive(Default)]
+pub(crate) struct BufferAge {
+    // Boxed so that the shared state doesn't grow when ages aren't tracked
+    summaries: Option<(SharedClock, Box<[BufferAgeSummary; 2]>)>,
+}
+
+impl BufferAge {
+    /// Tracks the ages of buffered items on `clock`
+    pub(crate) fn new<C>(clock: C) -> Self
+    where
+        C: Clock + Send + Sync + 'static,
+    {
+        Self {
+            summaries: Some((Arc::new(clock), Box::default())),
+        }
+    }
+
+    /// Makes `buf` record when each item was buffered, if ages are tracked
+    #[inline]
+    pub(crate) fn track<I>(&self, buf: &mut BoundedBuf<I>) {
+        if let Some((clock, _)) = &self.summaries {
+            buf.set_stamps(Stamps {
+                stamps: Some((clock.clone(), VecDeque::new())),
+            });
+        }
+    }
+
+    /// Records that the stream of `side` took an item that had been buffered
+    /// for `age`, which is `None` if ages aren't tracked
+    #[inline]
+    pub(crate) fn delivered(&mut self, side: Side, age: Option<Duration>) {
+        if let (Some((_, summaries)), Some(age)) = (&mut self.summaries, age) {
+            summaries[usize::from(side.is_false())].record(age);
+        }
+    }
+
+    /// The ages of the items the stream of `side` took from its buffer so far
+    pub(crate) fn summary(&self, side: Side) -> BufferAgeSummary {
+        self.summaries
+            .as_ref()
+            .map(|(_, summaries)| summaries[usize::from(side.is_false())].clone())
+            .unwrap_or_default()
+    }
+}
+
+/// How many histogram buckets a `BufferAgeSummary` has. Bucket `i` counts the
+/// ages below `2^i` microseconds, and the last one everything longer
+const BUCKETS: usize = 32;
+
+/// How long the items a stream took from its buffer had been waiting there.
+/// Returned by `buffer_age_summary` on the streams of a split built with
+/// `SplitBuilder::track_buffer_age`. Items a stream got straight from the
+/// underlying stream were never buffered, so they aren't counted
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BufferAgeSummary {
+    delivered: u64,
+    total: Duration,
+    max: Duration,
+    buckets: [u64; BUCKETS],
+}
+
+impl BufferAgeSummary {
+    fn record(&mut self, age: Duration) {
+        self.delivered += 1;
+        self.total = self.total.saturating_add(age);
+        self.max = self.max.max(age);
+        let micros = u64::try_from(age.as_micros()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+    }
+
+    /// How many buffered items the stream took
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// The longest any of them waited
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// How long they waited on average, or zero if there were none
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.delivered) {
+            Ok(0) => Duration::ZERO,
+            Ok(delivered) => self.total / delivered,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.delivered as f64),
+        }
+    }
+
+    /// Roughly how long `percent` percent of them waited at most. The ages
+    /// are only kept in buckets that double in size, starting at a
+    /// microsecond, so this is the upper end of the bucket the percentile
+    /// falls in, but never more than `max`. Panics if `percent` is more than
+    /// 100
+    pub fn percentile(&self, percent: f64) -> Duration {
+        assert!(
+            (0.0..=100.0).contains(&percent),
+            "a percentile has to be between 0 and 100"
+        );
+        // Rounded up by hand, as `f64::ceil` needs std
+        let exact = self.delivered as f64 * percent / 100.0;
+        let rank = exact as u64 + u64::from((exact as u64 as f64) < exact);
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) && count > 0 {
+                let upper = Duration::from_micros(1 << bucket);
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    use super::*;
+    use crate::{
+        testing::{poll_until_ready_with_waker, CountingWaker, ManualStream, MockTimer},
+        SplitBuilder,
+    };
+
+    #[test]
+    fn test_summary() {
+        let mut summary = BufferAgeSummary::default();
+        assert_eq!(Duration::ZERO, summary.mean());
+        assert_eq!(Duration::ZERO, summary.percentile(50.0));
+        for millis in [1, 2, 3, 4, 100] {
+            summary.record(Duration::from_millis(millis));
+        }
+        assert_eq!(5, summary.delivered());
+        assert_eq!(Duration::from_millis(100), summary.max());
+        assert_eq!(Duration::from_millis(22), summary.mean());
+        // 3ms is below 2^12 microseconds, and the bucket of 100ms goes past `max`
+        assert_eq!(Duration::from_micros(4096), summary.percentile(50.0));
+        assert_eq!(Duration::from_millis(100), summary.percentile(99.0));
+    }
+
+    #[test]
+    fn test_buffered_ages() {
+        let timer = MockTimer::new();
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(4)
+            .track_buffer_age_with(timer.clone())
+            .build()
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 0] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut evens, &waker.waker(), 2)
+        );
+        timer.advance(Duration::from_millis(100));
+        for n in [3, 2] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_until_ready_with_waker(&mut evens, &waker.waker(), 2)
+        );
+        assert_eq!(Some(Duration::from_millis(100)), odds.oldest_buffered_age());
+        assert_eq!(None, evens.oldest_buffered_age());
+        timer.advance(Duration::from_millis(50));
+        for n in [1, 3] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut odds, &waker.waker(), 1)
+            );
+        }
+        assert_eq!(None, odds.oldest_buffered_age());
+        let summary = odds.buffer_age_summary();
+        assert_eq!(2, summary.delivered());
+        assert_eq!(Duration::from_millis(150), summary.max());
+        assert_eq!(Duration::from_millis(100), summary.mean());
+        // The evens were never buffered
+        assert_eq!(BufferAgeSummary::default(), evens.buffer_age_summary());
+    }
+}
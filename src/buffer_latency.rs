@@ -0,0 +1,310 @@
+use alloc::{boxed::Box, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use crate::{
+    side_waker::SideWaker,
+    sync::Mutex,
+    timer::{Clock, DynTimer, Sleep},
+    Side,
+};
+
+/// The timer the maximum latency is measured with, shared with the timers
+/// that are running
+type SharedTimer = Arc<dyn DynTimer + Sync>;
+
+/// Wakes a stream again when the items buffered for it haven't been taken
+/// within the maximum latency, in case the wake from when they were buffered
+/// got lost. Nothing is done unless a maximum latency was set
+#[derive(Default)]
+pub(crate) struct BufferLatency {
+    max: Option<(Duration, SharedTimer)>,
+    true_side: Arc<Deadline>,
+    false_side: Arc<Deadline>,
+}
+
+/// What the timer of one side knows about it, shared with the timer so that
+/// it doesn't need the state of the split
+#[derive(Default)]
+struct Deadline {
+    armed: AtomicBool,
+    buffered: AtomicUsize,
+    taken: AtomicUsize,
+}
+
+impl BufferLatency {
+    /// Wakes a stream whose buffered items haven't been taken after `max` on
+    /// `timer`
+    pub(crate) fn new<T>(max: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + Sync + 'static,
+    {
+        Self {
+            max: Some((max, Arc::new(timer))),
+            ..Self::default()
+        }
+    }
+
+    /// Records that the stream of `side` returned an item
+    #[inline]
+    pub(crate) fn taken(&self, side: Side) {
+        if self.max.is_some() {
+            self.side(side).taken.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records how many items are buffered for each stream now, and returns
+    /// the timers to start for the streams that have items buffered but no
+    /// timer yet
+    #[inline]
+    pub(crate) fn check(
+        &self,
+        buffered_true: usize,
+        buffered_false: usize,
+        waker_true: &SideWaker,
+        waker_false: &SideWaker,
+    ) -> Timers {
+        if let Some((max, timer)) = &self.max {
+            let mut timers = Vec::new();
+            for (side, buffered, waker) in [
+                (Side::True, buffered_true, waker_true),
+                (Side::False, buffered_false, waker_false),
+            ] {
+                let deadline = self.side(side);
+                deadline.buffered.store(buffered, Ordering::Relaxed);
+                if buffered == 0 || deadline.armed.swap(true, Ordering::Relaxed) {
+                    continue;
+                }
+                match waker.get() {
+                    Some(waker) => timers.push(Timer {
+                        max: *max,
+                        timer: timer.clone(),
+                        deadline: deadline.clone(),
+                        waker: waker.clone(),
+                    }),
+                    // Nothing has polled the stream yet, so there's nobody to wake
+                    None => deadline.armed.store(false, Ordering::Relaxed),
+                }
+            }
+            return Timers(timers);
+        }
+        Timers::default()
+    }
+
+    fn side(&self, side: Side) -> &Arc<Deadline> {
+        match side {
+            Side::True => &self.true_side,
+            Side::False => &self.false_side,
+        }
+    }
+}
+
+/// A timer for one side that hasn't been started yet
+struct Timer {
+    max: Duration,
+    timer: SharedTimer,
+    deadline: Arc<Deadline>,
+    waker: Waker,
+}
+
+impl Timer {
+    /// Waits until the stream hasn't taken any items for `max` while it had
+    /// items buffered and wakes it then, or until its buffer is empty
+    async fn run(self) {
+        while self.timer.can_sleep() {
+            let taken = self.deadline.taken.load(Ordering::Relaxed);
+            self.timer.sleep(self.max).await;
+            if self.deadline.buffered.load(Ordering::Relaxed) == 0 {
+                break;
+            }
+            if self.deadline.taken.load(Ordering::Relaxed) == taken {
+                self.waker.wake_by_ref();
+                break;
+            }
+        }
+        self.deadline.armed.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The timers that `BufferLatency::check` asked for, which are started once
+/// the shared state of the split has been unlocked
+#[must_use]
+#[derive(Default)]
+pub(crate) struct Timers(Vec<Timer>);
+
+impl Timers {
+    /// Starts the timers. A timer that can't sleep right now, like the tokio
+    /// one outside of a runtime, isn't started, and a later check tries again
+    #[inline]
+    pub(crate) fn start(self) {
+        for timer in self.0 {
+            if timer.timer.can_sleep() {
+                Task::spawn(timer.run());
+            } else {
+                timer.deadline.armed.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Runs a timer without an executor. Whatever wakes it polls it, which is
+/// the timer itself once a sleep ends
+struct Task {
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    woken: AtomicBool,
+}
+
+impl Task {
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(Self {
+            future: Mutex::new(Some(Box::pin(future))),
+            woken: AtomicBool::new(false),
+        });
+        task.wake_by_ref();
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        // A wake while the future is being polled, even from within that poll, is left to
+        // whoever polls it, which checks for one once it's done
+        while self.woken.load(Ordering::SeqCst) {
+            let mut future = match self.future.try_lock() {
+                Some(future) => future,
+                None => return,
+            };
+            if !self.woken.swap(false, Ordering::SeqCst) {
+                continue;
+            }
+            if let Some(running) = future.as_mut() {
+                let waker = Waker::from(self.clone());
+                if running
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready()
+                {
+                    *future = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream, MockTimer},
+        SplitBuilder,
+    };
+
+    #[test]
+    fn test_rewakes_lost_waker() {
+        let timer = MockTimer::new();
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .max_buffer_latency_with(Duration::from_millis(100), timer.clone())
+            .build()
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        // Buffering the item woke the odd stream, but its task lost the wake and never polls
+        let wakes = odd_waker.count();
+        assert!(wakes > 0);
+        timer.advance(Duration::from_millis(50));
+        assert_eq!(wakes, odd_waker.count());
+        timer.advance(Duration::from_millis(100));
+        assert_eq!(wakes + 1, odd_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        // Once the item is taken, nothing is woken again
+        timer.advance(Duration::from_millis(500));
+        assert_eq!(wakes + 1, odd_waker.count());
+        assert_eq!(0, timer.sleeping());
+    }
+
+    #[test]
+    fn test_no_wake_while_taking_items() {
+        let timer = MockTimer::new();
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(4)
+            .max_buffer_latency_with(Duration::from_millis(100), timer.clone())
+            .build()
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        for n in [1, 3, 5] {
+            handle.push(n);
+            assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        }
+        let wakes = odd_waker.count();
+        // The odd stream keeps taking items, just slower than they were buffered
+        for n in [1, 3] {
+            timer.advance(Duration::from_millis(80));
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut odds, &odd_waker.waker())
+            );
+        }
+        timer.advance(Duration::from_millis(80));
+        assert_eq!(wakes, odd_waker.count());
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_rewakes_on_tokio_timer() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .max_buffer_latency(Duration::from_millis(100))
+            .build()
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        let wakes = odd_waker.count();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(wakes + 1, odd_waker.count());
+    }
+
+    #[cfg(feature = "futures-timer")]
+    #[test]
+    fn test_rewakes_on_futures_timer() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .max_buffer_latency_with(Duration::from_millis(20), crate::timer::FuturesTimer::new())
+            .build()
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        let wakes = odd_waker.count();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(wakes + 1, odd_waker.count());
+    }
+}
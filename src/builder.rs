@@ -0,0 +1,1254 @@
+use alloc::boxed::Box;
+#[cfg(feature = "spill")]
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "spill")]
+use std::path::PathBuf;
+
+use futures_core::Stream;
+
+#[cfg(feature = "timer")]
+use crate::timer::TokioTimer;
+use crate::{
+    buffer_age::BufferAge,
+    buffer_latency::BufferLatency,
+    control::SalvageHook,
+    offsets::Offsets,
+    routing_observer::{routing_observer, RoutingSender},
+    spill::Spill,
+    throttle::Throttle,
+    timer::{Clock, Sleep},
+    watermark::Watermarks,
+    AbandonedItems, Enumerated, FalseSplitByConfigured, RoutingObserver, Side, Split,
+    SplitByConfigured, TrueSplitByConfigured,
+};
+
+/// A hook that is called with every item as it's routed to a stream
+pub(crate) type RoutingHook<I> = Box<dyn FnMut(&I, Side) + Send>;
+
+/// What a split does when it pulls an item for a stream whose buffer is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Don't pull any more items for that stream until it takes some from its
+    /// buffer. This is what the methods of `SplitStreamByExt` do
+    #[default]
+    Wait,
+    /// Drop the oldest buffered item to make room for the new one
+    DropOldest,
+    /// Drop the new item
+    DropNewest,
+}
+
+/// Configures a split whose buffer sizes are chosen at runtime. By default
+/// each stream buffers one item and `Overflow::Wait` is used, which behaves
+/// like `split_by`
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{Overflow, SplitBuilder};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+///     let split = SplitBuilder::new(incoming_stream)
+///         .predicate(|&n| n % 2 == 0)
+///         .buffer_true(64)
+///         .buffer_false(8)
+///         .overflow(Overflow::DropOldest)
+///         .build();
+///
+///     let (evens, odds) = futures::join!(
+///         split.matches.collect::<Vec<_>>(),
+///         split.rest.collect::<Vec<_>>()
+///     );
+///     assert_eq!(vec![0,2,4], evens);
+///     assert_eq!(vec![1,3,5], odds);
+/// })
+/// ```
+pub struct SplitBuilder<S: Stream, P = ()> {
+    stream: S,
+    predicate: P,
+    buffer_true: usize,
+    buffer_false: usize,
+    boxed: bool,
+    overflow: Overflow,
+    driver: Option<Side>,
+    conflate: bool,
+    inspect_routing: Option<RoutingHook<S::Item>>,
+    observer: Option<RoutingSender>,
+    watermarks: Watermarks,
+    latency: BufferLatency,
+    ages: BufferAge,
+    offsets: Offsets,
+    throttle_true: Throttle,
+    throttle_false: Throttle,
+    salvage: Option<SalvageHook<S::Item>>,
+    spill: Spill<S::Item>,
+}
+
+impl<S> SplitBuilder<S>
+where
+    S: Stream,
+{
+    /// Starts configuring a split of `stream`. A predicate has to be set
+    /// before the split can be built
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            predicate: (),
+            buffer_true: 1,
+            buffer_false: 1,
+            boxed: false,
+            overflow: Overflow::default(),
+            driver: None,
+            conflate: false,
+            inspect_routing: None,
+            observer: None,
+            watermarks: Watermarks::default(),
+            latency: BufferLatency::default(),
+            ages: BufferAge::default(),
+            offsets: Offsets::default(),
+            throttle_true: Throttle::default(),
+            throttle_false: Throttle::default(),
+            salvage: None,
+            spill: Spill::default(),
+        }
+    }
+}
+
+impl<S> SplitBuilder<S>
+where
+    S: Stream,
+{
+    /// Makes both streams return `(index, item)` pairs, where `index` is the
+    /// position of the item in the underlying stream. This makes it easy to
+    /// put the items of the two streams back in their original order. The
+    /// predicate also receives these pairs, so this has to be called before
+    /// setting it. The same goes for `inspect_routing`, `salvage` and
+    /// `spill_to_disk`, and this panics if any of them has already been set
+    pub fn enumerated(self) -> SplitBuilder<Enumerated<S>> {
+        assert!(
+            self.inspect_routing.is_none(),
+            "`enumerated` has to be called before `inspect_routing`"
+        );
+        assert!(
+            self.salvage.is_none(),
+            "`enumerated` has to be called before `salvage`"
+        );
+        assert!(
+            !self.spill.is_enabled(),
+            "`enumerated` has to be called before `spill_to_disk`"
+        );
+        SplitBuilder {
+            stream: Enumerated::new(self.stream),
+            predicate: (),
+            buffer_true: self.buffer_true,
+            buffer_false: self.buffer_false,
+            boxed: self.boxed,
+            overflow: self.overflow,
+            driver: self.driver,
+            conflate: self.conflate,
+            inspect_routing: None,
+            observer: self.observer,
+            watermarks: self.watermarks,
+            latency: self.latency,
+            ages: self.ages,
+            offsets: self.offsets,
+            throttle_true: self.throttle_true,
+            throttle_false: self.throttle_false,
+            salvage: None,
+            spill: Spill::default(),
+        }
+    }
+}
+
+impl<S, P> SplitBuilder<S, P>
+where
+    S: Stream,
+{
+    /// Sets the predicate. Items for which it returns `true` go to the
+    /// `matches` stream and the rest go to the `rest` stream
+    pub fn predicate<Q>(self, predicate: Q) -> SplitBuilder<S, Q>
+    where
+        Q: Fn(&S::Item) -> bool,
+    {
+        SplitBuilder {
+            stream: self.stream,
+            predicate,
+            buffer_true: self.buffer_true,
+            buffer_false: self.buffer_false,
+            boxed: self.boxed,
+            overflow: self.overflow,
+            driver: self.driver,
+            conflate: self.conflate,
+            inspect_routing: self.inspect_routing,
+            observer: self.observer,
+            watermarks: self.watermarks,
+            latency: self.latency,
+            ages: self.ages,
+            offsets: self.offsets,
+            throttle_true: self.throttle_true,
+            throttle_false: self.throttle_false,
+            salvage: self.salvage,
+            spill: self.spill,
+        }
+    }
+
+    /// Sets how many items can be buffered for the `matches` stream while
+    /// the `rest` stream is being polled
+    pub fn buffer_true(mut self, capacity: usize) -> Self {
+        self.buffer_true = capacity;
+        self
+    }
+
+    /// Sets how many items can be buffered for the `rest` stream while the
+    /// `matches` stream is being polled
+    pub fn buffer_false(mut self, capacity: usize) -> Self {
+        self.buffer_false = capacity;
+        self
+    }
+
+    /// Sets the buffer size of both streams
+    pub fn buffer(self, capacity: usize) -> Self {
+        self.buffer_true(capacity).buffer_false(capacity)
+    }
+
+    /// Keeps each buffered item in its own box, which is allocated when the
+    /// item is buffered and freed when a stream takes it out. Buffering items
+    /// otherwise moves them in and out of the buffers and the buffers move
+    /// them when they grow, which for large items can cost more than the
+    /// allocation. Items that are returned without being buffered aren't
+    /// boxed, and the streams still return `I`
+    pub fn box_buffered(mut self) -> Self {
+        self.boxed = true;
+        self
+    }
+
+    /// Sets what happens when an item is pulled for a stream whose buffer is
+    /// full
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Makes `side` the only stream that pulls from the underlying stream.
+    /// The other stream only takes items out of its buffer, and when it's
+    /// empty it returns `Pending` until the driver buffers an item for it or
+    /// sees the underlying stream end. This keeps the cost of polling a slow
+    /// underlying stream off a latency-sensitive consumer.
+    ///
+    /// The other stream can't make progress on its own, so if the driver
+    /// stops being polled while it's still alive, the other stream waits
+    /// forever. Once the driver is dropped, the other stream pulls from the
+    /// underlying stream itself
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Side, SplitBuilder};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (urgent, background) = SplitBuilder::new(futures::stream::iter(0..10))
+    ///         .predicate(|&n| n % 5 == 0)
+    ///         .buffer(4)
+    ///         .driven_by(Side::False)
+    ///         .build()
+    ///         .into_tuple();
+    ///     let (urgent, background) = futures::join!(
+    ///         urgent.collect::<Vec<_>>(),
+    ///         background.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 5], urgent);
+    ///     assert_eq!(vec![1, 2, 3, 4, 6, 7, 8, 9], background);
+    /// })
+    /// ```
+    pub fn driven_by(mut self, side: Side) -> Self {
+        self.driver = Some(side);
+        self
+    }
+
+    /// Makes each stream only return the latest item routed to it, for
+    /// streams of snapshots like prices or presence where a consumer that
+    /// falls behind only wants the current value. Each buffer holds a single
+    /// item that newer items replace instead of waiting or being dropped.
+    /// When a stream pulls an item for itself, it keeps pulling while the
+    /// underlying stream has items ready and returns the newest of them, so
+    /// the last item routed to a stream is always the one it ends up
+    /// returning. `replaced` on each stream counts the items it never saw.
+    /// This sets the buffer sizes and the overflow policy, so calling
+    /// `buffer` or `overflow` afterwards undoes part of it
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitBuilder;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (mut prices, mut presence) = SplitBuilder::new(futures::stream::iter(0..10))
+    ///         .predicate(|&n| n < 5)
+    ///         .conflate()
+    ///         .build()
+    ///         .into_tuple();
+    ///     assert_eq!(Some(4), prices.next().await);
+    ///     assert_eq!(Some(9), presence.next().await);
+    ///     assert_eq!(4, prices.replaced());
+    ///     assert_eq!(4, presence.replaced());
+    /// })
+    /// ```
+    pub fn conflate(mut self) -> Self {
+        self.conflate = true;
+        self.overflow = Overflow::DropOldest;
+        self.buffer(1)
+    }
+
+    /// Calls `f` with every item pulled from the underlying stream and the
+    /// side it's routed to. This happens exactly once per item, whether it's
+    /// returned right away or buffered for the other stream. `f` runs while
+    /// the shared state is locked, so keep it cheap
+    pub fn inspect_routing<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&S::Item, Side) + Send + 'static,
+    {
+        self.inspect_routing = Some(Box::new(f));
+        self
+    }
+
+    /// Creates a `RoutingObserver`, a stream of the side each item is routed
+    /// to, which buffers up to `capacity` sides. Calling this again replaces
+    /// the previous observer, which then ends
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Side, SplitBuilder};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (builder, observer) = SplitBuilder::new(futures::stream::iter([0,1,2]))
+    ///         .predicate(|&n| n % 2 == 0)
+    ///         .with_routing_observer(8);
+    ///     let split = builder.build();
+    ///     futures::join!(split.matches.collect::<Vec<_>>(), split.rest.collect::<Vec<_>>());
+    ///     assert_eq!(vec![Side::True, Side::False, Side::True], observer.collect::<Vec<_>>().await);
+    /// })
+    /// ```
+    pub fn with_routing_observer(mut self, capacity: usize) -> (Self, RoutingObserver) {
+        let (sender, observer) = routing_observer(capacity);
+        self.observer = Some(sender);
+        (self, observer)
+    }
+
+    /// Calls `callback` whenever the number of items buffered for the `side`
+    /// stream rises to `threshold` from below it. It's called once for each
+    /// time that happens, not for every item while the buffer stays at or
+    /// above `threshold`, so together with `on_low_watermark` it can close
+    /// and reopen a valve upstream. The callback is called right after the
+    /// poll that filled the buffer, once the shared state is unlocked, so it
+    /// can wake tasks, send on channels or use the split. Watermarks can be
+    /// added more than once, and they all fire
+    ///
+    ///```rust
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Side, SplitBuilder};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let backed_up = Arc::new(AtomicBool::new(false));
+    ///     let (high, low) = (backed_up.clone(), backed_up.clone());
+    ///     let split = SplitBuilder::new(futures::stream::iter(0..20))
+    ///         .predicate(|&n| n % 2 == 0)
+    ///         .buffer(8)
+    ///         .on_high_watermark(Side::False, 6, move || high.store(true, Ordering::SeqCst))
+    ///         .on_low_watermark(Side::False, 2, move || low.store(false, Ordering::SeqCst))
+    ///         .build();
+    ///     futures::join!(split.matches.collect::<Vec<_>>(), split.rest.collect::<Vec<_>>());
+    ///     assert!(!backed_up.load(Ordering::SeqCst));
+    /// })
+    /// ```
+    pub fn on_high_watermark<F>(mut self, side: Side, threshold: usize, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.watermarks.add_high(side, threshold, callback);
+        self
+    }
+
+    /// Calls `callback` whenever the number of items buffered for the `side`
+    /// stream falls to `threshold` from above it. Like `on_high_watermark`,
+    /// it's called once for each time that happens, once the shared state is
+    /// unlocked
+    pub fn on_low_watermark<F>(mut self, side: Side, threshold: usize, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.watermarks.add_low(side, threshold, callback);
+        self
+    }
+
+    /// Wakes a stream again if the items buffered for it haven't been taken
+    /// within `latency`. Normally a stream is only woken when an item is
+    /// buffered for it, so if that wake is lost, for example because it was
+    /// used up by a spurious poll, the item could stay buffered until
+    /// something else wakes the task. This only re-notifies the stream, it
+    /// never drops items. A timer is started whenever a buffer stops being
+    /// empty, on the tokio timer, so the task that polled the split has to
+    /// be running on a tokio runtime for it to start. Requires the `timer`
+    /// feature
+    #[cfg(feature = "timer")]
+    pub fn max_buffer_latency(self, latency: Duration) -> Self {
+        self.max_buffer_latency_with(latency, TokioTimer::new())
+    }
+
+    /// Wakes a stream again like `max_buffer_latency`, but waits on `timer`
+    /// instead of the tokio timer. No task is spawned for it: the timer is
+    /// polled by whatever wakes it, so it works on any runtime
+    pub fn max_buffer_latency_with<T>(mut self, latency: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + Sync + 'static,
+    {
+        self.latency = BufferLatency::new(latency, timer);
+        self
+    }
+
+    /// Records when each item is buffered, so that both streams can tell how
+    /// long their items wait in the buffer with `oldest_buffered_age` and
+    /// `buffer_age_summary`. Without this, nothing is recorded. Items that
+    /// `set_predicate_reclassify` moves to the other buffer, or that
+    /// `spill_to_disk` reads back from disk, count from when they were put in
+    /// the buffer they're in. Time is measured on the tokio clock. Requires
+    /// the `timer` feature
+    #[cfg(feature = "timer")]
+    pub fn track_buffer_age(self) -> Self {
+        self.track_buffer_age_with(TokioTimer::new())
+    }
+
+    /// Records when each item is buffered like `track_buffer_age`, but
+    /// measures time on `clock` instead of the tokio clock
+    pub fn track_buffer_age_with<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + Send + Sync + 'static,
+    {
+        self.ages = BufferAge::new(clock);
+        self
+    }
+
+    /// Gives each item pulled from the underlying stream an offset, counting
+    /// up from 0 in the order they're pulled, like `enumerated` does, and
+    /// keeps track of which of them were delivered. An item is delivered once
+    /// one of the streams returns it from `poll_next` or `poll_peek`, and
+    /// the items that `overflow` or `conflate` drop count as delivered too,
+    /// since they were discarded on purpose. `abandoned` says what happens
+    /// to the items that were buffered for or routed to a stream that was
+    /// dropped, and the ones left when `close_graceful` gives up.
+    /// `delivered_watermark` on `SplitControl` and on each stream tells up to
+    /// which offset everything was delivered, which is what a source that
+    /// can resume from an offset needs to commit. Items that
+    /// `set_predicate_reclassify` moves hold the watermark back until all of
+    /// them were delivered, since their order isn't kept
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{AbandonedItems, SplitBuilder};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = SplitBuilder::new(futures::stream::iter(0..10))
+    ///         .predicate(|&n| n % 2 == 0)
+    ///         .buffer(8)
+    ///         .track_offsets(AbandonedItems::Pending)
+    ///         .build()
+    ///         .into_tuple();
+    ///     let control = evens.control();
+    ///     let (evens, odds) = futures::join!(evens.take(2).collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+    ///     assert_eq!(vec![0, 2], evens);
+    ///     assert_eq!(vec![1, 3, 5, 7, 9], odds);
+    ///     // 4 was buffered for the even stream when it was dropped
+    ///     assert_eq!(Some(3), control.delivered_watermark());
+    /// })
+    /// ```
+    pub fn track_offsets(mut self, abandoned: AbandonedItems) -> Self {
+        self.offsets = Offsets::new(abandoned);
+        self
+    }
+
+    /// Calls `callback` with the delivered watermark of `track_offsets`
+    /// whenever it advances, for example to commit offsets to the source.
+    /// Offsets are tracked with `AbandonedItems::Pending` unless
+    /// `track_offsets` was called first. Like `on_high_watermark`, the
+    /// callback is called once the shared state is unlocked, by whichever
+    /// task moved the watermark
+    pub fn on_delivered<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        self.offsets.on_delivered(callback);
+        self
+    }
+
+    /// Limits the `matches` stream to returning `items` items every `per`,
+    /// spaced out evenly. While it has to wait, polling it returns `Pending`
+    /// without touching the shared state and its task is woken by a tokio
+    /// timer once the next item is due, so it doesn't compete with the other
+    /// stream for the lock or the underlying stream. The other stream keeps
+    /// buffering items for it in the meantime. Panics if `items` is 0.
+    /// Requires the `timer` feature
+    #[cfg(feature = "timer")]
+    pub fn throttle_true(self, items: u32, per: Duration) -> Self {
+        self.throttle_true_with(items, per, TokioTimer::new())
+    }
+
+    /// Limits the `matches` stream like `throttle_true`, but waits on
+    /// `timer` instead of the tokio timer, so it works on any runtime
+    ///
+    ///```rust
+    /// # #[cfg(feature = "test-util")]
+    /// # {
+    /// use std::{task::Poll, time::Duration};
+    /// use split_stream_by::{testing::{poll_once, CountingWaker, MockTimer}, SplitBuilder};
+    ///
+    /// let timer = MockTimer::new();
+    /// let (mut evens, _odds) = SplitBuilder::new(futures::stream::iter([0, 2, 4]))
+    ///     .predicate(|&n| n % 2 == 0)
+    ///     .throttle_true_with(1, Duration::from_secs(1), timer.clone())
+    ///     .build()
+    ///     .into_tuple();
+    /// let waker = CountingWaker::new();
+    /// assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker.waker()));
+    /// assert_eq!(Poll::Pending, poll_once(&mut evens, &waker.waker()));
+    /// timer.advance(Duration::from_secs(1));
+    /// assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker.waker()));
+    /// # }
+    /// ```
+    pub fn throttle_true_with<T>(mut self, items: u32, per: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + 'static,
+    {
+        self.throttle_true = Throttle::new(items, per, timer);
+        self
+    }
+
+    /// Limits the `rest` stream to returning `items` items every `per`, like
+    /// `throttle_true`. Requires the `timer` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitBuilder;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (urgent, background) = SplitBuilder::new(futures::stream::iter(0..10))
+    ///         .predicate(|&n| n < 8)
+    ///         .buffer(8)
+    ///         .throttle_false(100, Duration::from_secs(1))
+    ///         .build()
+    ///         .into_tuple();
+    ///     let (urgent, background) = futures::join!(
+    ///         urgent.collect::<Vec<_>>(),
+    ///         background.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7], urgent);
+    ///     assert_eq!(vec![8, 9], background);
+    /// })
+    /// ```
+    #[cfg(feature = "timer")]
+    pub fn throttle_false(self, items: u32, per: Duration) -> Self {
+        self.throttle_false_with(items, per, TokioTimer::new())
+    }
+
+    /// Limits the `rest` stream like `throttle_false`, but waits on `timer`
+    /// instead of the tokio timer, like `throttle_true_with`
+    pub fn throttle_false_with<T>(mut self, items: u32, per: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + 'static,
+    {
+        self.throttle_false = Throttle::new(items, per, timer);
+        self
+    }
+
+    /// Sets a hook that is called with every item `SplitControl::close_graceful`
+    /// abandons, along with the side it was buffered for, instead of dropping
+    /// it. It's called outside of the lock on the shared state, after the
+    /// split has been aborted
+    pub fn salvage<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(S::Item, Side) + Send + 'static,
+    {
+        self.salvage = Some(Box::new(f));
+        self
+    }
+
+    /// Spills the items that don't fit in the buffer of a stream to a file in
+    /// `dir`, instead of waiting or dropping them as `overflow` says. Neither
+    /// stream ever waits for the other one to make room, and no item is
+    /// dropped. Items are only spilled once a buffer is full, and whenever
+    /// its stream takes an item out, the oldest spilled item is read back
+    /// into it, so the stream returns all of its items in order. Until a
+    /// buffer fills up, nothing touches the disk.
+    ///
+    /// `encode` turns a spilled item into bytes, and `decode` turns them
+    /// back. If writing or reading the spill file fails, or the bytes read
+    /// back aren't the ones that were written, `decode` is called with a
+    /// `SpillError` instead, and what it returns is returned by the stream in
+    /// place of the items that were lost, so the item type should be able to
+    /// hold an error. The spill files are created when they're first needed,
+    /// and deleted when the split is dropped. Items for a stream that was
+    /// dropped aren't spilled, and spilled items keep their side when the
+    /// predicate is changed. Requires the `spill` feature
+    ///
+    ///```rust
+    /// use std::convert::TryInto;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitBuilder;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = SplitBuilder::new(futures::stream::iter(0..100u32))
+    ///         .predicate(|&n| n % 2 == 0)
+    ///         .buffer(4)
+    ///         .spill_to_disk(
+    ///             std::env::temp_dir(),
+    ///             |n: &u32| n.to_le_bytes().to_vec(),
+    ///             // A real item type would carry the error
+    ///             |bytes| bytes.map_or(u32::MAX, |bytes| u32::from_le_bytes(bytes.try_into().unwrap())),
+    ///         )
+    ///         .build()
+    ///         .into_tuple();
+    ///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+    ///     assert_eq!((0..100).step_by(2).collect::<Vec<_>>(), evens);
+    ///     assert_eq!((1..100).step_by(2).collect::<Vec<_>>(), odds);
+    /// })
+    /// ```
+    #[cfg(feature = "spill")]
+    pub fn spill_to_disk<E, D>(mut self, dir: impl Into<PathBuf>, encode: E, decode: D) -> Self
+    where
+        E: Fn(&S::Item) -> Vec<u8> + Send + 'static,
+        D: Fn(Result<Vec<u8>, crate::SpillError>) -> S::Item + Send + 'static,
+    {
+        self.spill = Spill::new(dir.into(), Box::new(encode), Box::new(decode));
+        self
+    }
+}
+
+impl<S, P> SplitBuilder<S, P>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    /// Creates the split
+    #[allow(clippy::type_complexity)]
+    pub fn build(
+        self,
+    ) -> Split<TrueSplitByConfigured<S::Item, S, P>, FalseSplitByConfigured<S::Item, S, P>> {
+        let stream = SplitByConfigured::new(
+            self.stream,
+            self.predicate,
+            self.buffer_true,
+            self.buffer_false,
+            self.boxed,
+            self.overflow,
+            self.driver,
+            self.conflate,
+            self.inspect_routing,
+            self.observer,
+            self.watermarks,
+            self.latency,
+            self.ages,
+            self.offsets,
+            self.salvage,
+            self.spill,
+        );
+        let mut split = Split::share(
+            stream,
+            TrueSplitByConfigured::new,
+            FalseSplitByConfigured::new,
+        );
+        split.matches.set_throttle(self.throttle_true);
+        split.rest.set_throttle(self.throttle_false);
+        split
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        task::Poll,
+    };
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{
+        testing::{poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_defaults() {
+        let (source, handle) = ManualStream::new();
+        let Split { mut matches, .. } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .build();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(3);
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        // The buffer of the other stream holds one item, so nothing more is pulled
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        assert_eq!(1, handle.poll_count());
+    }
+
+    #[test]
+    fn test_defaults_match_split_by() {
+        let (built_source, built_handle) = ManualStream::new();
+        let built = SplitBuilder::new(built_source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .build();
+        let (source, handle) = ManualStream::new();
+        let split = source.split_by(|&n: &i32| n % 2 == 0);
+        let Split {
+            matches: mut built_matches,
+            rest: mut built_rest,
+        } = built;
+        let Split {
+            mut matches,
+            mut rest,
+        } = split;
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 2, 4, 7] {
+            built_handle.push(n);
+            handle.push(n);
+        }
+        built_handle.end();
+        handle.end();
+        for from_matches in [
+            true, true, false, true, false, false, true, false, true, false,
+        ] {
+            let (built_poll, poll) = if from_matches {
+                (
+                    poll_once(&mut built_matches, &waker.waker()),
+                    poll_once(&mut matches, &waker.waker()),
+                )
+            } else {
+                (
+                    poll_once(&mut built_rest, &waker.waker()),
+                    poll_once(&mut rest, &waker.waker()),
+                )
+            };
+            assert_eq!(poll, built_poll);
+            assert_eq!(handle.poll_count(), built_handle.poll_count());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_borrowed_items_and_predicate() {
+        let words = String::from("a bb ccc dd e");
+        let min_len = 2;
+        let split = SplitBuilder::new(futures::stream::iter(words.split(' ')))
+            .predicate(|word: &&str| word.len() >= min_len)
+            .buffer(4)
+            .build();
+        let (long, short) = futures::join!(
+            split.matches.collect::<Vec<_>>(),
+            split.rest.collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["bb", "ccc", "dd"], long);
+        assert_eq!(vec!["a", "e"], short);
+    }
+
+    #[test]
+    fn test_buffer_sizes() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer_true(0)
+            .buffer_false(3)
+            .build();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 7] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 4)
+        );
+        assert_eq!(3, handle.poll_count());
+        // There's no room to buffer anything for `matches`, so `rest` can't pull items
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &waker.waker()));
+        assert_eq!(3, handle.poll_count());
+    }
+
+    #[tokio::test]
+    async fn test_box_buffered() {
+        let split = SplitBuilder::new(futures::stream::iter(0..100).map(|n| [n; 512]))
+            .predicate(|item| item[0] % 3 == 0)
+            .buffer(16)
+            .box_buffered()
+            .build();
+        let (threes, rest) = futures::join!(
+            split.matches.collect::<Vec<_>>(),
+            split.rest.collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (0..100).step_by(3).map(|n| [n; 512]).collect::<Vec<_>>(),
+            threes
+        );
+        assert_eq!(
+            (0..100)
+                .filter(|n| n % 3 != 0)
+                .map(|n| [n; 512])
+                .collect::<Vec<_>>(),
+            rest
+        );
+    }
+
+    #[test]
+    fn test_buffer() {
+        let (source, handle) = ManualStream::new();
+        let Split { mut matches, .. } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(2)
+            .build();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 3)
+        );
+        assert_eq!(2, handle.poll_count());
+    }
+
+    fn overflow_rest(overflow: Overflow) -> Vec<i32> {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer_false(2)
+            .overflow(overflow)
+            .build();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 0] {
+            handle.push(n);
+        }
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 4)
+        );
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(n)) = poll_once(&mut rest, &waker.waker()) {
+            items.push(n);
+        }
+        items
+    }
+
+    #[test]
+    fn test_overflow_drop_oldest() {
+        assert_eq!(vec![3, 5], overflow_rest(Overflow::DropOldest));
+    }
+
+    #[test]
+    fn test_overflow_drop_newest() {
+        assert_eq!(vec![1, 3], overflow_rest(Overflow::DropNewest));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_routing() {
+        let routed = Arc::new(Mutex::new(Vec::new()));
+        let Split { matches, rest } = SplitBuilder::new(futures::stream::iter(0..6))
+            .predicate(|&n| n % 3 == 0)
+            .buffer(2)
+            .inspect_routing({
+                let routed = routed.clone();
+                move |&n, side| routed.lock().unwrap().push((n, side))
+            })
+            .build();
+        let (matches, rest) = futures::join!(matches.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+        assert_eq!(vec![0, 3], matches);
+        assert_eq!(vec![1, 2, 4, 5], rest);
+        assert_eq!(
+            vec![
+                (0, Side::True),
+                (1, Side::False),
+                (2, Side::False),
+                (3, Side::True),
+                (4, Side::False),
+                (5, Side::False),
+            ],
+            *routed.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enumerated() {
+        let Split { matches, rest } = SplitBuilder::new(futures::stream::iter("abcdef".chars()))
+            .enumerated()
+            .predicate(|(_, c)| "aeiou".contains(*c))
+            .buffer(4)
+            .build();
+        let (matches, rest) = futures::join!(matches.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+        assert_eq!(vec![(0, 'a'), (4, 'e')], matches);
+        assert_eq!(vec![(1, 'b'), (2, 'c'), (3, 'd'), (5, 'f')], rest);
+    }
+
+    #[test]
+    fn test_routing_observer() {
+        let (source, handle) = ManualStream::new();
+        let (builder, mut observer) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 3 == 0)
+            .with_routing_observer(1);
+        let Split {
+            mut matches,
+            mut rest,
+        } = builder.build();
+        let waker = CountingWaker::new();
+        for n in 0..6 {
+            handle.push(n);
+            let (item, side) = if n % 3 == 0 {
+                (poll_once(&mut matches, &waker.waker()), Side::True)
+            } else {
+                (poll_once(&mut rest, &waker.waker()), Side::False)
+            };
+            assert_eq!(Poll::Ready(Some(n)), item);
+            // An observer that keeps up sees every item
+            assert_eq!(
+                Poll::Ready(Some(side)),
+                poll_once(&mut observer, &waker.waker())
+            );
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut observer, &waker.waker()));
+        handle.end();
+        assert_eq!(Poll::Ready(None), poll_once(&mut matches, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut observer, &waker.waker()));
+        assert_eq!(0, observer.dropped());
+    }
+
+    #[tokio::test]
+    async fn test_routing_observer_drops() {
+        let (builder, observer) = SplitBuilder::new(futures::stream::iter(0..6))
+            .predicate(|&n| n % 3 == 0)
+            .buffer(4)
+            .with_routing_observer(2);
+        let Split { matches, rest } = builder.build();
+        // Nothing reads the observer while the split runs, which must not hold up the split
+        let (matches, rest) = futures::join!(matches.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+        assert_eq!(vec![0, 3], matches);
+        assert_eq!(vec![1, 2, 4, 5], rest);
+        assert_eq!(4, observer.dropped());
+        assert_eq!(
+            vec![Side::True, Side::False],
+            observer.collect::<Vec<_>>().await
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(3)
+            .build();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 7, 9] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 4)
+        );
+        assert_eq!(3, handle.poll_count());
+        // Shrinking keeps the items, but nothing more is buffered until they're taken out
+        rest.resize(1);
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        assert_eq!(3, handle.poll_count());
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut rest, &waker.waker()));
+        rest.resize(2);
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 3)
+        );
+        assert_eq!(5, handle.poll_count());
+        assert_eq!(Poll::Ready(Some(7)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(9)), poll_once(&mut rest, &waker.waker()));
+    }
+
+    #[test]
+    fn test_watermarks() {
+        let (source, handle) = ManualStream::new();
+        let high = Arc::new(AtomicUsize::new(0));
+        let low = Arc::new(AtomicUsize::new(0));
+        let (high_count, low_count) = (high.clone(), low.clone());
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer_false(4)
+            .on_high_watermark(Side::False, 3, move || {
+                high_count.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_low_watermark(Side::False, 1, move || {
+                low_count.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 7] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 4)
+        );
+        // Going from 3 to 4 items doesn't cross the watermark again
+        assert_eq!(1, high.load(Ordering::SeqCst));
+        assert_eq!(0, low.load(Ordering::SeqCst));
+        for n in [1, 3, 5, 7] {
+            assert_eq!(Poll::Ready(Some(n)), poll_once(&mut rest, &waker.waker()));
+        }
+        assert_eq!(1, high.load(Ordering::SeqCst));
+        assert_eq!(1, low.load(Ordering::SeqCst));
+        for n in [9, 11, 13] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 3)
+        );
+        assert_eq!(2, high.load(Ordering::SeqCst));
+        assert_eq!(1, low.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_driven_by_false() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(2)
+            .driven_by(Side::False)
+            .build();
+        let (true_waker, false_waker) = (CountingWaker::new(), CountingWaker::new());
+        handle.push(0);
+        // Only `rest` pulls from the underlying stream
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &true_waker.waker()));
+        assert_eq!(0, handle.poll_count());
+        assert!(matches.would_block_on_other());
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &false_waker.waker()));
+        assert_eq!(1, handle.poll_count());
+        assert_eq!(1, true_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut matches, &true_waker.waker())
+        );
+        handle.push(1);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut rest, &false_waker.waker())
+        );
+        handle.end();
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &true_waker.waker()));
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut rest, &false_waker.waker())
+        );
+        assert_eq!(2, true_waker.count());
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut matches, &true_waker.waker())
+        );
+        assert_eq!(3, handle.poll_count());
+    }
+
+    #[test]
+    fn test_driven_by_true() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(2)
+            .driven_by(Side::True)
+            .build();
+        let (true_waker, false_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [1, 0, 3] {
+            handle.push(n);
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &false_waker.waker()));
+        assert_eq!(0, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut matches, &true_waker.waker(), 2)
+        );
+        assert_eq!(1, false_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut rest, &false_waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &false_waker.waker()));
+        assert_eq!(2, handle.poll_count());
+        // Once the driver is gone, `rest` has to pull from the underlying stream itself
+        drop(matches);
+        assert_eq!(2, false_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut rest, &false_waker.waker())
+        );
+        assert_eq!(3, handle.poll_count());
+    }
+
+    #[test]
+    fn test_driven_by_full_buffer() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer_true(1)
+            .driven_by(Side::False)
+            .build();
+        let (true_waker, false_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [0, 2, 1] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut rest, &false_waker.waker(), 2)
+        );
+        assert_eq!(1, handle.poll_count());
+        // Taking an item out of the full buffer wakes the driver, since nothing else would
+        let wakes = false_waker.count();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut matches, &true_waker.waker())
+        );
+        assert_eq!(wakes + 1, false_waker.count());
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &false_waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut matches, &true_waker.waker())
+        );
+        assert_eq!(wakes + 2, false_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut rest, &false_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_conflate() {
+        let (source, handle) = ManualStream::new();
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .conflate()
+            .build();
+        let waker = CountingWaker::new();
+        for n in 0..10 {
+            handle.push(n);
+        }
+        // The item pulled for `matches` is replaced by the newer ones that were ready
+        assert_eq!(
+            Poll::Ready(Some(8)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!((4, 4), (matches.replaced(), rest.replaced()));
+        handle.push(11);
+        assert_eq!(Poll::Ready(Some(9)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(11)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &waker.waker()));
+        handle.push(13);
+        handle.push(12);
+        handle.push(15);
+        handle.end();
+        // Both the buffered item and the one pulled by `rest` are replaced
+        assert_eq!(Poll::Ready(Some(15)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(12)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut matches, &waker.waker()));
+        assert_eq!((4, 5), (matches.replaced(), rest.replaced()));
+    }
+
+    #[tokio::test]
+    async fn test_conflate_slow_consumer() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let (matches, rest) = SplitBuilder::new(receiver)
+            .predicate(|&n: &u32| n % 3 == 0)
+            .conflate()
+            .build()
+            .into_tuple();
+        let producer = tokio::spawn(async move {
+            for n in 0..1000 {
+                sender.unbounded_send(n).unwrap();
+                if n % 10 == 0 {
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        async fn last<T: Stream<Item = u32> + Unpin>(mut stream: T, delay: u64) -> Option<u32> {
+            let mut last = None;
+            while let Some(n) = stream.next().await {
+                assert!(last < Some(n));
+                last = Some(n);
+                tokio::time::sleep(std::time::Duration::from_micros(delay)).await;
+            }
+            last
+        }
+        // Only the slow consumer falls behind, but both end on the last item routed to them
+        let (last_match, last_rest) = futures::join!(last(matches, 200), last(rest, 0));
+        producer.await.unwrap();
+        assert_eq!(Some(999), last_match);
+        assert_eq!(Some(998), last_rest);
+    }
+
+    #[test]
+    fn test_pop_wakes_full_buffer_waiter() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(2)
+            .build()
+            .into_tuple();
+        for n in [1, 3, 5, 7, 0] {
+            handle.push(n);
+        }
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        while handle.poll_count() < 2 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut true_stream, &true_waker.waker())
+            );
+        }
+        // The buffer of the `false` stream is full, so the `true` stream is parked
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(0, true_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert!(true_waker.count() > 0);
+    }
+}
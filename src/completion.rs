@@ -0,0 +1,143 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::sync::Mutex;
+
+#[derive(Default)]
+struct CompletionState {
+    true_done: bool,
+    false_done: bool,
+    aborted: bool,
+    wakers: Vec<Waker>,
+}
+
+impl CompletionState {
+    fn is_complete(&self) -> bool {
+        self.aborted || (self.true_done && self.false_done)
+    }
+}
+
+/// Tracks whether each side of a split has finished. A side is finished once
+/// its stream has returned `None` (which means the underlying stream is done
+/// and its buffer is empty) or its stream has been dropped. For the map
+/// variants, the left side is tracked as the `true` side
+#[derive(Clone, Default)]
+pub(crate) struct CompletionTracker {
+    state: Arc<Mutex<CompletionState>>,
+}
+
+impl CompletionTracker {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn finish_true(&self) {
+        self.update(|state| state.true_done = true);
+    }
+
+    pub(crate) fn finish_false(&self) {
+        self.update(|state| state.false_done = true);
+    }
+
+    pub(crate) fn abort(&self) {
+        self.update(|state| state.aborted = true);
+    }
+
+    pub(crate) fn completion(&self) -> Completion {
+        Completion {
+            state: self.state.clone(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut CompletionState)) {
+        let wakers = {
+            let mut state = self.state.lock();
+            let was_complete = state.is_complete();
+            f(&mut state);
+            if was_complete || !state.is_complete() {
+                return;
+            }
+            core::mem::take(&mut state.wakers)
+        };
+        // Wake outside of the lock in case a waker polls the future inline
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// A future that resolves once a split is fully drained. That is when the
+/// underlying stream has terminated and both streams have returned `None`, or
+/// have been dropped. It also resolves if the split is aborted. Created by
+/// calling `completion()` on either of the streams
+pub struct Completion {
+    state: Arc<Mutex<CompletionState>>,
+}
+
+impl Future for Completion {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock();
+        if state.is_complete() {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{FutureExt, StreamExt};
+
+    use crate::SplitStreamByExt;
+
+    #[tokio::test]
+    async fn test_completion_after_both_drained() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+        let (true_stream, mut false_stream) = incoming_stream
+            .split_by_buffered::<3>(|&n| n % 2 == 0)
+            .into_tuple();
+        let mut completion = true_stream.completion();
+        let (true_items, _) = futures::join!(true_stream.collect::<Vec<_>>(), async {
+            assert_eq!(Some(1), false_stream.next().await);
+            assert_eq!(Some(3), false_stream.next().await);
+            assert_eq!(Some(5), false_stream.next().await);
+            // The false stream hasn't observed the end yet
+            assert!((&mut completion).now_or_never().is_none());
+            assert_eq!(None, false_stream.next().await);
+        });
+        assert_eq!(vec![0, 2, 4], true_items);
+        assert!(completion.now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_completion_dropped_stream() {
+        let incoming_stream = futures::stream::iter([0, 2, 4]);
+        let (true_stream, false_stream) = incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let mut completion = false_stream.completion();
+        drop(false_stream);
+        assert!((&mut completion).now_or_never().is_none());
+        assert_eq!(vec![0, 2, 4], true_stream.collect::<Vec<_>>().await);
+        assert!(completion.now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_completion_abort() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+        let (mut true_stream, mut false_stream) =
+            incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let completion = true_stream.completion();
+        assert_eq!(Some(0), true_stream.next().await);
+        false_stream.abort();
+        completion.await;
+        assert_eq!(None, true_stream.next().await);
+        assert_eq!(None, false_stream.next().await);
+    }
+}
@@ -0,0 +1,486 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{future::Future, pin::pin, task::Poll, time::Duration};
+
+#[cfg(feature = "timer")]
+use crate::timer::TokioTimer;
+use crate::{
+    side_waker::RoomWaker, sync::Mutex, timer::Sleep, Completion, CompletionTracker, Side,
+    SplitByConfigured,
+};
+
+/// A hook that is called with every item that a graceful close abandons
+pub(crate) type SalvageHook<I> = Box<dyn FnMut(I, Side) + Send>;
+
+/// How far a graceful close of a split has come, kept in its shared state
+pub(crate) struct Drain<I> {
+    draining: bool,
+    drained_true: usize,
+    drained_false: usize,
+    // The task waiting for the buffers to be drained
+    waker: RoomWaker,
+    salvage: Option<SalvageHook<I>>,
+}
+
+impl<I> Drain<I> {
+    pub(crate) fn new(salvage: Option<SalvageHook<I>>) -> Self {
+        Self {
+            draining: false,
+            drained_true: 0,
+            drained_false: 0,
+            waker: RoomWaker::default(),
+            salvage,
+        }
+    }
+
+    /// Whether a graceful close has started, so nothing is pulled from the
+    /// underlying stream anymore
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Records that the stream of `side` returned an item, which came out of
+    /// its buffer if the split is draining
+    pub(crate) fn returned(&mut self, side: Side) {
+        if self.draining {
+            match side {
+                Side::True => self.drained_true += 1,
+                Side::False => self.drained_false += 1,
+            }
+            self.waker.wake();
+        }
+    }
+
+    /// Wakes the task waiting for the buffers to be drained, because one of
+    /// the streams was dropped
+    pub(crate) fn closed(&mut self) {
+        self.waker.wake();
+    }
+
+    /// Starts draining the split
+    pub(crate) fn start(&mut self) {
+        self.draining = true;
+    }
+
+    /// Stores the waker of the task waiting for the buffers to be drained
+    pub(crate) fn register(&mut self, waker: &core::task::Waker) {
+        self.waker.register(waker);
+    }
+
+    /// Takes the salvage hook along with the report of the drain, given how
+    /// many items were abandoned on each side
+    pub(crate) fn finish(
+        &mut self,
+        abandoned_true: usize,
+        abandoned_false: usize,
+    ) -> (DrainReport, Option<SalvageHook<I>>) {
+        let report = DrainReport {
+            drained_true: self.drained_true,
+            drained_false: self.drained_false,
+            abandoned_true,
+            abandoned_false,
+        };
+        (report, self.salvage.take())
+    }
+}
+
+/// How many items each stream took from its buffer during a graceful close,
+/// and how many were abandoned. Returned by `SplitControl::close_graceful`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrainReport {
+    /// The buffered items the `matches` stream returned before the deadline
+    pub drained_true: usize,
+    /// The buffered items the `rest` stream returned before the deadline
+    pub drained_false: usize,
+    /// The items left for the `matches` stream, which were dropped or handed
+    /// to the salvage hook
+    pub abandoned_true: usize,
+    /// The items left for the `rest` stream, which were dropped or handed to
+    /// the salvage hook
+    pub abandoned_false: usize,
+}
+
+/// Controls a split made by `SplitBuilder` without being one of its streams.
+/// Created by `control` on either stream
+pub struct SplitControl<I, S, P> {
+    stream: Arc<Mutex<SplitByConfigured<I, S, P>>>,
+    completion: CompletionTracker,
+}
+
+impl<I, S, P> SplitControl<I, S, P> {
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByConfigured<I, S, P>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self { stream, completion }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Stops pulling from the underlying stream, for example to freeze
+    /// intake during a deploy. Each stream keeps returning the items left in
+    /// its buffer, and then returns `Pending` until the split is resumed.
+    /// Items are neither lost nor reordered by pausing
+    pub fn pause(&self) {
+        self.stream.lock().set_paused(true);
+    }
+
+    /// Resumes pulling from the underlying stream after `pause`, and wakes
+    /// both streams
+    pub fn resume(&self) {
+        self.stream.lock().set_paused(false);
+    }
+
+    /// Whether the split is paused
+    pub fn is_paused(&self) -> bool {
+        self.stream.lock().is_paused()
+    }
+
+    /// The highest offset such that every item with an offset up to it has
+    /// been returned by one of the streams, or given up on as the split was
+    /// built to, or `None` if there is no such item yet or offsets aren't
+    /// tracked. See `SplitBuilder::track_offsets`
+    pub fn delivered_watermark(&self) -> Option<u64> {
+        self.stream.lock().delivered_watermark(None)
+    }
+
+    /// Closes the split gracefully. Nothing is pulled from the underlying
+    /// stream anymore, and each stream returns the items left in its buffer
+    /// and then `None`. Both streams are woken when this starts. If they
+    /// haven't emptied their buffers within `deadline`, the split is aborted,
+    /// which wakes them again, and the items that are left are dropped or
+    /// handed to the hook set with `SplitBuilder::salvage`. The same happens
+    /// to items left for a stream that was dropped. Resolves with how many
+    /// items were drained and abandoned on each side. The deadline runs on
+    /// the tokio timer. Requires the `timer` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::{DrainReport, SplitBuilder};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = SplitBuilder::new(futures::stream::iter(0..10))
+    ///         .predicate(|&n| n % 2 == 0)
+    ///         .build()
+    ///         .into_tuple();
+    ///     let control = evens.control();
+    ///     let (report, evens, odds) = futures::join!(
+    ///         control.close_graceful(Duration::from_secs(1)),
+    ///         evens.collect::<Vec<_>>(),
+    ///         odds.collect::<Vec<_>>()
+    ///     );
+    ///     // Nothing had been pulled from the underlying stream, so nothing was left to drain
+    ///     assert!(evens.is_empty() && odds.is_empty());
+    ///     assert_eq!(DrainReport::default(), report);
+    /// })
+    /// ```
+    #[cfg(feature = "timer")]
+    pub async fn close_graceful(&self, deadline: Duration) -> DrainReport {
+        self.close_graceful_with(deadline, TokioTimer::new()).await
+    }
+
+    /// Closes the split gracefully like `close_graceful`, but waits for the
+    /// deadline on `timer` instead of the tokio timer
+    pub async fn close_graceful_with<T: Sleep>(&self, deadline: Duration, timer: T) -> DrainReport {
+        self.stream.lock().start_drain();
+        let mut sleep = pin!(timer.sleep(deadline));
+        // The buffers are checked first, so a split that drained just as the deadline passed
+        // isn't aborted
+        let timed_out = core::future::poll_fn(|cx| {
+            if self.stream.lock().poll_drained(cx).is_ready() {
+                return Poll::Ready(false);
+            }
+            sleep.as_mut().poll(cx).map(|()| true)
+        })
+        .await;
+        let mut state = self.stream.lock();
+        let (report, abandoned, salvage) = state.finish_drain();
+        if timed_out {
+            state.abort();
+        }
+        let advanced = state.delivered_advanced();
+        drop(state);
+        advanced.call();
+        if timed_out {
+            self.completion.abort();
+        }
+        // The hook runs outside of the lock, so it can do anything
+        match salvage {
+            Some(mut salvage) => abandoned
+                .into_iter()
+                .for_each(|(item, side)| salvage(item, side)),
+            None => drop(abandoned),
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    #[cfg(feature = "timer")]
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream, MockTimer},
+        DrainReport, ReuniteError, Side, SplitBuilder,
+    };
+
+    #[test]
+    fn test_pause_drains_buffers_then_resumes() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(4)
+            .build()
+            .into_tuple();
+        let (even_waker, odd_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [1, 3, 0, 5, 2, 4] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut evens, &even_waker.waker(), 3)
+        );
+        let control = evens.control();
+        control.pause();
+        assert!(control.is_paused());
+        let polls = handle.poll_count();
+        // The buffered items are still returned, but nothing more is pulled
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert_eq!(polls, handle.poll_count());
+        control.resume();
+        assert!(!control.is_paused());
+        assert_eq!(1, even_waker.count());
+        assert_eq!(1, odd_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_until_ready_with_waker(&mut evens, &even_waker.waker(), 2)
+        );
+        assert_eq!(
+            Poll::Ready(Some(5)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(4)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_pause_with_full_buffer_doesnt_wake() {
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..20))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(2)
+            .build()
+            .into_tuple();
+        let (even_waker, odd_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [0, 2] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut evens, &even_waker.waker(), 2)
+            );
+        }
+        // This buffers 3, which fills the buffer of the odd stream
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        let control = odds.control();
+        control.pause();
+        let wakes = (even_waker.count(), odd_waker.count());
+        // The buffer of the odd stream is full, but the streams don't wake each other while the
+        // split is paused
+        for _ in 0..3 {
+            assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        }
+        assert_eq!(wakes, (even_waker.count(), odd_waker.count()));
+        control.resume();
+        let (mut evens_left, mut odds_left) = (Vec::new(), Vec::new());
+        while evens_left.len() + odds_left.len() < 18 {
+            if let Poll::Ready(Some(n)) = poll_once(&mut evens, &even_waker.waker()) {
+                evens_left.push(n);
+            }
+            if let Poll::Ready(Some(n)) = poll_once(&mut odds, &odd_waker.waker()) {
+                odds_left.push(n);
+            }
+        }
+        assert_eq!((4..20).step_by(2).collect::<Vec<_>>(), evens_left);
+        assert_eq!((1..20).step_by(2).collect::<Vec<_>>(), odds_left);
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_close_graceful_drains_buffers() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(4)
+            .build()
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 0, 5] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut evens, &waker.waker(), 3)
+        );
+        let control = evens.control();
+        let close =
+            tokio::spawn(async move { control.close_graceful(Duration::from_secs(1)).await });
+        tokio::task::yield_now().await;
+        // Only the buffered items are returned, and 5 is never pulled
+        assert_eq!(vec![1, 3], (&mut odds).collect::<Vec<_>>().await);
+        assert_eq!(None, evens.next().await);
+        assert_eq!(
+            DrainReport {
+                drained_true: 0,
+                drained_false: 2,
+                abandoned_true: 0,
+                abandoned_false: 0,
+            },
+            close.await.unwrap()
+        );
+        assert!(!handle.is_empty());
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_close_graceful_salvages_after_deadline() {
+        let salvaged = Arc::new(Mutex::new(Vec::new()));
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..10))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(4)
+            .salvage({
+                let salvaged = salvaged.clone();
+                move |n, side| salvaged.lock().unwrap().push((n, side))
+            })
+            .build()
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 2, 4, 6] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut evens, &waker.waker(), 2)
+            );
+        }
+        // The odd stream takes one of its items and then stalls
+        let control = odds.control();
+        let close =
+            tokio::spawn(async move { control.close_graceful(Duration::from_secs(1)).await });
+        tokio::task::yield_now().await;
+        assert_eq!(Some(1), odds.next().await);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(
+            DrainReport {
+                drained_true: 0,
+                drained_false: 1,
+                abandoned_true: 0,
+                abandoned_false: 2,
+            },
+            close.await.unwrap()
+        );
+        assert_eq!(
+            vec![(3, Side::False), (5, Side::False)],
+            *salvaged.lock().unwrap()
+        );
+        assert_eq!(None, odds.next().await);
+        assert_eq!(None, evens.next().await);
+    }
+
+    #[test]
+    fn test_close_graceful_with_mock_timer() {
+        let timer = MockTimer::new();
+        let salvaged = Arc::new(Mutex::new(Vec::new()));
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..10))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(4)
+            .salvage({
+                let salvaged = salvaged.clone();
+                move |n, side| salvaged.lock().unwrap().push((n, side))
+            })
+            .build()
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 2, 4, 6] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut evens, &waker.waker(), 2)
+            );
+        }
+        let control = odds.control();
+        let mut close = pin!(control.close_graceful_with(Duration::from_secs(1), timer.clone()));
+        let close_waker = CountingWaker::new();
+        let close_waker_ref = close_waker.waker();
+        let mut cx = Context::from_waker(&close_waker_ref);
+        assert!(close.as_mut().poll(&mut cx).is_pending());
+        // The odd stream takes one of its items and then stalls
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut odds, &waker.waker()));
+        assert!(close.as_mut().poll(&mut cx).is_pending());
+        let wakes = close_waker.count();
+        timer.advance(Duration::from_secs(1));
+        assert!(close_waker.count() > wakes);
+        assert_eq!(
+            Poll::Ready(DrainReport {
+                drained_true: 0,
+                drained_false: 1,
+                abandoned_true: 0,
+                abandoned_false: 2,
+            }),
+            close.as_mut().poll(&mut cx)
+        );
+        assert_eq!(
+            vec![(3, Side::False), (5, Side::False)],
+            *salvaged.lock().unwrap()
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut evens, &waker.waker()));
+    }
+
+    #[test]
+    fn test_reunite_fails_while_controlled() {
+        let (mut evens, odds) = SplitBuilder::new(futures::stream::iter(0..4))
+            .predicate(|&n| n % 2 == 0)
+            .build()
+            .into_tuple();
+        let control = evens.control();
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker.waker()));
+        let (mut evens, mut odds) = match evens.reunite(odds) {
+            Ok(_) => panic!("the control handle still refers to the split"),
+            Err(ReuniteError(evens, odds)) => (evens, odds),
+        };
+        // Both streams keep working
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut odds, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker.waker()));
+        drop(control);
+        let (mut stream, buffered_true, buffered_false) = evens.reunite(odds).unwrap();
+        assert!(buffered_true.is_empty() && buffered_false.is_empty());
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut stream, &waker.waker()));
+    }
+}
@@ -0,0 +1,38 @@
+use core::fmt;
+
+/// Formats a split stream including the items that are currently buffered,
+/// which requires the items to implement `Debug`. Created by calling
+/// `verbose()` on any of the streams
+pub struct Verbose<'a, T>(pub(crate) &'a T);
+
+/// Formats one of the streams. `fields` adds the fields of the shared state,
+/// which is `None` if it couldn't be locked
+pub(crate) fn fmt_split<T>(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    state: Option<&T>,
+    terminated: bool,
+    fields: impl FnOnce(&T, &mut fmt::DebugStruct<'_, '_>),
+) -> fmt::Result {
+    let mut debug = f.debug_struct(name);
+    match state {
+        Some(state) => fields(state, &mut debug),
+        None => {
+            debug.field("state", &format_args!("<locked>"));
+        }
+    }
+    debug.field("terminated", &terminated).finish()
+}
+
+/// Formats the items of a buffer as a list
+pub(crate) struct Items<It>(pub(crate) It);
+
+impl<It> fmt::Debug for Items<It>
+where
+    It: Iterator + Clone,
+    It::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.clone()).finish()
+    }
+}
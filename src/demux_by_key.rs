@@ -0,0 +1,608 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_sink::Sink;
+use futures_util::{stream::Fuse, StreamExt};
+use pin_project::pin_project;
+
+#[cfg(feature = "timer")]
+use crate::timer::TokioTimer;
+use crate::timer::{Clock, DynTimer, Sleep};
+
+/// Decides whether the sink of a key has gone quiet. It's called with the key
+/// and the number of items that went to other keys since the sink's last item
+type IdlePolicy<K> = Box<dyn FnMut(&K, u64) -> bool + Send>;
+
+/// A sleep of the timer of an idle timeout
+type BoxSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Error returned by `DemuxByKey` when the sink of one of the keys fails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DemuxError<K, E> {
+    /// The key of the sink that failed
+    pub key: K,
+    /// The error of the sink
+    pub error: E,
+}
+
+impl<K, E> fmt::Display for DemuxError<K, E>
+where
+    K: fmt::Debug,
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the sink for key {:?} failed: {}", self.key, self.error)
+    }
+}
+
+impl<K, E> Error for DemuxError<K, E>
+where
+    K: fmt::Debug,
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+struct Open<Si> {
+    sink: Pin<Box<Si>>,
+    // The number of items that had been routed when this sink got its last one
+    last_item: u64,
+    // When this sink got its last item on the clock of the idle timeout, or was created
+    last_active: Duration,
+}
+
+/// Closes the sinks that haven't gotten an item for a while
+struct IdleTimeout {
+    timeout: Duration,
+    /// The sleep that's running, along with when it ends
+    sleep: Option<(Duration, BoxSleep)>,
+    timer: Box<dyn DynTimer>,
+}
+
+impl IdleTimeout {
+    fn now(&self) -> Duration {
+        self.timer.now()
+    }
+
+    /// Moves the sinks that have been idle for `timeout` to `closing`.
+    /// Otherwise the waker of `cx` is woken once the next one will have been,
+    /// by a sleep of the timer
+    fn evict<K, Si>(
+        &mut self,
+        open: &mut HashMap<K, Open<Si>>,
+        closing: &mut Vec<(K, Pin<Box<Si>>)>,
+        cx: &mut Context<'_>,
+    ) where
+        K: Eq + Hash + Clone,
+    {
+        loop {
+            let now = self.timer.now();
+            let idle = open
+                .iter()
+                .filter(|(_, open)| now - open.last_active >= self.timeout)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in idle {
+                let open = open.remove(&key).expect("the key is open");
+                closing.push((key, open.sink));
+            }
+            let next = match open.values().map(|open| open.last_active).min() {
+                Some(last_active) => last_active + self.timeout,
+                None => {
+                    self.sleep = None;
+                    return;
+                }
+            };
+            let sleep = match &mut self.sleep {
+                Some((until, sleep)) if *until == next => sleep,
+                _ => {
+                    let sleep = self.timer.sleep(next - now);
+                    &mut self.sleep.insert((next, sleep)).1
+                }
+            };
+            if sleep.as_mut().poll(cx).is_pending() {
+                return;
+            }
+            self.sleep = None;
+        }
+    }
+}
+
+/// A future that sends each item of a stream to a sink for its key, creating
+/// the sinks as new keys come along. Created by `demux_by_key`
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct DemuxByKey<S, K, F, M, Si>
+where
+    S: Stream,
+{
+    #[pin]
+    stream: Fuse<S>,
+    key_fn: F,
+    make_sink: M,
+    open: HashMap<K, Open<Si>>,
+    closing: Vec<(K, Pin<Box<Si>>)>,
+    // The item that was pulled from the stream but hasn't been accepted by its sink yet
+    pending: Option<(K, S::Item)>,
+    routed: u64,
+    idle_policy: Option<IdlePolicy<K>>,
+    idle_timeout: Option<IdleTimeout>,
+    max_open: Option<usize>,
+}
+
+/// Sends each item of `stream` to the sink for its key, as returned by
+/// `key_fn`. The first time a key comes along, `make_sink` creates its sink.
+/// An item only waits for the sink of its own key to be ready, and nothing
+/// more is pulled from the stream until it has been sent. Once the stream
+/// has ended, all sinks are closed and the future completes. If a sink fails,
+/// the future fails right away with the key of that sink. See `idle_policy`,
+/// `idle_timeout` and `max_open_sinks` for closing the sinks of keys that
+/// have gone quiet before then
+///
+///```rust
+/// use std::{collections::HashMap, sync::{Arc, Mutex}};
+/// use futures::{channel::mpsc, StreamExt};
+/// use split_stream_by::demux_by_key;
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let receivers = Arc::new(Mutex::new(HashMap::new()));
+///     let sessions = receivers.clone();
+///     let messages = futures::stream::iter([("a", 1), ("b", 2), ("a", 3)]);
+///     demux_by_key(messages, |&(session, _)| session, move |&session| {
+///         let (sender, receiver) = mpsc::unbounded();
+///         sessions.lock().unwrap().insert(session, receiver);
+///         sender
+///     })
+///     .await
+///     .unwrap();
+///     let a = receivers.lock().unwrap().remove("a").unwrap();
+///     assert_eq!(vec![("a", 1), ("a", 3)], a.collect::<Vec<_>>().await);
+/// })
+/// ```
+pub fn demux_by_key<S, K, F, M, Si>(
+    stream: S,
+    key_fn: F,
+    make_sink: M,
+) -> DemuxByKey<S, K, F, M, Si>
+where
+    S: Stream,
+    K: Eq + Hash + Clone,
+    F: Fn(&S::Item) -> K,
+    M: FnMut(&K) -> Si,
+    Si: Sink<S::Item>,
+{
+    DemuxByKey {
+        stream: stream.fuse(),
+        key_fn,
+        make_sink,
+        open: HashMap::new(),
+        closing: Vec::new(),
+        pending: None,
+        routed: 0,
+        idle_policy: None,
+        idle_timeout: None,
+        max_open: None,
+    }
+}
+
+impl<S, K, F, M, Si> DemuxByKey<S, K, F, M, Si>
+where
+    S: Stream,
+{
+    /// Closes the sinks of keys that have gone quiet. Whenever the stream has
+    /// nothing ready, `policy` is called for each open sink with its key and
+    /// the number of items that went to other keys since the sink got its
+    /// last one. The sinks it returns `true` for are closed and dropped, and
+    /// if their key comes along again, `make_sink` creates a new sink for it
+    pub fn idle_policy(mut self, policy: impl FnMut(&K, u64) -> bool + Send + 'static) -> Self {
+        self.idle_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Closes the sinks of keys that haven't gotten an item for `timeout`,
+    /// on the tokio clock. The sinks are closed and dropped once their time
+    /// is up, even while the stream has nothing ready, and if their key
+    /// comes along again, `make_sink` creates a new sink for it. A sink is
+    /// never closed while an item for it waits for it to be ready. Requires
+    /// the `timer` feature
+    #[cfg(feature = "timer")]
+    pub fn idle_timeout(self, timeout: Duration) -> Self {
+        self.idle_timeout_with(timeout, TokioTimer::new())
+    }
+
+    /// Closes the sinks of keys that have gone quiet like `idle_timeout`,
+    /// but on the clock of `timer` instead of the tokio one
+    ///
+    ///```rust
+    /// # #[cfg(feature = "test-util")]
+    /// # {
+    /// use std::{future::Future, pin::Pin, task::{Context, Poll}, time::Duration};
+    /// use futures::{channel::mpsc, sink::drain};
+    /// use split_stream_by::{demux_by_key, testing::{CountingWaker, ManualStream, MockTimer}};
+    ///
+    /// let (messages, handle) = ManualStream::new();
+    /// let timer = MockTimer::new();
+    /// let mut demux = demux_by_key(messages, |&(session, _): &(char, i32)| session, |_| drain())
+    ///     .idle_timeout_with(Duration::from_secs(60), timer.clone());
+    /// let waker = CountingWaker::new();
+    /// let waker_ref = waker.waker();
+    /// let mut cx = Context::from_waker(&waker_ref);
+    /// handle.push(('a', 0));
+    /// assert!(Pin::new(&mut demux).poll(&mut cx).is_pending());
+    /// assert_eq!(1, demux.open_sinks());
+    /// timer.advance(Duration::from_secs(60));
+    /// assert!(Pin::new(&mut demux).poll(&mut cx).is_pending());
+    /// assert_eq!(0, demux.open_sinks());
+    /// # }
+    /// ```
+    pub fn idle_timeout_with<T>(mut self, timeout: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + 'static,
+    {
+        self.idle_timeout = Some(IdleTimeout {
+            timeout,
+            sleep: None,
+            timer: Box::new(timer),
+        });
+        self
+    }
+
+    /// Keeps at most `max` sinks open. When an item comes along for a key
+    /// without a sink and `max` sinks are open already, the sink of the key
+    /// that least recently got an item is closed and dropped to make room.
+    /// Panics if `max` is 0
+    pub fn max_open_sinks(mut self, max: usize) -> Self {
+        assert!(max > 0, "the sink of an item has to be able to be open");
+        self.max_open = Some(max);
+        self
+    }
+
+    /// The number of sinks that are currently open
+    pub fn open_sinks(&self) -> usize {
+        self.open.len()
+    }
+}
+
+impl<S, K, F, M, Si> Future for DemuxByKey<S, K, F, M, Si>
+where
+    S: Stream,
+    K: Eq + Hash + Clone,
+    F: Fn(&S::Item) -> K,
+    M: FnMut(&K) -> Si,
+    Si: Sink<S::Item>,
+{
+    type Output = Result<(), DemuxError<K, Si::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            poll_closing(this.closing, cx)?;
+            if let Some((key, item)) = this.pending.take() {
+                match *this.max_open {
+                    Some(max) if this.open.len() >= max && !this.open.contains_key(&key) => {
+                        let lru = this
+                            .open
+                            .iter()
+                            .min_by_key(|(_, open)| open.last_item)
+                            .map(|(key, _)| key.clone())
+                            .expect("at least one sink is open");
+                        let open = this.open.remove(&lru).expect("the key is open");
+                        this.closing.push((lru, open.sink));
+                        poll_closing(this.closing, cx)?;
+                    }
+                    _ => {}
+                }
+                let make_sink = &mut *this.make_sink;
+                let routed = *this.routed;
+                let now = this
+                    .idle_timeout
+                    .as_ref()
+                    .map_or(Duration::ZERO, |idle| idle.now());
+                let open = this.open.entry(key.clone()).or_insert_with(|| Open {
+                    sink: Box::pin(make_sink(&key)),
+                    last_item: routed,
+                    last_active: now,
+                });
+                match open.sink.as_mut().poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(DemuxError { key, error })),
+                    Poll::Pending => {
+                        *this.pending = Some((key, item));
+                        return Poll::Pending;
+                    }
+                }
+                if let Err(error) = open.sink.as_mut().start_send(item) {
+                    return Poll::Ready(Err(DemuxError { key, error }));
+                }
+                *this.routed += 1;
+                open.last_item = *this.routed;
+                if let Some(idle) = this.idle_timeout {
+                    open.last_active = idle.now();
+                }
+            }
+            if this.stream.is_terminated() {
+                this.closing
+                    .extend(this.open.drain().map(|(key, open)| (key, open.sink)));
+                poll_closing(this.closing, cx)?;
+                return if this.closing.is_empty() {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                };
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let key = (this.key_fn)(&item);
+                    *this.pending = Some((key, item));
+                }
+                Poll::Ready(None) => {}
+                Poll::Pending => {
+                    if let Some(policy) = this.idle_policy {
+                        let routed = *this.routed;
+                        let idle = this
+                            .open
+                            .iter()
+                            .filter(|(key, open)| policy(key, routed - open.last_item))
+                            .map(|(key, _)| key.clone())
+                            .collect::<Vec<_>>();
+                        for key in idle {
+                            let open = this.open.remove(&key).expect("the key is open");
+                            this.closing.push((key, open.sink));
+                        }
+                    }
+                    if let Some(idle) = this.idle_timeout {
+                        idle.evict(this.open, this.closing, cx);
+                    }
+                    poll_closing(this.closing, cx)?;
+                    // Nothing will be sent for a while, so send what the sinks are holding on to
+                    for (key, open) in this.open.iter_mut() {
+                        if let Poll::Ready(Err(error)) = open.sink.as_mut().poll_flush(cx) {
+                            let key = key.clone();
+                            return Poll::Ready(Err(DemuxError { key, error }));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+/// Polls the sinks that are being closed, dropping those that are done
+fn poll_closing<K, Si, I>(
+    closing: &mut Vec<(K, Pin<Box<Si>>)>,
+    cx: &mut Context<'_>,
+) -> Result<(), DemuxError<K, Si::Error>>
+where
+    Si: Sink<I>,
+{
+    let mut i = 0;
+    while i < closing.len() {
+        match closing[i].1.as_mut().poll_close(cx) {
+            Poll::Ready(Ok(())) => {
+                closing.swap_remove(i);
+            }
+            Poll::Ready(Err(error)) => {
+                let (key, _) = closing.swap_remove(i);
+                return Err(DemuxError { key, error });
+            }
+            Poll::Pending => i += 1,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        task::Poll,
+    };
+
+    use futures::{channel::mpsc, StreamExt};
+
+    use super::*;
+    use crate::testing::{poll_once, CountingWaker, ManualStream, MockTimer};
+
+    type Receivers = Arc<Mutex<HashMap<char, Vec<mpsc::UnboundedReceiver<(char, i32)>>>>>;
+
+    /// Returns a sink factory that keeps the receiving end of every sink it
+    /// creates
+    fn channels() -> (
+        Receivers,
+        impl FnMut(&char) -> mpsc::UnboundedSender<(char, i32)>,
+    ) {
+        let receivers = Receivers::default();
+        let created = receivers.clone();
+        let make_sink = move |&key: &char| {
+            let (sender, receiver) = mpsc::unbounded();
+            created
+                .lock()
+                .unwrap()
+                .entry(key)
+                .or_default()
+                .push(receiver);
+            sender
+        };
+        (receivers, make_sink)
+    }
+
+    #[tokio::test]
+    async fn test_demux_by_key() {
+        let (receivers, make_sink) = channels();
+        let items = [('a', 0), ('b', 1), ('a', 2), ('c', 3), ('b', 4)];
+        demux_by_key(futures::stream::iter(items), |&(key, _)| key, make_sink)
+            .await
+            .unwrap();
+        let mut receivers = receivers.lock().unwrap();
+        let mut received = |key| {
+            let receiver = receivers.get_mut(&key).unwrap().pop().unwrap();
+            futures::executor::block_on(receiver.map(|(_, n)| n).collect::<Vec<_>>())
+        };
+        assert_eq!(vec![0, 2], received('a'));
+        assert_eq!(vec![1, 4], received('b'));
+        assert_eq!(vec![3], received('c'));
+    }
+
+    #[test]
+    fn test_idle_policy() {
+        let (source, handle) = ManualStream::new();
+        let (receivers, make_sink) = channels();
+        let mut demux = demux_by_key(source, |&(key, _)| key, make_sink)
+            .idle_policy(|_, others: u64| others >= 2);
+        let waker = CountingWaker::new().waker();
+        let mut cx = Context::from_waker(&waker);
+        for item in [('a', 0), ('b', 1), ('b', 2)] {
+            handle.push(item);
+        }
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        // Two items went to `b` since `a` got its last one
+        assert_eq!(1, demux.open_sinks());
+        let mut a = receivers.lock().unwrap().get_mut(&'a').unwrap().remove(0);
+        assert_eq!(Poll::Ready(Some(('a', 0))), poll_once(&mut a, &waker));
+        assert_eq!(Poll::Ready(None), poll_once(&mut a, &waker));
+        handle.push(('a', 3));
+        handle.end();
+        assert_eq!(Poll::Ready(Ok(())), Pin::new(&mut demux).poll(&mut cx));
+        let mut a = receivers.lock().unwrap().get_mut(&'a').unwrap().remove(0);
+        assert_eq!(Poll::Ready(Some(('a', 3))), poll_once(&mut a, &waker));
+        assert_eq!(Poll::Ready(None), poll_once(&mut a, &waker));
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let (source, handle) = ManualStream::new();
+        let (receivers, make_sink) = channels();
+        let timer = MockTimer::new();
+        let mut demux = demux_by_key(source, |&(key, _)| key, make_sink)
+            .idle_timeout_with(Duration::from_secs(10), timer.clone());
+        let waker = CountingWaker::new();
+        let waker_ref = waker.waker();
+        let mut cx = Context::from_waker(&waker_ref);
+        handle.push(('a', 0));
+        handle.push(('b', 1));
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        timer.advance(Duration::from_secs(5));
+        handle.push(('b', 2));
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        assert_eq!(2, demux.open_sinks());
+        // The sleep for `a` wakes the demux, even though the stream has nothing
+        let wakes = waker.count();
+        timer.advance(Duration::from_secs(5));
+        assert!(waker.count() > wakes);
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        assert_eq!(1, demux.open_sinks());
+        let mut a = receivers.lock().unwrap().get_mut(&'a').unwrap().remove(0);
+        assert_eq!(Poll::Ready(Some(('a', 0))), poll_once(&mut a, &waker_ref));
+        assert_eq!(Poll::Ready(None), poll_once(&mut a, &waker_ref));
+        // `a` comes back, and gets a new sink
+        handle.push(('a', 3));
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        assert_eq!(2, demux.open_sinks());
+        timer.advance(Duration::from_secs(5));
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        assert_eq!(1, demux.open_sinks());
+        handle.end();
+        assert_eq!(Poll::Ready(Ok(())), Pin::new(&mut demux).poll(&mut cx));
+        let mut receivers = receivers.lock().unwrap();
+        let mut received = |key| {
+            let receiver = receivers.get_mut(&key).unwrap().remove(0);
+            futures::executor::block_on(receiver.map(|(_, n)| n).collect::<Vec<_>>())
+        };
+        assert_eq!(vec![3], received('a'));
+        assert_eq!(vec![1, 2], received('b'));
+    }
+
+    #[tokio::test]
+    async fn test_max_open_sinks() {
+        let (receivers, make_sink) = channels();
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let make_sink = {
+            let opened = opened.clone();
+            let mut make_sink = make_sink;
+            move |key: &char| {
+                opened.lock().unwrap().push(*key);
+                make_sink(key)
+            }
+        };
+        let items = [('a', 0), ('b', 1), ('a', 2), ('c', 3), ('a', 4), ('b', 5)];
+        demux_by_key(futures::stream::iter(items), |&(key, _)| key, make_sink)
+            .max_open_sinks(2)
+            .await
+            .unwrap();
+        // `c` closed `b`, which got an item less recently than `a`, and `b` closed `c`
+        assert_eq!(vec!['a', 'b', 'c', 'b'], *opened.lock().unwrap());
+        let mut receivers = receivers.lock().unwrap();
+        let mut received = |key| {
+            let receiver = receivers.get_mut(&key).unwrap().remove(0);
+            futures::executor::block_on(receiver.map(|(_, n)| n).collect::<Vec<_>>())
+        };
+        assert_eq!(vec![0, 2, 4], received('a'));
+        assert_eq!(vec![1], received('b'));
+        assert_eq!(vec![5], received('b'));
+        assert_eq!(vec![3], received('c'));
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let receivers = Arc::new(Mutex::new(HashMap::new()));
+        let created = receivers.clone();
+        let mut demux = demux_by_key(
+            futures::stream::iter(0..4),
+            |&n: &i32| n % 2 == 0,
+            move |&even| {
+                // A channel with no buffer only has room for one message per sender
+                let (sender, receiver) = mpsc::channel(0);
+                created.lock().unwrap().insert(even, receiver);
+                sender
+            },
+        );
+        let waker = CountingWaker::new().waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, Pin::new(&mut demux).poll(&mut cx));
+        let mut evens = receivers.lock().unwrap().remove(&true).unwrap();
+        let mut odds = receivers.lock().unwrap().remove(&false).unwrap();
+        // 2 is waiting for room, so 3 wasn't pulled even though there's room for it
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker));
+        assert_eq!(Poll::Ready(Ok(())), Pin::new(&mut demux).poll(&mut cx));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker));
+        assert_eq!(Poll::Ready(None), poll_once(&mut evens, &waker));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &waker));
+    }
+
+    #[tokio::test]
+    async fn test_sink_error() {
+        let mut evens = None;
+        let err = demux_by_key(
+            futures::stream::iter(0..4),
+            |&n: &i32| n % 2 == 0,
+            |&even| {
+                let (sender, receiver) = mpsc::unbounded();
+                // The receiver for odd numbers is dropped, so sending to it fails
+                if even {
+                    evens = Some(receiver);
+                }
+                sender
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(!err.key);
+        assert!(err.to_string().starts_with("the sink for key false failed"));
+    }
+}
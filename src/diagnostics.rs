@@ -0,0 +1,295 @@
+#[cfg(feature = "diagnostics")]
+use alloc::sync::Arc;
+#[cfg(feature = "diagnostics")]
+use core::{fmt, time::Duration};
+#[cfg(feature = "diagnostics")]
+use std::{
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+
+use crate::Side;
+
+/// Why a poll of one side of a split returned `Pending`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PendingReason {
+    /// The buffer of the other side was full, so nothing could be pulled
+    /// until the other side takes an item out of it
+    OtherBufferFull,
+    /// The underlying stream returned `Pending`
+    SourcePending,
+    /// The other side was being polled at the same time and held the lock
+    LockContention,
+    /// The item pulled from the underlying stream belonged to the other side,
+    /// which was woken to take it
+    RoutedToOther,
+    /// The other side was classifying an item it pulled, which has to be
+    /// routed before the next one is pulled
+    Classifying,
+}
+
+/// What happened to one side of a split. Part of a `Diagnosis`
+#[cfg(feature = "diagnostics")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SideDiagnosis {
+    polls: u64,
+    since_delivery: Option<Duration>,
+    pending: Option<(PendingReason, Duration)>,
+    finished: bool,
+    waker_registered: bool,
+    buffered: usize,
+}
+
+#[cfg(feature = "diagnostics")]
+impl SideDiagnosis {
+    /// How many times the stream of this side was polled
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    /// How long ago the stream of this side last returned an item, or `None`
+    /// if it never did
+    pub fn since_last_delivery(&self) -> Option<Duration> {
+        self.since_delivery
+    }
+
+    /// Why the last poll of this side returned `Pending`, or `None` if it
+    /// hasn't returned `Pending` since it last returned something
+    pub fn pending_reason(&self) -> Option<PendingReason> {
+        self.pending.map(|(reason, _)| reason)
+    }
+
+    /// How long this side has been returning `Pending` for the same reason
+    pub fn pending_for(&self) -> Option<Duration> {
+        self.pending.map(|(_, since)| since)
+    }
+
+    /// Whether the stream of this side has returned `None`
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether a task has ever registered its waker with this side, which
+    /// only happens when the stream is polled
+    pub fn waker_registered(&self) -> bool {
+        self.waker_registered
+    }
+
+    /// The number of items buffered for this side
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+}
+
+/// A snapshot of what each side of a split has been doing, to find out why
+/// a pipeline stopped. Its `Display` output explains the state of both sides
+/// in words. Created by `diagnose` on the streams of `split_by_buffered` and
+/// `split_by_with_buffers`. Requires the `diagnostics` feature
+#[cfg(feature = "diagnostics")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnosis {
+    matches: SideDiagnosis,
+    rest: SideDiagnosis,
+    source_done: bool,
+}
+
+#[cfg(feature = "diagnostics")]
+impl Diagnosis {
+    /// What happened to the `matches` stream
+    pub fn matches(&self) -> &SideDiagnosis {
+        &self.matches
+    }
+
+    /// What happened to the `rest` stream
+    pub fn rest(&self) -> &SideDiagnosis {
+        &self.rest
+    }
+
+    /// What happened to the stream of `side`
+    pub fn side(&self, side: Side) -> &SideDiagnosis {
+        match side {
+            Side::True => &self.matches,
+            Side::False => &self.rest,
+        }
+    }
+
+    /// Whether the underlying stream has ended
+    pub fn is_source_done(&self) -> bool {
+        self.source_done
+    }
+
+    fn fmt_side(&self, f: &mut fmt::Formatter<'_>, side: Side) -> fmt::Result {
+        let (this, other) = (self.side(side), self.side(Side::from(!side.is_true())));
+        let (name, other_name) = (side_name(side), side_name(Side::from(!side.is_true())));
+        write!(f, "{} side: ", name)?;
+        if this.finished {
+            return write!(f, "finished");
+        }
+        if this.polls == 0 {
+            return write!(f, "never polled");
+        }
+        match this.pending {
+            Some((PendingReason::OtherBufferFull, since)) => write!(
+                f,
+                "waiting {:?} for the {} side to take items out of its full buffer of {}",
+                since, other_name, other.buffered
+            )?,
+            Some((PendingReason::SourcePending, since)) => {
+                write!(f, "waiting {:?} on the underlying stream", since)?
+            }
+            Some((PendingReason::LockContention, since)) => write!(
+                f,
+                "waiting {:?} for the {} side to release the lock",
+                since, other_name
+            )?,
+            Some((PendingReason::RoutedToOther, since)) => write!(
+                f,
+                "routed an item to the {} side {:?} ago and hasn't been polled since",
+                other_name, since
+            )?,
+            Some((PendingReason::Classifying, since)) => write!(
+                f,
+                "waiting {:?} for the {} side to classify an item",
+                since, other_name
+            )?,
+            None => match this.since_delivery {
+                Some(since) => write!(f, "last returned an item {:?} ago", since)?,
+                None => write!(f, "polled {} times without returning an item", this.polls)?,
+            },
+        }
+        if this.pending_reason() == Some(PendingReason::OtherBufferFull) && !other.waker_registered
+        {
+            write!(
+                f,
+                ", no waker ever registered for the {} side; is the {} stream being polled?",
+                other_name, other_name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_side(f, Side::True)?;
+        writeln!(f)?;
+        self.fmt_side(f, Side::False)?;
+        if self.source_done {
+            write!(f, "\nthe underlying stream has ended")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::True => "true",
+        Side::False => "false",
+    }
+}
+
+/// What one side did most recently
+#[cfg(feature = "diagnostics")]
+#[derive(Default)]
+struct SideRecord {
+    polls: u64,
+    last_delivery: Option<Instant>,
+    pending: Option<(PendingReason, Instant)>,
+    finished: bool,
+}
+
+#[cfg(feature = "diagnostics")]
+impl SideRecord {
+    fn snapshot(&self, now: Instant) -> SideDiagnosis {
+        SideDiagnosis {
+            polls: self.polls,
+            since_delivery: self.last_delivery.map(|at| now - at),
+            pending: self.pending.map(|(reason, since)| (reason, now - since)),
+            finished: self.finished,
+            waker_registered: false,
+            buffered: 0,
+        }
+    }
+}
+
+/// Records what each side of a split does, shared between the state of the
+/// split and both of its streams, so that a stream that can't get the lock
+/// can still record that. Without the `diagnostics` feature this is empty
+/// and recording compiles to nothing
+#[derive(Clone, Default)]
+pub(crate) struct Diagnostics {
+    #[cfg(feature = "diagnostics")]
+    records: Arc<Mutex<[SideRecord; 2]>>,
+}
+
+#[cfg_attr(not(feature = "diagnostics"), allow(unused_variables))]
+impl Diagnostics {
+    #[cfg(feature = "diagnostics")]
+    fn record(&self, side: Side, f: impl FnOnce(&mut SideRecord)) {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut records[usize::from(!side.is_true())]);
+    }
+
+    /// The stream of `side` is being polled
+    #[inline]
+    pub(crate) fn polled(&self, side: Side) {
+        #[cfg(feature = "diagnostics")]
+        self.record(side, |record| record.polls += 1);
+    }
+
+    /// The stream of `side` returned an item
+    #[inline]
+    pub(crate) fn delivered(&self, side: Side) {
+        #[cfg(feature = "diagnostics")]
+        self.record(side, |record| {
+            record.last_delivery = Some(Instant::now());
+            record.pending = None;
+        });
+    }
+
+    /// The stream of `side` returned `None`
+    #[inline]
+    pub(crate) fn finished(&self, side: Side) {
+        #[cfg(feature = "diagnostics")]
+        self.record(side, |record| {
+            record.finished = true;
+            record.pending = None;
+        });
+    }
+
+    /// The stream of `side` is about to return `Pending` because of `reason`.
+    /// A stall keeps the time it started at for as long as the reason stays
+    /// the same
+    #[inline]
+    pub(crate) fn pending(&self, side: Side, reason: PendingReason) {
+        #[cfg(feature = "diagnostics")]
+        self.record(side, |record| match record.pending {
+            Some((current, _)) if current == reason => {}
+            _ => record.pending = Some((reason, Instant::now())),
+        });
+    }
+
+    /// Puts together what both sides recorded with the state of the split
+    #[cfg(feature = "diagnostics")]
+    pub(crate) fn diagnose(
+        &self,
+        waker_registered: [bool; 2],
+        buffered: [usize; 2],
+        source_done: bool,
+    ) -> Diagnosis {
+        let now = Instant::now();
+        let records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let [mut matches, mut rest] = [records[0].snapshot(now), records[1].snapshot(now)];
+        matches.waker_registered = waker_registered[0];
+        matches.buffered = buffered[0];
+        rest.waker_registered = waker_registered[1];
+        rest.buffered = buffered[1];
+        Diagnosis {
+            matches,
+            rest,
+            source_done,
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use core::{
+    cell::RefCell,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use embassy_sync::blocking_mutex::{raw::RawMutex, Mutex};
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    debug::{self, Verbose},
+    ring::RingBuf,
+    ByPredicate, Split, SplitByBuffered,
+};
+
+/// The shared state of an `EmbassySplit`, which its streams borrow
+type State<M, I, S, P, const N: usize> = Mutex<M, RefCell<SplitByBuffered<I, S, P, RingBuf<I, N>>>>;
+
+/// A split for Embassy executors, which lives wherever the caller puts it,
+/// like in a `static` or on the stack. It works like `StaticSplit`, with a
+/// buffer of N items per stream and streams that borrow it, but the shared
+/// state is guarded by an Embassy `blocking_mutex::Mutex` whose `RawMutex`
+/// is chosen with `M`, like the channels of `embassy-sync`.
+/// `CriticalSectionRawMutex` works everywhere, `ThreadModeRawMutex` suits
+/// splits only used from thread mode tasks and `NoopRawMutex` suits splits
+/// that never leave a single executor. The underlying stream and the
+/// predicate run while the mutex is locked, so they should be cheap. Never
+/// allocates. Requires the `embassy` feature
+///
+///```rust
+/// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// use futures::StreamExt;
+/// use split_stream_by::EmbassySplit;
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let mut split = EmbassySplit::<NoopRawMutex, _, _, _, 4>::new(futures::stream::iter(0..6), |&n: &i32| n % 2 == 0);
+///     let (evens, odds) = split.split().into_tuple();
+///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3, 5], odds);
+/// })
+/// ```
+pub struct EmbassySplit<M: RawMutex, I, S, P, const N: usize> {
+    state: State<M, I, S, P, N>,
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> EmbassySplit<M, I, S, P, N>
+where
+    S: Stream<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    /// Creates the storage of a split of `stream`. Nothing is pulled from the
+    /// stream until one of the streams returned by `split` is polled
+    pub fn new(stream: S, predicate: P) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(SplitByBuffered::with_kind(
+                stream,
+                ByPredicate(predicate),
+                RingBuf::new(),
+                RingBuf::new(),
+                "embassy_split",
+                ["TrueEmbassySplit", "FalseEmbassySplit"],
+            ))),
+        }
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> EmbassySplit<M, I, S, P, N> {
+    /// Returns the two streams of the split, which borrow it. Items where the
+    /// predicate returns `true` go to `matches` and the rest to `rest`. Once
+    /// both streams are dropped, this can be called again to get new ones
+    /// that continue where the old ones left off
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &mut self,
+    ) -> Split<TrueEmbassySplit<'_, M, I, S, P, N>, FalseEmbassySplit<'_, M, I, S, P, N>> {
+        let state = self.state.get_mut().get_mut();
+        state.reopen();
+        let state = &self.state;
+        Split {
+            matches: TrueEmbassySplit {
+                state,
+                terminated: false,
+            },
+            rest: FalseEmbassySplit {
+                state,
+                terminated: false,
+            },
+        }
+    }
+}
+
+/// Runs `f` on the state of a split with its mutex locked. Returns `None`
+/// without running `f` if the state is already in use, which happens when a
+/// stream is polled from inside the underlying stream or the predicate
+fn with_state<M: RawMutex, I, S, P, const N: usize, R>(
+    state: &State<M, I, S, P, N>,
+    f: impl FnOnce(&mut SplitByBuffered<I, S, P, RingBuf<I, N>>) -> R,
+) -> Option<R> {
+    state.lock(|state| {
+        let mut state = state.try_borrow_mut().ok()?;
+        Some(f(&mut state))
+    })
+}
+
+/// The stream of a `EmbassySplit` which returns the items where the predicate
+/// returns `true`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueEmbassySplit<'a, M: RawMutex, I, S, P, const N: usize> {
+    state: &'a State<M, I, S, P, N>,
+    terminated: bool,
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> TrueEmbassySplit<'_, M, I, S, P, N> {
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> TrueEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = with_state(self.state, |state| state.poll_true(cx)).unwrap_or_else(|| {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> Stream for TrueEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<M: RawMutex, I, S, P, const N: usize> core::async_iter::AsyncIterator
+    for TrueEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        Stream::poll_next(self, cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> FusedStream for TrueEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> fmt::Debug for TrueEmbassySplit<'_, M, I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.state.lock(|state| {
+            let state = state.try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "TrueEmbassySplit",
+                state.as_deref(),
+                self.terminated,
+                SplitByBuffered::debug_fields,
+            )
+        })
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> fmt::Debug
+    for Verbose<'_, TrueEmbassySplit<'_, M, I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.state.lock(|state| {
+            let state = state.try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "TrueEmbassySplit",
+                state.as_deref(),
+                self.0.terminated,
+                |state, debug| {
+                    state.debug_fields(debug);
+                    state.debug_items(debug);
+                },
+            )
+        })
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> Drop for TrueEmbassySplit<'_, M, I, S, P, N> {
+    fn drop(&mut self) {
+        with_state(self.state, |state| state.close_true());
+    }
+}
+
+/// The stream of a `EmbassySplit` which returns the items where the predicate
+/// returns `false`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseEmbassySplit<'a, M: RawMutex, I, S, P, const N: usize> {
+    state: &'a State<M, I, S, P, N>,
+    terminated: bool,
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> FalseEmbassySplit<'_, M, I, S, P, N> {
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> FalseEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = with_state(self.state, |state| state.poll_false(cx)).unwrap_or_else(|| {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> Stream for FalseEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<M: RawMutex, I, S, P, const N: usize> core::async_iter::AsyncIterator
+    for FalseEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        Stream::poll_next(self, cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> FusedStream for FalseEmbassySplit<'_, M, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> fmt::Debug for FalseEmbassySplit<'_, M, I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.state.lock(|state| {
+            let state = state.try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "FalseEmbassySplit",
+                state.as_deref(),
+                self.terminated,
+                SplitByBuffered::debug_fields,
+            )
+        })
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> fmt::Debug
+    for Verbose<'_, FalseEmbassySplit<'_, M, I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.state.lock(|state| {
+            let state = state.try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "FalseEmbassySplit",
+                state.as_deref(),
+                self.0.terminated,
+                |state, debug| {
+                    state.debug_fields(debug);
+                    state.debug_items(debug);
+                },
+            )
+        })
+    }
+}
+
+impl<M: RawMutex, I, S, P, const N: usize> Drop for FalseEmbassySplit<'_, M, I, S, P, N> {
+    fn drop(&mut self) {
+        with_state(self.state, |state| state.close_false());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::task::Poll;
+
+    use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+    use futures::StreamExt;
+
+    use super::EmbassySplit;
+    use crate::testing::{poll_once, CountingWaker, ManualStream};
+
+    #[test]
+    fn test_buffers_until_full() {
+        let (source, handle) = ManualStream::new();
+        let mut split =
+            EmbassySplit::<NoopRawMutex, _, _, _, 1>::new(source, |&n: &i32| n % 2 == 0);
+        let (mut true_stream, mut false_stream) = split.split().into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 1] {
+            handle.push(n);
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+        // The true buffer is full, so the false stream waits without polling the source
+        let polls = handle.poll_count();
+        assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+        assert_eq!(polls, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_split_again() {
+        let mut split = EmbassySplit::<CriticalSectionRawMutex, _, _, _, 4>::new(
+            futures::stream::iter(0..6),
+            |&n: &i32| n % 2 == 0,
+        );
+        let (mut evens, mut odds) = split.split().into_tuple();
+        assert_eq!(
+            (Some(0), Some(1)),
+            futures::join!(evens.next(), odds.next())
+        );
+        drop((evens, odds));
+        let (evens, odds) = split.split().into_tuple();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!(vec![2, 4], evens);
+        assert_eq!(vec![3, 5], odds);
+    }
+}
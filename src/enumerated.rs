@@ -0,0 +1,58 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+/// A stream that pairs each item of the underlying stream with its index.
+/// Used by `SplitBuilder::enumerated`, where the split pulls from it, so each
+/// item gets its index exactly once when it's pulled, even if it's then
+/// buffered for the other stream
+#[pin_project]
+pub struct Enumerated<S> {
+    #[pin]
+    stream: S,
+    next_index: u64,
+}
+
+impl<S> Enumerated<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            next_index: 0,
+        }
+    }
+}
+
+impl<S> Stream for Enumerated<S>
+where
+    S: Stream,
+{
+    type Item = (u64, S::Item);
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let next_index = this.next_index;
+        this.stream.poll_next(cx).map(|item| {
+            item.map(|item| {
+                let index = *next_index;
+                *next_index += 1;
+                (index, item)
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S> FusedStream for Enumerated<S>
+where
+    S: FusedStream,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
@@ -0,0 +1,87 @@
+use core::{error, fmt};
+
+use crate::{ReuniteError, Side};
+
+/// The ways the fallible operations of this crate can fail. Where an
+/// operation was given an item that it couldn't take, the item is handed
+/// back in the error, so it can be retried or dealt with some other way.
+/// For the splits made with `split_by_map`, `Side::True` is the left stream
+/// and `Side::False` is the right one
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error<I> {
+    /// The buffer of `side` had no room for the item
+    Overflow { side: Side, item: Option<I> },
+    /// A consumer fell so far behind that this many items were skipped
+    Lagged(u64),
+    /// The other stream of the split was dropped, so the operation can't
+    /// reach it
+    OtherSideDropped,
+    /// The stream has already ended, so it won't take any more items
+    Ended { item: Option<I> },
+    /// The streams given to `reunite` don't come from the same split. Use
+    /// `ReuniteError` directly to get the streams back
+    Reunite,
+}
+
+impl<I> Error<I> {
+    /// The side the error happened on, if it's about one of them
+    pub fn side(&self) -> Option<Side> {
+        match self {
+            Error::Overflow { side, .. } => Some(*side),
+            _ => None,
+        }
+    }
+
+    /// Takes the item that couldn't be taken out of the error
+    pub fn into_item(self) -> Option<I> {
+        match self {
+            Error::Overflow { item, .. } | Error::Ended { item } => item,
+            _ => None,
+        }
+    }
+}
+
+impl<I> fmt::Display for Error<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Overflow { side, .. } => {
+                write!(f, "the buffer of the {:?} stream is full", side)
+            }
+            Error::Lagged(skipped) => write!(f, "fell behind and skipped {} items", skipped),
+            Error::OtherSideDropped => write!(f, "the other stream of the split was dropped"),
+            Error::Ended { .. } => write!(f, "the stream has already ended"),
+            Error::Reunite => write!(
+                f,
+                "tried to reunite two streams that don't come from the same split"
+            ),
+        }
+    }
+}
+
+impl<I: fmt::Debug> error::Error for Error<I> {}
+
+impl<I, T, F> From<ReuniteError<T, F>> for Error<I> {
+    fn from(_: ReuniteError<T, F>) -> Self {
+        Error::Reunite
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error() {
+        let err = Error::Overflow {
+            side: Side::False,
+            item: Some(3),
+        };
+        assert_eq!(Some(Side::False), err.side());
+        assert_eq!("the buffer of the False stream is full", err.to_string());
+        assert_eq!(Some(3), err.into_item());
+        let err = Error::<i32>::from(ReuniteError((), ()));
+        assert_eq!(Error::Reunite, err);
+        assert_eq!(None, err.into_item());
+    }
+}
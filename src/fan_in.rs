@@ -0,0 +1,204 @@
+use alloc::vec::Vec;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    ring::RingBuf, split_by_buffered, ByPredicate, FalseSplitByBuffered, Split, SplitByBuffered,
+    TrueSplitByBuffered,
+};
+
+/// A stream that merges several streams, polling them in turns. Used by
+/// `split_by_multi`, where the split pulls from it
+pub struct FanIn<S> {
+    sources: Vec<S>,
+    /// The source that's polled first next time
+    cursor: usize,
+}
+
+impl<S> FanIn<S> {
+    pub(crate) fn new(sources: Vec<S>) -> Self {
+        Self { sources, cursor: 0 }
+    }
+
+    /// How many of the sources haven't ended yet
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Whether every source has ended
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+impl<S> Stream for FanIn<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        // Every source is polled at most once, starting after the one that returned the last
+        // item, so a source that's always ready doesn't starve the others
+        let mut polled = 0;
+        while polled < this.sources.len() {
+            let index = this.cursor % this.sources.len();
+            match Pin::new(&mut this.sources[index]).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.cursor = index + 1;
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => {
+                    // The source after it takes its place, so the cursor stays
+                    this.sources.remove(index);
+                    this.cursor = index;
+                }
+                Poll::Pending => {
+                    this.cursor = index + 1;
+                    polled += 1;
+                }
+            }
+        }
+        if this.sources.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.sources.iter().map(Stream::size_hint).fold(
+            (0, Some(0)),
+            |(lower, upper), (source_lower, source_upper)| {
+                (
+                    lower.saturating_add(source_lower),
+                    upper.zip(source_upper).and_then(|(a, b)| a.checked_add(b)),
+                )
+            },
+        )
+    }
+}
+
+impl<S> FusedStream for FanIn<S>
+where
+    S: Stream + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+/// Merges several streams, like one per upstream shard, and splits the
+/// merged items by a predicate like `split_by_buffered`. The sources are
+/// polled in turns, starting after the one that returned the last item, so
+/// one that always has an item ready doesn't starve the others. A source that
+/// ends is dropped, and the split ends once all of them have. Since the split
+/// only pulls when the buffer of the other stream has room, no source is
+/// polled while the other stream is behind
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{split_by_multi, Split};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let shards = vec![
+///         futures::stream::iter(vec![0, 2, 4]),
+///         futures::stream::iter(vec![1, 3]),
+///     ];
+///     let Split { matches: evens, rest: odds } = split_by_multi::<_, _, 4>(shards, |&n| n % 2 == 0);
+///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3], odds);
+/// })
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn split_by_multi<S, P, const N: usize>(
+    sources: Vec<S>,
+    predicate: P,
+) -> Split<
+    TrueSplitByBuffered<S::Item, FanIn<S>, P, N>,
+    FalseSplitByBuffered<S::Item, FanIn<S>, P, N>,
+>
+where
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+{
+    let stream = SplitByBuffered::new(
+        FanIn::new(sources),
+        ByPredicate(predicate),
+        RingBuf::new(),
+        RingBuf::new(),
+        "split_by_multi",
+        split_by_buffered::STREAMS,
+    );
+    Split::share(stream, TrueSplitByBuffered::new, FalseSplitByBuffered::new)
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::testing::{poll_once, CountingWaker, ManualStream};
+
+    #[tokio::test]
+    async fn test_sources_end_at_different_times() {
+        let sources = vec![
+            futures::stream::iter(vec![0, 1]),
+            futures::stream::iter(vec![10, 11, 12, 13, 14]),
+            futures::stream::iter(vec![]),
+            futures::stream::iter(vec![20, 21, 22]),
+        ];
+        let (small, big) = split_by_multi::<_, _, 8>(sources, |&n| n < 10).into_tuple();
+        let (small, big) = futures::join!(small.collect::<Vec<_>>(), big.collect::<Vec<_>>());
+        assert_eq!(vec![0, 1], small);
+        // The sources take turns until they run out
+        assert_eq!(vec![10, 20, 11, 21, 12, 22, 13, 14], big);
+    }
+
+    #[test]
+    fn test_always_ready_source_doesnt_starve_others() {
+        let (quiet, handle) = ManualStream::new();
+        let busy = futures::stream::repeat(1).boxed();
+        let sources = vec![busy, quiet.boxed()];
+        let (mut ones, mut others) = split_by_multi::<_, _, 64>(sources, |&n| n == 1).into_tuple();
+        let waker = CountingWaker::new();
+        for _ in 0..3 {
+            assert_eq!(Poll::Ready(Some(1)), poll_once(&mut ones, &waker.waker()));
+        }
+        handle.push(2);
+        // The busy source just returned an item, so the quiet one is polled first
+        assert_eq!(Poll::Pending, poll_once(&mut ones, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut others, &waker.waker()));
+    }
+
+    #[test]
+    fn test_ends_once_all_sources_end() {
+        let (first, first_handle) = ManualStream::new();
+        let (second, second_handle) = ManualStream::new();
+        let (mut evens, _odds) =
+            split_by_multi::<_, _, 4>(vec![first, second], |&n: &i32| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new();
+        first_handle.end();
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &waker.waker()));
+        second_handle.push(4);
+        assert_eq!(Poll::Ready(Some(4)), poll_once(&mut evens, &waker.waker()));
+        second_handle.end();
+        assert_eq!(Poll::Ready(None), poll_once(&mut evens, &waker.waker()));
+    }
+
+    #[test]
+    fn test_no_sources() {
+        let (mut evens, mut odds) =
+            split_by_multi::<ManualStream<i32>, _, 4>(Vec::new(), |&n| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Ready(None), poll_once(&mut evens, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &waker.waker()));
+    }
+}
@@ -0,0 +1,245 @@
+use core::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{ready, stream::FusedStream, Stream};
+use futures_sink::Sink;
+use futures_util::{stream::Fuse, StreamExt};
+use pin_project::pin_project;
+
+use crate::Side;
+
+/// Error returned by `ForwardSplit` and `SplitSinkBy` when one of the sinks
+/// fails, telling which one it was
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ForwardSplitError<T, F> {
+    /// The sink for the items where the predicate returns `true` failed
+    True(T),
+    /// The sink for the items where the predicate returns `false` failed
+    False(F),
+}
+
+impl<T, F> ForwardSplitError<T, F> {
+    /// The side of the sink that failed
+    pub fn side(&self) -> Side {
+        match self {
+            ForwardSplitError::True(_) => Side::True,
+            ForwardSplitError::False(_) => Side::False,
+        }
+    }
+}
+
+impl<T, F> fmt::Display for ForwardSplitError<T, F>
+where
+    T: fmt::Display,
+    F: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForwardSplitError::True(err) => write!(f, "the `true` sink failed: {}", err),
+            ForwardSplitError::False(err) => write!(f, "the `false` sink failed: {}", err),
+        }
+    }
+}
+
+impl<T, F> Error for ForwardSplitError<T, F>
+where
+    T: Error + 'static,
+    F: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ForwardSplitError::True(err) => Some(err),
+            ForwardSplitError::False(err) => Some(err),
+        }
+    }
+}
+
+/// A future that sends the items of a stream to one of two sinks, depending
+/// on a predicate. Created by `SplitStreamByExt::forward_split`
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ForwardSplit<S, P, ST, SF>
+where
+    S: Stream,
+{
+    #[pin]
+    stream: Fuse<S>,
+    predicate: P,
+    #[pin]
+    sink_true: ST,
+    #[pin]
+    sink_false: SF,
+    // The item that was pulled from the stream but hasn't been accepted by its sink yet
+    pending: Option<(Side, S::Item)>,
+}
+
+impl<S, P, ST, SF> ForwardSplit<S, P, ST, SF>
+where
+    S: Stream,
+{
+    pub(crate) fn new(stream: S, predicate: P, sink_true: ST, sink_false: SF) -> Self {
+        Self {
+            stream: stream.fuse(),
+            predicate,
+            sink_true,
+            sink_false,
+            pending: None,
+        }
+    }
+}
+
+impl<S, P, ST, SF> Future for ForwardSplit<S, P, ST, SF>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+    ST: Sink<S::Item>,
+    SF: Sink<S::Item>,
+{
+    type Output = Result<(), ForwardSplitError<ST::Error, SF::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            if let Some((side, item)) = this.pending.take() {
+                // Only the sink the item goes to has to be ready, so a slow sink doesn't hold up
+                // items for the other one until one of its items comes along
+                let ready = match side {
+                    Side::True => this
+                        .sink_true
+                        .as_mut()
+                        .poll_ready(cx)
+                        .map_err(ForwardSplitError::True),
+                    Side::False => this
+                        .sink_false
+                        .as_mut()
+                        .poll_ready(cx)
+                        .map_err(ForwardSplitError::False),
+                };
+                match ready {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {
+                        *this.pending = Some((side, item));
+                        return Poll::Pending;
+                    }
+                }
+                match side {
+                    Side::True => this
+                        .sink_true
+                        .as_mut()
+                        .start_send(item)
+                        .map_err(ForwardSplitError::True)?,
+                    Side::False => this
+                        .sink_false
+                        .as_mut()
+                        .start_send(item)
+                        .map_err(ForwardSplitError::False)?,
+                }
+            }
+            if this.stream.is_terminated() {
+                // Both sinks are flushed every time, which is fine once they're done
+                let flushed_true = this
+                    .sink_true
+                    .as_mut()
+                    .poll_flush(cx)
+                    .map_err(ForwardSplitError::True)?;
+                let flushed_false = this
+                    .sink_false
+                    .as_mut()
+                    .poll_flush(cx)
+                    .map_err(ForwardSplitError::False)?;
+                ready!(flushed_true);
+                ready!(flushed_false);
+                return Poll::Ready(Ok(()));
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let side = Side::from((this.predicate)(&item));
+                    *this.pending = Some((side, item));
+                }
+                Poll::Ready(None) => {}
+                Poll::Pending => {
+                    // Nothing will be sent for a while, so send what the sinks are holding on to
+                    let _ = this
+                        .sink_true
+                        .as_mut()
+                        .poll_flush(cx)
+                        .map_err(ForwardSplitError::True)?;
+                    let _ = this
+                        .sink_false
+                        .as_mut()
+                        .poll_flush(cx)
+                        .map_err(ForwardSplitError::False)?;
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{channel::mpsc, StreamExt};
+
+    use super::*;
+    use crate::{
+        testing::{poll_once, CountingWaker},
+        SplitStreamByExt,
+    };
+
+    #[tokio::test]
+    async fn test_forward_split() {
+        let (sink_true, evens) = mpsc::unbounded();
+        let (sink_false, odds) = mpsc::unbounded();
+        futures::stream::iter(0..6)
+            .forward_split(|&n| n % 2 == 0, sink_true, sink_false)
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 2, 4], evens.collect::<Vec<_>>().await);
+        assert_eq!(vec![1, 3, 5], odds.collect::<Vec<_>>().await);
+    }
+
+    #[test]
+    fn test_backpressure() {
+        // A channel with no buffer only has room for one message per sender
+        let (sink_true, mut evens) = mpsc::channel(0);
+        let (sink_false, mut odds) = mpsc::unbounded();
+        let mut forward =
+            futures::stream::iter(0..6).forward_split(|&n| n % 2 == 0, sink_true, sink_false);
+        let waker = CountingWaker::new().waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Poll::Pending, Pin::new(&mut forward).poll(&mut cx));
+        // 0 was sent and 2 is waiting for room, so nothing after it was pulled
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker));
+        assert_eq!(Poll::Pending, Pin::new(&mut forward).poll(&mut cx));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut odds, &waker));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker));
+        // The stream has ended, but 4 hasn't been received, so the `true` sink isn't flushed
+        assert_eq!(Poll::Pending, Pin::new(&mut forward).poll(&mut cx));
+        assert_eq!(Poll::Ready(Some(4)), poll_once(&mut evens, &waker));
+        assert_eq!(Poll::Ready(Ok(())), Pin::new(&mut forward).poll(&mut cx));
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut odds, &waker));
+    }
+
+    #[tokio::test]
+    async fn test_sink_error() {
+        let (sink_true, evens) = mpsc::unbounded::<i32>();
+        let (sink_false, _odds) = mpsc::unbounded();
+        drop(evens);
+        let err = futures::stream::iter(0..6)
+            .forward_split(|&n| n % 2 == 0, sink_true, sink_false)
+            .await
+            .unwrap_err();
+        assert_eq!(Side::True, err.side());
+        assert!(err.to_string().starts_with("the `true` sink failed"));
+    }
+}
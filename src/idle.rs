@@ -0,0 +1,153 @@
+use alloc::sync::{Arc, Weak};
+use core::time::Duration;
+
+use crate::{
+    sync::Mutex,
+    timer::{Clock, Sleep},
+    Side,
+};
+
+/// When each stream of a split last returned an item, on the clock of the
+/// first thing that watched for a stream going idle. Nothing is recorded
+/// until something watches
+#[derive(Default)]
+pub(crate) struct Activity {
+    clock: Option<Arc<dyn Clock + Send + Sync>>,
+    last: [Option<Duration>; 2],
+}
+
+impl Activity {
+    /// The stream of `side` returned an item
+    #[inline]
+    pub(crate) fn delivered(&mut self, side: Side) {
+        if let Some(clock) = &self.clock {
+            self.last[usize::from(side.is_false())] = Some(clock.now());
+        }
+    }
+
+    /// Starts recording when items are returned on `clock`, if it wasn't yet
+    fn watch(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock.get_or_insert(clock);
+    }
+
+    /// How long ago the stream of `side` last returned an item
+    fn since(&self, side: Side) -> Option<Duration> {
+        let last = self.last[usize::from(side.is_false())]?;
+        Some(self.clock.as_ref()?.now().saturating_sub(last))
+    }
+}
+
+/// Returns a future that resolves once the stream of `side` hasn't returned
+/// an item for `idle`, counting from now, sleeping on `timer`. `activity`
+/// finds the activity in the shared state. The state is only locked when a
+/// deadline passes, and once it has been dropped the future resolves at the
+/// next deadline, since no more items can be returned
+pub(crate) fn idle<T, C>(
+    state: Weak<Mutex<T>>,
+    side: Side,
+    idle: Duration,
+    timer: C,
+    activity: fn(&mut T) -> &mut Activity,
+) -> impl core::future::Future<Output = ()>
+where
+    C: Sleep + Clock + Send + Sync + 'static,
+{
+    let timer = Arc::new(timer);
+    if let Some(state) = state.upgrade() {
+        activity(&mut state.lock()).watch(timer.clone());
+    }
+    let mut deadline = timer.now() + idle;
+    async move {
+        loop {
+            let now = timer.now();
+            if now < deadline {
+                timer.sleep(deadline - now).await;
+            }
+            let since = match state.upgrade() {
+                Some(state) => activity(&mut state.lock()).since(side),
+                None => return,
+            };
+            match since {
+                Some(since) if since < idle => deadline = timer.now().saturating_sub(since) + idle,
+                _ => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    use futures::FutureExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream, MockTimer},
+        SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_idle_with_mock_timer() {
+        let timer = MockTimer::new();
+        let (source, handle) = ManualStream::new();
+        let (mut evens, odds) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let evens_idle = evens.idle_with(Duration::from_secs(30), timer.clone());
+        let odds_idle = odds.idle_with(Duration::from_secs(30), timer.clone());
+        futures::pin_mut!(evens_idle, odds_idle);
+        assert_eq!(None, evens_idle.as_mut().now_or_never());
+        assert_eq!(None, odds_idle.as_mut().now_or_never());
+        timer.advance(Duration::from_secs(20));
+        handle.push(0);
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker.waker()));
+        timer.advance(Duration::from_secs(20));
+        // The even stream returned an item 20 seconds ago, but the odd one never did
+        assert_eq!(None, evens_idle.as_mut().now_or_never());
+        assert_eq!(Some(()), odds_idle.now_or_never());
+        timer.advance(Duration::from_secs(10));
+        assert_eq!(Some(()), evens_idle.now_or_never());
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_resets_on_delivery() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, odds) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let evens_idle = evens.idle(Duration::from_secs(30));
+        let odds_idle = odds.idle(Duration::from_secs(30));
+        futures::pin_mut!(evens_idle, odds_idle);
+        tokio::time::advance(Duration::from_secs(20)).await;
+        handle.push(0);
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker.waker()));
+        tokio::time::advance(Duration::from_secs(20)).await;
+        // The even stream returned an item 20 seconds ago, but the odd one never did
+        assert_eq!(None, evens_idle.as_mut().now_or_never());
+        assert_eq!(Some(()), odds_idle.now_or_never());
+        // A second watcher counts from when it was made
+        let late = evens.idle(Duration::from_secs(30));
+        futures::pin_mut!(late);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(Some(()), evens_idle.now_or_never());
+        assert_eq!(None, late.as_mut().now_or_never());
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert_eq!(Some(()), late.now_or_never());
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_doesnt_keep_split_alive() {
+        let (evens, odds) = futures::stream::iter(0..4)
+            .split_by_buffered::<4>(|&n| n % 2 == 0)
+            .into_tuple();
+        let idle = evens.idle(Duration::from_secs(30));
+        drop((evens, odds));
+        let start = tokio::time::Instant::now();
+        idle.await;
+        assert_eq!(Duration::from_secs(30), start.elapsed());
+    }
+}
@@ -0,0 +1,165 @@
+use core::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{Split, SplitMap};
+
+/// A stream of a split that calls a closure with each item right before
+/// returning it. Items that are buffered for this stream are only inspected
+/// once they're returned. Created by `inspect_true` and `inspect_false` on
+/// `Split`, or by `inspect_left` and `inspect_right` on `SplitMap`
+#[pin_project]
+pub struct InspectSide<St, F> {
+    #[pin]
+    stream: St,
+    f: F,
+}
+
+impl<St, F> InspectSide<St, F> {
+    pub(crate) fn new(stream: St, f: F) -> Self {
+        Self { stream, f }
+    }
+
+    /// The stream of the split that this wraps, which gives access to methods
+    /// like `completion` and `abort`
+    pub fn get_ref(&self) -> &St {
+        &self.stream
+    }
+
+    /// The stream of the split that this wraps
+    pub fn get_mut(&mut self) -> &mut St {
+        &mut self.stream
+    }
+
+    /// Removes the closure and returns the stream of the split
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St, F> Stream for InspectSide<St, F>
+where
+    St: Stream,
+    F: FnMut(&St::Item),
+{
+    type Item = St::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<St::Item>> {
+        let this = self.project();
+        let response = this.stream.poll_next(cx);
+        if let Poll::Ready(Some(item)) = &response {
+            (this.f)(item);
+        }
+        response
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<St, F> FusedStream for InspectSide<St, F>
+where
+    St: FusedStream,
+    F: FnMut(&St::Item),
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl<St, F> fmt::Debug for InspectSide<St, F>
+where
+    St: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InspectSide")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Split<T, F> {
+    /// Calls `f` with each item the `matches` stream returns
+    pub fn inspect_true<G>(self, f: G) -> Split<InspectSide<T, G>, F>
+    where
+        T: Stream,
+        G: FnMut(&T::Item),
+    {
+        Split {
+            matches: InspectSide::new(self.matches, f),
+            rest: self.rest,
+        }
+    }
+
+    /// Calls `f` with each item the `rest` stream returns
+    pub fn inspect_false<G>(self, f: G) -> Split<T, InspectSide<F, G>>
+    where
+        F: Stream,
+        G: FnMut(&F::Item),
+    {
+        Split {
+            matches: self.matches,
+            rest: InspectSide::new(self.rest, f),
+        }
+    }
+}
+
+impl<L, R> SplitMap<L, R> {
+    /// Calls `f` with each item the `left` stream returns
+    pub fn inspect_left<G>(self, f: G) -> SplitMap<InspectSide<L, G>, R>
+    where
+        L: Stream,
+        G: FnMut(&L::Item),
+    {
+        SplitMap {
+            left: InspectSide::new(self.left, f),
+            right: self.right,
+        }
+    }
+
+    /// Calls `f` with each item the `right` stream returns
+    pub fn inspect_right<G>(self, f: G) -> SplitMap<L, InspectSide<R, G>>
+    where
+        R: Stream,
+        G: FnMut(&R::Item),
+    {
+        SplitMap {
+            left: self.left,
+            right: InspectSide::new(self.right, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, task::Poll};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_inspects_on_delivery() {
+        let (source, handle) = ManualStream::new();
+        let inspected = Cell::new(0);
+        let mut split = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .inspect_false(|_| inspected.set(inspected.get() + 1));
+        let waker = CountingWaker::new();
+        handle.push(1);
+        // 1 is buffered for the `rest` stream, but it isn't inspected until it's returned
+        assert_eq!(Poll::Pending, poll_once(&mut split.matches, &waker.waker()));
+        assert_eq!(0, inspected.get());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut split.rest, &waker.waker())
+        );
+        assert_eq!(1, inspected.get());
+    }
+}
@@ -4,15 +4,15 @@
 //!
 //!```rust
 //! use futures::StreamExt;
-//! use split_stream_by::SplitStreamByExt;
+//! use split_stream_by::{Split, SplitStreamByExt};
 //!
 //! tokio::runtime::Runtime::new().unwrap().block_on(async {
 //!     let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
-//!     let (mut even_stream, mut odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+//!     let Split { matches: mut even_stream, rest: mut odd_stream } = incoming_stream.split_by(|&n| n % 2 == 0);
 //!
 //!
 //!     tokio::spawn(async move {
-//!     	assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
+//!         assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
 //!     });
 //!
 //!     assert_eq!(vec![1,3,5], odd_stream.collect::<Vec<_>>().await);
@@ -23,16 +23,16 @@
 //! In this case
 //!```rust
 //! use futures::StreamExt;
-//! use split_stream_by::SplitStreamByExt;
+//! use split_stream_by::{Split, SplitStreamByExt};
 //!
 //! tokio::runtime::Runtime::new().unwrap().block_on(async {
 //!     const BUFSIZE: usize = 10;
 //!     let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
-//!     let (mut even_stream, mut odd_stream) = incoming_stream.split_by_buffered::<BUFSIZE>(|&n| n % 2 == 0);
+//!     let Split { matches: mut even_stream, rest: mut odd_stream } = incoming_stream.split_by_buffered::<BUFSIZE>(|&n| n % 2 == 0);
 //!
 //!
 //!     tokio::spawn(async move {
-//!     	assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
+//!         assert_eq!(vec![0,2,4], even_stream.collect::<Vec<_>>().await);
 //!     });
 //!
 //!     assert_eq!(vec![1,3,5], odd_stream.collect::<Vec<_>>().await);
@@ -43,7 +43,7 @@
 //! while splitting
 //!
 //!```rust
-//! use split_stream_by::{Either,SplitStreamByMapExt};
+//! use split_stream_by::{Either, SplitMap, SplitStreamByMapExt};
 //! use futures::StreamExt;
 //!
 //! #[derive(Debug, PartialEq)]
@@ -53,106 +53,1369 @@
 //! struct Response;
 //!
 //! enum Message {
-//! 	Request(Request),
-//! 	Response(Response)
+//!     Request(Request),
+//!     Response(Response)
 //! }
 //!
 //! tokio::runtime::Runtime::new().unwrap().block_on(async {
 //!     let incoming_stream = futures::stream::iter([
-//!     	Message::Request(Request),
-//!     	Message::Response(Response),
-//!     	Message::Response(Response),
+//!         Message::Request(Request),
+//!         Message::Response(Response),
+//!         Message::Response(Response),
 //!     ]);
-//!     let (mut request_stream, mut response_stream) = incoming_stream.split_by_map(|item| match item {
-//!     	Message::Request(req) => Either::Left(req),
-//!     	Message::Response(res) => Either::Right(res),
+//!     let SplitMap { left: mut request_stream, right: mut response_stream } = incoming_stream.split_by_map(|item| match item {
+//!         Message::Request(req) => Either::Left(req),
+//!         Message::Response(res) => Either::Right(res),
 //!     });
 //!
 //!     let requests_fut = tokio::spawn(request_stream.collect::<Vec<_>>());
 //!     let responses_fut = tokio::spawn(response_stream.collect::<Vec<_>>());
 //!     let (requests,responses) = tokio::join!(requests_fut,responses_fut);
-//!    	assert_eq!(vec![Request], requests.unwrap());
+//!        assert_eq!(vec![Request], requests.unwrap());
 //!     assert_eq!(vec![Response,Response], responses.unwrap());
 //! })
 //! ```
-mod ring_buf;
+//!
+//! With the `tracing` feature, the splits made by `split_by` and
+//! `split_by_buffered` emit `tracing` events in a `split` span with the
+//! `split_stream_by` target: where each item is routed at `trace` level, and
+//! when a buffer fills up, a stream is dropped or the underlying stream ends
+//! at `debug` level. Unlike `metrics`, the feature doesn't need `std`, and
+//! `tracing` only gets its own `std` feature when `std` is on
+//!
+//! With the `metrics` feature, a split made by `split_by` or
+//! `split_by_buffered` can be given a name with `with_metrics`, after which
+//! it records how many items went to each side, how many are buffered and for
+//! how long, and how many were dropped, through the global `metrics` recorder:
+//!
+//!```rust
+//! # #[cfg(feature = "metrics")]
+//! # {
+//! use split_stream_by::SplitStreamByExt;
+//!
+//! let (evens, odds) = futures::stream::iter(0..6)
+//!     .split_by_buffered::<4>(|&n| n % 2 == 0)
+//!     .with_metrics("numbers")
+//!     .into_tuple();
+//! # }
+//! ```
+//!
+//! With the `diagnostics` feature, the streams of `split_by_buffered` and
+//! `split_by_with_buffers` record when they last returned an item and why
+//! they last returned `Pending`. When a pipeline stops, `diagnose` explains
+//! what each side is waiting for, for example that one side has been waiting
+//! for the other to make room in its buffer and the other one was never
+//! polled:
+//!
+//!```rust
+//! # #[cfg(feature = "diagnostics")]
+//! # {
+//! use std::task::{Context, Poll};
+//! use futures::{task::noop_waker_ref, StreamExt};
+//! use split_stream_by::{PendingReason, SplitStreamByExt};
+//!
+//! let (evens, mut odds) = futures::stream::iter(0..6)
+//!     .split_by_buffered::<2>(|&n| n % 2 == 0)
+//!     .into_tuple();
+//! let mut cx = Context::from_waker(noop_waker_ref());
+//! // 0 and 2 fill the buffer of `evens`, which is never polled
+//! let polls: Vec<_> = (0..4).map(|_| odds.poll_next_unpin(&mut cx)).collect();
+//! assert_eq!(vec![Poll::Pending, Poll::Ready(Some(1)), Poll::Pending, Poll::Pending], polls);
+//! let diagnosis = odds.diagnose();
+//! assert_eq!(Some(PendingReason::OtherBufferFull), diagnosis.rest().pending_reason());
+//! println!("{}", diagnosis);
+//! # }
+//! ```
+//!
+//! The crate works without `std` as long as `alloc` is available. Turn off
+//! the default `std` feature and turn on the `spin` feature to use it on such
+//! targets, and the state shared by the two streams of a split is guarded by
+//! a spin lock from the `spin` crate instead of a `std::sync::Mutex`. The
+//! `spin` crate is only a dependency with that feature, and the crate doesn't
+//! build with neither feature on. `demux_by_key` and the `async-lock` and
+//! `blocking` features need `std`. To check that the crate builds for a
+//! target without `std`:
+//!
+//! ```text
+//! cargo check --no-default-features --features spin --target thumbv7em-none-eabihf
+//! ```
+//!
+//! The unit tests link `std` either way, so they also run with the spin lock
+//! on the host:
+//!
+//! ```text
+//! cargo test --lib --no-default-features --features spin
+//! ```
+//!
+//! Rather than the `futures` facade, the crate depends on `futures-core` for
+//! `Stream`, `futures-sink` for `Sink` and `futures-util`, with none of its
+//! optional features, for `Either` and `Fuse`. Only the `split-futures`
+//! feature turns on its `alloc` feature, for `FuturesUnordered`. `futures-executor` is only
+//! pulled in by the `blocking` feature. `Either` is re-exported from
+//! `futures-util`, so it's the same type as `futures::future::Either`. With
+//! the default features, `cargo tree -e no-dev` lists `futures-core`,
+//! `futures-sink`, `futures-task`, `futures-util`, `pin-project` and
+//! `pin-project-lite` besides the proc macro crates of
+//! `pin-project`, where it used to also list `futures`, `futures-channel`,
+//! `futures-io`, `memchr` and `slab`
+//!
+//! The streams of every split are `Unpin`, so a `&mut` to one is a stream
+//! as well. They can stay in a struct field and be polled in place, as a
+//! branch of `select!` or through `StreamExt::by_ref`, and `poll_next_item`
+//! polls one without pinning it
+//!
+//! On nightly, the `nightly-async-iterator` feature implements
+//! `core::async_iter::AsyncIterator` for the streams of every split, next to
+//! `Stream`, so they can be passed to code written against the std trait.
+//! Since both traits have a `poll_next` method, call it as
+//! `AsyncIterator::poll_next(stream, cx)` when both are in scope
+//!
+//! The buffers of the buffered splits are `ring::RingBuf`s, which can also
+//! be used on their own. They're arrays of `MaybeUninit`, which takes a
+//! little `unsafe` code. The `safe-buffer` feature swaps them for
+//! arrays of `Option`, which are slightly bigger and slower, and forbids
+//! `unsafe` code in the whole crate. The test suite passes with and without
+//! it:
+//!
+//! ```text
+//! cargo test
+//! cargo test --features safe-buffer
+//! ```
+//!
+//! The `order-check` feature checks at runtime that each stream of
+//! `split_by_buffered`, `split_by_with_buffers` and `StaticSplit` returns
+//! the items routed to it in the order the underlying stream produced them.
+//! Each item is stamped with its position in the underlying stream when it's
+//! routed, next to the item rather than around it, and a stream panics if it
+//! takes an item out of its buffer that comes before the last one it
+//! returned. It's meant for test suites, including the crate's own, which
+//! passes with it on. Without the feature, nothing is stamped:
+//!
+//! ```text
+//! cargo test --features order-check
+//! ```
+//!
+//! The `concurrent-poll-check` feature panics when one stream of a split is
+//! polled from two tasks at once, which leaves one of them without a wakeup.
+//! A stream that moves to another task while it's waiting is fine, since the
+//! task it left doesn't poll it again. The crate's test suite passes with it
+//! on:
+//!
+//! ```text
+//! cargo test --features concurrent-poll-check
+//! ```
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+#![cfg_attr(feature = "nightly-async-iterator", feature(async_iterator))]
+#![cfg_attr(feature = "safe-buffer", forbid(unsafe_code))]
+
+extern crate alloc;
+
+mod bounded_buf;
+mod boxed;
+mod broadcast;
+mod buffer;
+mod buffer_age;
+mod buffer_latency;
+mod builder;
+mod completion;
+mod control;
+mod debug;
+#[cfg(feature = "std")]
+mod demux_by_key;
+mod diagnostics;
+#[cfg(feature = "embassy")]
+mod embassy;
+mod enumerated;
+mod error;
+mod fan_in;
+mod forward_split;
+mod idle;
+mod inspect_side;
+mod map_side;
+mod metric;
+mod offsets;
+mod order_check;
+mod partition_by;
+mod parts;
+#[cfg(feature = "split-futures")]
+mod process_split;
+mod resumable;
+mod reunite;
+mod reunite_ordered;
+pub mod ring;
+mod routing_observer;
+#[cfg(feature = "rand")]
+mod sample;
+mod select_by;
+mod side;
+mod side_waker;
+mod sink_passthrough;
+mod spill;
+mod split;
 mod split_by;
+#[cfg(feature = "split-futures")]
+mod split_by_async_concurrent;
+#[cfg(feature = "async-lock")]
+mod split_by_async_lock;
+#[cfg(feature = "spawn-blocking")]
+mod split_by_blocking;
 mod split_by_buffered;
+mod split_by_configured;
+mod split_by_context;
+mod split_by_delimiter;
+mod split_by_flat;
+mod split_by_fold_rest;
+#[cfg(feature = "split-futures")]
+mod split_by_handlers;
 mod split_by_map;
 mod split_by_map_buffered;
+mod split_core;
+mod split_first;
+mod split_flatten_by;
+#[cfg(feature = "split-futures")]
+mod split_futures_by;
+mod split_iter_by;
+mod split_sink_by;
+#[cfg(feature = "static-split")]
+mod static_split;
+mod sync;
+#[cfg(feature = "sync-bridge")]
+mod sync_bridge;
+mod tag_by;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+mod throttle;
+pub mod timer;
+mod trace;
+mod try_next;
+mod watermark;
+mod zip_partitions;
 
+pub use boxed::{
+    BoxedFalseSplit, BoxedFalseSplitBuffered, BoxedLeftSplitMap, BoxedLeftSplitMapBuffered,
+    BoxedMapPredicate, BoxedPredicate, BoxedRightSplitMap, BoxedRightSplitMapBuffered, BoxedSplit,
+    BoxedSplitBuffered, BoxedSplitMap, BoxedSplitMapBuffered, BoxedTrueSplit,
+    BoxedTrueSplitBuffered,
+};
+pub use broadcast::Subscriber;
+pub use buffer::Buffer;
+pub use buffer_age::BufferAgeSummary;
+pub use builder::{Overflow, SplitBuilder};
+pub use completion::Completion;
+pub(crate) use completion::CompletionTracker;
+pub use control::{DrainReport, SplitControl};
+pub use debug::Verbose;
+#[cfg(feature = "std")]
+pub use demux_by_key::{demux_by_key, DemuxByKey, DemuxError};
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::{Diagnosis, PendingReason, SideDiagnosis};
+#[cfg(feature = "embassy")]
+pub use embassy::{EmbassySplit, FalseEmbassySplit, TrueEmbassySplit};
+pub use enumerated::Enumerated;
+pub use error::Error;
+pub use fan_in::{split_by_multi, FanIn};
+pub use forward_split::{ForwardSplit, ForwardSplitError};
+pub use inspect_side::InspectSide;
+pub use map_side::MapSide;
+pub use offsets::AbandonedItems;
+pub(crate) use partition_by::PartitionBy;
+pub use partition_by::PartitionStream;
+pub use parts::{SplitMapParts, SplitParts};
+#[cfg(feature = "split-futures")]
+pub use process_split::ProcessSplit;
+pub use resumable::{split_by_resumable, Resumable};
+pub use reunite::ReuniteError;
+pub use reunite_ordered::{reunite_ordered, ReuniteOrdered};
+pub use routing_observer::RoutingObserver;
+#[cfg(feature = "rand")]
+pub use sample::{FalseSplitRandomly, Sampler, SplitRandomlyExt, TrueSplitRandomly};
+pub use select_by::{select_by, SelectBy};
+pub use side::Side;
+#[cfg(feature = "spill")]
+pub use spill::SpillError;
+pub use split::{Split, SplitMap};
 pub(crate) use split_by::SplitBy;
 pub use split_by::{FalseSplitBy, TrueSplitBy};
+#[cfg(feature = "split-futures")]
+pub use split_by_async_concurrent::SplitByAsyncConcurrentStream;
+#[cfg(feature = "split-futures")]
+pub(crate) use split_by_async_concurrent::{ByAsyncPredicate, SplitByAsyncConcurrent};
+#[cfg(feature = "async-lock")]
+pub(crate) use split_by_async_lock::SplitByAsyncLock;
+#[cfg(feature = "async-lock")]
+pub use split_by_async_lock::{FalseSplitByAsyncLock, TrueSplitByAsyncLock};
+#[cfg(feature = "spawn-blocking")]
+pub use split_by_blocking::SplitByBlockingStream;
 pub(crate) use split_by_buffered::SplitByBuffered;
-pub use split_by_buffered::{FalseSplitByBuffered, TrueSplitByBuffered};
+pub use split_by_buffered::{
+    FalseSplitByBuffered, FalseSplitWithBuffer, TrueSplitByBuffered, TrueSplitWithBuffer,
+};
+pub(crate) use split_by_configured::SplitByConfigured;
+pub use split_by_configured::{FalseSplitByConfigured, TrueSplitByConfigured};
+pub(crate) use split_by_context::SplitByContext;
+pub use split_by_context::{FalseSplitByContext, SplitContext, TrueSplitByContext};
+pub(crate) use split_by_delimiter::SplitByDelimiter;
+pub use split_by_delimiter::{Delimiter, Segment, Segments};
+pub(crate) use split_by_flat::SplitByFlat;
+pub use split_by_flat::{FalseSplitByFlat, TrueSplitByFlat};
+pub(crate) use split_by_fold_rest::SplitByFoldRest;
+pub use split_by_fold_rest::{FoldedRest, TrueSplitByFoldRest};
+#[cfg(feature = "split-futures")]
+pub use split_by_handlers::SplitByHandlers;
 pub(crate) use split_by_map::SplitByMap;
 pub use split_by_map::{LeftSplitByMap, RightSplitByMap};
 pub(crate) use split_by_map_buffered::SplitByMapBuffered;
 pub use split_by_map_buffered::{LeftSplitByMapBuffered, RightSplitByMapBuffered};
+pub(crate) use split_core::{ByMap, ByPredicate};
+pub use split_first::{split_first, SplitFirstHead, SplitFirstRest};
+pub(crate) use split_flatten_by::SplitFlattenBy;
+pub use split_flatten_by::SplitFlattenStream;
+#[cfg(feature = "split-futures")]
+pub(crate) use split_futures_by::SplitFuturesBy;
+#[cfg(feature = "split-futures")]
+pub use split_futures_by::SplitFuturesStream;
+pub(crate) use split_iter_by::SplitIterBy;
+pub use split_iter_by::{FalseSplitIterBy, TrueSplitIterBy};
+pub use split_sink_by::{split_sink_by, SplitSinkBy};
+#[cfg(feature = "static-split")]
+pub use static_split::{FalseStaticSplit, StaticSplit, TrueStaticSplit};
+#[cfg(feature = "sync-bridge")]
+pub use sync_bridge::BridgeSync;
+pub use tag_by::TagBy;
+pub use try_next::TryNext;
+pub use zip_partitions::{zip_partitions, Leftovers, ZipPartitions};
+
+pub use futures_util::future::Either;
+
+/// The buffer size used by `split_by_buffered_default` and
+/// `split_by_map_buffered_default`
+pub const DEFAULT_BUFFER_SIZE: usize = 16;
+use alloc::vec::Vec;
 
-pub use futures::future::Either;
-use futures::Stream;
+use ring::RingBuf;
+
+use futures_core::Stream;
+use futures_sink::Sink;
 
 /// This extension trait provides the functionality for splitting a
 /// stream by a predicate of type `Fn(&Self::Item) -> bool`. The two resulting
-/// streams will both yield `Self::Item`
+/// streams will both yield `Self::Item`. Neither the stream, its items nor
+/// the predicate have to be `'static`, so they can borrow from the enclosing
+/// scope. Only `split_by_async_lock` needs them to be `'static`
 pub trait SplitStreamByExt<P>: Stream {
     /// This takes ownership of a stream and returns two streams based on a
     /// predicate. When the predicate returns `true`, the item will appear in
-    /// the first of the pair of streams returned. Items that return false will
-    /// go into the second of the pair of streams
+    /// the `matches` stream of the returned `Split`. Items that return false
+    /// will go into the `rest` stream
     ///
     ///```rust
-    /// use split_stream_by::SplitStreamByExt;
+    /// use split_stream_by::{Split, SplitStreamByExt};
     ///
     /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
-    /// let (even_stream, odd_stream) = incoming_stream.split_by(|&n| n % 2 == 0);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by(|&n| n % 2 == 0);
     /// ```
+    #[allow(clippy::type_complexity)]
     fn split_by(
         self,
         predicate: P,
-    ) -> (
-        TrueSplitBy<Self::Item, Self, P>,
-        FalseSplitBy<Self::Item, Self, P>,
-    )
+    ) -> Split<TrueSplitBy<Self::Item, Self, P>, FalseSplitBy<Self::Item, Self, P>>
     where
         P: Fn(&Self::Item) -> bool,
         Self: Sized,
     {
-        let stream = SplitBy::new(self, predicate);
-        let true_stream = TrueSplitBy::new(stream.clone());
-        let false_stream = FalseSplitBy::new(stream);
-        (true_stream, false_stream)
+        let stream = SplitBy::new(
+            self,
+            ByPredicate(predicate),
+            RingBuf::new(),
+            RingBuf::new(),
+            "split_by",
+            split_by::STREAMS,
+        );
+        Split::share(stream, TrueSplitBy::new, FalseSplitBy::new)
     }
 
     /// This takes ownership of a stream and returns two streams based on a
     /// predicate. When the predicate returns `true`, the item will appear in
-    /// the first of the pair of streams returned. Items that return false will
-    /// go into the second of the pair of streams. This will buffer up to N
+    /// the `matches` stream of the returned `Split`. Items that return false
+    /// will go into the `rest` stream. This will buffer up to N
     /// items of the inactive stream before returning Pending and notifying that
-    /// stream
+    /// stream. The predicate runs without holding the lock of the split, so
+    /// the other stream can take its buffered items while an expensive
+    /// predicate classifies an item
     ///
     ///```rust
-    /// use split_stream_by::SplitStreamByExt;
+    /// use split_stream_by::{Split, SplitStreamByExt};
     ///
     /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
-    /// let (even_stream, odd_stream) = incoming_stream.split_by_buffered::<3>(|&n| n % 2 == 0);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_buffered::<3>(|&n| n % 2 == 0);
     /// ```
+    #[allow(clippy::type_complexity)]
     fn split_by_buffered<const N: usize>(
         self,
         predicate: P,
-    ) -> (
+    ) -> Split<
         TrueSplitByBuffered<Self::Item, Self, P, N>,
         FalseSplitByBuffered<Self::Item, Self, P, N>,
-    )
+    >
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        let stream = SplitByBuffered::new(
+            self,
+            ByPredicate(predicate),
+            RingBuf::new(),
+            RingBuf::new(),
+            "split_by_buffered",
+            split_by_buffered::STREAMS,
+        );
+        Split::share(stream, TrueSplitByBuffered::new, FalseSplitByBuffered::new)
+    }
+
+    /// The same as `split_by_buffered`, with a buffer size of
+    /// `DEFAULT_BUFFER_SIZE`
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_buffered_default(|&n| n % 2 == 0);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_buffered_default(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByBuffered<Self::Item, Self, P, DEFAULT_BUFFER_SIZE>,
+        FalseSplitByBuffered<Self::Item, Self, P, DEFAULT_BUFFER_SIZE>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        self.split_by_buffered::<DEFAULT_BUFFER_SIZE>(predicate)
+    }
+
+    /// The same as `split_by_buffered`, but the items of each stream are
+    /// buffered in the given `Buffer`s instead of a `RingBuf`. Once the
+    /// buffer of one stream is full, the other stream waits for room in it
+    /// before pulling more items. A `VecDeque` is never full, so the streams
+    /// never wait for each other, but its memory isn't bounded either
+    ///
+    ///```rust
+    /// use std::collections::VecDeque;
+    ///
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let incoming_stream = futures::stream::iter(0..100);
+    ///     let Split { matches: evens, rest: odds } =
+    ///         incoming_stream.split_by_with_buffers(|&n| n % 2 == 0, VecDeque::new(), VecDeque::new());
+    ///     let (evens, odds) = futures::join!(evens.count(), odds.count());
+    ///     assert_eq!((50, 50), (evens, odds));
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_with_buffers<B>(
+        self,
+        predicate: P,
+        buf_true: B,
+        buf_false: B,
+    ) -> Split<
+        TrueSplitWithBuffer<Self::Item, Self, P, B>,
+        FalseSplitWithBuffer<Self::Item, Self, P, B>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool,
+        B: Buffer<Self::Item>,
+        Self: Sized,
+    {
+        let stream = SplitByBuffered::new(
+            self,
+            ByPredicate(predicate),
+            buf_true,
+            buf_false,
+            "split_by_with_buffers",
+            split_by_buffered::STREAMS,
+        );
+        Split::share(stream, TrueSplitWithBuffer::new, FalseSplitWithBuffer::new)
+    }
+
+    /// Like `split_by_buffered_default`, but the predicate also gets a
+    /// `SplitContext`, which tells how full the buffers are and how many
+    /// items have been routed so far. This lets the predicate adapt to load,
+    /// for example by sending items to the other stream while the buffer of
+    /// their usual one is backed up. The predicate can be `FnMut`. The
+    /// context is a snapshot taken right before each call, so it's only
+    /// accurate for that instant
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Split, SplitContext, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let incoming_stream = futures::stream::iter(0..100);
+    ///     let Split { matches: premium, rest: standard } =
+    ///         incoming_stream.split_by_with_context(|context: &SplitContext, &n| {
+    ///             // Premium work overflows to the standard queue while the premium one is backed up
+    ///             n % 2 == 0 && context.buffered_true() * 10 <= context.capacity_true() * 8
+    ///         });
+    ///     let (premium, standard) = futures::join!(premium.count(), standard.count());
+    ///     assert_eq!(100, premium + standard);
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_with_context(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByContext<Self::Item, Self, P, DEFAULT_BUFFER_SIZE>,
+        FalseSplitByContext<Self::Item, Self, P, DEFAULT_BUFFER_SIZE>,
+    >
+    where
+        P: FnMut(&SplitContext, &Self::Item) -> bool,
+        Self: Sized,
+    {
+        self.split_by_with_context_buffered::<DEFAULT_BUFFER_SIZE>(predicate)
+    }
+
+    /// The same as `split_by_with_context`, but each stream buffers up to N
+    /// items
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitContext, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let Split { matches: even_stream, rest: odd_stream } =
+    ///     incoming_stream.split_by_with_context_buffered::<3>(|_: &SplitContext, &n| n % 2 == 0);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_with_context_buffered<const N: usize>(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByContext<Self::Item, Self, P, N>,
+        FalseSplitByContext<Self::Item, Self, P, N>,
+    >
+    where
+        P: FnMut(&SplitContext, &Self::Item) -> bool,
+        Self: Sized,
+    {
+        let stream = SplitByContext::new(self, predicate);
+        Split::share(stream, TrueSplitByContext::new, FalseSplitByContext::new)
+    }
+
+    /// Splits a stream of batches, like a `Stream<Item = Vec<I>>`, into two
+    /// streams of the items in those batches. This does what `flatten`
+    /// followed by `split_by` would, but each batch is routed to the buffers
+    /// of both streams at once instead of one item per poll. If the rest of a
+    /// batch doesn't fit, it's kept until there's room and the underlying
+    /// stream isn't polled again before then. Each stream buffers up to
+    /// `DEFAULT_BUFFER_SIZE` items
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([vec![0,1,2], vec![3,4,5]]);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_flat(|&n| n % 2 == 0);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_flat(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByFlat<Self, P, DEFAULT_BUFFER_SIZE>,
+        FalseSplitByFlat<Self, P, DEFAULT_BUFFER_SIZE>,
+    >
+    where
+        Self::Item: IntoIterator,
+        P: Fn(&<Self::Item as IntoIterator>::Item) -> bool,
+        Self: Sized,
+    {
+        self.split_by_flat_buffered::<DEFAULT_BUFFER_SIZE>(predicate)
+    }
+
+    /// The same as `split_by_flat`, but each stream buffers up to N items.
+    /// N has to be at least 1
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([vec![0,1,2], vec![3,4,5]]);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_flat_buffered::<3>(|&n| n % 2 == 0);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_flat_buffered<const N: usize>(
+        self,
+        predicate: P,
+    ) -> Split<TrueSplitByFlat<Self, P, N>, FalseSplitByFlat<Self, P, N>>
+    where
+        Self::Item: IntoIterator,
+        P: Fn(&<Self::Item as IntoIterator>::Item) -> bool,
+        Self: Sized,
+    {
+        assert!(N > 0, "a split by flat needs room for at least one item");
+        let stream = SplitByFlat::new(self, predicate);
+        Split::share(stream, TrueSplitByFlat::new, FalseSplitByFlat::new)
+    }
+
+    /// Splits off the items where the predicate returns `true` as a stream,
+    /// and folds the rest into an accumulator instead of buffering them. The
+    /// `rest` stream returns the accumulator whenever it's polled and
+    /// something was folded into it since the last time, starting over from
+    /// `init` each time, or once a set number of items were folded with
+    /// `FoldedRest::every`. Since the rejected items are folded as soon as
+    /// they're pulled, they never hold up the `matches` stream. Up to
+    /// `DEFAULT_BUFFER_SIZE` matches are buffered while the `rest` stream
+    /// pulls
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let codes = futures::stream::iter([200, 404, 200, 500, 404, 200]);
+    ///     let (ok, errors) = codes
+    ///         .split_by_fold_rest(|&code| code == 200, 0, |count, _| count + 1)
+    ///         .into_tuple();
+    ///     let (ok, errors) = futures::join!(ok.count(), errors.every(3).collect::<Vec<_>>());
+    ///     assert_eq!(3, ok);
+    ///     assert_eq!(vec![3], errors);
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_by_fold_rest<A, F>(
+        self,
+        predicate: P,
+        init: A,
+        fold: F,
+    ) -> Split<
+        TrueSplitByFoldRest<Self::Item, Self, P, A, F, DEFAULT_BUFFER_SIZE>,
+        FoldedRest<Self::Item, Self, P, A, F, DEFAULT_BUFFER_SIZE>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool,
+        A: Clone,
+        F: FnMut(A, Self::Item) -> A,
+        Self: Sized,
+    {
+        let stream = SplitByFoldRest::new(self, predicate, init, fold);
+        Split {
+            matches: TrueSplitByFoldRest::new(stream.clone()),
+            rest: FoldedRest::new(stream),
+        }
+    }
+
+    /// Sends the items where the predicate returns `true` to `sink_true` and
+    /// the rest to `sink_false`, all from one future. This doesn't split the
+    /// stream into two, so there's no locking or buffering involved. An item
+    /// only waits for its own sink to be ready, and nothing more is pulled
+    /// from the stream until it has been sent. The future completes once the
+    /// stream has ended and both sinks have been flushed, or with an error
+    /// telling which sink failed as soon as one does
+    ///
+    ///```rust
+    /// use futures::{channel::mpsc, StreamExt};
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, even_receiver) = mpsc::unbounded();
+    ///     let (odds, odd_receiver) = mpsc::unbounded();
+    ///     let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    ///     incoming_stream.forward_split(|&n| n % 2 == 0, evens, odds).await.unwrap();
+    ///     assert_eq!(vec![0,2,4], even_receiver.collect::<Vec<_>>().await);
+    ///     assert_eq!(vec![1,3,5], odd_receiver.collect::<Vec<_>>().await);
+    /// })
+    /// ```
+    fn forward_split<ST, SF>(
+        self,
+        predicate: P,
+        sink_true: ST,
+        sink_false: SF,
+    ) -> ForwardSplit<Self, P, ST, SF>
+    where
+        P: Fn(&Self::Item) -> bool,
+        ST: Sink<Self::Item>,
+        SF: Sink<Self::Item>,
+        Self: Sized,
+    {
+        ForwardSplit::new(self, predicate, sink_true, sink_false)
+    }
+
+    /// Splits the stream into `n` streams, where `f` returns the index of the
+    /// stream each item goes to. The number of streams is chosen at runtime,
+    /// for example the number of worker shards from a config. Each stream
+    /// buffers one item for when another stream pulls it. Panics if `n` is 0,
+    /// and when an item is pulled for which `f` returns an index of `n` or
+    /// more. Dropping one of the streams drops the items routed to it from
+    /// then on, so the other streams keep flowing
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let shards = 3;
+    ///     let partitions = futures::stream::iter(0..9).partition_by(shards, move |&n| n % shards);
+    ///     let shards = futures::future::join_all(
+    ///         partitions.into_iter().map(|partition| partition.collect::<Vec<_>>()),
+    ///     )
+    ///     .await;
+    ///     assert_eq!(vec![vec![0, 3, 6], vec![1, 4, 7], vec![2, 5, 8]], shards);
+    /// })
+    /// ```
+    fn partition_by(self, n: usize, f: P) -> Vec<PartitionStream<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> usize,
+        Self: Sized,
+    {
+        self.partition_by_buffered(n, 1, f)
+    }
+
+    /// The same as `partition_by`, but each stream buffers up to `capacity`
+    /// items
+    ///
+    ///```rust
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// let incoming_stream = futures::stream::iter(0..9);
+    /// let partitions = incoming_stream.partition_by_buffered(3, 16, |&n| n % 3);
+    /// ```
+    fn partition_by_buffered(
+        self,
+        n: usize,
+        capacity: usize,
+        f: P,
+    ) -> Vec<PartitionStream<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> usize,
+        Self: Sized,
+    {
+        PartitionStream::all(PartitionBy::new(self, n, capacity, f))
+    }
+
+    /// Splits a stream of futures in two by `predicate` and runs them, up to
+    /// `limit_true` at a time for the futures where it returns `true` and up
+    /// to `limit_false` for the rest. The first stream of the returned
+    /// `Split` returns the outputs of the first kind and the second one the
+    /// outputs of the rest, each in the order the futures complete rather
+    /// than the order they were pulled in.
+    ///
+    /// The futures are run while their stream is polled, by a
+    /// `FuturesUnordered` in the state the two streams share, so routing and
+    /// admission are decided in one place. A future is started as soon as its
+    /// pool has room. If it doesn't, it's held, and nothing more is pulled
+    /// from the underlying stream until it's been started, so the other
+    /// stream waits once its own pool is empty. When a stream is dropped, its
+    /// running futures are dropped and the futures routed to it aren't run.
+    /// Panics if either limit is 0. Requires the `split-futures` feature
+    ///
+    ///```rust
+    /// use std::{future::Future, pin::Pin, task::{Context, Poll}};
+    /// use futures::{future::BoxFuture, FutureExt, StreamExt};
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// struct Job {
+    ///     expensive: bool,
+    ///     work: BoxFuture<'static, u32>,
+    /// }
+    ///
+    /// impl Future for Job {
+    ///     type Output = u32;
+    ///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+    ///         self.work.poll_unpin(cx)
+    ///     }
+    /// }
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let jobs = futures::stream::iter(0..10).map(|n| Job {
+    ///         expensive: n % 5 == 0,
+    ///         work: async move { n * 10 }.boxed(),
+    ///     });
+    ///     let Split { matches: expensive, rest: cheap } =
+    ///         jobs.split_futures_by(|job| job.expensive, 2, 64);
+    ///     let (mut expensive, cheap) = futures::join!(
+    ///         expensive.collect::<Vec<_>>(),
+    ///         cheap.collect::<Vec<_>>()
+    ///     );
+    ///     expensive.sort();
+    ///     assert_eq!(vec![0, 50], expensive);
+    ///     assert_eq!(8, cheap.len());
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    #[allow(clippy::type_complexity)]
+    fn split_futures_by(
+        self,
+        predicate: P,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> Split<SplitFuturesStream<Self::Item, Self, P>, SplitFuturesStream<Self::Item, Self, P>>
+    where
+        Self::Item: core::future::Future,
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        let stream = SplitFuturesBy::new(self, predicate, limit_true, limit_false);
+        Split {
+            matches: SplitFuturesStream::new(stream.clone(), Side::True),
+            rest: SplitFuturesStream::new(stream, Side::False),
+        }
+    }
+
+    /// Runs `f_true` on the items where the predicate returns `true` and
+    /// `f_false` on the rest, and returns the outputs of the futures they
+    /// return as one stream, in the order of the underlying stream. This is
+    /// what splitting, running each side through its own pipeline and putting
+    /// the outputs back in order by hand would do, without the split.
+    ///
+    /// Up to `limit_true` and `limit_false` items of each side are handled
+    /// at a time, all of their futures polled while the returned stream is.
+    /// Every item is numbered when it's pulled, and an output that completes
+    /// before the outputs of the items that came before it is kept until
+    /// they've been returned. The item keeps its slot until then, so a slow
+    /// item holds back at most `limit_true + limit_false` items behind it.
+    /// When the side of an item has no free slot, nothing more is pulled from
+    /// the underlying stream until it does. Panics if either limit is 0.
+    /// Requires the `split-futures` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let outputs = futures::stream::iter(0..6u64)
+    ///         .process_split(
+    ///             |&n| n % 2 == 0,
+    ///             |n| async move {
+    ///                 tokio::time::sleep(Duration::from_millis(10 - n)).await;
+    ///                 format!("even {}", n)
+    ///             },
+    ///             |n| async move { format!("odd {}", n) },
+    ///             4,
+    ///             4,
+    ///         )
+    ///         .collect::<Vec<_>>()
+    ///         .await;
+    ///     assert_eq!(vec!["even 0", "odd 1", "even 2", "odd 3", "even 4", "odd 5"], outputs);
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    fn process_split<FT, FF, FutT, FutF>(
+        self,
+        predicate: P,
+        f_true: FT,
+        f_false: FF,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> ProcessSplit<Self, P, FT, FF, FutT, FutF>
+    where
+        P: Fn(&Self::Item) -> bool,
+        FT: FnMut(Self::Item) -> FutT,
+        FF: FnMut(Self::Item) -> FutF,
+        FutT: core::future::Future,
+        FutF: core::future::Future<Output = FutT::Output>,
+        Self: Sized,
+    {
+        ProcessSplit::new(
+            self,
+            predicate,
+            f_true,
+            f_false,
+            limit_true,
+            limit_false,
+            true,
+        )
+    }
+
+    /// Splits the stream like `split_by`, with a predicate that returns a
+    /// future of whether the item matches, such as a lookup in a remote
+    /// service. Up to `max_in_flight` items are classified at once, so the
+    /// throughput isn't capped at one item per latency of the predicate. The
+    /// items are still routed in the order of the underlying stream, so each
+    /// stream returns its items in that order, and an item whose predicate
+    /// resolves early waits for the ones before it. It keeps its slot while
+    /// it waits, so no more than `max_in_flight` items are ever pulled ahead.
+    ///
+    /// The predicates run while either stream is polled, in a
+    /// `FuturesOrdered` in the state the two streams share. The items routed
+    /// to a stream are kept until it takes them, up to `max_in_flight` of
+    /// them. Once that many are waiting, the other stream stops routing items
+    /// until this one catches up. The items routed to a stream that was
+    /// dropped are dropped. Panics if `max_in_flight` is 0. Requires the
+    /// `split-futures` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let Split { matches: allowed, rest: denied } = futures::stream::iter(0..8)
+    ///         .split_by_async_concurrent(
+    ///             |&n| async move {
+    ///                 // A lookup that takes longer for the first items
+    ///                 tokio::time::sleep(Duration::from_millis(8 - n)).await;
+    ///                 n % 3 != 0
+    ///             },
+    ///             4,
+    ///         );
+    ///     let (allowed, denied) =
+    ///         futures::join!(allowed.collect::<Vec<_>>(), denied.collect::<Vec<_>>());
+    ///     assert_eq!(vec![1, 2, 4, 5, 7], allowed);
+    ///     assert_eq!(vec![0, 3, 6], denied);
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    #[allow(clippy::type_complexity)]
+    fn split_by_async_concurrent<Fut>(
+        self,
+        predicate: P,
+        max_in_flight: usize,
+    ) -> Split<
+        SplitByAsyncConcurrentStream<Self::Item, Self, P, Fut>,
+        SplitByAsyncConcurrentStream<Self::Item, Self, P, Fut>,
+    >
+    where
+        P: Fn(&Self::Item) -> Fut,
+        Fut: core::future::Future<Output = bool>,
+        Self: Sized + Unpin,
+    {
+        let stream = SplitByAsyncConcurrent::new(
+            self,
+            ByAsyncPredicate(predicate),
+            max_in_flight,
+            "SplitByAsyncConcurrentStream",
+        );
+        Split {
+            matches: SplitByAsyncConcurrentStream::new(stream.clone(), Side::True),
+            rest: SplitByAsyncConcurrentStream::new(stream, Side::False),
+        }
+    }
+
+    /// Splits the stream like `split_by`, with a predicate that blocks, like
+    /// a call into a synchronous library. The predicate runs on the blocking
+    /// thread pool of tokio with `spawn_blocking`, so it holds up neither
+    /// the executor nor the other stream. The item goes along with it, and
+    /// its `JoinHandle` is kept in the state the two streams share until
+    /// the predicate returns, so an item isn't lost if the stream that
+    /// pulled it stops being polled. One item is classified at a time. If
+    /// the predicate panics, the stream that's polled for that item panics
+    /// with the same payload, and the split carries on with the next item.
+    /// The streams have to be polled within a tokio runtime. Requires the
+    /// `spawn-blocking` feature
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// fn is_valid(n: &u32) -> bool {
+    ///     // Imagine a call into a C library that takes a few milliseconds
+    ///     std::thread::sleep(std::time::Duration::from_millis(1));
+    ///     n % 3 != 0
+    /// }
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let Split { matches: valid, rest: invalid } =
+    ///         futures::stream::iter(0..6).split_by_blocking(is_valid);
+    ///     let (valid, invalid) =
+    ///         futures::join!(valid.collect::<Vec<_>>(), invalid.collect::<Vec<_>>());
+    ///     assert_eq!(vec![1, 2, 4, 5], valid);
+    ///     assert_eq!(vec![0, 3], invalid);
+    /// })
+    /// ```
+    #[cfg(feature = "spawn-blocking")]
+    #[allow(clippy::type_complexity)]
+    fn split_by_blocking(
+        self,
+        predicate: P,
+    ) -> Split<SplitByBlockingStream<Self::Item, Self, P>, SplitByBlockingStream<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> bool + Send + Sync + 'static,
+        Self::Item: Send + 'static,
+        Self: Sized + Unpin,
+    {
+        self.split_by_blocking_concurrent(predicate, 1)
+    }
+
+    /// Splits the stream like `split_by_blocking`, but classifies up to
+    /// `max_in_flight` items on the blocking thread pool at once, while
+    /// still routing them in the order of the underlying stream like
+    /// `split_by_async_concurrent`. Panics if `max_in_flight` is 0.
+    /// Requires the `spawn-blocking` feature
+    #[cfg(feature = "spawn-blocking")]
+    #[allow(clippy::type_complexity)]
+    fn split_by_blocking_concurrent(
+        self,
+        predicate: P,
+        max_in_flight: usize,
+    ) -> Split<SplitByBlockingStream<Self::Item, Self, P>, SplitByBlockingStream<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> bool + Send + Sync + 'static,
+        Self::Item: Send + 'static,
+        Self: Sized + Unpin,
+    {
+        let (matches, rest) = SplitByBlockingStream::new(self, predicate, max_in_flight);
+        Split { matches, rest }
+    }
+
+    /// This behaves like `process_split`, but returns the outputs in the
+    /// order they complete, so a slow item doesn't hold back the outputs of
+    /// the items after it. An item only takes up its slot while its future
+    /// runs. Requires the `split-futures` feature
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let mut outputs = futures::stream::iter(0..6)
+    ///         .process_split_unordered(|&n| n % 2 == 0, |n| async move { n * 10 }, |n| async move { -n }, 2, 2)
+    ///         .collect::<Vec<_>>()
+    ///         .await;
+    ///     outputs.sort();
+    ///     assert_eq!(vec![-5, -3, -1, 0, 20, 40], outputs);
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    fn process_split_unordered<FT, FF, FutT, FutF>(
+        self,
+        predicate: P,
+        f_true: FT,
+        f_false: FF,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> ProcessSplit<Self, P, FT, FF, FutT, FutF>
+    where
+        P: Fn(&Self::Item) -> bool,
+        FT: FnMut(Self::Item) -> FutT,
+        FF: FnMut(Self::Item) -> FutF,
+        FutT: core::future::Future,
+        FutF: core::future::Future<Output = FutT::Output>,
+        Self: Sized,
+    {
+        ProcessSplit::new(
+            self,
+            predicate,
+            f_true,
+            f_false,
+            limit_true,
+            limit_false,
+            false,
+        )
+    }
+
+    /// Runs `on_true` on the items where the predicate returns `true` and
+    /// `on_false` on the rest, from one future, for when all that's wanted of
+    /// each side is to handle its items. Nothing is split, so there's no
+    /// locking or buffering involved, and no two tasks waiting on each other.
+    /// The handler of an item is awaited before the next item is pulled, so
+    /// one handler runs at a time. The future completes once the stream has
+    /// ended and the last handler is done, or with the error of the first
+    /// handler that fails, dropping the rest of the stream. Requires the
+    /// `split-futures` feature
+    ///
+    ///```rust
+    /// use futures::{channel::mpsc, StreamExt};
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, even_receiver) = mpsc::unbounded();
+    ///     futures::stream::iter([0, 1, 2, 3, 4, 5])
+    ///         .split_by_handlers(
+    ///             |&n| n % 2 == 0,
+    ///             |n| {
+    ///                 let evens = evens.clone();
+    ///                 async move { evens.unbounded_send(n) }
+    ///             },
+    ///             |n| async move {
+    ///                 println!("odd {}", n);
+    ///                 Ok(())
+    ///             },
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    ///     drop(evens);
+    ///     assert_eq!(vec![0, 2, 4], even_receiver.collect::<Vec<_>>().await);
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    fn split_by_handlers<FT, FF, FutT, FutF, E>(
+        self,
+        predicate: P,
+        on_true: FT,
+        on_false: FF,
+    ) -> SplitByHandlers<Self, P, FT, FF, FutT, FutF>
+    where
+        P: Fn(&Self::Item) -> bool,
+        FT: FnMut(Self::Item) -> FutT,
+        FF: FnMut(Self::Item) -> FutF,
+        FutT: core::future::Future<Output = Result<(), E>>,
+        FutF: core::future::Future<Output = Result<(), E>>,
+        Self: Sized,
+    {
+        SplitByHandlers::new(self, predicate, on_true, on_false, 1, 1, 1)
+    }
+
+    /// Like `split_by_handlers`, but with up to `limit_true` and
+    /// `limit_false` handlers of each side running at a time. When the side
+    /// of an item has no free slot, nothing more is pulled from the stream
+    /// until one of its handlers is done, so a slow side holds up the other
+    /// one at most one item later. The handlers that are still running when
+    /// one fails are dropped. Panics if either limit is 0. Requires the
+    /// `split-futures` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     futures::stream::iter(0..8u64)
+    ///         .split_by_handlers_concurrent(
+    ///             |&n| n < 4,
+    ///             |n| async move {
+    ///                 tokio::time::sleep(Duration::from_millis(n)).await;
+    ///                 Ok::<_, std::io::Error>(())
+    ///             },
+    ///             |_| async { Ok(()) },
+    ///             4,
+    ///             1,
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    /// })
+    /// ```
+    #[cfg(feature = "split-futures")]
+    fn split_by_handlers_concurrent<FT, FF, FutT, FutF, E>(
+        self,
+        predicate: P,
+        on_true: FT,
+        on_false: FF,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> SplitByHandlers<Self, P, FT, FF, FutT, FutF>
+    where
+        P: Fn(&Self::Item) -> bool,
+        FT: FnMut(Self::Item) -> FutT,
+        FF: FnMut(Self::Item) -> FutF,
+        FutT: core::future::Future<Output = Result<(), E>>,
+        FutF: core::future::Future<Output = Result<(), E>>,
+        Self: Sized,
+    {
+        SplitByHandlers::new(
+            self,
+            predicate,
+            on_true,
+            on_false,
+            limit_true,
+            limit_false,
+            usize::MAX,
+        )
+    }
+
+    /// Splits a stream of streams, like one stream of messages per
+    /// connection, by routing each whole inner stream to one side according
+    /// to `classify`. The first stream of the returned `Split` returns the
+    /// items of the inner streams where it returns `true`, and the second one
+    /// the items of the rest, merged in the order they arrive like
+    /// `flatten_unordered` would.
+    ///
+    /// Each side flattens up to `limit_true` or `limit_false` inner streams
+    /// at a time, which are polled while its stream is polled. An inner
+    /// stream is added to its side as soon as one of the side's inner
+    /// streams ends and makes room. Until then, it's held and nothing more is
+    /// pulled from the underlying stream, so the other side only gets new
+    /// inner streams once that happened. Dropping one stream of the split
+    /// drops the inner streams of its side, and the inner streams routed to
+    /// it later, but leaves the other side alone. Panics if either limit is 0
+    ///
+    ///```rust
+    /// use std::{pin::Pin, task::{Context, Poll}};
+    /// use futures::{stream::{self, Iter}, Stream, StreamExt};
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// struct Connection {
+    ///     admin: bool,
+    ///     messages: Iter<std::vec::IntoIter<&'static str>>,
+    /// }
+    ///
+    /// impl Stream for Connection {
+    ///     type Item = &'static str;
+    ///     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    ///         self.messages.poll_next_unpin(cx)
+    ///     }
+    /// }
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let connections = stream::iter([
+    ///         Connection { admin: true, messages: stream::iter(vec!["shutdown"]) },
+    ///         Connection { admin: false, messages: stream::iter(vec!["hello", "bye"]) },
+    ///     ]);
+    ///     let Split { matches: admin, rest: users } =
+    ///         connections.split_flatten_by(|connection| connection.admin, 1, 64);
+    ///     let (admin, users) = futures::join!(admin.collect::<Vec<_>>(), users.collect::<Vec<_>>());
+    ///     assert_eq!(vec!["shutdown"], admin);
+    ///     assert_eq!(vec!["hello", "bye"], users);
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_flatten_by(
+        self,
+        classify: P,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> Split<SplitFlattenStream<Self, P>, SplitFlattenStream<Self, P>>
     where
+        Self::Item: Stream,
         P: Fn(&Self::Item) -> bool,
         Self: Sized,
     {
-        let stream = SplitByBuffered::new(self, predicate);
-        let true_stream = TrueSplitByBuffered::new(stream.clone());
-        let false_stream = FalseSplitByBuffered::new(stream);
-        (true_stream, false_stream)
+        let stream = SplitFlattenBy::new(self, classify, limit_true, limit_false);
+        Split {
+            matches: SplitFlattenStream::new(stream.clone(), Side::True),
+            rest: SplitFlattenStream::new(stream, Side::False),
+        }
+    }
+
+    /// Segments the stream at the items where the predicate returns `true`,
+    /// like blank lines or end-of-batch markers. The returned stream returns
+    /// one `Segment` per run of items between delimiters, which is itself a
+    /// stream of those items. `delimiter` decides whether a delimiter is
+    /// dropped, ends the segment before it or starts the one after it.
+    ///
+    /// A segment is only returned once its first item has been pulled, so a
+    /// delimiter at the end of the stream doesn't leave an empty segment after
+    /// it, and with `Delimiter::StartsSegment` neither does one at the start.
+    /// Two delimiters in a row leave an empty segment between them, unless
+    /// they start segments.
+    ///
+    /// Only the last segment that was returned pulls items. Asking for the
+    /// next segment before it has ended ends it there: the items up to its
+    /// delimiter are pulled and dropped rather than buffered, and the segment
+    /// returns `None` from then on
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Delimiter, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let lines = futures::stream::iter(["a", "b", "", "c"]);
+    ///     let mut records = lines.split_by_delimiter(|line| line.is_empty(), Delimiter::Discard);
+    ///     let mut collected = Vec::new();
+    ///     while let Some(record) = records.next().await {
+    ///         collected.push(record.collect::<Vec<_>>().await);
+    ///     }
+    ///     assert_eq!(vec![vec!["a", "b"], vec!["c"]], collected);
+    /// })
+    /// ```
+    fn split_by_delimiter(self, predicate: P, delimiter: Delimiter) -> Segments<Self::Item, Self, P>
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized + Unpin,
+    {
+        Segments::new(SplitByDelimiter::new(self, predicate, delimiter))
+    }
+
+    /// Instead of splitting the stream, this returns a single stream of the
+    /// items paired with the `Side` the predicate puts them on. Use this
+    /// when one consumer handles both sides, since it doesn't need any
+    /// locking or buffering
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Side, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let incoming_stream = futures::stream::iter([0,1,2]);
+    ///     let mut tagged = incoming_stream.tag_by(|&n| n % 2 == 0);
+    ///     while let Some((side, n)) = tagged.next().await {
+    ///         assert_eq!(side.is_true(), n % 2 == 0);
+    ///     }
+    /// })
+    /// ```
+    fn tag_by(self, predicate: P) -> TagBy<Self, P>
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        TagBy::new(self, predicate)
+    }
+
+    /// This behaves like `split_by`, but the shared state is guarded by a
+    /// `tokio::sync::Mutex` instead of a `std::sync::Mutex`. A stream that finds
+    /// the state locked by the other stream waits in the mutex's queue and is
+    /// woken when the lock is released, rather than waking itself and retrying.
+    /// The predicate runs without the lock held, so while one consumer
+    /// classifies an item the other can still take the items in its buffer,
+    /// and once that buffer is empty it waits without keeping its executor
+    /// thread busy. The cost is an allocation whenever the lock is contended and
+    /// the requirement that the stream, predicate and items are `Send +
+    /// 'static`. Requires the `async-lock` feature
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_async_lock(|&n| n % 2 == 0);
+    /// ```
+    #[cfg(feature = "async-lock")]
+    #[allow(clippy::type_complexity)]
+    fn split_by_async_lock(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByAsyncLock<Self::Item, Self, P, 1>,
+        FalseSplitByAsyncLock<Self::Item, Self, P, 1>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool + Send + 'static,
+        Self: Sized + Send + 'static,
+        Self::Item: Send + 'static,
+    {
+        self.split_by_buffered_async_lock::<1>(predicate)
+    }
+
+    /// The buffered version of `split_by_async_lock`. This will buffer up to N
+    /// items of the inactive stream before returning Pending and notifying that
+    /// stream. Requires the `async-lock` feature
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitStreamByExt};
+    ///
+    /// let incoming_stream = futures::stream::iter([0,1,2,3,4,5]);
+    /// let Split { matches: even_stream, rest: odd_stream } = incoming_stream.split_by_buffered_async_lock::<3>(|&n| n % 2 == 0);
+    /// ```
+    #[cfg(feature = "async-lock")]
+    #[allow(clippy::type_complexity)]
+    fn split_by_buffered_async_lock<const N: usize>(
+        self,
+        predicate: P,
+    ) -> Split<
+        TrueSplitByAsyncLock<Self::Item, Self, P, N>,
+        FalseSplitByAsyncLock<Self::Item, Self, P, N>,
+    >
+    where
+        P: Fn(&Self::Item) -> bool + Send + 'static,
+        Self: Sized + Send + 'static,
+        Self::Item: Send + 'static,
+    {
+        let stream = alloc::sync::Arc::new(tokio::sync::Mutex::new(SplitByAsyncLock::with_kind(
+            self,
+            ByPredicate(predicate),
+            RingBuf::new(),
+            RingBuf::new(),
+            "split_by_async_lock",
+            split_by_async_lock::STREAMS,
+        )));
+        let left = alloc::sync::Arc::new(sync::Mutex::default());
+        let true_stream = TrueSplitByAsyncLock::new(stream.clone(), left.clone());
+        let false_stream = FalseSplitByAsyncLock::new(stream, left);
+        Split {
+            matches: true_stream,
+            rest: false_stream,
+        }
     }
 }
 
@@ -165,95 +1428,165 @@ pub trait SplitStreamByMapExt<P, L, R>: Stream {
     /// This takes ownership of a stream and returns two streams based on a
     /// predicate. The predicate takes an item by value and returns
     /// `Either::Left(..)` or `Either::Right(..)` where the inner
-    /// values of `Left` and `Right` become the items of the two respective
-    /// streams
+    /// values of `Left` and `Right` become the items of the `left` and `right`
+    /// streams of the returned `SplitMap`
     ///
     /// ```
-    /// use split_stream_by::{Either,SplitStreamByMapExt};
+    /// use split_stream_by::{Either, SplitMap, SplitStreamByMapExt};
     /// struct Request {
-    /// 	//...
+    ///     //...
     /// }
     /// struct Response {
-    /// 	//...
+    ///     //...
     /// }
     /// enum Message {
-    /// 	Request(Request),
-    /// 	Response(Response)
+    ///     Request(Request),
+    ///     Response(Response)
     /// }
     /// let incoming_stream = futures::stream::iter([
-    /// 	Message::Request(Request {}),
-    /// 	Message::Response(Response {}),
-    /// 	Message::Response(Response {}),
+    ///     Message::Request(Request {}),
+    ///     Message::Response(Response {}),
+    ///     Message::Response(Response {}),
     /// ]);
-    /// let (mut request_stream, mut response_stream) = incoming_stream.split_by_map(|item| match item {
-    /// 	Message::Request(req) => Either::Left(req),
-    /// 	Message::Response(res) => Either::Right(res),
+    /// let SplitMap { left: mut request_stream, right: mut response_stream } = incoming_stream.split_by_map(|item| match item {
+    ///     Message::Request(req) => Either::Left(req),
+    ///     Message::Response(res) => Either::Right(res),
     /// });
     /// ```
-
+    #[allow(clippy::type_complexity)]
     fn split_by_map(
         self,
         predicate: P,
-    ) -> (
+    ) -> SplitMap<
         LeftSplitByMap<Self::Item, L, R, Self, P>,
         RightSplitByMap<Self::Item, L, R, Self, P>,
-    )
+    >
     where
         P: Fn(Self::Item) -> Either<L, R>,
         Self: Sized,
     {
-        let stream = SplitByMap::new(self, predicate);
-        let true_stream = LeftSplitByMap::new(stream.clone());
-        let false_stream = RightSplitByMap::new(stream);
-        (true_stream, false_stream)
+        let stream = SplitByMap::new(
+            self,
+            ByMap::new(predicate),
+            RingBuf::new(),
+            RingBuf::new(),
+            "split_by_map",
+            split_by_map::STREAMS,
+        );
+        SplitMap::share(stream, LeftSplitByMap::new, RightSplitByMap::new)
     }
 
     /// This takes ownership of a stream and returns two streams based on a
     /// predicate. The predicate takes an item by value and returns
     /// `Either::Left(..)` or `Either::Right(..)` where the inner
-    /// values of `Left` and `Right` become the items of the two respective
-    /// streams. This will buffer up to N items of the inactive stream before
+    /// values of `Left` and `Right` become the items of the `left` and `right`
+    /// streams of the returned `SplitMap`. This will buffer up to N items of the inactive stream before
     /// returning Pending and notifying that stream
     ///
     /// ```
-    /// use split_stream_by::{Either,SplitStreamByMapExt};
+    /// use split_stream_by::{Either, SplitMap, SplitStreamByMapExt};
     /// struct Request {
-    /// 	//...
+    ///     //...
     /// }
     /// struct Response {
-    /// 	//...
+    ///     //...
     /// }
     /// enum Message {
-    /// 	Request(Request),
-    /// 	Response(Response)
+    ///     Request(Request),
+    ///     Response(Response)
     /// }
     /// let incoming_stream = futures::stream::iter([
-    /// 	Message::Request(Request {}),
-    /// 	Message::Response(Response {}),
-    /// 	Message::Response(Response {}),
+    ///     Message::Request(Request {}),
+    ///     Message::Response(Response {}),
+    ///     Message::Response(Response {}),
     /// ]);
-    /// let (mut request_stream, mut response_stream) = incoming_stream.split_by_map_buffered::<3>(|item| match item {
-    /// 	Message::Request(req) => Either::Left(req),
-    /// 	Message::Response(res) => Either::Right(res),
+    /// let SplitMap { left: mut request_stream, right: mut response_stream } = incoming_stream.split_by_map_buffered::<3>(|item| match item {
+    ///     Message::Request(req) => Either::Left(req),
+    ///     Message::Response(res) => Either::Right(res),
     /// });
     /// ```
-
+    #[allow(clippy::type_complexity)]
     fn split_by_map_buffered<const N: usize>(
         self,
         predicate: P,
-    ) -> (
+    ) -> SplitMap<
         LeftSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
         RightSplitByMapBuffered<Self::Item, L, R, Self, P, N>,
-    )
+    >
+    where
+        P: Fn(Self::Item) -> Either<L, R>,
+        Self: Sized,
+    {
+        let stream = SplitByMapBuffered::new(
+            self,
+            ByMap::new(predicate),
+            RingBuf::new(),
+            RingBuf::new(),
+            "split_by_map_buffered",
+            split_by_map_buffered::STREAMS,
+        );
+        SplitMap::share(
+            stream,
+            LeftSplitByMapBuffered::new,
+            RightSplitByMapBuffered::new,
+        )
+    }
+
+    /// The same as `split_by_map_buffered`, with a buffer size of
+    /// `DEFAULT_BUFFER_SIZE`
+    #[allow(clippy::type_complexity)]
+    fn split_by_map_buffered_default(
+        self,
+        predicate: P,
+    ) -> SplitMap<
+        LeftSplitByMapBuffered<Self::Item, L, R, Self, P, DEFAULT_BUFFER_SIZE>,
+        RightSplitByMapBuffered<Self::Item, L, R, Self, P, DEFAULT_BUFFER_SIZE>,
+    >
     where
         P: Fn(Self::Item) -> Either<L, R>,
         Self: Sized,
     {
-        let stream = SplitByMapBuffered::new(self, predicate);
-        let true_stream = LeftSplitByMapBuffered::new(stream.clone());
-        let false_stream = RightSplitByMapBuffered::new(stream);
-        (true_stream, false_stream)
+        self.split_by_map_buffered::<DEFAULT_BUFFER_SIZE>(predicate)
     }
 }
 
 impl<T, P, L, R> SplitStreamByMapExt<P, L, R> for T where T: Stream + ?Sized {}
+
+/// This extension trait provides the same splitting as `SplitStreamByExt`
+/// for plain iterators, for code that doesn't run in an executor
+pub trait SplitIterByExt<P>: Iterator {
+    /// This takes ownership of an iterator and returns two iterators based on
+    /// a predicate. When the predicate returns `true`, the item will appear in
+    /// the `matches` iterator of the returned `Split`. Items that return false
+    /// will go into the `rest` iterator. Pulling from one iterator stores the
+    /// items it skips for the other one, and that buffer grows as needed, so
+    /// the iterators can be consumed in any order. Draining one iterator
+    /// first keeps all the items of the other one in memory until it's
+    /// consumed. Both iterators are `Send` when the iterator, its items and
+    /// the predicate are, so they can be consumed from different threads
+    ///
+    ///```rust
+    /// use split_stream_by::{Split, SplitIterByExt};
+    ///
+    /// let Split { matches: even, rest: odd } = (0..6).split_iter_by(|&n| n % 2 == 0);
+    /// assert_eq!(vec![1, 3, 5], odd.collect::<Vec<_>>());
+    /// assert_eq!(vec![0, 2, 4], even.collect::<Vec<_>>());
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn split_iter_by(
+        self,
+        predicate: P,
+    ) -> Split<TrueSplitIterBy<Self::Item, Self, P>, FalseSplitIterBy<Self::Item, Self, P>>
+    where
+        P: Fn(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        let iter = SplitIterBy::new(self, predicate);
+        Split {
+            matches: TrueSplitIterBy::new(iter.clone()),
+            rest: FalseSplitIterBy::new(iter),
+        }
+    }
+}
+
+impl<T, P> SplitIterByExt<P> for T where T: Iterator + ?Sized {}
@@ -0,0 +1,201 @@
+use core::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{Split, SplitMap};
+
+/// A stream of a split with a transform applied to each item it returns.
+/// Created by `map_true`, `map_false` and `map_each` on `Split`, or by
+/// `map_left`, `map_right` and `map_each` on `SplitMap`. The transform runs
+/// after the shared state of the split has been unlocked, so a slow transform
+/// doesn't hold up the other stream
+#[pin_project]
+pub struct MapSide<St, F> {
+    #[pin]
+    stream: St,
+    f: F,
+}
+
+impl<St, F> MapSide<St, F> {
+    pub(crate) fn new(stream: St, f: F) -> Self {
+        Self { stream, f }
+    }
+
+    /// The stream of the split that this wraps, which gives access to methods
+    /// like `completion` and `abort`
+    pub fn get_ref(&self) -> &St {
+        &self.stream
+    }
+
+    /// The stream of the split that this wraps
+    pub fn get_mut(&mut self) -> &mut St {
+        &mut self.stream
+    }
+
+    /// Removes the transform and returns the stream of the split
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St, F, U> Stream for MapSide<St, F>
+where
+    St: Stream,
+    F: FnMut(St::Item) -> U,
+{
+    type Item = U;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<U>> {
+        let this = self.project();
+        let f = this.f;
+        this.stream.poll_next(cx).map(|item| item.map(f))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<St, F, U> FusedStream for MapSide<St, F>
+where
+    St: FusedStream,
+    F: FnMut(St::Item) -> U,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl<St, F> fmt::Debug for MapSide<St, F>
+where
+    St: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapSide")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Split<T, F> {
+    /// Transforms each item of the `matches` stream with `f`
+    pub fn map_true<G, U>(self, f: G) -> Split<MapSide<T, G>, F>
+    where
+        T: Stream,
+        G: FnMut(T::Item) -> U,
+    {
+        Split {
+            matches: MapSide::new(self.matches, f),
+            rest: self.rest,
+        }
+    }
+
+    /// Transforms each item of the `rest` stream with `f`
+    pub fn map_false<G, U>(self, f: G) -> Split<T, MapSide<F, G>>
+    where
+        F: Stream,
+        G: FnMut(F::Item) -> U,
+    {
+        Split {
+            matches: self.matches,
+            rest: MapSide::new(self.rest, f),
+        }
+    }
+
+    /// Transforms each item of the `matches` stream with `f` and each item of
+    /// the `rest` stream with `g`. This is like splitting with
+    /// `split_by_map`, but the predicate doesn't have to do the conversion
+    pub fn map_each<G, U, H, V>(self, f: G, g: H) -> Split<MapSide<T, G>, MapSide<F, H>>
+    where
+        T: Stream,
+        F: Stream,
+        G: FnMut(T::Item) -> U,
+        H: FnMut(F::Item) -> V,
+    {
+        self.map_true(f).map_false(g)
+    }
+}
+
+impl<L, R> SplitMap<L, R> {
+    /// Transforms each item of the `left` stream with `f`
+    pub fn map_left<G, U>(self, f: G) -> SplitMap<MapSide<L, G>, R>
+    where
+        L: Stream,
+        G: FnMut(L::Item) -> U,
+    {
+        SplitMap {
+            left: MapSide::new(self.left, f),
+            right: self.right,
+        }
+    }
+
+    /// Transforms each item of the `right` stream with `f`
+    pub fn map_right<G, U>(self, f: G) -> SplitMap<L, MapSide<R, G>>
+    where
+        R: Stream,
+        G: FnMut(R::Item) -> U,
+    {
+        SplitMap {
+            left: self.left,
+            right: MapSide::new(self.right, f),
+        }
+    }
+
+    /// Transforms each item of the `left` stream with `f` and each item of
+    /// the `right` stream with `g`
+    pub fn map_each<G, U, H, V>(self, f: G, g: H) -> SplitMap<MapSide<L, G>, MapSide<R, H>>
+    where
+        L: Stream,
+        R: Stream,
+        G: FnMut(L::Item) -> U,
+        H: FnMut(R::Item) -> V,
+    {
+        self.map_left(f).map_right(g)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{stream::FusedStream, StreamExt};
+
+    use crate::{Either, SplitStreamByExt, SplitStreamByMapExt};
+
+    #[tokio::test]
+    async fn test_map_each() {
+        let split = futures::stream::iter(0..6)
+            .split_by_buffered::<2>(|&n| n % 2 == 0)
+            .map_each(|n| n * 10, |n| n.to_string());
+        let completion = split.matches.get_ref().completion();
+        let (evens, odds) = futures::join!(
+            split.matches.collect::<Vec<_>>(),
+            split.rest.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 20, 40], evens);
+        assert_eq!(vec!["1", "3", "5"], odds);
+        completion.await;
+    }
+
+    #[tokio::test]
+    async fn test_map_right() {
+        let mut split = futures::stream::iter(0..4)
+            .split_by_map(|n| {
+                if n < 2 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n)
+                }
+            })
+            .map_right(|n| n + 1);
+        let (left, right) = futures::join!(
+            (&mut split.left).collect::<Vec<_>>(),
+            (&mut split.right).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 1], left);
+        assert_eq!(vec![3, 4], right);
+        assert!(split.right.is_terminated());
+    }
+}
@@ -0,0 +1,302 @@
+#[cfg(feature = "metrics")]
+use std::{borrow::Cow, collections::VecDeque, time::Instant};
+
+#[cfg(feature = "metrics")]
+use metrics::{Counter, Gauge, Histogram};
+
+use crate::Side;
+
+/// The metrics of a split, which are only recorded once the split has been
+/// given a name with `with_metrics`. Without the `metrics` feature this is
+/// empty and recording compiles to nothing
+pub(crate) struct SplitMetrics {
+    #[cfg(feature = "metrics")]
+    named: Option<alloc::boxed::Box<Named>>,
+}
+
+/// The metric handles of a named split, registered up front so that recording
+/// doesn't need to look them up
+#[cfg(feature = "metrics")]
+struct Named {
+    dropped: Counter,
+    true_side: NamedSide,
+    false_side: NamedSide,
+}
+
+#[cfg(feature = "metrics")]
+struct NamedSide {
+    routed: Counter,
+    buffered: Gauge,
+    buffered_seconds: Histogram,
+    /// When each item in the buffer of this side was put there, oldest first
+    since: VecDeque<Instant>,
+}
+
+#[cfg(feature = "metrics")]
+impl NamedSide {
+    fn new(name: Cow<'static, str>, side: &'static str, buffered_len: usize) -> Self {
+        let labels = [("split", name), ("side", Cow::Borrowed(side))];
+        let buffered = metrics::gauge!("split_buffered", &labels);
+        buffered.set(buffered_len as f64);
+        let now = Instant::now();
+        Self {
+            routed: metrics::counter!("split_items_routed_total", &labels),
+            buffered,
+            buffered_seconds: metrics::histogram!("split_buffered_seconds", &labels),
+            since: (0..buffered_len).map(|_| now).collect(),
+        }
+    }
+
+    fn resync(&mut self, buffered_len: usize) {
+        let now = Instant::now();
+        self.since.truncate(buffered_len);
+        while self.since.len() < buffered_len {
+            self.since.push_back(now);
+        }
+        self.buffered.set(buffered_len as f64);
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+impl SplitMetrics {
+    /// Creates the metrics of a split that doesn't have a name yet, which
+    /// records nothing
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            named: None,
+        }
+    }
+
+    /// Names the split `name` and starts recording its metrics, where the
+    /// buffers already hold `buffered_true` and `buffered_false` items. If
+    /// the split already had a name, its metrics are recorded under the new
+    /// one from now on
+    #[cfg(feature = "metrics")]
+    pub(crate) fn set_name(
+        &mut self,
+        name: Cow<'static, str>,
+        buffered_true: usize,
+        buffered_false: usize,
+    ) {
+        // The buffered items move to the new name rather than being dropped
+        self.resync(Side::True, 0);
+        self.resync(Side::False, 0);
+        self.named = Some(alloc::boxed::Box::new(Named {
+            dropped: metrics::counter!("split_items_dropped_total", "split" => name.clone()),
+            true_side: NamedSide::new(name.clone(), "true", buffered_true),
+            false_side: NamedSide::new(name, "false", buffered_false),
+        }));
+    }
+
+    #[cfg(feature = "metrics")]
+    fn side(&mut self, side: Side) -> Option<&mut NamedSide> {
+        let named = self.named.as_deref_mut()?;
+        Some(match side {
+            Side::True => &mut named.true_side,
+            Side::False => &mut named.false_side,
+        })
+    }
+
+    /// An item from the underlying stream went to `side`, either straight to
+    /// its stream or into its buffer
+    #[inline]
+    pub(crate) fn routed(&mut self, side: Side) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.side(side) {
+            named.routed.increment(1);
+        }
+    }
+
+    /// An item was put at the back of the buffer of `side`, which holds
+    /// `buffered_len` items now
+    #[inline]
+    pub(crate) fn pushed_back(&mut self, side: Side, buffered_len: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.side(side) {
+            named.since.push_back(Instant::now());
+            named.buffered.set(buffered_len as f64);
+        }
+    }
+
+    /// An item was put back at the front of the buffer of `side`, which holds
+    /// `buffered_len` items now
+    #[inline]
+    pub(crate) fn pushed_front(&mut self, side: Side, buffered_len: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.side(side) {
+            named.since.push_front(Instant::now());
+            named.buffered.set(buffered_len as f64);
+        }
+    }
+
+    /// The item at the front of the buffer of `side` was taken out by its
+    /// stream, which leaves `buffered_len` items
+    #[inline]
+    pub(crate) fn popped_front(&mut self, side: Side, buffered_len: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.side(side) {
+            if let Some(since) = named.since.pop_front() {
+                named.buffered_seconds.record(since.elapsed());
+            }
+            named.buffered.set(buffered_len as f64);
+        }
+    }
+
+    /// The item at `index` in the buffer of `side` was dropped without being
+    /// returned by its stream
+    #[inline]
+    pub(crate) fn removed(&mut self, side: Side, index: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.named.as_deref_mut() {
+            named.dropped.increment(1);
+            let named = match side {
+                Side::True => &mut named.true_side,
+                Side::False => &mut named.false_side,
+            };
+            named.since.remove(index);
+        }
+    }
+
+    /// The buffer of `side` was rearranged as a whole and holds `buffered_len`
+    /// items now. The items that were added count as buffered from now on
+    #[inline]
+    pub(crate) fn resync(&mut self, side: Side, buffered_len: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(named) = self.side(side) {
+            named.resync(buffered_len);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for SplitMetrics {
+    /// The items still buffered when the split goes away are never returned by
+    /// either stream. Splits that hand their buffered items back call
+    /// `resync` with 0 first
+    fn drop(&mut self) {
+        if let Some(named) = self.named.as_deref_mut() {
+            let dropped = named.true_side.since.len() + named.false_side.since.len();
+            named.dropped.increment(dropped as u64);
+            named.true_side.resync(0);
+            named.false_side.resync(0);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use std::{collections::BTreeMap, task::Poll};
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// Formats every metric as `name{labels}` with its value, or the number of
+    /// samples for a histogram
+    fn values(snapshotter: &Snapshotter) -> BTreeMap<String, String> {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let labels = key
+                    .labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let value = match value {
+                    DebugValue::Counter(count) => count.to_string(),
+                    DebugValue::Gauge(value) => value.to_string(),
+                    DebugValue::Histogram(samples) => format!("{} samples", samples.len()),
+                };
+                (format!("{}{{{}}}", key.name(), labels), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = metrics::with_local_recorder(&recorder, || {
+            source
+                .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+                .with_metrics("numbers")
+                .into_tuple()
+        });
+        let waker = CountingWaker::new();
+        for n in [0, 2, 4, 1] {
+            handle.push(n);
+        }
+        // The false stream buffers both even numbers, and then the true buffer is full
+        for _ in 0..3 {
+            assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        let expected = [
+            ("split_buffered{split=numbers,side=false}", "0"),
+            ("split_buffered{split=numbers,side=true}", "1"),
+            (
+                "split_buffered_seconds{split=numbers,side=false}",
+                "0 samples",
+            ),
+            (
+                "split_buffered_seconds{split=numbers,side=true}",
+                "2 samples",
+            ),
+            ("split_items_dropped_total{split=numbers}", "0"),
+            ("split_items_routed_total{split=numbers,side=false}", "1"),
+            ("split_items_routed_total{split=numbers,side=true}", "3"),
+        ];
+        assert_eq!(
+            expected
+                .iter()
+                .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+                .collect::<BTreeMap<_, _>>(),
+            values(&snapshotter)
+        );
+        // The item still buffered for the true stream is dropped along with the split
+        drop(true_stream);
+        drop(false_stream);
+        let values = values(&snapshotter);
+        assert_eq!("1", values["split_items_dropped_total{split=numbers}"]);
+        assert_eq!("0", values["split_buffered{split=numbers,side=true}"]);
+    }
+
+    #[test]
+    fn test_retained_items() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let (mut true_stream, _false_stream) = metrics::with_local_recorder(&recorder, || {
+            futures::stream::iter(0..4)
+                .split_by_buffered::<4>(|&n: &i32| n < 4)
+                .with_metrics("numbers")
+                .into_tuple()
+        });
+        assert!(true_stream.push_back(10).is_ok());
+        assert!(true_stream.push_back(11).is_ok());
+        assert_eq!(1, true_stream.retain_buffered(|&mut n| n == 11));
+        let values = values(&snapshotter);
+        assert_eq!("1", values["split_items_dropped_total{split=numbers}"]);
+        assert_eq!("1", values["split_buffered{split=numbers,side=true}"]);
+    }
+}
@@ -0,0 +1,404 @@
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+
+use crate::{bounded_buf::Pushed, Side};
+
+/// A callback that is called with the delivered watermark of a split when it
+/// advances
+type DeliveredCallback = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// What the delivered watermark of a split does with the items the split
+/// gives up on: the ones buffered for or routed to a stream that was dropped,
+/// and the ones a graceful close abandons. Set with
+/// `SplitBuilder::track_offsets`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AbandonedItems {
+    /// The watermark stops below the first item that was given up on, so
+    /// that it's processed again once the source resumes from there
+    #[default]
+    Pending,
+    /// The items count as delivered, so the watermark moves past them
+    Delivered,
+}
+
+/// The offsets of the items buffered for one side, oldest first
+#[derive(Default)]
+struct TrackedSide {
+    pending: VecDeque<u64>,
+    /// How many of the items at the front of the buffer were rearranged by
+    /// `set_predicate_reclassify`, so their offsets are only known to be in
+    /// `Tracked::rearranged`
+    rearranged: usize,
+    /// The lowest offset of the items given up on with
+    /// `AbandonedItems::Pending`
+    held: Option<u64>,
+    closed: bool,
+}
+
+struct Tracked {
+    /// The offset of the next item pulled from the underlying stream
+    next: u64,
+    sides: [TrackedSide; 2],
+    /// The offsets of the items that were rearranged, lowest first, which
+    /// hold the watermark back until all of those items are gone
+    rearranged: VecDeque<u64>,
+    abandoned: AbandonedItems,
+    callback: Option<DeliveredCallback>,
+    /// The watermark that was last passed to the callback
+    reported: Option<u64>,
+}
+
+impl Tracked {
+    fn side(&mut self, side: Side) -> &mut TrackedSide {
+        &mut self.sides[usize::from(side.is_false())]
+    }
+
+    fn assign(&mut self) -> u64 {
+        let offset = self.next;
+        self.next += 1;
+        offset
+    }
+
+    fn give_up(&mut self, side: Side, offset: u64) {
+        if self.abandoned == AbandonedItems::Pending {
+            let held = &mut self.side(side).held;
+            *held = Some(held.map_or(offset, |held| held.min(offset)));
+        }
+    }
+
+    /// Takes the item at the front of the buffer of `side` out of the
+    /// bookkeeping
+    fn pop(&mut self, side: Side) {
+        let tracked = self.side(side);
+        if tracked.rearranged > 0 {
+            tracked.rearranged -= 1;
+            if self.sides.iter().all(|side| side.rearranged == 0) {
+                self.rearranged.clear();
+            }
+        } else {
+            tracked.pending.pop_front();
+        }
+    }
+
+    /// The lowest offset that hasn't been delivered, considering only the
+    /// items buffered for `sides`
+    fn undelivered(&self, sides: &[usize]) -> u64 {
+        sides
+            .iter()
+            .flat_map(|&side| {
+                self.sides[side]
+                    .pending
+                    .front()
+                    .into_iter()
+                    .chain(&self.sides[side].held)
+            })
+            .chain(self.rearranged.front())
+            .fold(self.next, |lowest, &offset| lowest.min(offset))
+    }
+}
+
+/// Assigns each item pulled from the underlying stream an offset, counting
+/// up from 0, and keeps track of which of them were delivered. Nothing is
+/// tracked unless it was turned on
+#[derive(Default)]
+pub(crate) struct Offsets {
+    // Boxed so that the shared state doesn't grow when offsets aren't tracked
+    tracked: Option<Box<Tracked>>,
+}
+
+impl Offsets {
+    pub(crate) fn new(abandoned: AbandonedItems) -> Self {
+        Self {
+            tracked: Some(Box::new(Tracked {
+                next: 0,
+                sides: Default::default(),
+                rearranged: VecDeque::new(),
+                abandoned,
+                callback: None,
+                reported: None,
+            })),
+        }
+    }
+
+    /// Calls `callback` with the delivered watermark whenever it advances,
+    /// tracking offsets if they weren't yet
+    pub(crate) fn on_delivered(&mut self, callback: impl Fn(u64) + Send + Sync + 'static) {
+        if self.tracked.is_none() {
+            *self = Self::new(AbandonedItems::default());
+        }
+        if let Some(tracked) = &mut self.tracked {
+            tracked.callback = Some(Arc::new(callback));
+        }
+    }
+
+    /// An item from the underlying stream was returned right away by the
+    /// stream that pulled it, or was replaced by a later one because the
+    /// stream is conflated
+    #[inline]
+    pub(crate) fn delivered(&mut self) {
+        if let Some(tracked) = &mut self.tracked {
+            tracked.assign();
+        }
+    }
+
+    /// An item from the underlying stream was routed to the buffer of `side`,
+    /// which did what `pushed` says with it
+    #[inline]
+    pub(crate) fn buffered(&mut self, side: Side, pushed: Pushed) {
+        if let Some(tracked) = &mut self.tracked {
+            let offset = tracked.assign();
+            if tracked.side(side).closed {
+                return tracked.give_up(side, offset);
+            }
+            match pushed {
+                Pushed::Kept => {}
+                Pushed::ReplacedOldest => tracked.pop(side),
+                Pushed::Dropped => return,
+            }
+            tracked.side(side).pending.push_back(offset);
+        }
+    }
+
+    /// The stream of `side` took the item at the front of its buffer
+    #[inline]
+    pub(crate) fn popped(&mut self, side: Side) {
+        if let Some(tracked) = &mut self.tracked {
+            tracked.pop(side);
+        }
+    }
+
+    /// The stream of `side` was dropped, or the items buffered for it were
+    /// abandoned by a graceful close, so none of them will be delivered
+    #[inline]
+    pub(crate) fn abandoned(&mut self, side: Side) {
+        if let Some(tracked) = &mut self.tracked {
+            if let Some(&lowest) = tracked.side(side).pending.front() {
+                tracked.give_up(side, lowest);
+            }
+            if tracked.side(side).rearranged > 0 {
+                if let Some(&lowest) = tracked.rearranged.front() {
+                    tracked.give_up(side, lowest);
+                }
+            }
+            let tracked_side = tracked.side(side);
+            tracked_side.pending.clear();
+            tracked_side.rearranged = 0;
+            tracked_side.closed = true;
+            if tracked.sides.iter().all(|side| side.rearranged == 0) {
+                tracked.rearranged.clear();
+            }
+        }
+    }
+
+    /// The buffered items are about to be rearranged by
+    /// `set_predicate_reclassify`. The first `len_true` and `len_false`
+    /// items are in the buffers, and the ones after them are spilled
+    pub(crate) fn rearranging(&mut self, len_true: usize, len_false: usize) {
+        if let Some(tracked) = &mut self.tracked {
+            let mut rearranged = core::mem::take(&mut tracked.rearranged);
+            for (side, len) in tracked.sides.iter_mut().zip([len_true, len_false]) {
+                // Nothing is tracked for the items of a stream that was dropped
+                let len = len.saturating_sub(side.rearranged).min(side.pending.len());
+                side.rearranged = 0;
+                rearranged.extend(side.pending.drain(..len));
+            }
+            rearranged.make_contiguous().sort_unstable();
+            tracked.rearranged = rearranged;
+        }
+    }
+
+    /// The buffers hold `len_true` and `len_false` rearranged items now, in
+    /// front of the ones that are still spilled
+    pub(crate) fn rearranged(&mut self, len_true: usize, len_false: usize) {
+        if let Some(tracked) = &mut self.tracked {
+            for (side, len) in tracked.sides.iter_mut().zip([len_true, len_false]) {
+                // A stream that was dropped never takes the items moved to it
+                side.rearranged = if side.closed { 0 } else { len };
+            }
+            if tracked.sides.iter().all(|side| side.rearranged == 0) {
+                tracked.rearranged.clear();
+            }
+        }
+    }
+
+    /// The highest offset such that every item with an offset up to it was
+    /// delivered or given up on, or `None` if there is none yet or offsets
+    /// aren't tracked. With `side`, only the items routed to that side are
+    /// considered
+    pub(crate) fn watermark(&self, side: Option<Side>) -> Option<u64> {
+        let tracked = self.tracked.as_ref()?;
+        let undelivered = match side {
+            Some(side) => tracked.undelivered(&[usize::from(side.is_false())]),
+            None => tracked.undelivered(&[0, 1]),
+        };
+        undelivered.checked_sub(1)
+    }
+
+    /// Returns the callback along with the watermark if it advanced since the
+    /// last check
+    pub(crate) fn advanced(&mut self) -> Advanced {
+        let watermark = self.watermark(None);
+        match &mut self.tracked {
+            Some(tracked) if tracked.callback.is_some() && watermark > tracked.reported => {
+                tracked.reported = watermark;
+                Advanced(tracked.callback.clone().zip(watermark))
+            }
+            _ => Advanced(None),
+        }
+    }
+}
+
+/// The callback of an advanced watermark, which is called once the shared
+/// state of the split has been unlocked
+#[must_use]
+pub(crate) struct Advanced(Option<(DeliveredCallback, u64)>);
+
+impl Advanced {
+    pub(crate) fn call(self) {
+        if let Some((callback, watermark)) = self.0 {
+            callback(watermark);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        task::Poll,
+    };
+
+    use super::AbandonedItems;
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        BoxedPredicate, Overflow, Split, SplitBuilder,
+    };
+
+    #[test]
+    fn test_watermark_follows_delivery() {
+        let (source, handle) = ManualStream::new();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer(4)
+            .on_delivered({
+                let reported = reported.clone();
+                move |watermark| reported.lock().unwrap().push(watermark)
+            })
+            .build();
+        let control = matches.control();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 0, 2] {
+            handle.push(n);
+        }
+        assert_eq!(None, control.delivered_watermark());
+        for _ in 0..2 {
+            assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        // 1 and 3 are only buffered, so nothing is delivered from the start on
+        assert_eq!(None, control.delivered_watermark());
+        assert_eq!(Some(2), matches.delivered_watermark());
+        assert_eq!(None, rest.delivered_watermark());
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Some(0), control.delivered_watermark());
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Some(2), control.delivered_watermark());
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!(Some(3), control.delivered_watermark());
+        assert_eq!(vec![0, 2, 3], *reported.lock().unwrap());
+    }
+
+    fn abandoned_watermark(abandoned: AbandonedItems) -> (Option<u64>, Option<u64>) {
+        let (source, handle) = ManualStream::new();
+        let Split { mut matches, rest } = SplitBuilder::new(source)
+            .predicate(|&n: &i32| n % 2 == 0)
+            .buffer_false(2)
+            .overflow(Overflow::DropOldest)
+            .track_offsets(abandoned)
+            .build();
+        let control = matches.control();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5, 0] {
+            handle.push(n);
+        }
+        for _ in 0..3 {
+            assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        // 1 was dropped to make room for 5, so it counts as delivered
+        assert_eq!(Some(0), control.delivered_watermark());
+        drop(rest);
+        let dropped = control.delivered_watermark();
+        handle.push(7);
+        handle.push(2);
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        (dropped, control.delivered_watermark())
+    }
+
+    #[test]
+    fn test_abandoned_pending() {
+        assert_eq!(
+            (Some(0), Some(0)),
+            abandoned_watermark(AbandonedItems::Pending)
+        );
+    }
+
+    #[test]
+    fn test_abandoned_delivered() {
+        assert_eq!(
+            (Some(3), Some(5)),
+            abandoned_watermark(AbandonedItems::Delivered)
+        );
+    }
+
+    #[test]
+    fn test_reclassified_items_hold_watermark() {
+        let (source, handle) = ManualStream::new();
+        let predicate: BoxedPredicate<i32> = Box::new(|&n| n % 2 == 0);
+        let Split {
+            mut matches,
+            mut rest,
+        } = SplitBuilder::new(source)
+            .predicate(predicate)
+            .buffer(4)
+            .track_offsets(AbandonedItems::Pending)
+            .build();
+        let control = matches.control();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 0] {
+            handle.push(n);
+        }
+        for _ in 0..2 {
+            assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        matches.set_predicate_reclassify(Box::new(|&n| n % 2 == 0 || n == 3));
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        // 1 is still buffered, and the split no longer knows which offset 3 had
+        assert_eq!(None, control.delivered_watermark());
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Some(2), control.delivered_watermark());
+    }
+}
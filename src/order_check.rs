@@ -0,0 +1,167 @@
+#[cfg(feature = "order-check")]
+use alloc::collections::VecDeque;
+
+use crate::Side;
+
+/// Checks that each stream of a split returns the items of the underlying
+/// stream in the order they were pulled. Every item is stamped with its
+/// position in the underlying stream when it's routed, and a stream that
+/// takes an item out of its buffer panics if the stamp isn't after that of
+/// the last item it returned. Items the streams put in their own buffers
+/// aren't stamped and aren't checked. Without the `order-check` feature this
+/// is empty and checking compiles to nothing
+#[derive(Default)]
+pub(crate) struct OrderCheck {
+    #[cfg(feature = "order-check")]
+    pulled: u64,
+    #[cfg(feature = "order-check")]
+    sides: [CheckedSide; 2],
+}
+
+/// The stamps of the items buffered for one side, oldest first, and the
+/// stamp of the last item that side returned
+#[cfg(feature = "order-check")]
+#[derive(Default)]
+struct CheckedSide {
+    stamps: VecDeque<Option<u64>>,
+    last: Option<u64>,
+}
+
+#[cfg(feature = "order-check")]
+impl CheckedSide {
+    fn deliver(&mut self, side: Side, stamp: u64) {
+        if let Some(last) = self.last {
+            assert!(
+                stamp > last,
+                "the {} stream of a split returned item {} of the underlying stream after item {}",
+                side.is_true(),
+                stamp,
+                last
+            );
+        }
+        self.last = Some(stamp);
+    }
+}
+
+#[cfg_attr(not(feature = "order-check"), allow(unused_variables))]
+impl OrderCheck {
+    #[cfg(feature = "order-check")]
+    fn side(&mut self, side: Side) -> &mut CheckedSide {
+        &mut self.sides[usize::from(!side.is_true())]
+    }
+
+    #[cfg(feature = "order-check")]
+    fn stamp(&mut self) -> u64 {
+        let stamp = self.pulled;
+        self.pulled += 1;
+        stamp
+    }
+
+    /// An item from the underlying stream was returned right away by the
+    /// stream of `side`
+    #[inline]
+    pub(crate) fn routed_to_stream(&mut self, side: Side) {
+        #[cfg(feature = "order-check")]
+        {
+            let stamp = self.stamp();
+            self.side(side).deliver(side, stamp);
+        }
+    }
+
+    /// An item from the underlying stream was put at the back of the buffer of
+    /// `side`
+    #[inline]
+    pub(crate) fn routed_to_buffer(&mut self, side: Side) {
+        #[cfg(feature = "order-check")]
+        {
+            let stamp = self.stamp();
+            self.side(side).stamps.push_back(Some(stamp));
+        }
+    }
+
+    /// An item from the underlying stream was dropped without going to either
+    /// stream
+    #[inline]
+    pub(crate) fn dropped(&mut self) {
+        #[cfg(feature = "order-check")]
+        self.stamp();
+    }
+
+    /// The stream of `side` put an item of its own at the front of its buffer
+    #[inline]
+    pub(crate) fn pushed_front(&mut self, side: Side) {
+        #[cfg(feature = "order-check")]
+        self.side(side).stamps.push_front(None);
+    }
+
+    /// The stream of `side` put an item of its own at the back of its buffer
+    #[inline]
+    pub(crate) fn pushed_back(&mut self, side: Side) {
+        #[cfg(feature = "order-check")]
+        self.side(side).stamps.push_back(None);
+    }
+
+    /// The item at the front of the buffer of `side` was taken out by its
+    /// stream. Panics if it came before the last item that stream returned
+    #[inline]
+    pub(crate) fn popped_front(&mut self, side: Side) {
+        #[cfg(feature = "order-check")]
+        {
+            let checked = self.side(side);
+            if let Some(Some(stamp)) = checked.stamps.pop_front() {
+                checked.deliver(side, stamp);
+            }
+        }
+    }
+
+    /// The item at `index` in the buffer of `side` was dropped without being
+    /// returned by its stream
+    #[inline]
+    pub(crate) fn removed(&mut self, side: Side, index: usize) {
+        #[cfg(feature = "order-check")]
+        self.side(side).stamps.remove(index);
+    }
+
+    /// The buffer of `side` was rearranged as a whole and holds `buffered_len`
+    /// items now, none of which are checked anymore
+    #[inline]
+    pub(crate) fn resync(&mut self, side: Side, buffered_len: usize) {
+        #[cfg(feature = "order-check")]
+        {
+            let checked = self.side(side);
+            checked.stamps.clear();
+            checked.stamps.resize(buffered_len, None);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "order-check"))]
+mod test {
+    use super::OrderCheck;
+    use crate::Side;
+
+    #[test]
+    fn test_in_order() {
+        let mut check = OrderCheck::default();
+        check.routed_to_buffer(Side::False);
+        check.routed_to_stream(Side::True);
+        check.pushed_front(Side::False);
+        check.routed_to_buffer(Side::False);
+        check.dropped();
+        check.routed_to_stream(Side::True);
+        for _ in 0..3 {
+            check.popped_front(Side::False);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "returned item 0 of the underlying stream after item 2")]
+    fn test_out_of_order() {
+        let mut check = OrderCheck::default();
+        check.routed_to_buffer(Side::False);
+        check.routed_to_buffer(Side::False);
+        check.routed_to_stream(Side::False);
+        check.popped_front(Side::False);
+        check.popped_front(Side::False);
+    }
+}
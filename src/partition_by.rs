@@ -0,0 +1,337 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{bounded_buf::BoundedBuf, side_waker::SideWaker, sync::Mutex};
+
+/// One partition of a `PartitionBy`
+struct Partition<I> {
+    buf: BoundedBuf<I>,
+    waker: SideWaker,
+    closed: bool,
+}
+
+#[pin_project]
+pub(crate) struct PartitionBy<I, S, F> {
+    partitions: Vec<Partition<I>>,
+    #[pin]
+    stream: S,
+    f: F,
+    done: bool,
+}
+
+impl<I, S, F> PartitionBy<I, S, F>
+where
+    S: Stream<Item = I>,
+    F: Fn(&I) -> usize,
+{
+    pub(crate) fn new(stream: S, n: usize, capacity: usize, f: F) -> Arc<Mutex<Self>> {
+        assert!(n > 0, "a stream has to be partitioned into at least one");
+        let partitions = (0..n)
+            .map(|_| Partition {
+                buf: BoundedBuf::new(capacity),
+                waker: SideWaker::default(),
+                closed: false,
+            })
+            .collect();
+        Arc::new(Mutex::new(Self {
+            partitions,
+            stream,
+            f,
+            done: false,
+        }))
+    }
+
+    fn poll_next_partition(
+        self: Pin<&mut Self>,
+        index: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<I>> {
+        let mut this = self.project();
+        this.partitions[index]
+            .waker
+            .register(cx.waker(), "PartitionStream");
+        let partition = &mut this.partitions[index];
+        let was_full = partition.buf.remaining() == 0;
+        if let Some(item) = partition.buf.pop_front() {
+            if was_full {
+                // The other partitions might be waiting for room in this buffer
+                for (other, partition) in this.partitions.iter_mut().enumerate() {
+                    if other != index {
+                        partition.waker.wake();
+                    }
+                }
+            }
+            return Poll::Ready(Some(item));
+        }
+        loop {
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            // The next item could be for any of the partitions, so it's only pulled once all of
+            // them have room for it
+            if let Some(full) = this
+                .partitions
+                .iter_mut()
+                .enumerate()
+                .find(|(other, partition)| {
+                    *other != index && !partition.closed && partition.buf.remaining() == 0
+                })
+                .map(|(_, partition)| partition)
+            {
+                full.waker.wake();
+                return Poll::Pending;
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let target = (this.f)(&item);
+                    let len = this.partitions.len();
+                    assert!(
+                        target < len,
+                        "the item was routed to partition {}, but there are only {}",
+                        target,
+                        len
+                    );
+                    if target == index {
+                        return Poll::Ready(Some(item));
+                    }
+                    let partition = &mut this.partitions[target];
+                    // Nothing takes the items of a dropped partition, so they're dropped right
+                    // away and the others keep flowing
+                    if !partition.closed {
+                        let _ = partition.buf.push_back(item);
+                        partition.waker.wake();
+                    }
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // The other partitions have to find out that the underlying stream is done
+                    for (other, partition) in this.partitions.iter_mut().enumerate() {
+                        if other != index {
+                            partition.waker.wake();
+                        }
+                    }
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items that the
+/// function of `partition_by` routes to one partition
+#[must_use = "streams do nothing unless polled"]
+pub struct PartitionStream<I, S, F> {
+    stream: Arc<Mutex<PartitionBy<I, S, F>>>,
+    index: usize,
+    terminated: bool,
+}
+
+impl<I, S, F> PartitionStream<I, S, F> {
+    /// Creates the streams of all the partitions of `stream`
+    pub(crate) fn all(stream: Arc<Mutex<PartitionBy<I, S, F>>>) -> Vec<Self> {
+        let n = stream.lock().partitions.len();
+        (0..n)
+            .map(|index| Self {
+                stream: stream.clone(),
+                index,
+                terminated: false,
+            })
+            .collect()
+    }
+
+    /// The index of this partition
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<I, S, F> PartitionStream<I, S, F>
+where
+    S: Stream<Item = I> + Unpin,
+    F: Fn(&I) -> usize,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = PartitionBy::poll_next_partition(Pin::new(&mut guard), self.index, cx);
+            if response.is_ready() {
+                guard.partitions[self.index].waker.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through a partition stream
+impl<I, S, F> Unpin for PartitionStream<I, S, F> {}
+
+impl<I, S, F> Stream for PartitionStream<I, S, F>
+where
+    S: Stream<Item = I> + Unpin,
+    F: Fn(&I) -> usize,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, F> core::async_iter::AsyncIterator for PartitionStream<I, S, F>
+where
+    S: Stream<Item = I> + Unpin,
+    F: Fn(&I) -> usize,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, F> FusedStream for PartitionStream<I, S, F>
+where
+    S: Stream<Item = I> + Unpin,
+    F: Fn(&I) -> usize,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, F> Drop for PartitionStream<I, S, F> {
+    fn drop(&mut self) {
+        let mut state = self.stream.lock();
+        let partition = &mut state.partitions[self.index];
+        partition.closed = true;
+        // Nothing takes the buffered items of this partition anymore, and the other partitions
+        // might be waiting for room in its buffer
+        while partition.buf.pop_front().is_some() {}
+        for (other, partition) in state.partitions.iter_mut().enumerate() {
+            if other != self.index {
+                partition.waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// Partitions 0..100 by the remainder of dividing by `n` and collects
+    /// every partition concurrently
+    async fn collect_partitions(n: usize, capacity: usize) -> Vec<Vec<usize>> {
+        let partitions =
+            futures::stream::iter(0..100).partition_by_buffered(n, capacity, |&i| i % n);
+        assert_eq!(n, partitions.len());
+        futures::future::join_all(
+            partitions
+                .into_iter()
+                .map(|partition| partition.collect::<Vec<_>>()),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_partition_counts() {
+        for n in [1, 2, 8] {
+            for capacity in [1, 4] {
+                let expected = (0..n)
+                    .map(|partition| (partition..100).step_by(n).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                assert_eq!(expected, collect_partitions(n, capacity).await);
+            }
+        }
+    }
+
+    #[test]
+    fn test_waits_for_full_partition() {
+        let (source, handle) = ManualStream::new();
+        let mut partitions = source.partition_by(3, |&i: &usize| i % 3);
+        let waker = CountingWaker::new();
+        for i in [1, 4, 0] {
+            handle.push(i);
+        }
+        // 1 is buffered for partition 1, and then its buffer is full, so 4 isn't pulled
+        assert_eq!(Poll::Pending, poll_once(&mut partitions[0], &waker.waker()));
+        assert_eq!(1, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut partitions[1], &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut partitions[0], &waker.waker()));
+        assert_eq!(2, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(4)),
+            poll_once(&mut partitions[1], &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut partitions[0], &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_dropped_partition_is_abandoned() {
+        let (source, handle) = ManualStream::new();
+        let mut partitions = source.partition_by(3, |&i: &usize| i % 3);
+        let waker = CountingWaker::new();
+        for i in [1, 4, 7, 2, 0] {
+            handle.push(i);
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut partitions[0], &waker.waker()));
+        // Partition 1 holds up the others until it's dropped, and then its items are dropped
+        drop(partitions.remove(1));
+        assert_eq!(Poll::Pending, poll_once(&mut partitions[0], &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut partitions[1], &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut partitions[0], &waker.waker())
+        );
+        assert!(handle.is_empty());
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut partitions[0], &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut partitions[1], &waker.waker())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the item was routed to partition 5, but there are only 2")]
+    fn test_out_of_range_panics() {
+        let mut partitions = futures::stream::iter([5]).partition_by(2, |&i: &usize| i);
+        let _ = poll_once(&mut partitions[0], &CountingWaker::new().waker());
+    }
+}
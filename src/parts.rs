@@ -0,0 +1,29 @@
+use alloc::vec::Vec;
+
+/// Everything a split made with `split_by` or `split_by_buffered` was built
+/// from. Returned by `into_parts`
+pub struct SplitParts<I, S, P> {
+    /// The underlying stream. Polling it continues after the last item the
+    /// split pulled from it
+    pub stream: S,
+    /// The predicate the split was created with
+    pub predicate: P,
+    /// Items that were pulled for the `true` stream but not returned by it
+    pub buffered_true: Vec<I>,
+    /// Items that were pulled for the `false` stream but not returned by it
+    pub buffered_false: Vec<I>,
+}
+
+/// Everything a split made with `split_by_map` or `split_by_map_buffered` was
+/// built from. Returned by `into_parts`
+pub struct SplitMapParts<L, R, S, P> {
+    /// The underlying stream. Polling it continues after the last item the
+    /// split pulled from it
+    pub stream: S,
+    /// The predicate the split was created with
+    pub predicate: P,
+    /// Items that were pulled for the left stream but not returned by it
+    pub buffered_left: Vec<L>,
+    /// Items that were pulled for the right stream but not returned by it
+    pub buffered_right: Vec<R>,
+}
@@ -0,0 +1,373 @@
+use alloc::collections::BTreeMap;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use pin_project::pin_project;
+
+use crate::Side;
+
+/// A future along with the index of the item it was started for, so its
+/// output can be put back in the order of the underlying stream
+#[pin_project]
+struct Sequenced<F> {
+    #[pin]
+    future: F,
+    index: u64,
+}
+
+impl<F> Future for Sequenced<F>
+where
+    F: Future,
+{
+    type Output = (u64, F::Output);
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let index = *this.index;
+        this.future.poll(cx).map(|output| (index, output))
+    }
+}
+
+/// A stream that runs one of two async handlers on each item of the
+/// underlying stream, depending on the side the predicate puts it on, and
+/// returns their outputs. Created by `process_split`, which returns the
+/// outputs in the order of the underlying stream, and
+/// `process_split_unordered`, which returns them as they complete
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct ProcessSplit<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+    FutT: Future,
+{
+    #[pin]
+    stream: S,
+    predicate: P,
+    f_true: FT,
+    f_false: FF,
+    running_true: FuturesUnordered<Sequenced<FutT>>,
+    running_false: FuturesUnordered<Sequenced<FutF>>,
+    limit_true: usize,
+    limit_false: usize,
+    /// How many items of each side take up a slot, which they do from when
+    /// their handler is started until their output is returned
+    in_flight_true: usize,
+    in_flight_false: usize,
+    /// An item that was pulled for a side without a free slot, along with its
+    /// side. Nothing else is pulled until its handler has been started
+    held: Option<(S::Item, Side)>,
+    /// The outputs that completed before the outputs of the items that came
+    /// before them, by the index of their item
+    finished: BTreeMap<u64, (FutT::Output, Side)>,
+    /// The index of the next item pulled from the underlying stream
+    next_index: u64,
+    /// The index of the item whose output is returned next
+    next_output: u64,
+    ordered: bool,
+    done: bool,
+}
+
+impl<S, P, FT, FF, FutT, FutF> ProcessSplit<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+    FutT: Future,
+{
+    pub(crate) fn new(
+        stream: S,
+        predicate: P,
+        f_true: FT,
+        f_false: FF,
+        limit_true: usize,
+        limit_false: usize,
+        ordered: bool,
+    ) -> Self {
+        assert!(
+            limit_true > 0 && limit_false > 0,
+            "each side has to be able to run at least one handler"
+        );
+        Self {
+            stream,
+            predicate,
+            f_true,
+            f_false,
+            running_true: FuturesUnordered::new(),
+            running_false: FuturesUnordered::new(),
+            limit_true,
+            limit_false,
+            in_flight_true: 0,
+            in_flight_false: 0,
+            held: None,
+            finished: BTreeMap::new(),
+            next_index: 0,
+            next_output: 0,
+            ordered,
+            done: false,
+        }
+    }
+
+    /// How many items are being handled or waiting for their output to be
+    /// returned
+    pub fn in_flight(&self) -> usize {
+        self.in_flight_true + self.in_flight_false
+    }
+}
+
+impl<S, P, FT, FF, FutT, FutF> Stream for ProcessSplit<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+    FT: FnMut(S::Item) -> FutT,
+    FF: FnMut(S::Item) -> FutF,
+    FutT: Future,
+    FutF: Future<Output = FutT::Output>,
+{
+    type Item = FutT::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        // Starts the handlers of the held item and the items after it as long as their side has
+        // a free slot
+        loop {
+            if let Some((item, side)) = this.held.take() {
+                let index = *this.next_index - 1;
+                match side {
+                    Side::True if *this.in_flight_true < *this.limit_true => {
+                        *this.in_flight_true += 1;
+                        let future = (this.f_true)(item);
+                        this.running_true.push(Sequenced { future, index });
+                    }
+                    Side::False if *this.in_flight_false < *this.limit_false => {
+                        *this.in_flight_false += 1;
+                        let future = (this.f_false)(item);
+                        this.running_false.push(Sequenced { future, index });
+                    }
+                    _ => {
+                        *this.held = Some((item, side));
+                        break;
+                    }
+                }
+            }
+            if *this.done {
+                break;
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let side = Side::from((this.predicate)(&item));
+                    *this.next_index += 1;
+                    *this.held = Some((item, side));
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    break;
+                }
+                Poll::Pending => break,
+            }
+        }
+        // Polls both sides before returning anything, so the handlers of both can wake this
+        // stream
+        while let Poll::Ready(Some((index, output))) = this.running_true.poll_next_unpin(cx) {
+            this.finished.insert(index, (output, Side::True));
+        }
+        while let Poll::Ready(Some((index, output))) = this.running_false.poll_next_unpin(cx) {
+            this.finished.insert(index, (output, Side::False));
+        }
+        let next = if *this.ordered {
+            let next = this.finished.remove(this.next_output);
+            if next.is_some() {
+                *this.next_output += 1;
+            }
+            next
+        } else {
+            this.finished.pop_first().map(|(_, next)| next)
+        };
+        match next {
+            Some((output, side)) => {
+                match side {
+                    Side::True => *this.in_flight_true -= 1,
+                    Side::False => *this.in_flight_false -= 1,
+                }
+                Poll::Ready(Some(output))
+            }
+            None if *this.done
+                && this.held.is_none()
+                && this.running_true.is_empty()
+                && this.running_false.is_empty() =>
+            {
+                Poll::Ready(None)
+            }
+            // Whatever is returned next is the output of a running handler, which wakes this
+            // stream when it's done, or of an item that hasn't been pulled yet
+            None => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.in_flight() + usize::from(self.held.is_some());
+        let (lower, upper) = self.stream.size_hint();
+        (
+            lower.saturating_add(pending),
+            upper.and_then(|upper| upper.checked_add(pending)),
+        )
+    }
+}
+
+impl<S, P, FT, FF, FutT, FutF> FusedStream for ProcessSplit<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+    FT: FnMut(S::Item) -> FutT,
+    FF: FnMut(S::Item) -> FutF,
+    FutT: Future,
+    FutF: Future<Output = FutT::Output>,
+{
+    fn is_terminated(&self) -> bool {
+        self.done && self.held.is_none() && self.in_flight() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Poll,
+        time::Duration,
+    };
+
+    use futures::{channel::oneshot, FutureExt, StreamExt};
+
+    use crate::{
+        testing::{poll_once, CountingWaker},
+        SplitStreamByExt,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_source_order() {
+        // The later items of each side finish first
+        let outputs = futures::stream::iter(0..20u64)
+            .process_split(
+                |n| n % 3 == 0,
+                |n| async move {
+                    tokio::time::sleep(Duration::from_millis(40 - n)).await;
+                    n * 10
+                },
+                |n| async move {
+                    tokio::time::sleep(Duration::from_millis(20 - n)).await;
+                    n
+                },
+                4,
+                4,
+            )
+            .collect::<Vec<_>>()
+            .await;
+        let expected = (0..20)
+            .map(|n| if n % 3 == 0 { n * 10 } else { n })
+            .collect::<Vec<_>>();
+        assert_eq!(expected, outputs);
+    }
+
+    #[test]
+    fn test_head_of_line_blocking() {
+        let (finish, finished) = oneshot::channel::<()>();
+        let mut finished = Some(finished);
+        let started = Arc::new(AtomicUsize::new(0));
+        let mut outputs = futures::stream::iter(0..6).process_split(
+            |&n| n == 0,
+            |n| {
+                let finished = finished.take().expect("only the first item is slow");
+                async move {
+                    finished.await.unwrap();
+                    n
+                }
+                .boxed()
+            },
+            {
+                let started = started.clone();
+                move |n| {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    async move { n }.boxed()
+                }
+            },
+            1,
+            2,
+        );
+        let waker = CountingWaker::new();
+        // The outputs of 1 and 2 are held back by the slow handler of 0, and they keep the slots
+        // of their side until they're returned, so 3 isn't started
+        assert_eq!(Poll::Pending, poll_once(&mut outputs, &waker.waker()));
+        assert_eq!(2, started.load(Ordering::SeqCst));
+        assert_eq!(3, outputs.in_flight());
+        finish.send(()).unwrap();
+        assert!(waker.count() > 0);
+        let rest = futures::executor::block_on(outputs.collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], rest);
+        assert_eq!(5, started.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_unordered_isnt_blocked() {
+        let (finish, finished) = oneshot::channel::<()>();
+        let mut finished = Some(finished);
+        let mut outputs = futures::stream::iter(0..6).process_split_unordered(
+            |&n| n == 0,
+            |n| {
+                let finished = finished.take().expect("only the first item is slow");
+                async move {
+                    finished.await.unwrap();
+                    n
+                }
+                .boxed()
+            },
+            |n| async move { n }.boxed(),
+            1,
+            2,
+        );
+        let waker = CountingWaker::new();
+        for n in 1..6 {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut outputs, &waker.waker())
+            );
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut outputs, &waker.waker()));
+        finish.send(()).unwrap();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut outputs, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut outputs, &waker.waker()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_limits() {
+        let running = [Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+        let max = [Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+        let handler = |side: usize| {
+            let (running, max) = (running[side].clone(), max[side].clone());
+            move |n: u64| {
+                let (running, max) = (running.clone(), max.clone());
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    n
+                }
+            }
+        };
+        // The items of each side come in a row, so a full side doesn't keep the other one from
+        // reaching its limit
+        let outputs = futures::stream::iter(0..30)
+            .process_split_unordered(|&n| n >= 20, handler(0), handler(1), 3, 5)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(30, outputs.len());
+        assert_eq!(3, max[0].load(Ordering::SeqCst));
+        assert_eq!(5, max[1].load(Ordering::SeqCst));
+    }
+}
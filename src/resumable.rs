@@ -0,0 +1,294 @@
+use alloc::boxed::Box;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    ring::RingBuf, split_by_buffered, ByPredicate, FalseSplitByBuffered, Split, SplitByBuffered,
+    TrueSplitByBuffered,
+};
+
+/// A stream that starts over with a new stream from a factory each time the
+/// current one ends. Used by `split_by_resumable`, where the split pulls from
+/// it
+pub struct Resumable<F, Fut, S> {
+    factory: F,
+    stream: Option<S>,
+    /// The future of the factory that's making the next stream. Boxed, since
+    /// the future of an `async` block can't be moved once it's polled
+    next: Option<Pin<Box<Fut>>>,
+    /// How many streams the factory has made
+    streams: usize,
+    done: bool,
+}
+
+impl<F, Fut, S> Resumable<F, Fut, S> {
+    pub(crate) fn new(factory: F) -> Self {
+        Self {
+            factory,
+            stream: None,
+            next: None,
+            streams: 0,
+            done: false,
+        }
+    }
+
+    /// How many streams the factory has made so far, including the current
+    /// one
+    pub fn streams(&self) -> usize {
+        self.streams
+    }
+}
+
+impl<F, Fut, S> Stream for Resumable<F, Fut, S>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<S>>,
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if let Some(stream) = &mut this.stream {
+                match Pin::new(stream).poll_next(cx) {
+                    Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                    Poll::Ready(None) => this.stream = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            // The factory is called once per stream, and its future is kept until it resolves
+            let factory = &mut this.factory;
+            let next = this.next.get_or_insert_with(|| Box::pin(factory()));
+            match next.as_mut().poll(cx) {
+                Poll::Ready(Some(stream)) => {
+                    this.next = None;
+                    this.stream = Some(stream);
+                    this.streams += 1;
+                }
+                Poll::Ready(None) => {
+                    this.next = None;
+                    this.done = true;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (&self.stream, self.done) {
+            (_, true) => (0, Some(0)),
+            // The factory might make any number of streams after this one
+            (Some(stream), false) => (stream.size_hint().0, None),
+            (None, false) => (0, None),
+        }
+    }
+}
+
+impl<F, Fut, S> FusedStream for Resumable<F, Fut, S>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<S>>,
+    S: Stream + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+impl<F, Fut, S> Unpin for Resumable<F, Fut, S> {}
+
+/// Splits the items of a stream that has to be started over whenever it
+/// ends, like a subscription that has to be re-established, by a predicate
+/// like `split_by_buffered`. `factory` is called for the first stream and
+/// again each time the current one ends, by whichever stream of the split
+/// is pulling at that point, and its future is polled like the underlying
+/// stream would be. The split, along with what's buffered for either stream,
+/// carries on with the stream it resolves to, so the two streams see the
+/// items of all of them as one sequence. They only end once the future
+/// resolves to `None`
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{split_by_resumable, Split};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let mut sessions = vec![vec![4, 5], vec![0, 1, 2, 3]];
+///     let Split { matches: evens, rest: odds } = split_by_resumable::<_, _, _, _, 4>(
+///         move || {
+///             let session = sessions.pop().map(futures::stream::iter);
+///             async move { session }
+///         },
+///         |&n| n % 2 == 0,
+///     );
+///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3, 5], odds);
+/// })
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn split_by_resumable<F, Fut, S, P, const N: usize>(
+    factory: F,
+    predicate: P,
+) -> Split<
+    TrueSplitByBuffered<S::Item, Resumable<F, Fut, S>, P, N>,
+    FalseSplitByBuffered<S::Item, Resumable<F, Fut, S>, P, N>,
+>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<S>>,
+    S: Stream + Unpin,
+    P: Fn(&S::Item) -> bool,
+{
+    let stream = SplitByBuffered::new(
+        Resumable::new(factory),
+        ByPredicate(predicate),
+        RingBuf::new(),
+        RingBuf::new(),
+        "split_by_resumable",
+        split_by_buffered::STREAMS,
+    );
+    Split::share(stream, TrueSplitByBuffered::new, FalseSplitByBuffered::new)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Poll,
+    };
+
+    use futures::{channel::oneshot, StreamExt};
+
+    use super::*;
+    use crate::testing::{poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream};
+
+    #[tokio::test]
+    async fn test_streams_continue_one_another() {
+        let mut streams = vec![
+            futures::stream::iter(vec![10, 11, 12]),
+            futures::stream::iter(vec![]),
+            futures::stream::iter(vec![0, 1, 2, 3]),
+        ];
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (small, big) = split_by_resumable::<_, _, _, _, 4>(
+            {
+                let calls = calls.clone();
+                move || {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    let stream = streams.pop();
+                    async move { stream }
+                }
+            },
+            |&n| n < 10,
+        )
+        .into_tuple();
+        let (small, big) = futures::join!(small.collect::<Vec<_>>(), big.collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 2, 3], small);
+        assert_eq!(vec![10, 11, 12], big);
+        // Once for each stream, and once more for the `None` that ended the split
+        assert_eq!(4, calls.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_buffered_items_kept_while_factory_pending() {
+        let (first, first_handle) = ManualStream::new();
+        let (second, second_handle) = ManualStream::new();
+        let (sender, receiver) = oneshot::channel();
+        let mut streams = vec![Some(first)].into_iter();
+        let mut receiver = Some(receiver);
+        let (mut evens, mut odds) = split_by_resumable::<_, _, _, _, 4>(
+            move || {
+                let stream = streams.next().flatten();
+                // The next stream takes a while to set up
+                let receiver = if stream.is_none() {
+                    receiver.take()
+                } else {
+                    None
+                };
+                async move {
+                    match stream {
+                        Some(stream) => Some(stream),
+                        None => receiver?.await.ok(),
+                    }
+                }
+            },
+            |&n: &i32| n % 2 == 0,
+        )
+        .into_tuple();
+        let (even_waker, odd_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [1, 3, 0] {
+            first_handle.push(n);
+        }
+        first_handle.end();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut evens, &even_waker.waker(), 3)
+        );
+        // The first stream ended, and the odd items it had are still buffered while the factory
+        // makes the next one
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert!(sender.send(second).is_ok());
+        assert_eq!(1, even_waker.count());
+        second_handle.push(5);
+        second_handle.push(2);
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(5)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        second_handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &odd_waker.waker()));
+    }
+
+    #[test]
+    fn test_counts_streams() {
+        let mut streams = vec![
+            futures::stream::iter(vec![1]),
+            futures::stream::iter(vec![0]),
+        ];
+        let mut resumable = Resumable::new(move || {
+            let stream = streams.pop();
+            async move { stream }
+        });
+        let waker = CountingWaker::new();
+        assert_eq!(0, resumable.streams());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut resumable, &waker.waker())
+        );
+        assert_eq!(1, resumable.streams());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut resumable, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut resumable, &waker.waker()));
+        assert_eq!(2, resumable.streams());
+        assert!(resumable.is_terminated());
+    }
+}
@@ -0,0 +1,22 @@
+use core::{error::Error, fmt};
+
+/// Error returned by `reunite` when the two streams don't come from the same
+/// split. Both streams are given back unchanged
+pub struct ReuniteError<T, F>(pub T, pub F);
+
+impl<T, F> fmt::Debug for ReuniteError<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReuniteError").field(&"..").finish()
+    }
+}
+
+impl<T, F> fmt::Display for ReuniteError<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tried to reunite two streams that don't come from the same split"
+        )
+    }
+}
+
+impl<T, F> Error for ReuniteError<T, F> {}
@@ -0,0 +1,169 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::{stream::Fuse, StreamExt};
+use pin_project::pin_project;
+
+/// Merges the two streams of an enumerated split back into one stream, in
+/// the order of the original stream. Created by `reunite_ordered`
+#[pin_project]
+pub struct ReuniteOrdered<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    #[pin]
+    a: Fuse<A>,
+    #[pin]
+    b: Fuse<B>,
+    head_a: Option<A::Item>,
+    head_b: Option<B::Item>,
+    next_index: u64,
+}
+
+/// Merges the two streams of a split made with `SplitBuilder::enumerated`
+/// back into a single stream of items in the order they came out of the
+/// original stream, with the indices removed. The streams can be transformed
+/// in between, as long as each item keeps its index and every item makes it
+/// through, like with `map_each`. An item is only returned once all the items
+/// before it have been, so at most one item per stream is held back. When one
+/// of the streams ends early, the items of the other one are returned as they
+/// come
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{reunite_ordered, SplitBuilder};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let split = SplitBuilder::new(futures::stream::iter([0,1,2,3,4,5]))
+///         .enumerated()
+///         .predicate(|(_, n)| n % 2 == 0)
+///         .build()
+///         .map_each(|(i, n)| (i, n * 10), |(i, n)| (i, -n));
+///     let merged = reunite_ordered(split.matches, split.rest);
+///     assert_eq!(vec![0,-1,20,-3,40,-5], merged.collect::<Vec<_>>().await);
+/// })
+/// ```
+pub fn reunite_ordered<A, B, T>(a: A, b: B) -> ReuniteOrdered<A, B>
+where
+    A: Stream<Item = (u64, T)>,
+    B: Stream<Item = (u64, T)>,
+{
+    ReuniteOrdered {
+        a: a.fuse(),
+        b: b.fuse(),
+        head_a: None,
+        head_b: None,
+        next_index: 0,
+    }
+}
+
+impl<A, B, T> Stream for ReuniteOrdered<A, B>
+where
+    A: Stream<Item = (u64, T)>,
+    B: Stream<Item = (u64, T)>,
+{
+    type Item = T;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut this = self.project();
+        // Only pull from a stream once its previous item has been returned, so
+        // a stream that's ahead is held back
+        if this.head_a.is_none() {
+            if let Poll::Ready(item) = this.a.as_mut().poll_next(cx) {
+                *this.head_a = item;
+            }
+        }
+        if this.head_b.is_none() {
+            if let Poll::Ready(item) = this.b.as_mut().poll_next(cx) {
+                *this.head_b = item;
+            }
+        }
+        let next_index = *this.next_index;
+        let take_a = match (&*this.head_a, &*this.head_b) {
+            (Some((index_a, _)), Some((index_b, _))) => index_a < index_b,
+            // Without the next item of the other stream, it's only known that this item is next
+            // if it has the next index or the other stream has ended
+            (Some((index, _)), None) if *index == next_index || this.b.is_done() => true,
+            (None, Some((index, _))) if *index == next_index || this.a.is_done() => false,
+            (None, None) if this.a.is_done() && this.b.is_done() => return Poll::Ready(None),
+            _ => return Poll::Pending,
+        };
+        let head = if take_a {
+            this.head_a.take()
+        } else {
+            this.head_b.take()
+        };
+        let (index, item) = head.expect("the chosen stream has an item");
+        *this.next_index = index + 1;
+        Poll::Ready(Some(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let held = usize::from(self.head_a.is_some()) + usize::from(self.head_b.is_some());
+        let (lower_a, upper_a) = self.a.size_hint();
+        let (lower_b, upper_b) = self.b.size_hint();
+        let lower = lower_a.saturating_add(lower_b).saturating_add(held);
+        let upper = upper_a
+            .zip(upper_b)
+            .and_then(|(a, b)| a.checked_add(b))
+            .and_then(|upper| upper.checked_add(held));
+        (lower, upper)
+    }
+}
+
+impl<A, B, T> FusedStream for ReuniteOrdered<A, B>
+where
+    A: Stream<Item = (u64, T)>,
+    B: Stream<Item = (u64, T)>,
+{
+    fn is_terminated(&self) -> bool {
+        self.a.is_done() && self.b.is_done() && self.head_a.is_none() && self.head_b.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::SplitBuilder;
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        // A small linear congruential generator, so the cases are random but reproducible
+        let mut seed = 0x2545_f491_u64;
+        let mut random = move || {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) as u32
+        };
+        for _ in 0..50 {
+            let len = random() % 40;
+            let items = (0..len).map(|_| random() % 100).collect::<Vec<_>>();
+            let modulus = random() % 5 + 1;
+            let buffer = (random() % 4 + 1) as usize;
+            let split = SplitBuilder::new(futures::stream::iter(items.clone()))
+                .enumerated()
+                .predicate(move |(_, n)| n % modulus == 0)
+                .buffer(buffer)
+                .build();
+            let merged = reunite_ordered(split.matches, split.rest)
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(items, merged);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_side_ends_early() {
+        let a = futures::stream::iter([(0, 'a'), (3, 'd')]);
+        let b = futures::stream::iter([(1, 'b'), (4, 'e'), (5, 'f')]);
+        // The item with index 2 never arrives, but once `a` ends the rest of `b` isn't held back
+        let merged = reunite_ordered(a, b).collect::<String>().await;
+        assert_eq!("abdef", merged);
+    }
+}
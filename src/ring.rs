@@ -0,0 +1,258 @@
+//! A fixed-capacity queue, the one behind the buffered splits. `RingBuf<T,
+//! N>` holds up to `N` items inline and never allocates, which makes it a
+//! cheap single-producer, single-consumer queue for code that owns it, or
+//! guards it with a lock, from both ends.
+//!
+//! By default it stores items in an array of `MaybeUninit`, which takes
+//! `unsafe` code. With the `safe-buffer` feature, it stores them in an array
+//! of `Option` instead, so the crate has no `unsafe` code at all. Both
+//! behave the same
+//!
+//!```rust
+//! use split_stream_by::ring::RingBuf;
+//!
+//! let mut ring = RingBuf::<_, 2>::new();
+//! assert_eq!(None, ring.push_back(1));
+//! assert_eq!(None, ring.push_back(2));
+//! // It's full, so the item is handed back
+//! assert_eq!(Some(3), ring.push_back(3));
+//! assert_eq!(Some(1), ring.pop_front());
+//! assert_eq!(None, ring.push_back(3));
+//! assert_eq!(vec![&2, &3], ring.iter().collect::<Vec<_>>());
+//! ```
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "safe-buffer")]
+mod safe;
+#[cfg(not(feature = "safe-buffer"))]
+mod uninit;
+
+#[cfg(feature = "safe-buffer")]
+pub use safe::RingBuf;
+#[cfg(not(feature = "safe-buffer"))]
+pub use uninit::RingBuf;
+
+impl<T, const N: usize> RingBuf<T, N> {
+    /// The number of items the buffer can hold
+    pub const CAPACITY: usize = N;
+
+    /// The item at the front of the buffer, which `pop_front` returns next
+    pub fn front(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    /// The item at the back of the buffer, which `push_back` added last
+    pub fn back(&self) -> Option<&T> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Whether the buffer holds no items
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the buffer holds `N` items, so `push_back` and `push_front`
+    /// hand their item back
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Takes all the items out of the buffer, front first
+    pub fn into_vec(mut self) -> Vec<T> {
+        let mut items = Vec::with_capacity(self.len());
+        while let Some(item) = self.pop_front() {
+            items.push(item);
+        }
+        items
+    }
+}
+
+impl<T, const N: usize> Default for RingBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for RingBuf<T, N>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn test_buf_1() {
+        let mut buf = RingBuf::<_, 1>::new();
+        assert!(buf.push_back(1).is_none());
+        assert!(buf.push_back(2).is_some());
+        assert_eq!(buf.pop_front(), Some(1));
+    }
+
+    #[test]
+    fn test_buf_3() {
+        let mut buf = RingBuf::<_, 3>::new();
+        assert!(buf.push_back(1).is_none());
+        assert!(buf.push_back(2).is_none());
+        assert!(buf.push_back(3).is_none());
+        assert!(buf.push_back(4).is_some());
+        assert_eq!(buf.pop_front(), Some(1));
+        assert_eq!(buf.pop_front(), Some(2));
+        assert_eq!(buf.pop_front(), Some(3));
+        assert_eq!(buf.pop_front(), None);
+    }
+
+    #[test]
+    fn test_push_front() {
+        let mut buf = RingBuf::<_, 3>::new();
+        assert!(buf.push_back(2).is_none());
+        assert!(buf.push_front(1).is_none());
+        assert!(buf.push_back(3).is_none());
+        assert!(buf.push_front(0).is_some());
+        assert_eq!(vec![&1, &2, &3], buf.iter().collect::<Vec<_>>());
+        assert_eq!(buf.pop_front(), Some(1));
+        assert_eq!(buf.into_vec(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_iter_wraps() {
+        let mut buf = RingBuf::<_, 3>::new();
+        assert!(buf.push_back(1).is_none());
+        assert!(buf.push_back(2).is_none());
+        assert_eq!(buf.pop_front(), Some(1));
+        assert!(buf.push_back(3).is_none());
+        assert!(buf.push_back(4).is_none());
+        assert_eq!(vec![&2, &3, &4], buf.iter().collect::<Vec<_>>());
+        assert_eq!(Some(&4), buf.get(2));
+        assert_eq!(None, buf.get(3));
+        assert_eq!(Some(&4), buf.back());
+    }
+
+    #[test]
+    fn test_drops_items() {
+        let item = Rc::new(());
+        let mut buf = RingBuf::<_, 3>::new();
+        assert!(buf.push_back(item.clone()).is_none());
+        assert!(buf.push_front(item.clone()).is_none());
+        assert_eq!(3, Rc::strong_count(&item));
+        drop(buf);
+        assert_eq!(1, Rc::strong_count(&item));
+    }
+
+    #[test]
+    fn test_wraps_many_times() {
+        let mut buf = RingBuf::<_, 4>::new();
+        let mut next = 0;
+        let mut expected = 0;
+        // Pushing three and popping two at a time moves the start around the array over and over
+        for _ in 0..50 {
+            while !buf.is_full() && next - expected < 3 {
+                assert!(buf.push_back(next).is_none());
+                next += 1;
+            }
+            for _ in 0..2 {
+                assert_eq!(Some(&expected), buf.front());
+                assert_eq!(Some(expected), buf.pop_front());
+                expected += 1;
+            }
+            assert_eq!(next - expected, buf.len());
+            assert_eq!(
+                (expected..next).collect::<Vec<_>>(),
+                buf.iter().copied().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_full_and_empty() {
+        let mut buf = RingBuf::<_, 2>::default();
+        assert!(buf.is_empty() && !buf.is_full());
+        assert_eq!((0, 2), (buf.len(), buf.remaining()));
+        assert_eq!(None, buf.front());
+        assert_eq!(None, buf.back());
+        assert_eq!(None, buf.pop_front());
+        assert!(buf.push_back(1).is_none());
+        assert!(!buf.is_empty() && !buf.is_full());
+        assert!(buf.push_front(0).is_none());
+        assert!(buf.is_full());
+        assert_eq!((2, 0), (buf.len(), buf.remaining()));
+        assert_eq!(Some(2), buf.push_back(2));
+        assert_eq!(Some(-1), buf.push_front(-1));
+        assert_eq!("[0, 1]", format!("{:?}", buf));
+        assert_eq!(Some(0), buf.pop_front());
+        assert_eq!(Some(1), buf.pop_front());
+        assert!(buf.is_empty());
+        assert_eq!(None, buf.pop_front());
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut buf = RingBuf::<i32, 0>::new();
+        assert!(buf.is_empty() && buf.is_full());
+        assert_eq!(Some(1), buf.push_back(1));
+        assert_eq!(Some(1), buf.push_front(1));
+        assert_eq!(None, buf.pop_front());
+        assert_eq!(0, buf.iter().count());
+        assert!(buf.into_vec().is_empty());
+    }
+
+    #[test]
+    fn test_zero_sized_items() {
+        let mut buf = RingBuf::<(), 3>::new();
+        for _ in 0..3 {
+            assert!(buf.push_back(()).is_none());
+        }
+        assert_eq!(Some(()), buf.push_back(()));
+        assert_eq!(3, buf.iter().count());
+        assert_eq!(Some(()), buf.pop_front());
+        assert!(buf.push_front(()).is_none());
+        assert_eq!(vec![(); 3], buf.into_vec());
+    }
+
+    /// Counts how many times it's dropped
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_drop_counts() {
+        let drops = Cell::new(0);
+        let mut buf = RingBuf::<_, 4>::new();
+        for _ in 0..4 {
+            assert!(buf.push_back(DropCounter(&drops)).is_none());
+        }
+        // A rejected item is handed back rather than dropped by the buffer
+        let rejected = buf.push_back(DropCounter(&drops));
+        assert_eq!(0, drops.get());
+        drop(rejected);
+        assert_eq!(1, drops.get());
+        drop(buf.pop_front());
+        drop(buf.pop_front());
+        assert_eq!(3, drops.get());
+        // The two left wrap around the end of the array
+        assert!(buf.push_back(DropCounter(&drops)).is_none());
+        drop(buf);
+        assert_eq!(6, drops.get());
+
+        let drops = Cell::new(0);
+        let mut buf = RingBuf::<_, 2>::new();
+        assert!(buf.push_back(DropCounter(&drops)).is_none());
+        let items = buf.into_vec();
+        // Taking the items out doesn't drop them
+        assert_eq!(0, drops.get());
+        drop(items);
+        assert_eq!(1, drops.get());
+    }
+}
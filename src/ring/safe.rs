@@ -0,0 +1,84 @@
+/// The `RingBuf` used with the `safe-buffer` feature. Each slot is an
+/// `Option`, so the buffer needs no `unsafe` code and still never allocates,
+/// at the cost of a discriminant per slot and a check on every read
+pub struct RingBuf<T, const N: usize> {
+    index: usize,
+    count: usize,
+    data: [Option<T>; N],
+}
+
+impl<T, const N: usize> RingBuf<T, N> {
+    /// Creates an empty buffer
+    pub fn new() -> Self {
+        Self {
+            index: 0,
+            count: 0,
+            data: [(); N].map(|_| None),
+        }
+    }
+
+    /// The number of items in the buffer
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The item `index` places from the front of the buffer
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.count {
+            self.data[(self.index + index) % N].as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the items in the buffer, front first
+    pub fn iter(&self) -> impl Iterator<Item = &T> + Clone {
+        (0..self.count).map(move |offset| {
+            self.data[(self.index + offset) % N]
+                .as_ref()
+                .expect("the `count` slots starting at `index` hold values")
+        })
+    }
+
+    /// How many more items fit in the buffer
+    pub fn remaining(&self) -> usize {
+        N - self.count
+    }
+
+    /// Adds an item to the back of the buffer, or hands it back if the
+    /// buffer is full
+    pub fn push_back(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            self.data[(self.index + self.count) % N] = Some(item);
+            self.count += 1;
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Adds an item to the front of the buffer, so it's the next one
+    /// `pop_front` returns, or hands it back if the buffer is full
+    pub fn push_front(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            self.index = (self.index + N - 1) % N;
+            self.data[self.index] = Some(item);
+            self.count += 1;
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Takes the item at the front out of the buffer
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.count > 0 {
+            let item = self.data[self.index].take();
+            self.index = (self.index + 1) % N;
+            self.count -= 1;
+            item
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use core::mem::MaybeUninit;
+
+/// The default `RingBuf`, which stores items in an array of `MaybeUninit`
+/// so empty slots take no space beyond the item itself
+pub struct RingBuf<T, const N: usize> {
+    index: usize,
+    count: usize,
+    data: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> RingBuf<T, N> {
+    /// Creates an empty buffer
+    pub fn new() -> Self {
+        Self {
+            index: 0,
+            count: 0,
+            // From rust docs,  The `assume_init` is
+            // safe because the type we are claiming to have initialized here is a
+            // bunch of `MaybeUninit`s, which do not require initialization.
+            data: unsafe { MaybeUninit::uninit().assume_init() },
+        }
+    }
+
+    /// The number of items in the buffer
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The item `index` places from the front of the buffer
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.count {
+            let ptr = self.data[(self.index + index) % N].as_ptr();
+            // This is safe because the `count` slots starting at `index` hold values
+            Some(unsafe { &*ptr })
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the items in the buffer, front first
+    pub fn iter(&self) -> impl Iterator<Item = &T> + Clone {
+        (0..self.count).map(move |offset| {
+            let ptr = self.data[(self.index + offset) % N].as_ptr();
+            // This is safe because the `count` slots starting at `index` hold values
+            unsafe { &*ptr }
+        })
+    }
+
+    /// How many more items fit in the buffer
+    pub fn remaining(&self) -> usize {
+        N - self.count
+    }
+
+    /// Adds an item to the back of the buffer, or hands it back if the
+    /// buffer is full
+    pub fn push_back(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            let ptr = self.data[(self.index + self.count) % N].as_mut_ptr();
+            // This is safe because there is space available so self.data[self.tail] points
+            // to is ununsed
+            unsafe { ptr.write(item) };
+            self.count += 1;
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Adds an item to the front of the buffer, so it's the next one
+    /// `pop_front` returns, or hands it back if the buffer is full
+    pub fn push_front(&mut self, item: T) -> Option<T> {
+        if self.remaining() > 0 {
+            self.index = (self.index + N - 1) % N;
+            let ptr = self.data[self.index].as_mut_ptr();
+            // This is safe because there is space available so the slot before the first item
+            // is unused
+            unsafe { ptr.write(item) };
+            self.count += 1;
+            None
+        } else {
+            Some(item)
+        }
+    }
+
+    /// Takes the item at the front out of the buffer
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.count > 0 {
+            let ptr = self.data[self.index].as_mut_ptr();
+            // This is safe because there are items in the buffer so self.data[self.head]
+            // points to a value
+            let item = unsafe { ptr.read() };
+            self.index = (self.index + 1) % N;
+            self.count -= 1;
+            Some(item)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuf<T, N> {
+    fn drop(&mut self) {
+        // pop_front reads values from MaybeUninit which will then run its drop code
+        while self.pop_front().is_some() {}
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    bounded_buf::BoundedBuf,
+    sync::{Mutex, MutexGuard},
+    Side,
+};
+
+struct ObserverState {
+    sides: BoundedBuf<Side>,
+    dropped: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+fn lock(state: &Mutex<ObserverState>) -> MutexGuard<'_, ObserverState> {
+    state.lock()
+}
+
+/// The end of a routing observer that the split feeds. It has its own lock,
+/// which is never held while the state of the split is being locked, so the
+/// observer can't hold up either stream of the split
+pub(crate) struct RoutingSender {
+    state: Arc<Mutex<ObserverState>>,
+}
+
+impl RoutingSender {
+    /// Records where an item was routed, or counts it as dropped if the
+    /// observer has fallen behind
+    pub(crate) fn send(&self, side: Side) {
+        let waker = {
+            let mut state = lock(&self.state);
+            if state.sides.push_back(side).is_some() {
+                state.dropped += 1;
+                return;
+            }
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Ends the observer once it has returned everything that was sent
+    pub(crate) fn close(&self) {
+        let waker = {
+            let mut state = lock(&self.state);
+            state.closed = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for RoutingSender {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// A stream of the side each item of a split was routed to, in the order the
+/// items were pulled from the underlying stream. Created by
+/// `SplitBuilder::with_routing_observer`. It only buffers a limited number of
+/// sides, and when it falls behind any further sides are dropped instead of
+/// holding up the split. `dropped` tells how many were. It ends after the
+/// underlying stream has ended, or the split has been dropped
+pub struct RoutingObserver {
+    state: Arc<Mutex<ObserverState>>,
+    terminated: bool,
+}
+
+impl RoutingObserver {
+    /// The number of sides that were dropped because the observer's buffer
+    /// was full
+    pub fn dropped(&self) -> u64 {
+        lock(&self.state).dropped
+    }
+}
+
+/// Creates an observer that buffers up to `capacity` sides, along with the
+/// end the split uses to feed it
+pub(crate) fn routing_observer(capacity: usize) -> (RoutingSender, RoutingObserver) {
+    let state = Arc::new(Mutex::new(ObserverState {
+        sides: BoundedBuf::new(capacity),
+        dropped: 0,
+        closed: false,
+        waker: None,
+    }));
+    (
+        RoutingSender {
+            state: state.clone(),
+        },
+        RoutingObserver {
+            state,
+            terminated: false,
+        },
+    )
+}
+
+impl Stream for RoutingObserver {
+    type Item = Side;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Side>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let mut state = lock(&self.state);
+        if let Some(side) = state.sides.pop_front() {
+            return Poll::Ready(Some(side));
+        }
+        if !state.closed {
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        drop(state);
+        self.terminated = true;
+        Poll::Ready(None)
+    }
+}
+
+impl FusedStream for RoutingObserver {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl fmt::Debug for RoutingObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = lock(&self.state);
+        f.debug_struct("RoutingObserver")
+            .field("buffered", &state.sides.len())
+            .field("capacity", &state.sides.capacity())
+            .field("dropped", &state.dropped)
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
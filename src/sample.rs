@@ -0,0 +1,185 @@
+use alloc::boxed::Box;
+
+use futures_core::Stream;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    ring::RingBuf, split_by_buffered, sync::Mutex, ByPredicate, FalseSplitByBuffered, Split,
+    SplitByBuffered, TrueSplitByBuffered, DEFAULT_BUFFER_SIZE,
+};
+
+/// The predicate of a split made by `split_randomly`, which ignores the item
+/// and draws from a random number generator instead
+pub type Sampler<I> = Box<dyn Fn(&I) -> bool + Send + Sync>;
+
+/// A stream of a split made by `split_randomly`
+pub type TrueSplitRandomly<I, S> = TrueSplitByBuffered<I, S, Sampler<I>, DEFAULT_BUFFER_SIZE>;
+
+/// A stream of a split made by `split_randomly`
+pub type FalseSplitRandomly<I, S> = FalseSplitByBuffered<I, S, Sampler<I>, DEFAULT_BUFFER_SIZE>;
+
+fn check_probability(p: f64) {
+    assert!(
+        (0.0..=1.0).contains(&p),
+        "the probability of an item matching has to be between 0 and 1, not {}",
+        p
+    );
+}
+
+/// This extension trait provides splits that route each item by chance
+/// rather than by what it is, like for A/B experiments or for sampling
+/// traces. Requires the `rand` feature
+pub trait SplitRandomlyExt: Stream {
+    /// Splits the stream like `split_by_buffered_default`, but each item
+    /// goes to the `matches` stream with probability `p`, drawn from the
+    /// thread-local random number generator of whichever stream pulls it.
+    /// There is exactly one draw per item pulled from the underlying stream,
+    /// however the streams are polled, so the share of items that match
+    /// holds no matter how much is buffered. Panics if `p` isn't between 0
+    /// and 1
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Split, SplitRandomlyExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let Split { matches: sampled, rest } = futures::stream::iter(0..1000).split_randomly(0.1);
+    ///     let (sampled, rest) = futures::join!(sampled.count(), rest.count());
+    ///     assert_eq!(1000, sampled + rest);
+    /// })
+    /// ```
+    fn split_randomly(
+        self,
+        p: f64,
+    ) -> Split<TrueSplitRandomly<Self::Item, Self>, FalseSplitRandomly<Self::Item, Self>>
+    where
+        Self: Sized,
+    {
+        check_probability(p);
+        split_with_sampler(self, Box::new(move |_| rand::rng().random_bool(p)))
+    }
+
+    /// Splits the stream like `split_randomly`, but draws from `rng`
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    /// use split_stream_by::{Split, SplitRandomlyExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let Split { matches: treatment, rest: control } = futures::stream::iter(0..100)
+    ///         .split_randomly_with(StdRng::seed_from_u64(1), 0.5);
+    ///     let (treatment, control) =
+    ///         futures::join!(treatment.collect::<Vec<_>>(), control.collect::<Vec<_>>());
+    ///     assert_eq!(100, treatment.len() + control.len());
+    /// })
+    /// ```
+    fn split_randomly_with<R>(
+        self,
+        rng: R,
+        p: f64,
+    ) -> Split<TrueSplitRandomly<Self::Item, Self>, FalseSplitRandomly<Self::Item, Self>>
+    where
+        R: Rng + Send + 'static,
+        Self: Sized,
+    {
+        check_probability(p);
+        let rng = Mutex::new(rng);
+        split_with_sampler(self, Box::new(move |_| rng.lock().random_bool(p)))
+    }
+
+    /// Splits the stream like `split_randomly`, but draws from a `StdRng`
+    /// seeded with `seed`, so the same items go to the same streams every
+    /// time
+    fn split_randomly_seeded(
+        self,
+        seed: u64,
+        p: f64,
+    ) -> Split<TrueSplitRandomly<Self::Item, Self>, FalseSplitRandomly<Self::Item, Self>>
+    where
+        Self: Sized,
+    {
+        self.split_randomly_with(StdRng::seed_from_u64(seed), p)
+    }
+}
+
+impl<T> SplitRandomlyExt for T where T: Stream + ?Sized {}
+
+fn split_with_sampler<S>(
+    stream: S,
+    sampler: Sampler<S::Item>,
+) -> Split<TrueSplitRandomly<S::Item, S>, FalseSplitRandomly<S::Item, S>>
+where
+    S: Stream,
+{
+    let stream = SplitByBuffered::new(
+        stream,
+        ByPredicate(sampler),
+        RingBuf::new(),
+        RingBuf::new(),
+        "split_randomly",
+        split_by_buffered::STREAMS,
+    );
+    Split::share(stream, TrueSplitByBuffered::new, FalseSplitByBuffered::new)
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{stream::FusedStream, StreamExt};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::SplitRandomlyExt;
+    use crate::testing::{poll_once, CountingWaker};
+
+    #[tokio::test]
+    async fn test_seeded_routing_is_reproducible() {
+        let (sampled, rest) = futures::stream::iter(0..20)
+            .split_randomly_seeded(7, 0.25)
+            .into_tuple();
+        let (sampled, rest) = futures::join!(sampled.collect::<Vec<_>>(), rest.collect::<Vec<_>>());
+        // One draw per item, in the order of the underlying stream
+        let mut rng = StdRng::seed_from_u64(7);
+        let (expected, expected_rest): (Vec<i32>, Vec<i32>) =
+            (0..20).partition(|_| rng.random_bool(0.25));
+        assert_eq!(expected, sampled);
+        assert_eq!(expected_rest, rest);
+        assert_eq!(vec![0, 2, 6, 8, 11, 12], sampled);
+    }
+
+    #[test]
+    fn test_one_draw_per_item_however_polled() {
+        let (mut sampled, mut rest) = futures::stream::iter(0..10_000)
+            .split_randomly_seeded(3, 0.3)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let (mut matched, mut other) = (Vec::new(), Vec::new());
+        let mut polls = 0u32;
+        while !(sampled.is_terminated() && rest.is_terminated()) {
+            // Poll one stream more often than the other, so that items pile up in the buffer
+            polls += 1;
+            if !polls.is_multiple_of(3) {
+                if let Poll::Ready(Some(n)) = poll_once(&mut sampled, &waker.waker()) {
+                    matched.push(n);
+                }
+            } else if let Poll::Ready(Some(n)) = poll_once(&mut rest, &waker.waker()) {
+                other.push(n);
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(3);
+        let expected = (0..10_000)
+            .filter(|_| rng.random_bool(0.3))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, matched);
+        assert_eq!(10_000, matched.len() + other.len());
+        // Roughly 3,000 matches
+        assert!((2_800..3_200).contains(&matched.len()));
+    }
+
+    #[test]
+    #[should_panic(expected = "between 0 and 1")]
+    fn test_invalid_probability() {
+        let _ = futures::stream::iter(0..1).split_randomly(1.5);
+    }
+}
@@ -0,0 +1,178 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::{stream::Fuse, StreamExt};
+use pin_project::pin_project;
+
+use crate::Side;
+
+/// Merges two streams, using a function to pick which item to return first
+/// when both streams have one ready. Created by `select_by`
+#[pin_project]
+pub struct SelectBy<A, B, C>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    #[pin]
+    a: Fuse<A>,
+    #[pin]
+    b: Fuse<B>,
+    head_a: Option<A::Item>,
+    head_b: Option<A::Item>,
+    choose: C,
+}
+
+/// Merges two streams into one, the opposite of splitting a stream. Whenever
+/// both streams have an item ready, `choose` is called with both items and
+/// the item of the returned side is returned first, with `Side::True` meaning
+/// `a` and `Side::False` meaning `b`. The other item is kept and given to
+/// `choose` again, together with the next item of the chosen stream. When
+/// only one stream has an item ready, that item is returned. Both streams are
+/// polled every time, so neither of them is starved. The merged stream ends
+/// once both streams have ended
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{select_by, Side};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let control = futures::stream::iter(["stop", "start"]);
+///     let data = futures::stream::iter(["a", "b"]);
+///     // Prefer control messages over data
+///     let merged = select_by(control, data, |_, _| Side::True);
+///     assert_eq!(vec!["stop", "start", "a", "b"], merged.collect::<Vec<_>>().await);
+/// })
+/// ```
+pub fn select_by<A, B, C>(a: A, b: B, choose: C) -> SelectBy<A, B, C>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+    C: Fn(&A::Item, &B::Item) -> Side,
+{
+    SelectBy {
+        a: a.fuse(),
+        b: b.fuse(),
+        head_a: None,
+        head_b: None,
+        choose,
+    }
+}
+
+impl<A, B, C> SelectBy<A, B, C>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    /// Consumes this combinator, returning the two streams. Items that were
+    /// pulled but not returned yet are dropped
+    pub fn into_inner(self) -> (A, B) {
+        (self.a.into_inner(), self.b.into_inner())
+    }
+}
+
+impl<A, B, C> Stream for SelectBy<A, B, C>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+    C: Fn(&A::Item, &B::Item) -> Side,
+{
+    type Item = A::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<A::Item>> {
+        let mut this = self.project();
+        if this.head_a.is_none() {
+            if let Poll::Ready(item) = this.a.as_mut().poll_next(cx) {
+                *this.head_a = item;
+            }
+        }
+        if this.head_b.is_none() {
+            if let Poll::Ready(item) = this.b.as_mut().poll_next(cx) {
+                *this.head_b = item;
+            }
+        }
+        let side = match (&*this.head_a, &*this.head_b) {
+            (Some(item_a), Some(item_b)) => (this.choose)(item_a, item_b),
+            (Some(_), None) => Side::True,
+            (None, Some(_)) => Side::False,
+            (None, None) if this.a.is_done() && this.b.is_done() => return Poll::Ready(None),
+            (None, None) => return Poll::Pending,
+        };
+        Poll::Ready(match side {
+            Side::True => this.head_a.take(),
+            Side::False => this.head_b.take(),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let held = usize::from(self.head_a.is_some()) + usize::from(self.head_b.is_some());
+        let (lower_a, upper_a) = self.a.size_hint();
+        let (lower_b, upper_b) = self.b.size_hint();
+        let lower = lower_a.saturating_add(lower_b).saturating_add(held);
+        let upper = upper_a
+            .zip(upper_b)
+            .and_then(|(a, b)| a.checked_add(b))
+            .and_then(|upper| upper.checked_add(held));
+        (lower, upper)
+    }
+}
+
+impl<A, B, C> FusedStream for SelectBy<A, B, C>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+    C: Fn(&A::Item, &B::Item) -> Side,
+{
+    fn is_terminated(&self) -> bool {
+        self.a.is_done() && self.b.is_done() && self.head_a.is_none() && self.head_b.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{stream::FusedStream, StreamExt};
+
+    use super::*;
+    use crate::testing::{poll_once, CountingWaker, ManualStream};
+
+    #[tokio::test]
+    async fn test_biased_choice() {
+        let a = futures::stream::iter([1, 5, 6]);
+        let b = futures::stream::iter([2, 3, 4]);
+        // Always take the smaller item, which merges two sorted streams
+        let merged = select_by(a, b, |a, b| Side::from(a <= b));
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], merged.collect::<Vec<_>>().await);
+    }
+
+    #[test]
+    fn test_one_ready() {
+        let (a, a_handle) = ManualStream::new();
+        let (b, b_handle) = ManualStream::new();
+        // Prefers `a`, but that doesn't hold back `b` when `a` has nothing ready
+        let mut merged = select_by(a, b, |_, _| Side::True);
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut merged, &waker.waker()));
+        b_handle.push(1);
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut merged, &waker.waker()));
+        a_handle.push(2);
+        b_handle.push(3);
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut merged, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut merged, &waker.waker()));
+    }
+
+    #[tokio::test]
+    async fn test_one_ends_early() {
+        let a = futures::stream::iter([1]);
+        let b = futures::stream::iter([2, 3, 4]);
+        let mut merged = select_by(a, b, |_, _| Side::True);
+        assert_eq!(Some(1), merged.next().await);
+        assert!(!merged.is_terminated());
+        assert_eq!(vec![2, 3, 4], (&mut merged).collect::<Vec<_>>().await);
+        assert!(merged.is_terminated());
+        assert_eq!(None, merged.next().await);
+    }
+}
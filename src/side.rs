@@ -0,0 +1,35 @@
+/// Which of the two streams of a split an item belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// The predicate returned `true`, so the item goes to the `matches`
+    /// stream
+    True,
+    /// The predicate returned `false`, so the item goes to the `rest` stream
+    False,
+}
+
+impl Side {
+    pub fn is_true(self) -> bool {
+        self == Side::True
+    }
+
+    pub fn is_false(self) -> bool {
+        self == Side::False
+    }
+}
+
+impl From<bool> for Side {
+    fn from(matches: bool) -> Self {
+        if matches {
+            Side::True
+        } else {
+            Side::False
+        }
+    }
+}
+
+impl From<Side> for bool {
+    fn from(side: Side) -> Self {
+        side.is_true()
+    }
+}
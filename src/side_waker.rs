@@ -0,0 +1,224 @@
+use core::task::Waker;
+
+/// The waker of the task that is polling one side of a split
+#[derive(Default)]
+pub(crate) struct SideWaker {
+    waker: Option<Waker>,
+    // Whether the task that registered `waker` got `Pending` and hasn't been woken since
+    waiting: bool,
+    /// The waker that `waker` replaced while its task was still waiting,
+    /// until the task of `waker` is done waiting
+    #[cfg(feature = "concurrent-poll-check")]
+    replaced: Option<Waker>,
+}
+
+impl SideWaker {
+    /// Stores the waker of the task currently polling this side, replacing
+    /// the previous one if it belongs to a different task. A stream can move
+    /// to another task while it's waiting, like when the future of `next` is
+    /// dropped, and then the previous task isn't polling it anymore. With the
+    /// `concurrent-poll-check` feature, the previous task is woken when its
+    /// waker is replaced, and if it polls this side again while the new one
+    /// is still waiting, two tasks are polling the same stream and one of
+    /// them would never be woken. That panics with a message naming `stream`
+    #[cfg_attr(not(feature = "concurrent-poll-check"), allow(unused_variables))]
+    pub(crate) fn register(&mut self, waker: &Waker, stream: &str) {
+        match &self.waker {
+            Some(current) if current.will_wake(waker) => {}
+            _ => {
+                #[cfg(feature = "concurrent-poll-check")]
+                {
+                    let back = self
+                        .replaced
+                        .as_ref()
+                        .is_some_and(|replaced| replaced.will_wake(waker));
+                    if back && self.waiting {
+                        panic!(
+                            "{} polled from two tasks; only the most recent will be woken",
+                            stream
+                        );
+                    }
+                    self.replaced = None;
+                    if self.waiting {
+                        // If the task is still polling this side, it polls again and gets caught
+                        self.replaced = self.waker.take();
+                        self.wake_replaced();
+                    }
+                }
+                self.waker = Some(waker.clone());
+            }
+        }
+        self.waiting = true;
+    }
+
+    #[cfg(feature = "concurrent-poll-check")]
+    fn wake_replaced(&self) {
+        if let Some(replaced) = &self.replaced {
+            replaced.wake_by_ref();
+        }
+    }
+
+    /// Whether a task has polled this side yet
+    pub(crate) fn is_registered(&self) -> bool {
+        self.waker.is_some()
+    }
+
+    /// The waker of the task that polled this side most recently
+    pub(crate) fn get(&self) -> Option<&Waker> {
+        self.waker.as_ref()
+    }
+
+    /// Marks that the poll which registered the waker returned `Ready`, so
+    /// the task isn't waiting anymore
+    pub(crate) fn ready(&mut self) {
+        self.waiting = false;
+        #[cfg(feature = "concurrent-poll-check")]
+        {
+            self.replaced = None;
+        }
+    }
+
+    /// Wakes the registered task if the last poll of this side returned
+    /// `Pending`. A task whose last poll got `Ready` polls again on its own
+    /// when it wants the next item, so waking it would only poll it for
+    /// nothing. This keeps a task that polls both streams of a split, like
+    /// in one `select!` loop, from being woken for every item routed to the
+    /// side it just took an item from. The wake can't be skipped just
+    /// because the waiting task is the one routing the item, since it might
+    /// have polled this side earlier in the same poll and won't poll it again
+    /// until it's woken
+    pub(crate) fn wake_waiting(&mut self) {
+        if self.waiting {
+            self.wake();
+        }
+    }
+
+    /// Wakes the registered task, if any
+    pub(crate) fn wake(&mut self) {
+        self.waiting = false;
+        if let Some(waker) = &self.waker {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl Drop for SideWaker {
+    /// The shared state of a split can go away while a task is still waiting
+    /// on it, and that task would never run again if its waker was just
+    /// dropped. Woken instead, it polls again and finds the stream ended
+    fn drop(&mut self) {
+        if self.waiting {
+            self.wake();
+        }
+    }
+}
+
+/// The waker of a task waiting for room in the buffer of one side of a split
+#[derive(Default)]
+pub(crate) struct RoomWaker {
+    waker: Option<Waker>,
+}
+
+impl RoomWaker {
+    /// Stores the waker to be woken once room frees up, replacing the
+    /// previous one
+    pub(crate) fn register(&mut self, waker: &Waker) {
+        match &self.waker {
+            Some(current) if current.will_wake(waker) => {}
+            _ => self.waker = Some(waker.clone()),
+        }
+    }
+
+    /// Wakes the waiting task, if any. It has to register again to be woken
+    /// the next time
+    pub(crate) fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for RoomWaker {
+    /// A task waiting for room is woken when the split goes away, like one
+    /// waiting for an item
+    fn drop(&mut self) {
+        self.wake();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_wakes_most_recent_task() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) =
+            source.split_by(|&n: &i32| n % 2 == 0).into_tuple();
+        let first = CountingWaker::new();
+        let second = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &first.waker()));
+        handle.push(0);
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &first.waker())
+        );
+        // The stream moved to a different task after the first one was done with it
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &second.waker()));
+        handle.push_pending();
+        handle.push(2);
+        let (first_wakes, second_wakes) = (first.count(), second.count());
+        let false_waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(first_wakes, first.count());
+        assert_eq!(second_wakes + 1, second.count());
+    }
+
+    #[cfg(feature = "concurrent-poll-check")]
+    #[test]
+    #[should_panic(expected = "TrueSplitBy polled from two tasks")]
+    fn test_concurrent_polls_panic() {
+        let (source, _handle) = ManualStream::<i32>::new();
+        let (mut true_stream, _false_stream) = source.split_by(|&n| n % 2 == 0).into_tuple();
+        let first = CountingWaker::new();
+        let second = CountingWaker::new();
+        let _ = poll_once(&mut true_stream, &first.waker());
+        let _ = poll_once(&mut true_stream, &second.waker());
+        // The first task was woken when it was replaced, and it's still polling the stream
+        assert_eq!(1, first.count());
+        let _ = poll_once(&mut true_stream, &first.waker());
+    }
+
+    #[cfg(feature = "concurrent-poll-check")]
+    #[test]
+    fn test_moved_stream_doesnt_panic() {
+        let (source, handle) = ManualStream::<i32>::new();
+        let (mut true_stream, _false_stream) = source.split_by(|&n| n % 2 == 0).into_tuple();
+        let first = CountingWaker::new();
+        let second = CountingWaker::new();
+        let third = CountingWaker::new();
+        // The stream moves to another task while it's waiting, and then to a third one
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &first.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &second.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &third.waker()));
+        handle.push(0);
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &third.waker())
+        );
+        // Once the third task got its item, the first can take the stream back
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &first.waker()));
+    }
+}
@@ -0,0 +1,27 @@
+use core::task::{Context, Poll};
+
+use crate::sync::Mutex;
+
+/// Polls the sink half of the underlying stream of a split through the
+/// shared state. If the other stream is being polled at the same time, this
+/// wakes right away and returns `Pending`, the same way polling for an item
+/// does, so a write never waits on a read
+pub(crate) fn poll_locked<T, R>(
+    state: &Mutex<T>,
+    cx: &mut Context<'_>,
+    poll: impl FnOnce(&mut T, &mut Context<'_>) -> Poll<R>,
+) -> Poll<R> {
+    match state.try_lock() {
+        Some(mut guard) => poll(&mut guard, cx),
+        None => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Locks the shared state to start sending an item. There's no context to
+/// wake, so this waits for the lock, which is only ever held briefly
+pub(crate) fn with_locked<T, R>(state: &Mutex<T>, send: impl FnOnce(&mut T) -> R) -> R {
+    send(&mut state.lock())
+}
@@ -0,0 +1,485 @@
+//! Spilling the items that don't fit in the buffer of a configured split to
+//! disk, so that the split neither drops them nor stops pulling. Without the
+//! `spill` feature this is empty and checking it compiles to nothing
+#[cfg(feature = "spill")]
+use std::{
+    boxed::Box,
+    convert::TryFrom,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    vec::Vec,
+};
+
+use crate::{bounded_buf::BoundedBuf, Side};
+
+#[cfg(feature = "spill")]
+type Encode<I> = Box<dyn Fn(&I) -> Vec<u8> + Send>;
+#[cfg(feature = "spill")]
+type Decode<I> = Box<dyn Fn(Result<Vec<u8>, SpillError>) -> I + Send>;
+
+/// Why items that were spilled to disk couldn't be read back. Passed to the
+/// `decode` function of `SplitBuilder::spill_to_disk` instead of the bytes
+/// of an item, so that it can return an error item. Requires the `spill`
+/// feature
+#[cfg(feature = "spill")]
+#[derive(Debug)]
+pub enum SpillError {
+    /// Writing items to the spill file or reading them back failed. If
+    /// writing failed, this takes the place of the item that couldn't be
+    /// written and of the items that were spilled after it before this was
+    /// read back. If reading failed, it takes the place of all the items
+    /// still in the spill file. Either way those items are lost
+    Io(io::Error),
+    /// An item read back from the spill file doesn't match what was written.
+    /// This takes the place of all the items still in the spill file, which
+    /// are lost
+    Corrupt,
+}
+
+#[cfg(feature = "spill")]
+impl fmt::Display for SpillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpillError::Io(error) => write!(f, "the spill file failed: {}", error),
+            SpillError::Corrupt => write!(f, "the spill file is corrupt"),
+        }
+    }
+}
+
+#[cfg(feature = "spill")]
+impl std::error::Error for SpillError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpillError::Io(error) => Some(error),
+            SpillError::Corrupt => None,
+        }
+    }
+}
+
+/// The spill files of both sides of a split, if spilling was turned on
+pub(crate) struct Spill<I> {
+    #[cfg(feature = "spill")]
+    inner: Option<Box<Inner<I>>>,
+    #[cfg(not(feature = "spill"))]
+    inner: core::marker::PhantomData<fn(I) -> I>,
+}
+
+#[cfg(feature = "spill")]
+struct Inner<I> {
+    encode: Encode<I>,
+    decode: Decode<I>,
+    true_side: SpillFile,
+    false_side: SpillFile,
+}
+
+impl<I> Default for Spill<I> {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "spill"), allow(unused_variables))]
+impl<I> Spill<I> {
+    /// Spills the items that don't fit in the buffers to files in `dir`
+    #[cfg(feature = "spill")]
+    pub(crate) fn new(dir: PathBuf, encode: Encode<I>, decode: Decode<I>) -> Self {
+        Self {
+            inner: Some(Box::new(Inner {
+                encode,
+                decode,
+                true_side: SpillFile::new(&dir, "true"),
+                false_side: SpillFile::new(&dir, "false"),
+            })),
+        }
+    }
+
+    /// Whether spilling is turned on, so the buffers never fill up
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        #[cfg(feature = "spill")]
+        return self.inner.is_some();
+        #[cfg(not(feature = "spill"))]
+        false
+    }
+
+    /// How many items are spilled for `side`
+    #[inline]
+    pub(crate) fn len(&self, side: Side) -> usize {
+        #[cfg(feature = "spill")]
+        if let Some(inner) = &self.inner {
+            return inner.side(side).len();
+        }
+        0
+    }
+
+    /// Puts `item` in `buf`, or spills it if `buf` is full or items are
+    /// already spilled, since those come first. Returns the item if spilling
+    /// is turned off
+    #[inline]
+    pub(crate) fn push(&mut self, side: Side, buf: &mut BoundedBuf<I>, item: I) -> Option<I> {
+        #[cfg(feature = "spill")]
+        if let Some(inner) = self.inner.as_deref_mut() {
+            let Inner {
+                encode,
+                true_side,
+                false_side,
+                ..
+            } = inner;
+            let file = match side {
+                Side::True => true_side,
+                Side::False => false_side,
+            };
+            if file.len() == 0 && buf.remaining() > 0 {
+                let _ = buf.push_back(item);
+            } else {
+                file.write(&encode(&item));
+            }
+            return None;
+        }
+        Some(item)
+    }
+
+    /// Moves spilled items of `side` back into `buf`, oldest first, until it's
+    /// full. Call this whenever an item is taken out of `buf`
+    #[inline]
+    pub(crate) fn refill(&mut self, side: Side, buf: &mut BoundedBuf<I>) {
+        #[cfg(feature = "spill")]
+        if let Some(inner) = self.inner.as_deref_mut() {
+            let Inner {
+                decode,
+                true_side,
+                false_side,
+                ..
+            } = inner;
+            let file = match side {
+                Side::True => true_side,
+                Side::False => false_side,
+            };
+            while file.len() > 0 && buf.remaining() > 0 {
+                let _ = buf.push_back(decode(file.read()));
+            }
+        }
+    }
+
+    /// Reads back all the spilled items of `side`, oldest first
+    #[inline]
+    pub(crate) fn drain(&mut self, side: Side) -> impl Iterator<Item = I> + '_ {
+        #[cfg(feature = "spill")]
+        let drained = self.inner.as_deref_mut().map(|inner| {
+            let Inner {
+                decode,
+                true_side,
+                false_side,
+                ..
+            } = inner;
+            let file = match side {
+                Side::True => true_side,
+                Side::False => false_side,
+            };
+            core::iter::from_fn(move || (file.len() > 0).then(|| decode(file.read())))
+        });
+        #[cfg(not(feature = "spill"))]
+        let drained: Option<core::iter::Empty<I>> = None;
+        drained.into_iter().flatten()
+    }
+}
+
+#[cfg(feature = "spill")]
+impl<I> Inner<I> {
+    fn side(&self, side: Side) -> &SpillFile {
+        match side {
+            Side::True => &self.true_side,
+            Side::False => &self.false_side,
+        }
+    }
+}
+
+/// Makes the names of spill files unique within the process
+#[cfg(feature = "spill")]
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// The spill file of one side. Each item is written as its length, a checksum
+/// and its bytes, and is read back from the front. The file is created when
+/// the first item is spilled, emptied whenever all its items have been read
+/// back and deleted when the split is dropped
+#[cfg(feature = "spill")]
+struct SpillFile {
+    path: PathBuf,
+    file: Option<(BufWriter<File>, BufReader<File>)>,
+    /// How many items are in the file and haven't been read back
+    written: usize,
+    /// Why writing to the file failed. Nothing more is written until this is
+    /// read back in place of the items that couldn't be written
+    failed: Option<io::Error>,
+}
+
+#[cfg(feature = "spill")]
+impl SpillFile {
+    fn new(dir: &Path, side: &str) -> Self {
+        let name = format!(
+            "split-stream-by-{}-{}-{}.spill",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed),
+            side
+        );
+        Self {
+            path: dir.join(name),
+            file: None,
+            written: 0,
+            failed: None,
+        }
+    }
+
+    /// How many items are waiting to be read back, where a failed write
+    /// counts as one
+    fn len(&self) -> usize {
+        self.written + usize::from(self.failed.is_some())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if self.failed.is_some() {
+            // The error already stands in for this item
+            return;
+        }
+        match self.try_write(bytes) {
+            Ok(()) => self.written += 1,
+            Err(error) => self.failed = Some(error),
+        }
+    }
+
+    fn try_write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let (writer, _) = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let writer = File::create(&self.path)?;
+                let reader = File::open(&self.path)?;
+                self.file
+                    .insert((BufWriter::new(writer), BufReader::new(reader)))
+            }
+        };
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the item is too big"))?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&checksum(bytes).to_le_bytes())?;
+        writer.write_all(bytes)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, SpillError> {
+        let result = match self.written {
+            0 => Err(SpillError::Io(self.failed.take().unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "nothing was spilled")
+            }))),
+            _ => self.try_read(),
+        };
+        match &result {
+            Ok(_) => self.written -= 1,
+            // Nothing after a bad item can be trusted, and a failed write comes after all the
+            // items that were written
+            Err(_) => {
+                self.written = 0;
+                self.failed = None;
+            }
+        }
+        if self.len() == 0 {
+            // Start over at the beginning, so the file doesn't keep growing
+            if let Err(error) = self.truncate() {
+                // The file can't be reused, so the next spilled item creates a new one
+                self.file = None;
+                if result.is_ok() {
+                    return Err(SpillError::Io(error));
+                }
+            }
+        }
+        result
+    }
+
+    fn try_read(&mut self) -> Result<Vec<u8>, SpillError> {
+        let (writer, reader) = self.file.as_mut().ok_or(SpillError::Corrupt)?;
+        writer.flush().map_err(SpillError::Io)?;
+        let mut header = [0; 8];
+        read_exact(reader, &mut header)?;
+        let [l0, l1, l2, l3, c0, c1, c2, c3] = header;
+        let len = u32::from_le_bytes([l0, l1, l2, l3]);
+        let expected = u32::from_le_bytes([c0, c1, c2, c3]);
+        let mut bytes = vec![0; len as usize];
+        read_exact(reader, &mut bytes)?;
+        if checksum(&bytes) != expected {
+            return Err(SpillError::Corrupt);
+        }
+        Ok(bytes)
+    }
+
+    fn truncate(&mut self) -> io::Result<()> {
+        if let Some((writer, reader)) = &mut self.file {
+            writer.flush()?;
+            writer.get_ref().set_len(0)?;
+            writer.seek(SeekFrom::Start(0))?;
+            reader.seek(SeekFrom::Start(0))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "spill")]
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Reads exactly enough bytes to fill `buf`, where running out of bytes means
+/// the file was cut short
+#[cfg(feature = "spill")]
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), SpillError> {
+    reader.read_exact(buf).map_err(|error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => SpillError::Corrupt,
+        _ => SpillError::Io(error),
+    })
+}
+
+/// The FNV-1a hash of `bytes`, which catches items that were changed or cut
+/// short on disk
+#[cfg(feature = "spill")]
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(all(test, feature = "spill"))]
+mod test {
+    use std::{
+        fs::{self, OpenOptions},
+        io::{Seek, SeekFrom, Write},
+        path::PathBuf,
+        task::Poll,
+    };
+
+    use futures::Stream;
+
+    use crate::{
+        testing::{poll_until_ready_with_waker, CountingWaker},
+        SplitBuilder,
+    };
+
+    type Item = Result<u32, String>;
+
+    /// An empty directory for the spill files of one test
+    fn spill_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "split-stream-by-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn spill_files(dir: &PathBuf) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    /// Takes all the items of one stream of a split without polling the other
+    /// one. The evens and odds alternate, so each item takes at most two polls
+    fn take_all(stream: &mut (impl Stream<Item = Item> + Unpin)) -> Vec<Item> {
+        let waker = CountingWaker::new();
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = poll_until_ready_with_waker(stream, &waker.waker(), 2) {
+            items.push(item);
+        }
+        items
+    }
+
+    /// Splits `0..n` into evens and odds, spilling to `dir`. Each spilled
+    /// item takes up `size` bytes
+    fn split_numbers(
+        n: u32,
+        dir: PathBuf,
+        size: usize,
+    ) -> (
+        impl Stream<Item = Item> + Unpin,
+        impl Stream<Item = Item> + Unpin,
+    ) {
+        SplitBuilder::new(futures::stream::iter((0..n).map(Ok)))
+            .predicate(|item: &Item| matches!(item, Ok(n) if n % 2 == 0))
+            .buffer(2)
+            .spill_to_disk(
+                dir,
+                move |item: &Item| {
+                    let mut bytes = item.clone().unwrap().to_le_bytes().to_vec();
+                    bytes.resize(size, 0);
+                    bytes
+                },
+                |bytes| match bytes {
+                    Ok(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                    Err(error) => Err(error.to_string()),
+                },
+            )
+            .build()
+            .into_tuple()
+    }
+
+    #[test]
+    fn test_spilled_items_in_order() {
+        let dir = spill_dir("order");
+        let (mut evens, mut odds) = split_numbers(100, dir.clone(), 4);
+        assert_eq!(
+            (0..100).step_by(2).map(Ok).collect::<Vec<_>>(),
+            take_all(&mut evens)
+        );
+        // The odds that didn't fit in the buffer are on disk
+        assert_eq!(1, spill_files(&dir).len());
+        assert_eq!(
+            (1..100).step_by(2).map(Ok).collect::<Vec<_>>(),
+            take_all(&mut odds)
+        );
+        drop((evens, odds));
+        assert!(spill_files(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_io_error_item() {
+        // The directory doesn't exist, so the spill file can't be created
+        let dir = spill_dir("io").join("missing");
+        let (mut evens, mut odds) = split_numbers(10, dir, 4);
+        assert_eq!(5, take_all(&mut evens).len());
+        let odds = take_all(&mut odds);
+        // 5, 7 and 9 were lost, and the error takes their place
+        assert_eq!(3, odds.len());
+        assert_eq!(vec![Ok(1), Ok(3)], odds[..2]);
+        assert!(odds[2]
+            .as_ref()
+            .unwrap_err()
+            .starts_with("the spill file failed"));
+    }
+
+    #[test]
+    fn test_corrupt_item() {
+        let dir = spill_dir("corrupt");
+        // The items are too big to stay in the write buffer, so they're on disk right away
+        let (mut evens, mut odds) = split_numbers(12, dir.clone(), 16 * 1024);
+        assert_eq!(6, take_all(&mut evens).len());
+        let files = spill_files(&dir);
+        assert_eq!(1, files.len());
+        let mut file = OpenOptions::new().write(true).open(&files[0]).unwrap();
+        file.seek(SeekFrom::Start(100)).unwrap();
+        file.write_all(&[1]).unwrap();
+        drop(file);
+        // 5 was changed on disk, so it and everything spilled after it is lost
+        assert_eq!(
+            vec![Ok(1), Ok(3), Err("the spill file is corrupt".to_string())],
+            take_all(&mut odds)
+        );
+    }
+}
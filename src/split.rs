@@ -0,0 +1,162 @@
+use futures_core::Stream;
+
+#[cfg(feature = "sync-bridge")]
+use crate::BridgeSync;
+use crate::{zip_partitions, CompletionTracker, ZipPartitions};
+
+/// The two streams created by the methods of `SplitStreamByExt`. Naming the
+/// fields means the two streams can't be swapped by accident
+#[derive(Debug)]
+pub struct Split<T, F> {
+    /// The stream of items for which the predicate returned `true`
+    pub matches: T,
+    /// The stream of items for which the predicate returned `false`
+    pub rest: F,
+}
+
+impl<T, F> Split<T, F> {
+    /// Creates the two streams of a split from its shared state, tracking
+    /// their completion together. Every way of creating a split goes through
+    /// here, so the streams of `SplitBuilder` and of `SplitStreamByExt` are
+    /// put together the same way
+    pub(crate) fn share<S: Clone>(
+        state: S,
+        matches: impl FnOnce(S, CompletionTracker) -> T,
+        rest: impl FnOnce(S, CompletionTracker) -> F,
+    ) -> Self {
+        let completion = CompletionTracker::new();
+        Split {
+            matches: matches(state.clone(), completion.clone()),
+            rest: rest(state, completion),
+        }
+    }
+
+    /// Converts into a `(matches, rest)` tuple
+    pub fn into_tuple(self) -> (T, F) {
+        (self.matches, self.rest)
+    }
+
+    /// Pairs up the items of the two streams. See `zip_partitions`
+    pub fn zip_partitions(self, cap: usize) -> ZipPartitions<T, F>
+    where
+        T: Stream,
+        F: Stream,
+    {
+        zip_partitions(self.matches, self.rest, cap)
+    }
+
+    /// Hands the two streams to threads that don't run async code, through
+    /// bounded `crossbeam_channel`s that hold up to `cap_true` and
+    /// `cap_false` items. The returned future moves the items into the
+    /// channels and has to be spawned on the async side. Each channel is fed
+    /// by a thread of its own, which holds one more item while the channel is
+    /// full, so with a capacity of 0 the items are handed over one at a time.
+    /// While that thread is busy, the future stops pulling from that stream,
+    /// so the buffer of the split fills up and the underlying stream isn't
+    /// pulled either. Once a receiver is dropped, the items of its stream are
+    /// pulled and dropped, so the other stream keeps going. The future
+    /// resolves once both streams have ended. Requires the `sync-bridge`
+    /// feature
+    ///
+    ///```rust
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let split = futures::stream::iter(0..100).split_by_buffered::<8>(|&n| n % 2 == 0);
+    ///     let (evens, odds, driver) = split.bridge_sync(4, 4);
+    ///     let evens = std::thread::spawn(move || evens.iter().sum::<i32>());
+    ///     let odds = std::thread::spawn(move || odds.iter().count());
+    ///     driver.await;
+    ///     assert_eq!(2450, evens.join().unwrap());
+    ///     assert_eq!(50, odds.join().unwrap());
+    /// })
+    /// ```
+    #[cfg(feature = "sync-bridge")]
+    #[allow(clippy::type_complexity)]
+    pub fn bridge_sync(
+        self,
+        cap_true: usize,
+        cap_false: usize,
+    ) -> (
+        crossbeam_channel::Receiver<T::Item>,
+        crossbeam_channel::Receiver<F::Item>,
+        BridgeSync<T, F>,
+    )
+    where
+        T: Stream + Unpin,
+        F: Stream + Unpin,
+        T::Item: Send + 'static,
+        F::Item: Send + 'static,
+    {
+        BridgeSync::new(self.matches, self.rest, cap_true, cap_false)
+    }
+}
+
+impl<T, F> From<Split<T, F>> for (T, F) {
+    fn from(split: Split<T, F>) -> Self {
+        split.into_tuple()
+    }
+}
+
+/// The two streams created by the methods of `SplitStreamByMapExt`
+#[derive(Debug)]
+pub struct SplitMap<L, R> {
+    /// The stream of values for which the predicate returned `Either::Left`
+    pub left: L,
+    /// The stream of values for which the predicate returned `Either::Right`
+    pub right: R,
+}
+
+impl<L, R> SplitMap<L, R> {
+    /// The same as `Split::share`, for the streams of `SplitStreamByMapExt`
+    pub(crate) fn share<S: Clone>(
+        state: S,
+        left: impl FnOnce(S, CompletionTracker) -> L,
+        right: impl FnOnce(S, CompletionTracker) -> R,
+    ) -> Self {
+        let Split { matches, rest } = Split::share(state, left, right);
+        SplitMap {
+            left: matches,
+            right: rest,
+        }
+    }
+
+    /// Converts into a `(left, right)` tuple
+    pub fn into_tuple(self) -> (L, R) {
+        (self.left, self.right)
+    }
+}
+
+impl<L, R> From<SplitMap<L, R>> for (L, R) {
+    fn from(split: SplitMap<L, R>) -> Self {
+        split.into_tuple()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use crate::{Either, SplitStreamByExt, SplitStreamByMapExt};
+
+    #[tokio::test]
+    async fn test_tuple_conversions() {
+        let (evens, odds) = futures::stream::iter([0, 1, 2, 3])
+            .split_by(|&n| n % 2 == 0)
+            .into();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!((vec![0, 2], vec![1, 3]), (evens, odds));
+
+        let (left, right) = futures::stream::iter([0, 1, 2, 3])
+            .split_by_map(|n| {
+                if n < 2 {
+                    Either::Left(n)
+                } else {
+                    Either::Right(n)
+                }
+            })
+            .into_tuple();
+        let (left, right) = futures::join!(left.collect::<Vec<_>>(), right.collect::<Vec<_>>());
+        assert_eq!((vec![0, 1], vec![2, 3]), (left, right));
+    }
+}
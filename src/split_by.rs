@@ -1,146 +1,394 @@
-use std::{
-    pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+#[cfg(feature = "metrics")]
+use alloc::borrow::Cow;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use futures_sink::Sink;
+
+use crate::{
+    completion::{Completion, CompletionTracker},
+    debug::{self, Verbose},
+    parts::SplitParts,
+    reunite::ReuniteError,
+    ring::RingBuf,
+    sink_passthrough,
+    split_core::{poll_unlocked, ByPredicate, SplitCore},
+    sync::{Mutex, SharedState},
+    FalseSplitByBuffered, FalseSplitByConfigured, Side, Split, SplitByBuffered, SplitByConfigured,
+    TrueSplitByBuffered, TrueSplitByConfigured,
 };
 
-use futures::Stream;
-use pin_project::pin_project;
+/// The shared state of a split made with `split_by`, which is that of a
+/// buffered split whose buffers hold a single item
+pub(crate) type SplitBy<I, S, P> = SplitCore<I, S, ByPredicate<P>, RingBuf<I, 1>>;
+
+/// What the streams of a split made with `split_by` are called
+pub(crate) const STREAMS: [&str; 2] = ["TrueSplitBy", "FalseSplitBy"];
+
+#[cfg(feature = "metrics")]
+impl<I, S, P> Split<TrueSplitBy<I, S, P>, FalseSplitBy<I, S, P>> {
+    /// Names the split, so that it records metrics through the global
+    /// `metrics` recorder, labeled with `split` set to `name` and, except for
+    /// the dropped items, `side` set to `true` or `false`:
+    /// `split_items_routed_total` counts the items from the underlying stream
+    /// that went to each side, `split_buffered` is the number of items in the
+    /// buffer of each side, `split_buffered_seconds` is how long each item
+    /// spent in a buffer, and `split_items_dropped_total` counts the items that
+    /// were still buffered when the split was dropped. Requires the `metrics`
+    /// feature
+    pub fn with_metrics(self, name: impl Into<Cow<'static, str>>) -> Self {
+        let mut state = self.matches.stream.lock();
+        let buffered_true = state.buf_true.len();
+        let buffered_false = state.buf_false.len();
+        state
+            .metrics
+            .set_name(name.into(), buffered_true, buffered_false);
+        drop(state);
+        self
+    }
+}
 
-#[pin_project]
-pub(crate) struct SplitBy<I, S, P> {
-    buf_true: Option<I>,
-    buf_false: Option<I>,
-    waker_true: Option<Waker>,
-    waker_false: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitBy<I, S, P> {
+    stream: SharedState<Mutex<SplitBy<I, S, P>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
 }
 
-impl<I, S, P> SplitBy<I, S, P>
+impl<I, S, P> TrueSplitBy<I, S, P> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>, completion: CompletionTracker) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `false` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_false
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `false` stream is
+    /// full. In that case this stream can only make progress once the `false`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_true()
+    }
+
+    /// Polls for room in the buffer of the `false` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `false` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `false` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `false` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_false(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `false` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_true.insert(0, item);
+        }
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `true` and `false` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        self,
+        other: FalseSplitBy<I, S, P>,
+    ) -> Result<(S, Vec<I>, Vec<I>), ReuniteError<Self, FalseSplitBy<I, S, P>>> {
+        let parts = self.into_parts_with(other)?;
+        Ok((parts.stream, parts.buffered_true, parts.buffered_false))
+    }
+
+    /// Takes the split apart like `into_parts`, consuming both of its streams
+    #[allow(clippy::type_complexity)]
+    fn into_parts_with(
+        self,
+        mut other: FalseSplitBy<I, S, P>,
+    ) -> Result<SplitParts<I, S, P>, ReuniteError<Self, FalseSplitBy<I, S, P>>> {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.stream.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.stream = SharedState::new(this.stream.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, S, P> TrueSplitBy<I, S, P>
 where
     S: Stream<Item = I>,
     P: Fn(&I) -> bool,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            buf_false: None,
-            buf_true: None,
-            waker_false: None,
-            waker_true: None,
-            stream,
-            predicate,
-        }))
-    }
-
-    fn poll_next_true(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_true.is_none() {
-            *this.waker_true = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_true.take() {
-            // There was already a value in the buffer. Return that value
+    /// Turns the two streams of the split into the streams of a split that
+    /// buffers up to `N` items for each stream, like `split_by_buffered`. The
+    /// new split continues with the same underlying stream and predicate, and
+    /// the items that were buffered or peeked at stay in the same stream and
+    /// order. The `Completion` futures of the old streams resolve. Fails if
+    /// `other` doesn't come from the same split. Panics if `N` is 0
+    #[allow(clippy::type_complexity)]
+    pub fn into_buffered<const N: usize>(
+        self,
+        other: FalseSplitBy<I, S, P>,
+    ) -> Result<
+        Split<TrueSplitByBuffered<I, S, P, N>, FalseSplitByBuffered<I, S, P, N>>,
+        ReuniteError<Self, FalseSplitBy<I, S, P>>,
+    > {
+        assert!(
+            N > 0,
+            "a split that is converted needs room for its buffered items"
+        );
+        Ok(SplitByBuffered::split_from_parts(
+            self.into_parts_with(other)?,
+        ))
+    }
+
+    /// Turns the two streams of the split into the streams of a split that
+    /// buffers up to `capacity` items for each stream, like `SplitBuilder`
+    /// with `buffer(capacity)`. This works like `into_buffered`, except that
+    /// the buffer size can be chosen at runtime and changed later with
+    /// `resize`
+    #[allow(clippy::type_complexity)]
+    pub fn with_capacity(
+        self,
+        other: FalseSplitBy<I, S, P>,
+        capacity: usize,
+    ) -> Result<
+        Split<TrueSplitByConfigured<I, S, P>, FalseSplitByConfigured<I, S, P>>,
+        ReuniteError<Self, FalseSplitBy<I, S, P>>,
+    > {
+        Ok(SplitByConfigured::split_from_parts(
+            self.into_parts_with(other)?,
+            capacity,
+        ))
+    }
+}
+
+impl<I, S, P> TrueSplitBy<I, S, P>
+where
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.router = Some(ByPredicate(predicate));
+        state.reclassify();
+    }
+}
+
+impl<I, S, P> TrueSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
             return Poll::Ready(Some(item));
         }
-        if this.buf_false.is_some() {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_false {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    Poll::Ready(Some(item))
-                } else {
-                    // This value is not what we wanted. Store it and notify other partition task if
-                    // it exists
-                    let _ = this.buf_false.replace(item);
-                    if let Some(waker) = this.waker_false {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                }
-            }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `false` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_false {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
             }
-            Poll::Pending => Poll::Pending,
+            self.terminated = false;
         }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::True, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
+        }
+        response
     }
 
-    fn poll_next_false(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_false.is_none() {
-            *this.waker_false = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_false.take() {
-            // There was already a value in the buffer. Return that value
-            return Poll::Ready(Some(item));
-        }
-        if this.buf_true.is_some() {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_true {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    // This value is not what we wanted. Store it and notify other stream if waker
-                    // exists
-                    let _ = this.buf_true.replace(item);
-                    if let Some(waker) = this.waker_true {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Some(item))
-                }
-            }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `true` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_true {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
             }
-            Poll::Pending => Poll::Pending,
         }
+        Poll::Ready(self.peeked.as_ref())
     }
-}
-
-/// A struct that implements `Stream` which returns the items where the
-/// predicate returns `true`
-pub struct TrueSplitBy<I, S, P> {
-    stream: Arc<Mutex<SplitBy<I, S, P>>>,
-}
 
-impl<I, S, P> TrueSplitBy<I, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>) -> Self {
-        Self { stream }
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
     }
 }
 
+// The peeked item is never pinned
+impl<I, S, P> Unpin for TrueSplitBy<I, S, P> {}
+
 impl<I, S, P> Stream for TrueSplitBy<I, S, P>
 where
     S: Stream<Item = I> + Unpin,
@@ -148,31 +396,388 @@ where
 {
     type Item = I;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitBy::poll_next_true(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P> core::async_iter::AsyncIterator for TrueSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P> FusedStream for TrueSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, T> Sink<T> for TrueSplitBy<I, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P> fmt::Debug for TrueSplitBy<I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitBy",
+            state.as_deref(),
+            self.terminated,
+            SplitBy::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P> fmt::Debug for Verbose<'_, TrueSplitBy<I, S, P>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitBy",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P> Drop for TrueSplitBy<I, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_true();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
     }
 }
 
 /// A struct that implements `Stream` which returns the items where the
 /// predicate returns `false`
+#[must_use = "streams do nothing unless polled"]
 pub struct FalseSplitBy<I, S, P> {
-    stream: Arc<Mutex<SplitBy<I, S, P>>>,
+    stream: SharedState<Mutex<SplitBy<I, S, P>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
 }
 
 impl<I, S, P> FalseSplitBy<I, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>) -> Self {
-        Self { stream }
+    pub(crate) fn new(stream: Arc<Mutex<SplitBy<I, S, P>>>, completion: CompletionTracker) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `true` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_true
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `true` stream is
+    /// full. In that case this stream can only make progress once the `true`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_false()
+    }
+
+    /// Polls for room in the buffer of the `true` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `true` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `true` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `true` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_true(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `true` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, S, P> FalseSplitBy<I, S, P>
+where
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.router = Some(ByPredicate(predicate));
+        state.reclassify();
     }
 }
 
+impl<I, S, P> FalseSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::False, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked item is never pinned
+impl<I, S, P> Unpin for FalseSplitBy<I, S, P> {}
+
 impl<I, S, P> Stream for FalseSplitBy<I, S, P>
 where
     S: Stream<Item = I> + Unpin,
@@ -180,15 +785,545 @@ where
 {
     type Item = I;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitBy::poll_next_false(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P> core::async_iter::AsyncIterator for FalseSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P> FusedStream for FalseSplitBy<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, T> Sink<T> for FalseSplitBy<I, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P> fmt::Debug for FalseSplitBy<I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitBy",
+            state.as_deref(),
+            self.terminated,
+            SplitBy::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P> fmt::Debug for Verbose<'_, FalseSplitBy<I, S, P>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitBy",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P> Drop for FalseSplitBy<I, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_false();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{channel::mpsc, stream::FusedStream, Sink, SinkExt, Stream, StreamExt};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        BoxedPredicate, SplitStreamByExt,
+    };
+
+    /// A transport that reads from a stream and writes to a channel
+    struct Duplex<St> {
+        incoming: St,
+        outgoing: mpsc::UnboundedSender<String>,
+    }
+
+    impl<St> Stream for Duplex<St>
+    where
+        St: Stream + Unpin,
+    {
+        type Item = St::Item;
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<St::Item>> {
+            self.incoming.poll_next_unpin(cx)
+        }
+    }
+
+    impl<St> Sink<String> for Duplex<St>
+    where
+        St: Unpin,
+    {
+        type Error = mpsc::SendError;
+
+        fn poll_ready(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_ready_unpin(cx)
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, item: String) -> Result<(), Self::Error> {
+            self.outgoing.start_send_unpin(item)
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_flush_unpin(cx)
+        }
+
+        fn poll_close(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_close_unpin(cx)
+        }
+    }
+
+    struct Config {
+        threshold: u8,
+    }
+
+    #[tokio::test]
+    async fn test_borrowed_items_and_predicate() {
+        let data = [1u8, 5, 2, 7];
+        let config = Config { threshold: 4 };
+        let (big, small) = futures::stream::iter(data.chunks(1))
+            .split_by(|chunk: &&[u8]| chunk[0] > config.threshold)
+            .into_tuple();
+        // Boxing only needs the split to live as long as what it borrows
+        let (big, small) = futures::join!(
+            big.boxed().collect::<Vec<_>>(),
+            small.boxed_local().collect::<Vec<_>>()
+        );
+        assert_eq!(vec![&[5u8][..], &[7]], big);
+        assert_eq!(vec![&[1u8][..], &[2]], small);
+    }
+
+    #[tokio::test]
+    async fn test_sink_passthrough() {
+        let (outgoing, sent) = mpsc::unbounded();
+        let transport = Duplex {
+            incoming: futures::stream::iter([0, 1, 2, 3, 4, 5]),
+            outgoing,
+        };
+        let (mut requests, mut events) = transport.split_by(|&n| n % 2 == 0).into_tuple();
+        futures::join!(
+            async {
+                while let Some(n) = requests.next().await {
+                    requests.send(format!("request {}", n)).await.unwrap();
+                }
+            },
+            async {
+                while let Some(n) = events.next().await {
+                    events.send(format!("event {}", n)).await.unwrap();
+                }
+            }
+        );
+        // Dropping the split drops the transport, which ends the channel
+        drop((requests, events));
+        let mut sent = sent.collect::<Vec<_>>().await;
+        sent.sort();
+        assert_eq!(
+            vec![
+                "event 1",
+                "event 3",
+                "event 5",
+                "request 0",
+                "request 2",
+                "request 4"
+            ],
+            sent
+        );
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test]
+    async fn test_blocking_iter() {
+        let incoming_stream = futures::stream::iter(0..100);
+        let (true_stream, false_stream) = incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let thread =
+            std::thread::spawn(move || true_stream.into_blocking_iter().collect::<Vec<_>>());
+        let false_items = tokio::spawn(false_stream.collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!((1..100).step_by(2).collect::<Vec<_>>(), false_items);
+        assert_eq!(
+            (0..100).step_by(2).collect::<Vec<_>>(),
+            thread.join().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poll_next_item() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3]);
+        let (mut true_stream, mut false_stream) =
+            incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let mut items = Vec::new();
+        // A hand-written future that drains both streams without pinning them
+        futures::future::poll_fn(|cx| {
+            let mut done = true;
+            for stream_item in [
+                true_stream.poll_next_item(cx),
+                false_stream.poll_next_item(cx),
+            ] {
+                match stream_item {
+                    Poll::Ready(Some(item)) => {
+                        items.push(item);
+                        done = false;
+                    }
+                    Poll::Ready(None) => {}
+                    Poll::Pending => done = false,
+                }
+            }
+            if done {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+        items.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 3], items);
+    }
+
+    #[test]
+    fn test_set_predicate_reclassify() {
+        let (source, handle) = ManualStream::new();
+        let predicate: BoxedPredicate<i32> = Box::new(|&n| n % 2 == 0);
+        let (mut true_stream, false_stream) = source.split_by(predicate).into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        let wakes = waker.count();
+        false_stream.set_predicate_reclassify(Box::new(|&n| n % 2 == 0 || n == 1));
+        assert!(waker.count() > wakes);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_into_buffered() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) =
+            source.split_by(|&n: &i32| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new().waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        for n in [1, 3, 0, 5, 2, 4] {
+            handle.push(n);
+        }
+        // 1 is parked for the false stream, which peeks at it, and then 3 is parked
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        assert_eq!(Poll::Ready(Some(&1)), false_stream.poll_peek(&mut cx));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        let (mut true_stream, mut false_stream) = true_stream
+            .into_buffered::<4>(false_stream)
+            .unwrap()
+            .into_tuple();
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut true_stream, &waker));
+        // 5 is buffered now, while the old split would have had to wait
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut true_stream, &waker));
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut false_stream, &waker));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut false_stream, &waker));
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut false_stream, &waker));
+        assert_eq!(Poll::Ready(Some(4)), poll_once(&mut true_stream, &waker));
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source.split_by(|&n: &i32| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new().waker();
+        handle.push(1);
+        handle.push(3);
+        handle.push(5);
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        let (mut true_stream, mut false_stream) = true_stream
+            .with_capacity(false_stream, 2)
+            .unwrap()
+            .into_tuple();
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker));
+        // The buffer of the false stream is full with 3 and 5
+        assert!(true_stream.would_block_on_other());
+        handle.end();
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(n)) = poll_once(&mut false_stream, &waker) {
+            items.push(n);
+        }
+        assert_eq!(vec![1, 3, 5], items);
+    }
+
+    #[tokio::test]
+    async fn test_peek() {
+        let incoming_stream = futures::stream::iter([1, 2, 3, 4]);
+        let (mut true_stream, false_stream) =
+            incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let (_, false_items) = futures::join!(
+            async {
+                assert_eq!(Some(&2), true_stream.peek().await);
+                assert_eq!(Some(&2), true_stream.peek().await);
+                assert_eq!((1, Some(3)), true_stream.size_hint());
+                assert_eq!(Some(2), true_stream.next().await);
+                assert_eq!(Some(&4), true_stream.peek().await);
+                assert_eq!(Some(4), true_stream.next().await);
+                assert_eq!(None, true_stream.peek().await);
+            },
+            false_stream.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![1, 3], false_items);
+    }
+
+    #[test]
+    fn test_wakes_other_stream() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) =
+            source.split_by(|&n: &i32| n % 2 == 0).into_tuple();
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        handle.push(1);
+        handle.push(3);
+        let false_wakes = false_waker.count();
+        // The true stream finds an item for the false stream, so it has to store it and notify
+        // the false stream
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(false_wakes + 1, false_waker.count());
+        // Only a single item can be stored, so the true stream can't make progress
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let incoming_stream = futures::stream::iter([1, 2]);
+        let (mut true_stream, mut false_stream) =
+            incoming_stream.split_by(|&n| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new();
+        assert_eq!((0, Some(2)), true_stream.size_hint());
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!((0, Some(1)), true_stream.size_hint());
+        assert_eq!((1, Some(2)), false_stream.size_hint());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!((0, Some(0)), true_stream.size_hint());
+        assert_eq!((0, Some(0)), false_stream.size_hint());
+    }
+
+    #[cfg(feature = "nightly-async-iterator")]
+    #[test]
+    fn test_async_iterator() {
+        use core::async_iter::AsyncIterator;
+
+        fn poll_async_iter<A: AsyncIterator + Unpin>(
+            iter: &mut A,
+            waker: &CountingWaker,
+        ) -> Poll<Option<A::Item>> {
+            AsyncIterator::poll_next(Pin::new(iter), &mut Context::from_waker(&waker.waker()))
+        }
+
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) =
+            source.split_by(|&n: &i32| n % 2 == 0).into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(2);
+        handle.end();
+        assert_eq!((0, None), AsyncIterator::size_hint(&true_stream));
+        assert_eq!(Poll::Pending, poll_async_iter(&mut true_stream, &waker));
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_async_iter(&mut false_stream, &waker)
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_async_iter(&mut true_stream, &waker)
+        );
+        assert_eq!(Poll::Ready(None), poll_async_iter(&mut true_stream, &waker));
+        assert_eq!(
+            Poll::Ready(None),
+            poll_async_iter(&mut false_stream, &waker)
+        );
+    }
+
+    type Numbers = futures::stream::Iter<std::ops::Range<i32>>;
+
+    /// Holds both streams of a split in fields, like a long-lived task would
+    struct Router {
+        evens: super::TrueSplitBy<i32, Numbers, fn(&i32) -> bool>,
+        odds: super::FalseSplitBy<i32, Numbers, fn(&i32) -> bool>,
+    }
+
+    fn is_even(n: &i32) -> bool {
+        n % 2 == 0
+    }
+
+    #[tokio::test]
+    async fn test_select_on_borrowed_fields() {
+        let (evens, odds) = futures::stream::iter(0..10)
+            .split_by(is_even as fn(&i32) -> bool)
+            .into_tuple();
+        let mut router = Router { evens, odds };
+        // `&mut` streams are streams too, so the fields can be borrowed for a while and then used
+        // again
+        let (first_evens, first_odds) = futures::join!(
+            (&mut router.evens).take(2).collect::<Vec<_>>(),
+            router.odds.by_ref().take(2).collect::<Vec<_>>()
+        );
+        assert_eq!((vec![0, 2], vec![1, 3]), (first_evens, first_odds));
+        let mut evens = Vec::new();
+        let mut odds = Vec::new();
+        loop {
+            tokio::select! {
+                Some(n) = router.evens.next(), if !router.evens.is_terminated() => evens.push(n),
+                Some(n) = router.odds.next(), if !router.odds.is_terminated() => odds.push(n),
+                else => break,
+            }
+        }
+        assert_eq!(vec![4, 6, 8], evens);
+        assert_eq!(vec![5, 7, 9], odds);
     }
 }
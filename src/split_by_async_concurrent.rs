@@ -0,0 +1,458 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::stream::{FuturesOrdered, StreamExt};
+use pin_project::pin_project;
+
+use crate::{side_waker::SideWaker, sync::Mutex, Side};
+
+/// Starts classifying an item in a future `F`
+pub(crate) trait Classifier<I, F>
+where
+    F: Future,
+{
+    fn classify(&self, item: I) -> F;
+
+    /// Turns what the future of an item resolved to into the item along with
+    /// the side it goes to. This is called once the future was taken out of
+    /// the ones in flight, so a panic here doesn't leave a gap in their order
+    fn classified(output: F::Output) -> (I, Side);
+}
+
+/// Classifies items with a predicate that returns a future, made by
+/// `split_by_async_concurrent`
+pub(crate) struct ByAsyncPredicate<P>(pub(crate) P);
+
+impl<I, P, Fut> Classifier<I, Classify<I, Fut>> for ByAsyncPredicate<P>
+where
+    P: Fn(&I) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    fn classify(&self, item: I) -> Classify<I, Fut> {
+        Classify {
+            future: (self.0)(&item),
+            item: Some(item),
+        }
+    }
+
+    fn classified(output: (I, Side)) -> (I, Side) {
+        output
+    }
+}
+
+/// An item along with the future of the predicate that classifies it
+#[pin_project]
+pub(crate) struct Classify<I, Fut> {
+    #[pin]
+    future: Fut,
+    item: Option<I>,
+}
+
+impl<I, Fut> Future for Classify<I, Fut>
+where
+    Fut: Future<Output = bool>,
+{
+    type Output = (I, Side);
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<(I, Side)> {
+        let this = self.project();
+        let matches = futures_core::ready!(this.future.poll(cx));
+        let item = this
+            .item
+            .take()
+            .expect("a classified item is only returned once");
+        Poll::Ready((item, Side::from(matches)))
+    }
+}
+
+/// The items that were classified for one side of a split but not returned
+/// yet
+struct Classified<I> {
+    buf: VecDeque<I>,
+    waker: SideWaker,
+    closed: bool,
+}
+
+impl<I> Default for Classified<I> {
+    fn default() -> Self {
+        Self {
+            buf: VecDeque::new(),
+            waker: SideWaker::default(),
+            closed: false,
+        }
+    }
+}
+
+/// The shared state of a split whose items are classified by futures, up to
+/// `max_in_flight` of them at once. `C` starts the future `F` for each item
+pub(crate) struct SplitByAsyncConcurrent<I, S, C, F>
+where
+    F: Future,
+{
+    /// The items being classified, in the order they were pulled. An item
+    /// whose classification resolved stays here until the ones before it
+    /// have, so it counts against `max_in_flight` until it's routed
+    in_flight: FuturesOrdered<F>,
+    max_in_flight: usize,
+    classified_true: Classified<I>,
+    classified_false: Classified<I>,
+    stream: S,
+    classifier: C,
+    done: bool,
+    /// What the streams are called in the message of `concurrent-poll-check`
+    name: &'static str,
+}
+
+impl<I, S, C, F> SplitByAsyncConcurrent<I, S, C, F>
+where
+    F: Future,
+{
+    pub(crate) fn new(
+        stream: S,
+        classifier: C,
+        max_in_flight: usize,
+        name: &'static str,
+    ) -> Arc<Mutex<Self>> {
+        assert!(
+            max_in_flight > 0,
+            "at least one item has to be classified at a time"
+        );
+        Arc::new(Mutex::new(Self {
+            in_flight: FuturesOrdered::new(),
+            max_in_flight,
+            classified_true: Classified::default(),
+            classified_false: Classified::default(),
+            stream,
+            classifier,
+            done: false,
+            name,
+        }))
+    }
+
+    /// How many items are being classified
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Marks the stream of `side` as dropped, dropping the items that were
+    /// classified for it
+    pub(crate) fn close(&mut self, side: Side) {
+        let (closed, other) = match side {
+            Side::True => (&mut self.classified_true, &mut self.classified_false),
+            Side::False => (&mut self.classified_false, &mut self.classified_true),
+        };
+        closed.closed = true;
+        // Nothing takes these items anymore, and the other stream might be waiting for room
+        closed.buf.clear();
+        other.waker.wake();
+    }
+}
+
+impl<I, S, C, F> SplitByAsyncConcurrent<I, S, C, F>
+where
+    S: Stream<Item = I> + Unpin,
+    C: Classifier<I, F>,
+    F: Future,
+{
+    /// Pulls from the underlying stream and starts classifying what it
+    /// returns, for as long as fewer than `max_in_flight` items are in flight
+    fn pull(&mut self, cx: &mut Context<'_>) {
+        while !self.done && self.in_flight.len() < self.max_in_flight {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let future = self.classifier.classify(item);
+                    self.in_flight.push_back(future);
+                }
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return,
+            }
+        }
+    }
+
+    fn poll_next_side(&mut self, side: Side, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        let (own, other) = match side {
+            Side::True => (&mut self.classified_true, &mut self.classified_false),
+            Side::False => (&mut self.classified_false, &mut self.classified_true),
+        };
+        own.waker.register(cx.waker(), self.name);
+        if let Some(item) = own.buf.pop_front() {
+            if own.buf.len() + 1 == self.max_in_flight {
+                // The other stream might be waiting for room in this buffer
+                other.waker.wake_waiting();
+            }
+            return Poll::Ready(Some(item));
+        }
+        loop {
+            self.pull(cx);
+            let other = match side {
+                Side::True => &mut self.classified_false,
+                Side::False => &mut self.classified_true,
+            };
+            if other.buf.len() >= self.max_in_flight {
+                // The next item to be routed might be for the other stream, which has as many
+                // items waiting as can be in flight, so nothing is routed until it catches up
+                other.waker.wake_waiting();
+                return Poll::Pending;
+            }
+            match self
+                .in_flight
+                .poll_next_unpin(cx)
+                .map(|output| output.map(C::classified))
+            {
+                Poll::Ready(Some((item, target))) if target == side => {
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(Some((item, _))) => {
+                    // Its slot is free now, so another item can be pulled
+                    if !other.closed {
+                        other.buf.push_back(item);
+                        other.waker.wake_waiting();
+                    }
+                }
+                Poll::Ready(None) if self.done => {
+                    // Nothing is in flight and nothing more will be pulled
+                    other.waker.wake_waiting();
+                    return Poll::Ready(None);
+                }
+                // Nothing is in flight, and the underlying stream wakes this stream once it has
+                // another item. Otherwise the classifications that are running wake it
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Polls for the next item of the stream of `side`, for the streams of the
+/// splits that share a `SplitByAsyncConcurrent`. Sets `terminated` once it
+/// has ended
+pub(crate) fn poll_next_side<I, S, C, F>(
+    state: &Mutex<SplitByAsyncConcurrent<I, S, C, F>>,
+    side: Side,
+    cx: &mut Context<'_>,
+    terminated: &mut bool,
+) -> Poll<Option<I>>
+where
+    S: Stream<Item = I> + Unpin,
+    C: Classifier<I, F>,
+    F: Future,
+{
+    if *terminated {
+        return Poll::Ready(None);
+    }
+    let response = if let Some(mut guard) = state.try_lock() {
+        let response = guard.poll_next_side(side, cx);
+        if response.is_ready() {
+            match side {
+                Side::True => guard.classified_true.waker.ready(),
+                Side::False => guard.classified_false.waker.ready(),
+            }
+        }
+        response
+    } else {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    };
+    if let Poll::Ready(None) = response {
+        *terminated = true;
+    }
+    response
+}
+
+/// The shared state of a split made by `split_by_async_concurrent`
+type AsyncConcurrentState<I, S, P, Fut> =
+    SplitByAsyncConcurrent<I, S, ByAsyncPredicate<P>, Classify<I, Fut>>;
+
+/// A struct that implements `Stream` which returns the items that
+/// `split_by_async_concurrent` routed to one side, in the order of the
+/// underlying stream
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    Fut: Future<Output = bool>,
+{
+    stream: Arc<Mutex<AsyncConcurrentState<I, S, P, Fut>>>,
+    side: Side,
+    terminated: bool,
+}
+
+impl<I, S, P, Fut> SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    Fut: Future<Output = bool>,
+{
+    pub(crate) fn new(stream: Arc<Mutex<AsyncConcurrentState<I, S, P, Fut>>>, side: Side) -> Self {
+        Self {
+            stream,
+            side,
+            terminated: false,
+        }
+    }
+
+    /// Which side of the split this stream returns the items of
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// How many items are being classified, including the ones whose
+    /// predicate resolved but that wait for an earlier item to be routed
+    pub fn in_flight(&self) -> usize {
+        self.stream.lock().in_flight()
+    }
+}
+
+impl<I, S, P, Fut> SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        poll_next_side(&self.stream, self.side, cx, &mut self.terminated)
+    }
+}
+
+// The state is never pinned through one of its streams
+impl<I, S, P, Fut> Unpin for SplitByAsyncConcurrentStream<I, S, P, Fut> where
+    Fut: Future<Output = bool>
+{
+}
+
+impl<I, S, P, Fut> Stream for SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, P, Fut> FusedStream for SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, Fut> Drop for SplitByAsyncConcurrentStream<I, S, P, Fut>
+where
+    Fut: Future<Output = bool>,
+{
+    fn drop(&mut self) {
+        self.stream.lock().close(self.side);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    use futures::{channel::oneshot, StreamExt};
+    use tokio::time::{sleep, Instant};
+
+    use crate::{
+        testing::{poll_once, CountingWaker},
+        SplitStreamByExt,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_order_kept_when_predicates_resolve_out_of_order() {
+        let (evens, odds) = futures::stream::iter(0..20u64)
+            .split_by_async_concurrent(
+                |&n| async move {
+                    // The later items resolve first
+                    sleep(Duration::from_millis(20 - n)).await;
+                    n % 2 == 0
+                },
+                5,
+            )
+            .into_tuple();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!((0..20).step_by(2).collect::<Vec<_>>(), evens);
+        assert_eq!((1..20).step_by(2).collect::<Vec<_>>(), odds);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_predicates_run_concurrently() {
+        let start = Instant::now();
+        let (small, big) = futures::stream::iter(0..16)
+            .split_by_async_concurrent(
+                |&n| async move {
+                    sleep(Duration::from_millis(100)).await;
+                    n < 8
+                },
+                4,
+            )
+            .into_tuple();
+        let (small, big) = futures::join!(small.collect::<Vec<_>>(), big.collect::<Vec<_>>());
+        assert_eq!((0..8).collect::<Vec<_>>(), small);
+        assert_eq!((8..16).collect::<Vec<_>>(), big);
+        // 4 batches of 4 items, rather than 16 items one after another
+        assert_eq!(Duration::from_millis(400), start.elapsed());
+    }
+
+    #[test]
+    fn test_in_flight_bounded_while_other_side_stalls() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..8).map(|_| oneshot::channel()).unzip();
+        let receivers = std::sync::Mutex::new(receivers.into_iter());
+        let (mut matches, mut rest) = futures::stream::iter(0..8)
+            .split_by_async_concurrent(
+                move |_| {
+                    let receiver = receivers.lock().unwrap().next().unwrap();
+                    async move { receiver.await.unwrap() }
+                },
+                2,
+            )
+            .into_tuple();
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        assert_eq!(2, matches.in_flight());
+        let mut senders = senders.into_iter();
+        for _ in 0..4 {
+            senders.next().unwrap().send(false).unwrap();
+            assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+            assert!(matches.in_flight() <= 2);
+        }
+        // 2 items wait for `rest`, so nothing more is routed until it takes them
+        assert_eq!(2, matches.in_flight());
+        for sender in senders {
+            sender.send(true).unwrap();
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut matches, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut rest, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut rest, &waker.waker()));
+        for n in 4..8 {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut matches, &waker.waker())
+            );
+        }
+        assert_eq!(Poll::Ready(None), poll_once(&mut matches, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut rest, &waker.waker()));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_side_doesnt_hold_up_other() {
+        let (matches, rest) = futures::stream::iter(0..100)
+            .split_by_async_concurrent(|&n| futures::future::ready(n % 10 == 0), 2)
+            .into_tuple();
+        drop(rest);
+        assert_eq!(
+            (0..100).step_by(10).collect::<Vec<_>>(),
+            matches.collect::<Vec<_>>().await
+        );
+    }
+}
@@ -0,0 +1,955 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    fmt,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    debug::{self, Verbose},
+    parts::SplitParts,
+    reunite::ReuniteError,
+    ring::RingBuf,
+    split_core::{ByPredicate, Pulled, Router, SplitCore},
+    sync::{self, SharedState},
+    Side,
+};
+use futures_core::{
+    future::BoxFuture,
+    ready,
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// The shared state of a split made with `split_by_async_lock`, which is that
+/// of a buffered split kept behind an async mutex
+pub(crate) type SplitByAsyncLock<I, S, P, const N: usize> =
+    SplitCore<I, S, ByPredicate<P>, RingBuf<I, N>>;
+
+/// What the streams of a split made with `split_by_async_lock` are called
+pub(crate) const STREAMS: [&str; 2] = ["TrueSplitByAsyncLock", "FalseSplitByAsyncLock"];
+
+/// An item that a stream pulled and classified without holding the lock,
+/// along with the stream it's for and the predicate, which goes back into the
+/// shared state once the lock is taken again
+type Classified<I, P> = (I, Side, ByPredicate<P>);
+
+/// What a stream leaves for the other one when it's dropped, or its
+/// predicate panics, while it classifies an item without holding the lock.
+/// It can't wait for the lock then, so the other stream puts the predicate
+/// back the next time it takes the lock
+pub(crate) struct Left<I, P> {
+    predicate: Option<ByPredicate<P>>,
+    /// The stream that pulled the item that was classified, the item and the
+    /// stream it's for
+    item: Option<(Side, I, Side)>,
+    /// The task of the other stream while it waits for the item to be routed
+    waker: Option<Waker>,
+}
+
+impl<I, P> Default for Left<I, P> {
+    fn default() -> Self {
+        Self {
+            predicate: None,
+            item: None,
+            waker: None,
+        }
+    }
+}
+
+impl<I, P> Left<I, P> {
+    /// Leaves the predicate, and the item that was classified if there is
+    /// one, for the other stream and wakes it
+    fn leave(left: &sync::Mutex<Self>, predicate: ByPredicate<P>, item: Option<(Side, I, Side)>) {
+        let mut left = left.lock();
+        left.predicate = Some(predicate);
+        left.item = item;
+        let waker = left.waker.take();
+        drop(left);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Puts what was left back into the shared state, routing the item that
+    /// was classified. An item for the stream that was dropped is dropped
+    fn settle<S, const N: usize>(&mut self, state: &mut SplitByAsyncLock<I, S, P, N>)
+    where
+        S: Stream<Item = I> + Unpin,
+        P: Fn(&I) -> bool,
+    {
+        if let Some(predicate) = self.predicate.take() {
+            match self.item.take() {
+                Some((side, item, target)) => {
+                    let _ = state.route_classified(side, predicate, item, target);
+                }
+                None => state.finish_classifying(predicate),
+            }
+        }
+    }
+
+    /// Puts the predicate back into a split that is being taken apart, along
+    /// with the item that was classified if it's for the stream of `side`,
+    /// which is the only one left
+    fn settle_parts<S, const N: usize>(
+        &mut self,
+        state: &mut SplitByAsyncLock<I, S, P, N>,
+        side: Side,
+    ) -> Option<I> {
+        let predicate = self.predicate.take()?;
+        state.finish_classifying(predicate);
+        match self.item.take() {
+            Some((_, item, target)) if target == side => Some(item),
+            _ => None,
+        }
+    }
+}
+
+/// Puts the predicate back if it panics while an item is classified, so that
+/// the other stream doesn't wait for that item forever
+struct Classifying<'a, I, P> {
+    left: &'a sync::Mutex<Left<I, P>>,
+    predicate: Option<ByPredicate<P>>,
+}
+
+impl<I, P> Drop for Classifying<'_, I, P> {
+    fn drop(&mut self) {
+        if let Some(predicate) = self.predicate.take() {
+            Left::leave(self.left, predicate, None);
+        }
+    }
+}
+
+/// Polls for the next item of the stream of `side`. An item pulled from the
+/// underlying stream is classified without holding the lock, so a slow
+/// predicate doesn't keep the other stream from taking the items in its
+/// buffer. The item is kept in `classified` until the lock is taken again to
+/// route it
+fn poll_side<I, S, P, const N: usize>(
+    lock: &mut AsyncLock<SplitByAsyncLock<I, S, P, N>>,
+    left: &sync::Mutex<Left<I, P>>,
+    classified: &mut Option<Classified<I, P>>,
+    side: Side,
+    cx: &mut Context<'_>,
+) -> Poll<Option<I>>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    loop {
+        let mut state = ready!(lock.poll_lock(cx));
+        left.lock().settle(&mut state);
+        if let Some((item, target, predicate)) = classified.take() {
+            return state.route_classified(side, predicate, item, target);
+        }
+        let item = match state.poll_item(side, cx) {
+            Poll::Ready(Pulled::Item(item)) => item,
+            Poll::Ready(Pulled::Ready(item)) => return Poll::Ready(item),
+            Poll::Pending => {
+                if state.is_classifying() {
+                    // The other stream wakes this one once it routed the item it's classifying,
+                    // unless it's dropped before that and leaves the item here
+                    let mut left = left.lock();
+                    if left.predicate.is_some() {
+                        left.settle(&mut state);
+                        continue;
+                    }
+                    left.waker = Some(cx.waker().clone());
+                }
+                return Poll::Pending;
+            }
+        };
+        let mut classifying = Classifying {
+            left,
+            predicate: Some(state.start_classifying(side)),
+        };
+        drop(state);
+        let predicate = classifying
+            .predicate
+            .as_ref()
+            .expect("the predicate was just taken out");
+        let (target, item) = predicate.route(item);
+        let predicate = classifying
+            .predicate
+            .take()
+            .expect("the predicate didn't panic");
+        *classified = Some((item, target, predicate));
+    }
+}
+
+/// Holds a handle's reference to the shared state along with the lock
+/// acquisition that is currently in progress, if any. Keeping the future
+/// around between polls means a handle that is waiting on the lock stays in
+/// the mutex's queue and is woken when it's its turn, instead of waking itself
+/// and retrying
+struct AsyncLock<T> {
+    mutex: SharedState<Mutex<T>>,
+    acquiring: Option<BoxFuture<'static, OwnedMutexGuard<T>>>,
+}
+
+impl<T> AsyncLock<T>
+where
+    T: Send + 'static,
+{
+    fn new(mutex: Arc<Mutex<T>>) -> Self {
+        Self {
+            mutex: SharedState::new(mutex),
+            acquiring: None,
+        }
+    }
+
+    fn poll_lock(&mut self, cx: &mut Context<'_>) -> Poll<OwnedMutexGuard<T>> {
+        if self.acquiring.is_none() {
+            // Avoid allocating a future in the uncontended case
+            if let Ok(guard) = self.mutex.clone().try_lock_owned() {
+                return Poll::Ready(guard);
+            }
+            self.acquiring = Some(Box::pin(self.mutex.clone().lock_owned()));
+        }
+        match self.acquiring.as_mut().map(|fut| fut.as_mut().poll(cx)) {
+            Some(Poll::Ready(guard)) => {
+                self.acquiring = None;
+                Poll::Ready(guard)
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`. Created by `split_by_async_lock` or
+/// `split_by_buffered_async_lock`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitByAsyncLock<I, S, P, const N: usize> {
+    lock: AsyncLock<SplitByAsyncLock<I, S, P, N>>,
+    left: Arc<sync::Mutex<Left<I, P>>>,
+    classified: Option<Classified<I, P>>,
+    terminated: bool,
+    peeked: Option<I>,
+}
+
+impl<I, S, P, const N: usize> TrueSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Send + 'static,
+    P: Send + 'static,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByAsyncLock<I, S, P, N>>>,
+        left: Arc<sync::Mutex<Left<I, P>>>,
+    ) -> Self {
+        Self {
+            lock: AsyncLock::new(stream),
+            left,
+            classified: None,
+            terminated: false,
+            peeked: None,
+        }
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream. The state can't be locked without waiting while the
+    /// `false` stream is being polled, so this returns `false` in that case
+    pub fn is_source_done(&self) -> bool {
+        self.lock.mutex.try_lock().is_ok_and(|state| state.done)
+    }
+
+    /// Whether the `false` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        // The lock can't be taken when a stream is dropped, but the streams and a lock
+        // acquisition in progress are the only long lived references to the shared state
+        Arc::strong_count(&self.lock.mutex) == 1 + usize::from(self.lock.acquiring.is_some())
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `false` stream is
+    /// full. In that case this stream can only make progress once the `false`
+    /// stream is polled. Returns `false` while the `false` stream is being
+    /// polled, like `is_source_done`
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none()
+            && self.classified.is_none()
+            && !self.terminated
+            && self
+                .lock
+                .mutex
+                .try_lock()
+                .is_ok_and(|state| state.would_block_true())
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // A lock acquisition in progress holds a reference to the shared state
+        self.lock.acquiring = None;
+        let mut state = match self.lock.mutex.try_take() {
+            Some(state) => state.into_inner(),
+            None => return Err(self),
+        };
+        let mut stray = self.left.lock().settle_parts(&mut state, Side::True);
+        let mut classified_other = None;
+        if let Some((item, target, predicate)) = self.classified.take() {
+            state.finish_classifying(predicate);
+            // The item was pulled once everything before it was routed, so it goes last
+            match target {
+                Side::True => stray = Some(item),
+                _ => classified_other = Some(item),
+            }
+        }
+        let mut parts = state.into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_true.insert(0, item);
+        }
+        parts.buffered_true.extend(stray);
+        parts.buffered_false.extend(classified_other);
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `true` and `false` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        mut self,
+        mut other: FalseSplitByAsyncLock<I, S, P, N>,
+    ) -> Result<(S, Vec<I>, Vec<I>), ReuniteError<Self, FalseSplitByAsyncLock<I, S, P, N>>> {
+        if !Arc::ptr_eq(&self.lock.mutex, &other.lock.mutex) {
+            return Err(ReuniteError(self, other));
+        }
+        // Both lock acquisitions in progress hold references to the shared state
+        self.lock.acquiring = None;
+        other.lock.acquiring = None;
+        if let Some((item, target, predicate)) = other.classified.take() {
+            // The item `other` has classified is settled when `self` takes the split apart
+            Left::leave(&other.left, predicate, Some((Side::False, item, target)));
+        }
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.lock.mutex.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.lock.mutex = SharedState::new(this.lock.mutex.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok((parts.stream, parts.buffered_true, parts.buffered_false))
+    }
+}
+
+impl<I, S, P, const N: usize> TrueSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or when the other stream releases the lock
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = poll_side(
+            &mut self.lock,
+            &self.left,
+            &mut self.classified,
+            Side::True,
+            cx,
+        );
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked and classified items are never pinned
+impl<I, S, P, const N: usize> Unpin for TrueSplitByAsyncLock<I, S, P, N> {}
+
+impl<I, S, P, const N: usize> Drop for TrueSplitByAsyncLock<I, S, P, N> {
+    fn drop(&mut self) {
+        if let Some((item, target, predicate)) = self.classified.take() {
+            // The lock can't be waited for here, so the other stream routes the item
+            Left::leave(&self.left, predicate, Some((Side::True, item, target)));
+        }
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for TrueSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.lock.mutex.try_lock() {
+            Ok(guard) => guard.size_hint_true(),
+            Err(_) => (0, None),
+        };
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for TrueSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for TrueSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for TrueSplitByAsyncLock<I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock.mutex.try_lock().ok();
+        debug::fmt_split(
+            f,
+            "TrueSplitByAsyncLock",
+            state.as_deref(),
+            self.terminated,
+            SplitByAsyncLock::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, TrueSplitByAsyncLock<I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock.mutex.try_lock().ok();
+        debug::fmt_split(
+            f,
+            "TrueSplitByAsyncLock",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false`. Created by `split_by_async_lock` or
+/// `split_by_buffered_async_lock`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseSplitByAsyncLock<I, S, P, const N: usize> {
+    lock: AsyncLock<SplitByAsyncLock<I, S, P, N>>,
+    left: Arc<sync::Mutex<Left<I, P>>>,
+    classified: Option<Classified<I, P>>,
+    terminated: bool,
+    peeked: Option<I>,
+}
+
+impl<I, S, P, const N: usize> FalseSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Send + 'static,
+    P: Send + 'static,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByAsyncLock<I, S, P, N>>>,
+        left: Arc<sync::Mutex<Left<I, P>>>,
+    ) -> Self {
+        Self {
+            lock: AsyncLock::new(stream),
+            left,
+            classified: None,
+            terminated: false,
+            peeked: None,
+        }
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream. The state can't be locked without waiting while the
+    /// `true` stream is being polled, so this returns `false` in that case
+    pub fn is_source_done(&self) -> bool {
+        self.lock.mutex.try_lock().is_ok_and(|state| state.done)
+    }
+
+    /// Whether the `true` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        // The lock can't be taken when a stream is dropped, but the streams and a lock
+        // acquisition in progress are the only long lived references to the shared state
+        Arc::strong_count(&self.lock.mutex) == 1 + usize::from(self.lock.acquiring.is_some())
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `true` stream is
+    /// full. In that case this stream can only make progress once the `true`
+    /// stream is polled. Returns `false` while the `true` stream is being
+    /// polled, like `is_source_done`
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none()
+            && self.classified.is_none()
+            && !self.terminated
+            && self
+                .lock
+                .mutex
+                .try_lock()
+                .is_ok_and(|state| state.would_block_false())
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // A lock acquisition in progress holds a reference to the shared state
+        self.lock.acquiring = None;
+        let mut state = match self.lock.mutex.try_take() {
+            Some(state) => state.into_inner(),
+            None => return Err(self),
+        };
+        let mut stray = self.left.lock().settle_parts(&mut state, Side::False);
+        let mut classified_other = None;
+        if let Some((item, target, predicate)) = self.classified.take() {
+            state.finish_classifying(predicate);
+            // The item was pulled once everything before it was routed, so it goes last
+            match target {
+                Side::False => stray = Some(item),
+                _ => classified_other = Some(item),
+            }
+        }
+        let mut parts = state.into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        parts.buffered_false.extend(stray);
+        parts.buffered_true.extend(classified_other);
+        Ok(parts)
+    }
+}
+
+impl<I, S, P, const N: usize> FalseSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or when the other stream releases the lock
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = poll_side(
+            &mut self.lock,
+            &self.left,
+            &mut self.classified,
+            Side::False,
+            cx,
+        );
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked and classified items are never pinned
+impl<I, S, P, const N: usize> Unpin for FalseSplitByAsyncLock<I, S, P, N> {}
+
+impl<I, S, P, const N: usize> Drop for FalseSplitByAsyncLock<I, S, P, N> {
+    fn drop(&mut self) {
+        if let Some((item, target, predicate)) = self.classified.take() {
+            // The lock can't be waited for here, so the other stream routes the item
+            Left::leave(&self.left, predicate, Some((Side::False, item, target)));
+        }
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for FalseSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.lock.mutex.try_lock() {
+            Ok(guard) => guard.size_hint_false(),
+            Err(_) => (0, None),
+        };
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for FalseSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for FalseSplitByAsyncLock<I, S, P, N>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Send + Unpin + 'static,
+    P: Fn(&I) -> bool + Send + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for FalseSplitByAsyncLock<I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock.mutex.try_lock().ok();
+        debug::fmt_split(
+            f,
+            "FalseSplitByAsyncLock",
+            state.as_deref(),
+            self.terminated,
+            SplitByAsyncLock::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, FalseSplitByAsyncLock<I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock.mutex.try_lock().ok();
+        debug::fmt_split(
+            f,
+            "FalseSplitByAsyncLock",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    use futures::{FutureExt, StreamExt};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    #[test]
+    fn test_other_side_closed() {
+        let split = futures::stream::iter(0..3).split_by_async_lock(|&n| n % 2 == 0);
+        assert!(!split.matches.other_side_closed());
+        drop(split.rest);
+        assert!(split.matches.other_side_closed());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_predicate() {
+        let incoming = futures::stream::iter(0..20);
+        let split = incoming.split_by_buffered_async_lock::<4>(|&n| {
+            std::thread::sleep(Duration::from_millis(10));
+            n % 4 == 0
+        });
+        let true_task = tokio::spawn(split.matches.collect::<Vec<_>>());
+        let false_task = tokio::spawn(split.rest.collect::<Vec<_>>());
+        let (true_items, false_items) = tokio::join!(true_task, false_task);
+        assert_eq!(vec![0, 4, 8, 12, 16], true_items.unwrap());
+        assert_eq!(
+            (0..20).filter(|n| n % 4 != 0).collect::<Vec<_>>(),
+            false_items.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_buffered_items_while_classifying() {
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let (release, release_receiver) = std::sync::mpsc::channel::<()>();
+        let (mut true_stream, mut false_stream) = futures::stream::iter([1, 3, 5, 0])
+            .split_by_buffered_async_lock::<4>(move |&n| {
+                if n == 0 {
+                    started_sender.send(()).unwrap();
+                    release_receiver.recv().unwrap();
+                }
+                n % 2 == 0
+            })
+            .into_tuple();
+        for _ in 0..3 {
+            assert_eq!(None, true_stream.next().now_or_never());
+        }
+        let classifying =
+            std::thread::spawn(move || futures::executor::block_on(true_stream.next()));
+        started.recv().unwrap();
+        // The buffered items come out while the `true` stream is still classifying its item
+        assert_eq!(Some(Some(1)), false_stream.next().now_or_never());
+        assert_eq!(Some(Some(3)), false_stream.next().now_or_never());
+        assert_eq!(Some(Some(5)), false_stream.next().now_or_never());
+        assert_eq!(None, false_stream.next().now_or_never());
+        release.send(()).unwrap();
+        assert_eq!(Some(0), classifying.join().unwrap());
+    }
+
+    #[test]
+    fn test_into_parts_while_acquiring() {
+        let split = futures::stream::iter(0..4).split_by_buffered_async_lock::<2>(|&n| n % 2 == 0);
+        let (mut true_stream, false_stream) = split.into_tuple();
+        let held = true_stream.lock.mutex.clone().try_lock_owned().unwrap();
+        assert_eq!(None, true_stream.next().now_or_never());
+        assert!(true_stream.lock.acquiring.is_some());
+        drop(held);
+        drop(false_stream);
+        let parts = true_stream.into_parts().ok().unwrap();
+        assert!(parts.buffered_true.is_empty() && parts.buffered_false.is_empty());
+    }
+
+    #[test]
+    fn test_reunite_while_acquiring() {
+        let split = futures::stream::iter(0..4).split_by_buffered_async_lock::<2>(|&n| n % 2 == 0);
+        let (mut true_stream, mut false_stream) = split.into_tuple();
+        assert_eq!(Some(Some(0)), true_stream.next().now_or_never());
+        let held = true_stream.lock.mutex.clone().try_lock_owned().unwrap();
+        assert_eq!(None, true_stream.next().now_or_never());
+        assert_eq!(None, false_stream.next().now_or_never());
+        drop(held);
+        let (stream, buffered_true, buffered_false) =
+            true_stream.reunite(false_stream).ok().unwrap();
+        assert!(buffered_true.is_empty() && buffered_false.is_empty());
+        assert_eq!(
+            vec![1, 2, 3],
+            futures::executor::block_on(stream.collect::<Vec<_>>())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unbuffered() {
+        let incoming = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+        let (true_stream, false_stream) =
+            incoming.split_by_async_lock(|&n| n % 2 == 0).into_tuple();
+        let (evens, odds) = tokio::join!(
+            true_stream.collect::<Vec<_>>(),
+            false_stream.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 2, 4], evens);
+        assert_eq!(vec![1, 3, 5], odds);
+    }
+
+    #[test]
+    fn test_pop_wakes_full_buffer_waiter() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered_async_lock::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        for n in [1, 3, 5, 7, 0] {
+            handle.push(n);
+        }
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        while handle.poll_count() < 2 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut true_stream, &true_waker.waker())
+            );
+        }
+        // The buffer of the `false` stream is full, so the `true` stream is parked
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(0, true_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert!(true_waker.count() > 0);
+    }
+}
@@ -0,0 +1,224 @@
+use alloc::sync::Arc;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::{
+    split_by_async_concurrent::{poll_next_side, Classifier, SplitByAsyncConcurrent},
+    sync::Mutex,
+    Side,
+};
+
+/// Classifies items with a predicate that runs on the blocking thread pool of
+/// tokio, made by `split_by_blocking`. The `JoinHandle` of an item is what's
+/// in flight, and the item goes along with the predicate until it returns
+pub(crate) struct ByBlocking<P>(pub(crate) Arc<P>);
+
+impl<I, P> Classifier<I, JoinHandle<(I, bool)>> for ByBlocking<P>
+where
+    I: Send + 'static,
+    P: Fn(&I) -> bool + Send + Sync + 'static,
+{
+    fn classify(&self, item: I) -> JoinHandle<(I, bool)> {
+        let predicate = self.0.clone();
+        tokio::task::spawn_blocking(move || {
+            let matches = predicate(&item);
+            (item, matches)
+        })
+    }
+
+    fn classified(output: Result<(I, bool), JoinError>) -> (I, Side) {
+        match output {
+            Ok((item, matches)) => (item, Side::from(matches)),
+            // The stream that's polled panics with the panic of the predicate, rather than
+            // waiting for an item that never comes
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => panic!("the predicate of split_by_blocking didn't run: {}", err),
+        }
+    }
+}
+
+/// The shared state of a split made by `split_by_blocking`
+type BlockingState<I, S, P> = SplitByAsyncConcurrent<I, S, ByBlocking<P>, JoinHandle<(I, bool)>>;
+
+/// A struct that implements `Stream` which returns the items that
+/// `split_by_blocking` routed to one side, in the order of the underlying
+/// stream
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitByBlockingStream<I, S, P> {
+    stream: Arc<Mutex<BlockingState<I, S, P>>>,
+    side: Side,
+    terminated: bool,
+}
+
+impl<I, S, P> SplitByBlockingStream<I, S, P> {
+    pub(crate) fn new(stream: S, predicate: P, max_in_flight: usize) -> (Self, Self) {
+        let stream = SplitByAsyncConcurrent::new(
+            stream,
+            ByBlocking(Arc::new(predicate)),
+            max_in_flight,
+            "SplitByBlockingStream",
+        );
+        let matches = Self {
+            stream: stream.clone(),
+            side: Side::True,
+            terminated: false,
+        };
+        let rest = Self {
+            stream,
+            side: Side::False,
+            terminated: false,
+        };
+        (matches, rest)
+    }
+
+    /// Which side of the split this stream returns the items of
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// How many items are on the blocking thread pool, including the ones
+    /// whose predicate returned but that wait for an earlier item to be
+    /// routed
+    pub fn in_flight(&self) -> usize {
+        self.stream.lock().in_flight()
+    }
+}
+
+impl<I, S, P> SplitByBlockingStream<I, S, P>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool + Send + Sync + 'static,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. Panics with the panic of
+    /// the predicate if it panicked on the item that's next in line
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        poll_next_side(&self.stream, self.side, cx, &mut self.terminated)
+    }
+}
+
+// The state is never pinned through one of its streams
+impl<I, S, P> Unpin for SplitByBlockingStream<I, S, P> {}
+
+impl<I, S, P> Stream for SplitByBlockingStream<I, S, P>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool + Send + Sync + 'static,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, P> FusedStream for SplitByBlockingStream<I, S, P>
+where
+    I: Send + 'static,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool + Send + Sync + 'static,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P> Drop for SplitByBlockingStream<I, S, P> {
+    fn drop(&mut self) {
+        self.stream.lock().close(self.side);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        panic::AssertUnwindSafe,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use futures::{FutureExt, StreamExt};
+
+    use crate::SplitStreamByExt;
+
+    #[tokio::test]
+    async fn test_predicate_runs_off_the_executor() {
+        let (evens, odds) = futures::stream::iter(0..20)
+            .split_by_blocking(|&n| {
+                // A synchronous call that would stall the executor
+                thread::sleep(Duration::from_millis(1));
+                n % 2 == 0
+            })
+            .into_tuple();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!((0..20).step_by(2).collect::<Vec<_>>(), evens);
+        assert_eq!((1..20).step_by(2).collect::<Vec<_>>(), odds);
+    }
+
+    #[tokio::test]
+    async fn test_one_classification_in_flight() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (small, big) = futures::stream::iter(0..10)
+            .split_by_blocking({
+                let (running, most) = (running.clone(), most.clone());
+                move |&n| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(1));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    n < 5
+                }
+            })
+            .into_tuple();
+        let (small, big) = futures::join!(small.collect::<Vec<_>>(), big.collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 2, 3, 4], small);
+        assert_eq!(vec![5, 6, 7, 8, 9], big);
+        assert_eq!(1, most.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_poll_keeps_item() {
+        let (mut matches, _rest) = futures::stream::iter(0..3)
+            .split_by_blocking(|_| {
+                thread::sleep(Duration::from_millis(20));
+                true
+            })
+            .into_tuple();
+        // The first poll starts classifying 0, and then the consumer gives up on it
+        assert_eq!(None, matches.next().now_or_never());
+        assert_eq!(1, matches.in_flight());
+        assert_eq!(vec![0, 1, 2], matches.collect::<Vec<_>>().await);
+    }
+
+    #[tokio::test]
+    async fn test_panic_surfaces() {
+        let (mut small, _big) = futures::stream::iter(0..3)
+            .split_by_blocking(|&n| {
+                assert_ne!(n, 1, "can't classify 1");
+                true
+            })
+            .into_tuple();
+        assert_eq!(Some(0), small.next().await);
+        let panic = AssertUnwindSafe(small.next())
+            .catch_unwind()
+            .await
+            .unwrap_err();
+        assert!(panic
+            .downcast_ref::<String>()
+            .unwrap()
+            .contains("can't classify 1"));
+        // The item that panicked is gone, but the rest of the split carries on
+        assert_eq!(Some(2), small.next().await);
+    }
+}
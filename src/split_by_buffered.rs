@@ -1,195 +1,3123 @@
-use std::{
-    pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+#[cfg(feature = "metrics")]
+use alloc::borrow::Cow;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, pin::Pin, task::Poll};
+
+use crate::{
+    broadcast::{Broadcast, Subscriber},
+    buffer::Buffer,
+    completion::{Completion, CompletionTracker},
+    debug::{self, Verbose},
+    diagnostics::{Diagnostics, PendingReason},
+    parts::SplitParts,
+    reunite::ReuniteError,
+    ring::RingBuf,
+    sink_passthrough,
+    split_core::{fill, poll_unlocked, ByPredicate, SplitCore},
+    sync::{Mutex, SharedState},
+    timer::{Clock, Sleep},
+    try_next::TryNext,
+    Error, Overflow, Side, Split,
+};
+use futures_core::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
 };
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use futures_sink::Sink;
 
-use crate::ring_buf::RingBuf;
-use futures::Stream;
-use pin_project::pin_project;
+/// The shared state of a split made with `split_by_buffered` or
+/// `split_by_with_buffers`
+pub(crate) type SplitByBuffered<I, S, P, B> = SplitCore<I, S, ByPredicate<P>, B>;
 
-#[pin_project]
-pub(crate) struct SplitByBuffered<I, S, P, const N: usize> {
-    buf_true: RingBuf<I, N>,
-    buf_false: RingBuf<I, N>,
-    waker_true: Option<Waker>,
-    waker_false: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
-}
+/// What the streams of a split made with `split_by_buffered` are called
+pub(crate) const STREAMS: [&str; 2] = ["TrueSplitByBuffered", "FalseSplitByBuffered"];
 
-impl<I, S, P, const N: usize> SplitByBuffered<I, S, P, N>
+impl<I, S, P, B> SplitByBuffered<I, S, P, B>
 where
+    B: Buffer<I>,
     S: Stream<Item = I>,
     P: Fn(&I) -> bool,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            buf_false: RingBuf::new(),
-            buf_true: RingBuf::new(),
-            waker_false: None,
-            waker_true: None,
-            stream,
-            predicate,
-        }))
-    }
-
-    fn poll_next_true(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_true.is_none() {
-            *this.waker_true = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_true.pop_front() {
-            // There was already a value in the buffer. Return that value
-            return Poll::Ready(Some(item));
+    /// Creates a split that continues where the split `parts` came from
+    /// left off. The first item buffered for a stream goes in its peeked slot
+    /// if the items don't all fit in the buffer, since that slot comes first
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn split_from_parts(
+        parts: SplitParts<I, S, P>,
+    ) -> Split<TrueSplitWithBuffer<I, S, P, B>, FalseSplitWithBuffer<I, S, P, B>>
+    where
+        B: Default,
+    {
+        let stream = Self::new(
+            parts.stream,
+            ByPredicate(parts.predicate),
+            B::default(),
+            B::default(),
+            "split_by_buffered",
+            STREAMS,
+        );
+        let mut split = Split::share(
+            stream.clone(),
+            TrueSplitWithBuffer::new,
+            FalseSplitWithBuffer::new,
+        );
+        let mut state = stream.lock();
+        split.matches.peeked = fill(&mut state.buf_true, parts.buffered_true);
+        split.rest.peeked = fill(&mut state.buf_false, parts.buffered_false);
+        let buffered_true = state.buf_true.len();
+        state.order.resync(Side::True, buffered_true);
+        let buffered_false = state.buf_false.len();
+        state.order.resync(Side::False, buffered_false);
+        drop(state);
+        split
+    }
+}
+
+impl<I, S, P, B> Split<TrueSplitWithBuffer<I, S, P, B>, FalseSplitWithBuffer<I, S, P, B>>
+where
+    B: Buffer<I>,
+    I: PartialEq,
+{
+    /// Drops an item routed to a stream that isn't polling if it's equal to
+    /// the last item already waiting in the buffer of that stream, so that a
+    /// storm of identical events takes up a single slot instead of filling
+    /// the buffer and stalling the other stream. Only adjacent items are
+    /// compared, so distinct items and their order are kept. Items a stream
+    /// pulls for itself are never dropped. `coalesced` on each stream counts
+    /// the items dropped for it
+    pub fn coalesce_buffered(self) -> Self {
+        self.matches.stream.lock().coalesce = Some(<I as PartialEq>::eq);
+        self
+    }
+}
+
+impl<I, S, P, B> Split<TrueSplitWithBuffer<I, S, P, B>, FalseSplitWithBuffer<I, S, P, B>>
+where
+    B: Buffer<I>,
+{
+    /// Caps how many items both buffers hold together at `max`, on top of
+    /// the capacity of each buffer, so that either stream can use all of it
+    /// while the other one's buffer is empty. Nothing is pulled from the
+    /// underlying stream while the buffers are at the cap, and a stream
+    /// waiting for that is woken once the other stream takes an item out.
+    /// This bounds the memory of a split with unbounded buffers, like ones
+    /// made by `split_by_with_buffers` with `VecDeque`s. Items put in a
+    /// buffer with `push_front` or `push_back` count too, but they're never
+    /// refused because of the cap. Panics if `max` is 0, since nothing could
+    /// ever be pulled
+    ///
+    ///```rust
+    /// use std::collections::VecDeque;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = futures::stream::iter(0..100)
+    ///         .split_by_with_buffers(|&n| n % 2 == 0, VecDeque::new(), VecDeque::new())
+    ///         .max_buffered(16)
+    ///         .into_tuple();
+    ///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+    ///     assert_eq!(50, evens.len());
+    ///     assert_eq!(50, odds.len());
+    /// })
+    /// ```
+    pub fn max_buffered(self, max: usize) -> Self {
+        assert!(
+            max > 0,
+            "the buffers of a split have to hold at least one item"
+        );
+        self.matches.stream.lock().max_buffered = max;
+        self
+    }
+
+    /// Treats a `None` from the underlying stream as there being nothing to
+    /// pull for now rather than as its end. Both streams return `Pending`
+    /// instead of `None`, and the underlying stream is polled again the next
+    /// time either of them is. Since the underlying stream doesn't wake
+    /// anything when it has more items, something else has to wake or poll
+    /// the streams then, like a timer or a message saying a batch is ready.
+    /// Neither stream ever ends, unless the split is aborted. This is only
+    /// for underlying streams whose contract allows polling them after they
+    /// return `None`, which a fused stream doesn't
+    ///
+    ///```rust
+    /// use std::{collections::VecDeque, task::Poll};
+    /// use futures::{FutureExt, StreamExt};
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// // A source that ends each batch with `None`
+    /// let mut script = VecDeque::from([Some(0), None, Some(2)]);
+    /// let source = futures::stream::poll_fn(move |_| match script.pop_front() {
+    ///     Some(next) => Poll::Ready(next),
+    ///     None => Poll::Pending,
+    /// });
+    /// let (mut evens, _odds) = source
+    ///     .split_by_buffered::<4>(|&n| n % 2 == 0)
+    ///     .resumable_source()
+    ///     .into_tuple();
+    /// assert_eq!(Some(Some(0)), evens.next().now_or_never());
+    /// // The end of the first batch
+    /// assert_eq!(None, evens.next().now_or_never());
+    /// assert_eq!(Some(Some(2)), evens.next().now_or_never());
+    /// ```
+    pub fn resumable_source(self) -> Self {
+        self.matches.stream.lock().resumable_source = true;
+        self
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<I, S, P, B> Split<TrueSplitWithBuffer<I, S, P, B>, FalseSplitWithBuffer<I, S, P, B>>
+where
+    B: Buffer<I>,
+{
+    /// Names the split, so that it records metrics through the global
+    /// `metrics` recorder. See `Split::with_metrics` of `split_by` for the
+    /// metrics. Requires the `metrics` feature
+    pub fn with_metrics(self, name: impl Into<Cow<'static, str>>) -> Self {
+        let mut state = self.matches.stream.lock();
+        let buffered_true = state.buf_true.len();
+        let buffered_false = state.buf_false.len();
+        state
+            .metrics
+            .set_name(name.into(), buffered_true, buffered_false);
+        drop(state);
+        self
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`, made by `split_by_buffered`
+pub type TrueSplitByBuffered<I, S, P, const N: usize> = TrueSplitWithBuffer<I, S, P, RingBuf<I, N>>;
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`, buffering the items of the other stream in a `B`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitWithBuffer<I, S, P, B> {
+    stream: SharedState<Mutex<SplitByBuffered<I, S, P, B>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
+    diagnostics: Diagnostics,
+    subscribers: Broadcast<I>,
+}
+
+impl<I, S, P, B> TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByBuffered<I, S, P, B>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        // The stream records lock contention itself, since it can't get to the shared state then
+        #[cfg(feature = "diagnostics")]
+        let diagnostics = stream.lock().diagnostics.clone();
+        #[cfg(not(feature = "diagnostics"))]
+        let diagnostics = Diagnostics::default();
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+            diagnostics,
+            subscribers: Broadcast::default(),
         }
-        if this.buf_false.remaining() == 0 {
-            // The other buffer is full, so notify that stream and return pending
-            if let Some(waker) = this.waker_false {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Explains what both streams of the split have been doing, to find out
+    /// why a pipeline stopped: when each stream last returned an item, why it
+    /// last returned `Pending` and for how long, and whether it was ever
+    /// polled at all. See `Diagnosis`. Requires the `diagnostics` feature
+    #[cfg(feature = "diagnostics")]
+    pub fn diagnose(&self) -> crate::Diagnosis {
+        let state = self.stream.lock();
+        self.diagnostics.diagnose(
+            [
+                state.waker_true.is_registered(),
+                state.waker_false.is_registered(),
+            ],
+            [state.buf_true.len(), state.buf_false.len()],
+            state.done,
+        )
+    }
+
+    /// Returns a future that resolves once this stream hasn't returned an
+    /// item for `idle`, for example to raise an alert when one side of the
+    /// split goes quiet without wrapping the stream in a timeout. The time
+    /// counts from when this is called, and starts over whenever this stream
+    /// returns an item. Any number of these futures can wait at once, and
+    /// they don't keep the split alive. Once the split is dropped, they
+    /// resolve at their next deadline. Time is measured on the tokio clock.
+    /// Requires the `timer` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = futures::stream::iter(0..10)
+    ///         .split_by_buffered::<4>(|&n| n % 2 == 0)
+    ///         .into_tuple();
+    ///     let quiet = evens.idle(Duration::from_millis(10));
+    ///     futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+    ///     quiet.await;
+    /// })
+    /// ```
+    #[cfg(feature = "timer")]
+    pub fn idle(&self, idle: core::time::Duration) -> impl core::future::Future<Output = ()> {
+        self.idle_with(idle, crate::timer::TokioTimer::new())
+    }
+
+    /// Returns a future that resolves once this stream hasn't returned an
+    /// item for `idle` like `idle`, but sleeps and measures time on `timer`
+    /// instead of the tokio timer. The clock of the first of these futures
+    /// is the one the split records deliveries on
+    pub fn idle_with<C>(
+        &self,
+        idle: core::time::Duration,
+        timer: C,
+    ) -> impl core::future::Future<Output = ()>
+    where
+        C: Sleep + Clock + Send + Sync + 'static,
+    {
+        crate::idle::idle(
+            Arc::downgrade(&self.stream),
+            Side::True,
+            idle,
+            timer,
+            |state| &mut state.activity,
+        )
+    }
+
+    /// Whether the `false` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_false
+    }
+
+    /// Once the `false` stream has been dropped, makes this stream return every
+    /// item instead of leaving the items the predicate puts on the other side
+    /// to fill up a buffer nobody empties. This stream first returns what's
+    /// buffered for itself, then what was buffered for the `false` stream, and
+    /// then every item of the underlying stream in order, without calling the
+    /// predicate. The two buffers are returned one after the other because
+    /// the split doesn't record how their items were interleaved. Returns
+    /// whether this stream took over, which it can't while the `false` stream
+    /// is still alive
+    pub fn take_over(&mut self) -> bool {
+        let mut state = self.stream.lock();
+        if !state.closed_false || state.aborted {
+            return false;
         }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    Poll::Ready(Some(item))
-                } else {
-                    // This value is not what we wanted. Store it and notify other partition task if
-                    // it exists. This can't fail because we checked above that the buffer isn't
-                    // full
-                    let _ = this.buf_false.push_back(item);
-                    if let Some(waker) = this.waker_false {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                }
-            }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `false` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_false {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
+        state.taken_over = Some(Side::True);
+        // The items of the other stream aren't checked against what it returned before
+        let buffered = state.buf_false.len();
+        state.order.resync(Side::False, buffered);
+        true
+    }
+
+    /// How many items routed to this stream were dropped because they were
+    /// equal to the last item in its buffer. Always 0 unless the split was
+    /// made with `coalesce_buffered`
+    pub fn coalesced(&self) -> usize {
+        self.stream.lock().coalesced_true
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `false` stream is
+    /// full. In that case this stream can only make progress once the `false`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_true()
+    }
+
+    /// Polls for room in the buffer of the `false` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `false` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `false` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `false` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_false(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `false` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Returns an item that is already buffered for this stream, without
+    /// polling the underlying stream or registering a waker, so it never does
+    /// any work on behalf of the other stream. `None` means that nothing is
+    /// buffered right now, not that the stream has ended. Use
+    /// `try_next_or_ended` to tell those apart. This also returns `None` when
+    /// the other stream is being polled at the same time
+    pub fn try_next(&mut self) -> Option<I> {
+        self.try_next_or_ended().item()
+    }
+
+    /// Like `try_next`, but also tells whether the stream has ended
+    pub fn try_next_or_ended(&mut self) -> TryNext<I> {
+        if let Some(item) = self.peeked.take() {
+            return TryNext::Item(item);
+        }
+        if self.terminated {
+            return TryNext::Ended;
+        }
+        match self.stream.try_lock() {
+            Some(mut guard) => guard.try_next_true(),
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Takes every item buffered for this stream at once, oldest first. The
+    /// state is locked once, so nothing is routed in between, and the
+    /// underlying stream is never polled. If the buffer was full, the other
+    /// stream is woken, since it might have been waiting for room in it
+    pub fn drain_buffered(&mut self) -> Vec<I> {
+        let mut items = Vec::new();
+        self.drain_buffered_into(&mut items);
+        items
+    }
+
+    /// Like `drain_buffered`, but adds the items to the back of `items`, so
+    /// its allocation can be reused
+    pub fn drain_buffered_into(&mut self, items: &mut Vec<I>) {
+        items.extend(self.peeked.take());
+        self.stream.lock().drain_true(items, usize::MAX);
+    }
+
+    /// Keeps only the items buffered for this stream for which `f` returns
+    /// `true`, and drops the rest, like `Vec::retain`. `f` can also change the
+    /// items it keeps. The items that are kept stay in the order they were
+    /// in, the state is locked once, and the underlying stream is never
+    /// polled. The dropped items count as dropped in the metrics of the
+    /// split. If the buffer was full and anything was dropped, the other
+    /// stream is woken, since it might have been waiting for room in it.
+    /// Returns how many items were dropped
+    pub fn retain_buffered(&mut self, mut f: impl FnMut(&mut I) -> bool) -> usize {
+        let mut removed = 0;
+        if let Some(mut item) = self.peeked.take() {
+            if f(&mut item) {
+                self.peeked = Some(item);
+            } else {
+                removed += 1;
             }
-            Poll::Pending => Poll::Pending,
+        }
+        removed + self.stream.lock().retain(Side::True, f)
+    }
+
+    /// Puts an item back at the front of this stream's buffer, so that it's
+    /// returned by the next poll before anything that was already buffered.
+    /// The item is never given to the predicate and never affects the other
+    /// stream, which only sees that this stream's buffer has less space left.
+    /// Fails with `Error::Overflow` if the buffer is full, or with
+    /// `Error::Ended` if the stream has already ended, handing back the item
+    pub fn push_front(&mut self, item: I) -> Result<(), Error<I>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        // An item taken by `poll_peek` comes before everything in the buffer, so it goes back in
+        // first
+        let needed = 1 + usize::from(self.peeked.is_some());
+        if state.buf_true.remaining() < needed {
+            return Err(Error::Overflow {
+                side: Side::True,
+                item: Some(item),
+            });
+        }
+        if let Some(peeked) = self.peeked.take() {
+            let _ = state.push_front(Side::True, peeked);
+        }
+        let _ = state.push_front(Side::True, item);
+        Ok(())
+    }
+
+    /// Adds an item to the back of this stream's buffer, after the items that
+    /// are already buffered but before any items that haven't been pulled
+    /// from the underlying stream yet. Like `push_front`, the item never
+    /// affects the other stream, and it fails the same way
+    pub fn push_back(&mut self, item: I) -> Result<(), Error<I>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        match state.push_back(Side::True, item) {
+            Some(item) => Err(Error::Overflow {
+                side: Side::True,
+                item: Some(item),
+            }),
+            None => Ok(()),
         }
     }
 
-    fn poll_next_false(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<I>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_false.is_none() {
-            *this.waker_false = Some(cx.waker().clone());
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_true.insert(0, item);
+        }
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `true` and `false` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        self,
+        mut other: FalseSplitWithBuffer<I, S, P, B>,
+    ) -> Result<(S, Vec<I>, Vec<I>), ReuniteError<Self, FalseSplitWithBuffer<I, S, P, B>>> {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
         }
-        if let Some(item) = this.buf_false.pop_front() {
-            // There was already a value in the buffer. Return that value
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.stream.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.stream = SharedState::new(this.stream.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok((parts.stream, parts.buffered_true, parts.buffered_false))
+    }
+}
+
+impl<I, S, P, B> TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.router = Some(ByPredicate(predicate));
+        state.reclassify();
+    }
+}
+
+impl<I, S, P, B> TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
             return Poll::Ready(Some(item));
         }
-        if this.buf_true.remaining() == 0 {
-            // The other buffer is full, so notify that stream and return pending
-            if let Some(waker) = this.waker_true {
-                waker.wake_by_ref();
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
             }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        // Every subscriber that waits instead of dropping items needs room for this one
+        if self.subscribers.poll_room(cx).is_pending() {
             return Poll::Pending;
         }
-        match this.stream.poll_next(cx) {
+        self.diagnostics.polled(Side::True);
+        let response = poll_unlocked(&self.stream, Side::True, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                self.diagnostics
+                    .pending(Side::True, PendingReason::LockContention);
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            });
+        match &response {
             Poll::Ready(Some(item)) => {
-                if (this.predicate)(&item) {
-                    // This value is not what we wanted. Store it and notify other stream if waker
-                    // it exists. This can't fail because we checked above that the buffer isn't
-                    // full
-                    let _ = this.buf_true.push_back(item);
-                    if let Some(waker) = this.waker_true {
-                        waker.wake_by_ref();
-                    }
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Some(item))
-                }
+                self.diagnostics.delivered(Side::True);
+                self.subscribers.send(core::slice::from_ref(item));
             }
             Poll::Ready(None) => {
-                // If the underlying stream is finished, the `true` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_true {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
+                self.subscribers.end();
+                self.terminated = true;
+                self.completion.finish_true();
+                self.diagnostics.finished(Side::True);
             }
-            Poll::Pending => Poll::Pending,
+            Poll::Pending => {}
         }
+        response
     }
-}
 
-/// A struct that implements `Stream` which returns the items where the
-/// predicate returns `true`
-pub struct TrueSplitByBuffered<I, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>,
-}
+    /// Returns a stream that gets a clone of every item this stream returns
+    /// from now on, in its own buffer of up to `cap` items, so that several
+    /// consumers can each see every item of this side. This stream works
+    /// the same as before. With `Overflow::Wait`, it doesn't return an item
+    /// until the subscriber has room for it. With `Overflow::DropOldest` or
+    /// `Overflow::DropNewest`, the subscriber drops items instead and
+    /// returns `Err(Error::Lagged)` with how many it dropped. Dropping the
+    /// subscriber frees its buffer. Panics if `cap` is 0
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Overflow, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (mut evens, odds) = futures::stream::iter(0..6)
+    ///         .split_by_buffered::<8>(|&n| n % 2 == 0)
+    ///         .into_tuple();
+    ///     let audit = evens.subscribe(8, Overflow::Wait);
+    ///     let (evens, odds, audit) = futures::join!(
+    ///         evens.collect::<Vec<_>>(),
+    ///         odds.collect::<Vec<_>>(),
+    ///         audit.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 2, 4], evens);
+    ///     assert_eq!(vec![1, 3, 5], odds);
+    ///     assert_eq!(vec![Ok(0), Ok(2), Ok(4)], audit);
+    /// })
+    /// ```
+    pub fn subscribe(&mut self, cap: usize, overflow: Overflow) -> Subscriber<I>
+    where
+        I: Clone,
+    {
+        self.subscribers.subscribe(cap, overflow)
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
 
-impl<I, S, P, const N: usize> TrueSplitByBuffered<I, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>) -> Self {
-        Self { stream }
+    /// Polls for a batch of items like `poll_next_item` does for one. Once
+    /// there is an item for this stream, it's moved to the back of `items`
+    /// along with up to `limit` items in total: first the ones buffered for
+    /// this stream, taken out under a single lock, and then whatever the
+    /// underlying stream has ready for this stream, until it returns an item
+    /// for the other stream or nothing. Returns how many items were added,
+    /// where `Ready(0)` means the stream has ended, or that `limit` is 0
+    pub fn poll_next_many(
+        &mut self,
+        cx: &mut core::task::Context<'_>,
+        items: &mut Vec<I>,
+        limit: usize,
+    ) -> Poll<usize> {
+        if limit == 0 {
+            return Poll::Ready(0);
+        }
+        match self.poll_next_item(cx) {
+            Poll::Ready(Some(item)) => items.push(item),
+            Poll::Ready(None) => return Poll::Ready(0),
+            Poll::Pending => return Poll::Pending,
+        }
+        let start = items.len() - 1;
+        let room = match self.subscribers.poll_room(cx) {
+            Poll::Ready(room) => room,
+            Poll::Pending => 0,
+        };
+        self.stream.lock().drain_true(items, (limit - 1).min(room));
+        self.subscribers.send(&items[start + 1..]);
+        while items.len() - start < limit {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                // The end is returned by the next call
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+        Poll::Ready(items.len() - start)
+    }
+
+    /// Waits for at least one item and moves up to `limit` items to the back
+    /// of `items`, like `tokio::sync::mpsc::Receiver::recv_many`. See
+    /// `poll_next_many`. Returns how many items were added, where 0 means the
+    /// stream has ended. This is cancel safe: the items are only taken out in
+    /// the poll that returns them, so if the future is dropped before it
+    /// resolves, none were removed
+    pub async fn next_many(&mut self, items: &mut Vec<I>, limit: usize) -> usize {
+        core::future::poll_fn(|cx| self.poll_next_many(cx, items, limit)).await
     }
 }
 
-impl<I, S, P, const N: usize> Stream for TrueSplitByBuffered<I, S, P, N>
+// The peeked item is never pinned
+impl<I, S, P, B> Unpin for TrueSplitWithBuffer<I, S, P, B> {}
+
+impl<I, S, P, B> Stream for TrueSplitWithBuffer<I, S, P, B>
 where
+    B: Buffer<I>,
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
 {
     type Item = I;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByBuffered::poll_next_true(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
     }
 }
 
-/// A struct that implements `Stream` which returns the items where the
-/// predicate returns `false`
-pub struct FalseSplitByBuffered<I, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>,
-}
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, B> core::async_iter::AsyncIterator for TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
 
-impl<I, S, P, const N: usize> FalseSplitByBuffered<I, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByBuffered<I, S, P, N>>>) -> Self {
-        Self { stream }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
     }
 }
 
-impl<I, S, P, const N: usize> Stream for FalseSplitByBuffered<I, S, P, N>
+impl<I, S, P, B> FusedStream for TrueSplitWithBuffer<I, S, P, B>
 where
+    B: Buffer<I>,
     S: Stream<Item = I> + Unpin,
     P: Fn(&I) -> bool,
 {
-    type Item = I;
-    fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByBuffered::poll_next_false(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
-        };
-        response
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, B, T> Sink<T> for TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P, B> fmt::Debug for TrueSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByBuffered",
+            state.as_deref(),
+            self.terminated,
+            SplitByBuffered::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, B> fmt::Debug for Verbose<'_, TrueSplitWithBuffer<I, S, P, B>>
+where
+    B: Buffer<I>,
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByBuffered",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P, B> Drop for TrueSplitWithBuffer<I, S, P, B> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_true();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false`, made by `split_by_buffered`
+pub type FalseSplitByBuffered<I, S, P, const N: usize> =
+    FalseSplitWithBuffer<I, S, P, RingBuf<I, N>>;
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false`, buffering the items of the other stream in a `B`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseSplitWithBuffer<I, S, P, B> {
+    stream: SharedState<Mutex<SplitByBuffered<I, S, P, B>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
+    diagnostics: Diagnostics,
+    subscribers: Broadcast<I>,
+}
+
+impl<I, S, P, B> FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByBuffered<I, S, P, B>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        // The stream records lock contention itself, since it can't get to the shared state then
+        #[cfg(feature = "diagnostics")]
+        let diagnostics = stream.lock().diagnostics.clone();
+        #[cfg(not(feature = "diagnostics"))]
+        let diagnostics = Diagnostics::default();
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+            diagnostics,
+            subscribers: Broadcast::default(),
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Explains what both streams of the split have been doing, to find out
+    /// why a pipeline stopped: when each stream last returned an item, why it
+    /// last returned `Pending` and for how long, and whether it was ever
+    /// polled at all. See `Diagnosis`. Requires the `diagnostics` feature
+    #[cfg(feature = "diagnostics")]
+    pub fn diagnose(&self) -> crate::Diagnosis {
+        let state = self.stream.lock();
+        self.diagnostics.diagnose(
+            [
+                state.waker_true.is_registered(),
+                state.waker_false.is_registered(),
+            ],
+            [state.buf_true.len(), state.buf_false.len()],
+            state.done,
+        )
+    }
+
+    /// Returns a future that resolves once this stream hasn't returned an
+    /// item for `idle`, for example to raise an alert when one side of the
+    /// split goes quiet without wrapping the stream in a timeout. The time
+    /// counts from when this is called, and starts over whenever this stream
+    /// returns an item. Any number of these futures can wait at once, and
+    /// they don't keep the split alive. Once the split is dropped, they
+    /// resolve at their next deadline. Time is measured on the tokio clock.
+    /// Requires the `timer` feature
+    ///
+    ///```rust
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use split_stream_by::SplitStreamByExt;
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, odds) = futures::stream::iter(0..10)
+    ///         .split_by_buffered::<4>(|&n| n % 2 == 0)
+    ///         .into_tuple();
+    ///     let quiet = evens.idle(Duration::from_millis(10));
+    ///     futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+    ///     quiet.await;
+    /// })
+    /// ```
+    #[cfg(feature = "timer")]
+    pub fn idle(&self, idle: core::time::Duration) -> impl core::future::Future<Output = ()> {
+        self.idle_with(idle, crate::timer::TokioTimer::new())
+    }
+
+    /// Returns a future that resolves once this stream hasn't returned an
+    /// item for `idle` like `idle`, but sleeps and measures time on `timer`
+    /// instead of the tokio timer. The clock of the first of these futures
+    /// is the one the split records deliveries on
+    pub fn idle_with<C>(
+        &self,
+        idle: core::time::Duration,
+        timer: C,
+    ) -> impl core::future::Future<Output = ()>
+    where
+        C: Sleep + Clock + Send + Sync + 'static,
+    {
+        crate::idle::idle(
+            Arc::downgrade(&self.stream),
+            Side::False,
+            idle,
+            timer,
+            |state| &mut state.activity,
+        )
+    }
+
+    /// Whether the `true` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_true
+    }
+
+    /// Once the `true` stream has been dropped, makes this stream return every
+    /// item instead of leaving the items the predicate puts on the other side
+    /// to fill up a buffer nobody empties. This stream first returns what's
+    /// buffered for itself, then what was buffered for the `true` stream, and
+    /// then every item of the underlying stream in order, without calling the
+    /// predicate. The two buffers are returned one after the other because
+    /// the split doesn't record how their items were interleaved. Returns
+    /// whether this stream took over, which it can't while the `true` stream
+    /// is still alive
+    pub fn take_over(&mut self) -> bool {
+        let mut state = self.stream.lock();
+        if !state.closed_true || state.aborted {
+            return false;
+        }
+        state.taken_over = Some(Side::False);
+        // The items of the other stream aren't checked against what it returned before
+        let buffered = state.buf_true.len();
+        state.order.resync(Side::True, buffered);
+        true
+    }
+
+    /// How many items routed to this stream were dropped because they were
+    /// equal to the last item in its buffer. Always 0 unless the split was
+    /// made with `coalesce_buffered`
+    pub fn coalesced(&self) -> usize {
+        self.stream.lock().coalesced_false
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `true` stream is
+    /// full. In that case this stream can only make progress once the `true`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_false()
+    }
+
+    /// Polls for room in the buffer of the `true` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `true` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `true` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `true` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_true(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `true` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Returns an item that is already buffered for this stream, without
+    /// polling the underlying stream or registering a waker, so it never does
+    /// any work on behalf of the other stream. `None` means that nothing is
+    /// buffered right now, not that the stream has ended. Use
+    /// `try_next_or_ended` to tell those apart. This also returns `None` when
+    /// the other stream is being polled at the same time
+    pub fn try_next(&mut self) -> Option<I> {
+        self.try_next_or_ended().item()
+    }
+
+    /// Like `try_next`, but also tells whether the stream has ended
+    pub fn try_next_or_ended(&mut self) -> TryNext<I> {
+        if let Some(item) = self.peeked.take() {
+            return TryNext::Item(item);
+        }
+        if self.terminated {
+            return TryNext::Ended;
+        }
+        match self.stream.try_lock() {
+            Some(mut guard) => guard.try_next_false(),
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Takes every item buffered for this stream at once, oldest first. The
+    /// state is locked once, so nothing is routed in between, and the
+    /// underlying stream is never polled. If the buffer was full, the other
+    /// stream is woken, since it might have been waiting for room in it
+    pub fn drain_buffered(&mut self) -> Vec<I> {
+        let mut items = Vec::new();
+        self.drain_buffered_into(&mut items);
+        items
+    }
+
+    /// Like `drain_buffered`, but adds the items to the back of `items`, so
+    /// its allocation can be reused
+    pub fn drain_buffered_into(&mut self, items: &mut Vec<I>) {
+        items.extend(self.peeked.take());
+        self.stream.lock().drain_false(items, usize::MAX);
+    }
+
+    /// Keeps only the items buffered for this stream for which `f` returns
+    /// `true`, and drops the rest, like `Vec::retain`. `f` can also change the
+    /// items it keeps. The items that are kept stay in the order they were
+    /// in, the state is locked once, and the underlying stream is never
+    /// polled. The dropped items count as dropped in the metrics of the
+    /// split. If the buffer was full and anything was dropped, the other
+    /// stream is woken, since it might have been waiting for room in it.
+    /// Returns how many items were dropped
+    pub fn retain_buffered(&mut self, mut f: impl FnMut(&mut I) -> bool) -> usize {
+        let mut removed = 0;
+        if let Some(mut item) = self.peeked.take() {
+            if f(&mut item) {
+                self.peeked = Some(item);
+            } else {
+                removed += 1;
+            }
+        }
+        removed + self.stream.lock().retain(Side::False, f)
+    }
+
+    /// Puts an item back at the front of this stream's buffer, so that it's
+    /// returned by the next poll before anything that was already buffered.
+    /// The item is never given to the predicate and never affects the other
+    /// stream, which only sees that this stream's buffer has less space left.
+    /// Fails with `Error::Overflow` if the buffer is full, or with
+    /// `Error::Ended` if the stream has already ended, handing back the item
+    pub fn push_front(&mut self, item: I) -> Result<(), Error<I>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        // An item taken by `poll_peek` comes before everything in the buffer, so it goes back in
+        // first
+        let needed = 1 + usize::from(self.peeked.is_some());
+        if state.buf_false.remaining() < needed {
+            return Err(Error::Overflow {
+                side: Side::False,
+                item: Some(item),
+            });
+        }
+        if let Some(peeked) = self.peeked.take() {
+            let _ = state.push_front(Side::False, peeked);
+        }
+        let _ = state.push_front(Side::False, item);
+        Ok(())
+    }
+
+    /// Adds an item to the back of this stream's buffer, after the items that
+    /// are already buffered but before any items that haven't been pulled
+    /// from the underlying stream yet. Like `push_front`, the item never
+    /// affects the other stream, and it fails the same way
+    pub fn push_back(&mut self, item: I) -> Result<(), Error<I>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        match state.push_back(Side::False, item) {
+            Some(item) => Err(Error::Overflow {
+                side: Side::False,
+                item: Some(item),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_predicate_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, S, P, B> FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.router = Some(ByPredicate(predicate));
+        state.reclassify();
+    }
+}
+
+impl<I, S, P, B> FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        // Every subscriber that waits instead of dropping items needs room for this one
+        if self.subscribers.poll_room(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.diagnostics.polled(Side::False);
+        let response = poll_unlocked(&self.stream, Side::False, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                self.diagnostics
+                    .pending(Side::False, PendingReason::LockContention);
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            });
+        match &response {
+            Poll::Ready(Some(item)) => {
+                self.diagnostics.delivered(Side::False);
+                self.subscribers.send(core::slice::from_ref(item));
+            }
+            Poll::Ready(None) => {
+                self.subscribers.end();
+                self.terminated = true;
+                self.completion.finish_false();
+                self.diagnostics.finished(Side::False);
+            }
+            Poll::Pending => {}
+        }
+        response
+    }
+
+    /// Returns a stream that gets a clone of every item this stream returns
+    /// from now on, in its own buffer of up to `cap` items, so that several
+    /// consumers can each see every item of this side. This stream works
+    /// the same as before. With `Overflow::Wait`, it doesn't return an item
+    /// until the subscriber has room for it. With `Overflow::DropOldest` or
+    /// `Overflow::DropNewest`, the subscriber drops items instead and
+    /// returns `Err(Error::Lagged)` with how many it dropped. Dropping the
+    /// subscriber frees its buffer. Panics if `cap` is 0
+    ///
+    ///```rust
+    /// use futures::StreamExt;
+    /// use split_stream_by::{Overflow, SplitStreamByExt};
+    ///
+    /// tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let (evens, mut odds) = futures::stream::iter(0..6)
+    ///         .split_by_buffered::<8>(|&n| n % 2 == 0)
+    ///         .into_tuple();
+    ///     let audit = odds.subscribe(8, Overflow::Wait);
+    ///     let (evens, odds, audit) = futures::join!(
+    ///         evens.collect::<Vec<_>>(),
+    ///         odds.collect::<Vec<_>>(),
+    ///         audit.collect::<Vec<_>>()
+    ///     );
+    ///     assert_eq!(vec![0, 2, 4], evens);
+    ///     assert_eq!(vec![1, 3, 5], odds);
+    ///     assert_eq!(vec![Ok(1), Ok(3), Ok(5)], audit);
+    /// })
+    /// ```
+    pub fn subscribe(&mut self, cap: usize, overflow: Overflow) -> Subscriber<I>
+    where
+        I: Clone,
+    {
+        self.subscribers.subscribe(cap, overflow)
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+
+    /// Polls for a batch of items like `poll_next_item` does for one. Once
+    /// there is an item for this stream, it's moved to the back of `items`
+    /// along with up to `limit` items in total: first the ones buffered for
+    /// this stream, taken out under a single lock, and then whatever the
+    /// underlying stream has ready for this stream, until it returns an item
+    /// for the other stream or nothing. Returns how many items were added,
+    /// where `Ready(0)` means the stream has ended, or that `limit` is 0
+    pub fn poll_next_many(
+        &mut self,
+        cx: &mut core::task::Context<'_>,
+        items: &mut Vec<I>,
+        limit: usize,
+    ) -> Poll<usize> {
+        if limit == 0 {
+            return Poll::Ready(0);
+        }
+        match self.poll_next_item(cx) {
+            Poll::Ready(Some(item)) => items.push(item),
+            Poll::Ready(None) => return Poll::Ready(0),
+            Poll::Pending => return Poll::Pending,
+        }
+        let start = items.len() - 1;
+        let room = match self.subscribers.poll_room(cx) {
+            Poll::Ready(room) => room,
+            Poll::Pending => 0,
+        };
+        self.stream.lock().drain_false(items, (limit - 1).min(room));
+        self.subscribers.send(&items[start + 1..]);
+        while items.len() - start < limit {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                // The end is returned by the next call
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+        Poll::Ready(items.len() - start)
+    }
+
+    /// Waits for at least one item and moves up to `limit` items to the back
+    /// of `items`, like `tokio::sync::mpsc::Receiver::recv_many`. See
+    /// `poll_next_many`. Returns how many items were added, where 0 means the
+    /// stream has ended. This is cancel safe: the items are only taken out in
+    /// the poll that returns them, so if the future is dropped before it
+    /// resolves, none were removed
+    pub async fn next_many(&mut self, items: &mut Vec<I>, limit: usize) -> usize {
+        core::future::poll_fn(|cx| self.poll_next_many(cx, items, limit)).await
+    }
+}
+
+// The peeked item is never pinned
+impl<I, S, P, B> Unpin for FalseSplitWithBuffer<I, S, P, B> {}
+
+impl<I, S, P, B> Stream for FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        };
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, B> core::async_iter::AsyncIterator for FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, B> FusedStream for FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, B, T> Sink<T> for FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P, B> fmt::Debug for FalseSplitWithBuffer<I, S, P, B>
+where
+    B: Buffer<I>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByBuffered",
+            state.as_deref(),
+            self.terminated,
+            SplitByBuffered::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, B> fmt::Debug for Verbose<'_, FalseSplitWithBuffer<I, S, P, B>>
+where
+    B: Buffer<I>,
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByBuffered",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P, B> Drop for FalseSplitWithBuffer<I, S, P, B> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_false();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+        rc::Rc,
+        task::Poll,
+    };
+
+    use futures::{stream::FusedStream, Stream, StreamExt};
+
+    use crate::{
+        testing::{
+            poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream, ManualStreamHandle,
+        },
+        Buffer, Error, ReuniteError, Side, Split, SplitStreamByExt, TryNext,
+    };
+
+    #[test]
+    fn test_buffers_until_full() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        for n in [0, 2, 4, 1] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        // The true buffer is full now, so the false stream has to wait on the true stream
+        // without polling the source
+        let polls = handle.poll_count();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(polls, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(4)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert!(false_waker.count() > 0);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_borrowed_items_and_predicate() {
+        let data = [1u8, 5, 2, 7];
+        let threshold = &4;
+        let (mut big, small) = futures::stream::iter(data.iter())
+            .split_by_buffered::<2>(|&&n| n > *threshold)
+            .into_tuple();
+        // The 1 is buffered for the other stream on the way to the 5
+        let waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Ready(Some(&5)),
+            poll_until_ready_with_waker(&mut big, &waker.waker(), 2)
+        );
+        let (_, buffered_true, buffered_false) = big.reunite(small).ok().unwrap();
+        assert!(buffered_true.is_empty());
+        assert_eq!(vec![&1], buffered_false);
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let incoming_stream = futures::stream::iter([0, 1, 2, 3, 4, 5]);
+        let (mut true_stream, mut false_stream) = incoming_stream
+            .split_by_buffered::<3>(|&n| n % 2 == 0)
+            .into_tuple();
+        assert_eq!((0, Some(6)), true_stream.size_hint());
+        assert_eq!((0, Some(6)), false_stream.size_hint());
+        let waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        // 1 is buffered for the false stream
+        assert_eq!((0, Some(4)), true_stream.size_hint());
+        assert_eq!((1, Some(5)), false_stream.size_hint());
+        for n in [2, 4] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 2)
+            );
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        // Everything has been pulled from the source, but it hasn't reported that it's done yet
+        assert_eq!((0, Some(0)), true_stream.size_hint());
+        assert_eq!((3, Some(3)), false_stream.size_hint());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!((0, Some(0)), true_stream.size_hint());
+        assert_eq!((2, Some(2)), false_stream.size_hint());
+    }
+
+    #[test]
+    fn test_poll_after_termination() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.end();
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert!(true_stream.is_terminated());
+        assert!(!false_stream.is_terminated());
+        let polls = handle.poll_count();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert!(false_stream.is_terminated());
+        // Neither stream polls the source again once it's finished
+        assert_eq!(polls, handle.poll_count());
+    }
+
+    #[tokio::test]
+    async fn test_select_without_fuse() {
+        let incoming_stream = futures::stream::iter(0..10);
+        let (mut true_stream, mut false_stream) = incoming_stream
+            .split_by_buffered::<2>(|&n| n % 3 == 0)
+            .into_tuple();
+        let mut true_items = Vec::new();
+        let mut false_items = Vec::new();
+        loop {
+            futures::select! {
+                item = true_stream.next() => true_items.extend(item),
+                item = false_stream.next() => false_items.extend(item),
+                complete => break,
+            }
+        }
+        assert_eq!(vec![0, 3, 6, 9], true_items);
+        assert_eq!(vec![1, 2, 4, 5, 7, 8], false_items);
+    }
+
+    #[test]
+    fn test_debug() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(
+            "TrueSplitByBuffered { buffered_true: 0, buffered_false: 1, capacity: 2, \
+             waker_true: true, waker_false: false, done: false, closed_true: false, \
+             closed_false: false, aborted: false, terminated: false }",
+            format!("{:?}", true_stream)
+        );
+        let verbose = format!("{:?}", false_stream.verbose());
+        assert!(verbose.contains("items_true: [], items_false: [1]"));
+        // Formatting while the state is locked, such as from inside a poll, must not block
+        let _guard = true_stream.stream.lock();
+        assert_eq!(
+            "FalseSplitByBuffered { state: <locked>, terminated: false }",
+            format!("{:?}", false_stream)
+        );
+    }
+
+    #[test]
+    fn test_reunite() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<3>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(3);
+        handle.push(4);
+        handle.push(5);
+        assert_eq!(
+            Poll::Ready(Some(4)),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 3)
+        );
+        let (mut source, buf_true, buf_false) = true_stream.reunite(false_stream).unwrap();
+        assert_eq!(Vec::<i32>::new(), buf_true);
+        assert_eq!(vec![1, 3], buf_false);
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut source, &waker.waker()));
+    }
+
+    #[test]
+    fn test_reunite_peeked() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<3>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new().waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        handle.push(1);
+        handle.push(2);
+        handle.push(3);
+        // Peeking buffers 1 for the other stream, just like polling would
+        assert_eq!(Poll::Pending, true_stream.poll_peek(&mut cx));
+        assert_eq!(Poll::Ready(Some(&2)), true_stream.poll_peek(&mut cx));
+        assert_eq!(Poll::Ready(Some(&1)), false_stream.poll_peek(&mut cx));
+        let (_, buf_true, buf_false) = true_stream.reunite(false_stream).unwrap();
+        assert_eq!(vec![2], buf_true);
+        assert_eq!(vec![1], buf_false);
+    }
+
+    #[test]
+    fn test_try_next() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<3>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        assert_eq!(None, true_stream.try_next());
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        handle.push(0);
+        handle.push(2);
+        handle.push(1);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut false_stream, &false_waker.waker(), 3)
+        );
+        let false_wakes = false_waker.count();
+        let polls = handle.poll_count();
+        assert_eq!(TryNext::Item(0), true_stream.try_next_or_ended());
+        assert_eq!(Some(2), true_stream.try_next());
+        assert_eq!(TryNext::Empty, true_stream.try_next_or_ended());
+        // Neither the underlying stream nor the other stream were touched
+        assert_eq!(polls, handle.poll_count());
+        assert_eq!(false_wakes, false_waker.count());
+        assert_eq!(0, true_waker.count());
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(TryNext::Ended, true_stream.try_next_or_ended());
+    }
+
+    #[test]
+    fn test_push_front() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(0);
+        handle.push(2);
+        handle.push(4);
+        handle.push(1);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut false_stream, &waker.waker(), 4)
+        );
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(Ok(()), true_stream.push_front(0));
+        assert_eq!(Ok(()), true_stream.push_back(100));
+        // The buffer holds 0, 2, 4 and 100 now
+        assert_eq!(
+            Err(Error::Overflow {
+                side: Side::True,
+                item: Some(101)
+            }),
+            true_stream.push_back(101)
+        );
+        assert_eq!(
+            Some(-2),
+            true_stream.push_front(-2).unwrap_err().into_item()
+        );
+        handle.push(6);
+        handle.end();
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = poll_once(&mut true_stream, &waker.waker()) {
+            items.push(item);
+        }
+        assert_eq!(vec![0, 2, 4, 100, 6], items);
+        assert_eq!(
+            Err(Error::Ended { item: Some(8) }),
+            true_stream.push_front(8)
+        );
+    }
+
+    #[test]
+    fn test_state_accessors() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        assert!(!true_stream.is_source_done());
+        assert!(!true_stream.would_block_on_other());
+        handle.push(1);
+        handle.push(3);
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        // The buffer of the false stream is full now
+        assert!(true_stream.would_block_on_other());
+        assert!(!false_stream.would_block_on_other());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert!(!true_stream.would_block_on_other());
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert!(true_stream.is_source_done());
+        assert!(false_stream.is_source_done());
+        assert!(!true_stream.other_side_closed());
+        drop(false_stream);
+        assert!(true_stream.other_side_closed());
+    }
+
+    #[test]
+    fn test_other_side_closed() {
+        let (true_stream, false_stream) = futures::stream::iter(0..3)
+            .split_by_buffered::<2>(|&n| n % 2 == 0)
+            .into_tuple();
+        assert!(!false_stream.other_side_closed());
+        drop(true_stream);
+        assert!(false_stream.other_side_closed());
+    }
+
+    #[test]
+    fn test_draining_full_buffer_wakes_other_stream() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let (true_waker, false_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [1, 3, 5, 7, 0] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut true_stream, &true_waker.waker(), 4)
+        );
+        // Only the false stream is polled from here on, and it takes its items out slowly. The
+        // true stream has to be woken once there's room, or it never pulls the 0 meant for it
+        let wakes = true_waker.count();
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(wakes + 1, true_waker.count());
+        for n in [3, 5] {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut true_stream, &true_waker.waker())
+            );
+            let wakes = true_waker.count();
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut false_stream, &false_waker.waker())
+            );
+            assert_eq!(wakes + 1, true_waker.count());
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_single_task_skips_redundant_wakes() {
+        let (mut true_stream, mut false_stream) = futures::stream::iter(0..100)
+            .split_by_buffered::<4>(|&n| n % 10 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let mut items = Vec::new();
+        while !true_stream.is_terminated() || !false_stream.is_terminated() {
+            if let Poll::Ready(Some(n)) = poll_once(&mut true_stream, &waker.waker()) {
+                items.push(n);
+            }
+            if let Poll::Ready(Some(n)) = poll_once(&mut false_stream, &waker.waker()) {
+                items.push(n);
+            }
+        }
+        items.sort_unstable();
+        assert_eq!((0..100).collect::<Vec<_>>(), items);
+        // The false stream returned each item the true stream routed to it on its next poll, so it
+        // was never waiting when one was routed. Waking it for every one of them woke the task 81
+        // times
+        assert_eq!(0, waker.count());
+    }
+
+    #[test]
+    fn test_two_tasks_woken_once_per_pending() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let (true_waker, false_waker) = (CountingWaker::new(), CountingWaker::new());
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        handle.push(0);
+        handle.push(2);
+        for _ in 0..2 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut false_stream, &false_waker.waker())
+            );
+        }
+        // Both items were routed to the true stream, which was woken for the first one and hasn't
+        // polled since
+        assert_eq!(1, true_waker.count());
+        for n in [0, 2] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut true_stream, &true_waker.waker())
+            );
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        handle.push(4);
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(2, true_waker.count());
+    }
+
+    #[test]
+    fn test_take_over() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 5] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 4)
+        );
+        assert!(true_stream.would_block_on_other());
+        assert!(!true_stream.take_over());
+        drop(false_stream);
+        assert!(true_stream.take_over());
+        assert!(!true_stream.would_block_on_other());
+        for n in [6, 7] {
+            handle.push(n);
+        }
+        handle.end();
+        let items = futures::executor::block_on(true_stream.collect::<Vec<_>>());
+        assert_eq!(vec![1, 3, 5, 6, 7], items);
+    }
+
+    #[test]
+    fn test_take_over_drains_own_buffer_first() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 0] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 4)
+        );
+        true_stream.push_back(10).unwrap();
+        drop(false_stream);
+        assert!(true_stream.take_over());
+        handle.push(4);
+        handle.push(5);
+        handle.end();
+        let items = futures::executor::block_on(true_stream.collect::<Vec<_>>());
+        assert_eq!(vec![10, 1, 3, 4, 5], items);
+    }
+
+    fn is_even(n: &i32) -> bool {
+        n % 2 == 0
+    }
+
+    fn is_odd(n: &i32) -> bool {
+        n % 2 != 0
+    }
+
+    #[test]
+    fn test_set_predicate() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(is_even as fn(&i32) -> bool)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(0);
+        handle.push(2);
+        handle.push(3);
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 2)
+        );
+        false_stream.set_predicate(is_odd);
+        // 1 was already buffered, so it stays in the false stream
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_set_predicate_reclassify() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(is_even as fn(&i32) -> bool)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(3);
+        handle.push(5);
+        handle.push(0);
+        handle.push(2);
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 4)
+        );
+        true_stream.set_predicate_reclassify(|&n| n < 4);
+        // 1 and 3 move to the true stream, 5 stays and 2 is pulled with the new predicate
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(5)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_set_predicate_reclassify_full_buffers() {
+        let (source, _handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(is_even as fn(&i32) -> bool)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 2, 4, 6] {
+            true_stream.push_back(n).unwrap();
+        }
+        for n in [1, 3, 5, 7] {
+            false_stream.push_back(n).unwrap();
+        }
+        // Three items want to move to the true stream but only one has to leave it, so two
+        // stay in the false stream where they were
+        true_stream.set_predicate_reclassify(|&n| n < 6);
+        let mut taken_true = Vec::new();
+        while let Poll::Ready(Some(n)) = poll_once(&mut true_stream, &waker.waker()) {
+            taken_true.push(n);
+        }
+        let mut taken_false = Vec::new();
+        while let Poll::Ready(Some(n)) = poll_once(&mut false_stream, &waker.waker()) {
+            taken_false.push(n);
+        }
+        assert_eq!(vec![0, 2, 4, 1], taken_true);
+        assert_eq!(vec![3, 5, 7, 6], taken_false);
+        // Every item is on the side of either the new predicate or the one it was buffered for
+        assert!(taken_true.iter().all(|n| n < &6 || is_even(n)));
+        assert!(taken_false.iter().all(|n| n >= &6 || !is_even(n)));
+    }
+
+    #[test]
+    fn test_replace_stream() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(3);
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 3)
+        );
+        assert!(true_stream.is_terminated());
+        let (new_source, new_handle) = ManualStream::new();
+        let wakes = waker.count();
+        assert!(matches!(false_stream.replace_stream(new_source), Ok(None)));
+        assert!(waker.count() > wakes);
+        assert!(!true_stream.is_terminated());
+        assert!(!false_stream.is_source_done());
+        new_handle.push(2);
+        new_handle.push(5);
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        // The items buffered from the old stream come first
+        let items = futures::executor::block_on_stream(&mut false_stream)
+            .take(3)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 3, 5], items);
+        // A stream that hasn't ended is returned
+        let (old_source, old_handle) = ManualStream::new();
+        assert!(matches!(
+            true_stream.replace_stream(old_source),
+            Ok(Some(_))
+        ));
+        old_handle.push(4);
+        assert_eq!(
+            Poll::Ready(Some(4)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        true_stream.abort();
+        assert!(false_stream.replace_stream(ManualStream::new().0).is_err());
+    }
+
+    #[test]
+    fn test_with_stream() {
+        let (mut evens, _odds) = futures::stream::iter(0..10)
+            .split_by_buffered::<4>(|&n| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut evens, &waker.waker()));
+        assert_eq!(
+            (9, Some(9)),
+            evens.with_stream_ref(|stream| stream.size_hint())
+        );
+        // An item taken from the underlying stream directly never reaches the split
+        assert_eq!(
+            Some(Some(1)),
+            evens.with_stream_mut(|stream| futures::FutureExt::now_or_never(stream.next()))
+        );
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker.waker()));
+    }
+
+    #[test]
+    fn test_with_stream_while_polled() {
+        let (evens, odds) = futures::stream::iter(0..100)
+            .split_by_buffered::<4>(|&n| n % 2 == 0)
+            .into_tuple();
+        let evens = std::thread::scope(|scope| {
+            let evens = scope.spawn(move || futures::executor::block_on(evens.collect::<Vec<_>>()));
+            // The even stream fills the buffer of the odd one in the meantime and waits for it
+            for _ in 0..1_000 {
+                let (_, upper) = odds.with_stream_mut(|stream| stream.size_hint());
+                assert!(upper.is_some_and(|upper| upper <= 100));
+            }
+            let odds = futures::executor::block_on(odds.collect::<Vec<_>>());
+            assert_eq!((1..100).step_by(2).collect::<Vec<_>>(), odds);
+            evens.join().unwrap()
+        });
+        assert_eq!((0..100).step_by(2).collect::<Vec<_>>(), evens);
+    }
+
+    #[test]
+    fn test_poll_capacity() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let capacity_waker = CountingWaker::new();
+        let capacity_cx_waker = capacity_waker.waker();
+        let mut cx = std::task::Context::from_waker(&capacity_cx_waker);
+        assert_eq!(Poll::Ready(2), true_stream.poll_capacity(&mut cx));
+        for n in [1, 3, 5] {
+            handle.push(n);
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Ready(1), true_stream.poll_capacity(&mut cx));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Pending, true_stream.poll_capacity(&mut cx));
+        assert_eq!(0, capacity_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(1, capacity_waker.count());
+        assert_eq!(1, futures::executor::block_on(true_stream.ready()));
+        // Filling the buffer up again, and then dropping the stream it belongs to, means no room
+        // will ever free up
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Pending, true_stream.poll_capacity(&mut cx));
+        drop(false_stream);
+        assert_eq!(2, capacity_waker.count());
+        assert_eq!(Poll::Ready(0), true_stream.poll_capacity(&mut cx));
+    }
+
+    #[test]
+    fn test_reunite_different_splits() {
+        fn is_zero(n: &i32) -> bool {
+            *n == 0
+        }
+        let (true_a, false_a) = futures::stream::iter(0..3)
+            .split_by_buffered::<2>(is_zero)
+            .into_tuple();
+        let (true_b, false_b) = futures::stream::iter(0..3)
+            .split_by_buffered::<2>(is_zero)
+            .into_tuple();
+        let ReuniteError(true_a, false_b) = true_a.reunite(false_b).unwrap_err();
+        assert!(true_a.reunite(false_a).is_ok());
+        assert!(true_b.reunite(false_b).is_ok());
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<3>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.push(3);
+        handle.push(5);
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        let true_stream = match true_stream.into_parts() {
+            Ok(_) => panic!("the false stream still exists"),
+            Err(true_stream) => true_stream,
+        };
+        drop(false_stream);
+        let mut parts = true_stream.into_parts().unwrap();
+        assert_eq!(Vec::<i32>::new(), parts.buffered_true);
+        assert_eq!(vec![1, 3], parts.buffered_false);
+        assert!((parts.predicate)(&4));
+        assert_eq!(
+            Poll::Ready(Some(5)),
+            poll_once(&mut parts.stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_default_buffer_size() {
+        let (source, handle) = ManualStream::new();
+        let Split { mut matches, .. } = source.split_by_buffered_default(|&n: &i32| n % 2 == 0);
+        let waker = CountingWaker::new();
+        for n in 0..20 {
+            handle.push(n * 2 + 1);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut matches, &waker.waker(), 20)
+        );
+        // Changing the default is a breaking change for anyone relying on it
+        assert_eq!(16, crate::DEFAULT_BUFFER_SIZE);
+        assert_eq!(crate::DEFAULT_BUFFER_SIZE, handle.poll_count());
+    }
+
+    #[tokio::test]
+    async fn test_dropping_split_wakes_parked_task() {
+        use std::{
+            sync::{Arc, Mutex},
+            time::Duration,
+        };
+
+        let (true_stream, false_stream) = futures::stream::iter([1, 3])
+            .split_by_buffered::<1>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        // The task only reaches the stream through the slot, so the stream can be dropped while
+        // the task waits on it
+        let slot = Arc::new(Mutex::new(Some(true_stream)));
+        let task = tokio::spawn({
+            let slot = slot.clone();
+            std::future::poll_fn(move |cx| match slot.lock().unwrap().as_mut() {
+                Some(true_stream) => true_stream.poll_next_unpin(cx),
+                None => Poll::Ready(None),
+            })
+        });
+        // The task buffers 1 and then waits for the `false` stream to take it
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+        assert!(!task.is_finished());
+        drop(false_stream);
+        let true_stream = slot.lock().unwrap().take();
+        drop(true_stream);
+        let item = tokio::time::timeout(Duration::from_secs(5), task).await;
+        assert_eq!(None, item.expect("the parked task was woken").unwrap());
+    }
+
+    /// Holds items until their total weight would go past `max`
+    #[derive(Default)]
+    struct Weighted {
+        items: std::collections::VecDeque<u32>,
+        max: u32,
+    }
+
+    impl Buffer<u32> for Weighted {
+        fn len(&self) -> usize {
+            self.items.len()
+        }
+
+        fn remaining(&self) -> usize {
+            let weight: u32 = self.items.iter().sum();
+            // Another item fits as long as the buffer isn't over its weight yet
+            usize::from(weight < self.max)
+        }
+
+        fn push_back(&mut self, item: u32) -> Option<u32> {
+            if self.remaining() == 0 {
+                return Some(item);
+            }
+            self.items.push_back(item);
+            None
+        }
+
+        fn push_front(&mut self, item: u32) -> Option<u32> {
+            if self.remaining() == 0 {
+                return Some(item);
+            }
+            self.items.push_front(item);
+            None
+        }
+
+        fn pop_front(&mut self) -> Option<u32> {
+            self.items.pop_front()
+        }
+
+        fn get(&self, index: usize) -> Option<&u32> {
+            self.items.get(index)
+        }
+    }
+
+    #[test]
+    fn test_custom_buffer() {
+        let (source, handle) = ManualStream::new();
+        let (mut small, mut big) = source
+            .split_by_with_buffers(
+                |&n: &u32| n < 10,
+                Weighted {
+                    max: 100,
+                    ..Weighted::default()
+                },
+                Weighted {
+                    max: 100,
+                    ..Weighted::default()
+                },
+            )
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [60, 50, 40, 1] {
+            handle.push(n);
+        }
+        // 60 and 50 go past the weight of the buffer, so 40 isn't pulled
+        assert_eq!(Poll::Pending, poll_once(&mut small, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut small, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut small, &waker.waker()));
+        assert_eq!(2, handle.poll_count());
+        assert!(small.would_block_on_other());
+        assert_eq!(Poll::Ready(Some(60)), poll_once(&mut big, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut small, &waker.waker(), 2)
+        );
+        let (_, buffered_small, buffered_big) = small.reunite(big).ok().unwrap();
+        assert!(buffered_small.is_empty());
+        assert_eq!(vec![50, 40], buffered_big);
+    }
+
+    #[test]
+    fn test_unbounded_buffer() {
+        let (mut evens, odds) = futures::stream::iter(0..1000)
+            .split_by_with_buffers(|&n| n % 2 == 0, VecDeque::new(), VecDeque::new())
+            .into_tuple();
+        let waker = CountingWaker::new();
+        // Nothing ever waits for room, so one stream can be drained before the other is polled
+        for n in (0..1000).step_by(2) {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut evens, &waker.waker(), 2)
+            );
+        }
+        assert!(!evens.would_block_on_other());
+        // 999 hasn't been pulled yet
+        assert_eq!((499, Some(500)), odds.size_hint());
+        let (_, _, buffered_odds) = evens.reunite(odds).ok().unwrap();
+        assert_eq!((1..999).step_by(2).collect::<Vec<_>>(), buffered_odds);
+    }
+
+    #[test]
+    fn test_drain_buffered() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let false_waker = CountingWaker::new();
+        for n in [0, 2, 4, 1] {
+            handle.push(n);
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut false_stream, &false_waker.waker())
+            );
+        }
+        // The true buffer is full, so the false stream waits on it
+        assert!(false_stream.would_block_on_other());
+        let wakes = false_waker.count();
+        let polls = handle.poll_count();
+        let mut items = Vec::with_capacity(4);
+        true_stream.drain_buffered_into(&mut items);
+        assert_eq!(vec![0, 2], items);
+        assert_eq!(polls, handle.poll_count());
+        assert!(false_waker.count() > wakes);
+        assert!(!false_stream.would_block_on_other());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut false_stream, &false_waker.waker(), 2)
+        );
+        // The peeked item comes first
+        assert_eq!(
+            Poll::Ready(Some(&4)),
+            true_stream.poll_peek(&mut std::task::Context::from_waker(
+                &CountingWaker::new().waker()
+            ))
+        );
+        assert_eq!(vec![4], true_stream.drain_buffered());
+        assert!(true_stream.drain_buffered().is_empty());
+    }
+
+    #[test]
+    fn test_retain_buffered() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let false_waker = CountingWaker::new();
+        for n in [0, 2, 4, 6] {
+            handle.push(n);
+        }
+        for _ in 0..4 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut false_stream, &false_waker.waker())
+            );
+        }
+        assert_eq!(Some(0), true_stream.try_next());
+        assert_eq!(Some(2), true_stream.try_next());
+        // 8 and 10 go around the end of the ring
+        for n in [8, 10, 1] {
+            handle.push(n);
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut false_stream, &false_waker.waker())
+            );
+        }
+        assert!(false_stream.would_block_on_other());
+        let wakes = false_waker.count();
+        let polls = handle.poll_count();
+        let mut seen = Vec::new();
+        let removed = true_stream.retain_buffered(|n| {
+            seen.push(*n);
+            *n += 100;
+            *n % 4 == 0
+        });
+        assert_eq!(2, removed);
+        assert_eq!(vec![4, 6, 8, 10], seen);
+        assert_eq!(polls, handle.poll_count());
+        assert!(false_waker.count() > wakes);
+        assert!(!false_stream.would_block_on_other());
+        // There's room for new items behind the ones that were kept
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut false_stream, &false_waker.waker(), 2)
+        );
+        handle.push(12);
+        assert_eq!(
+            Poll::Ready(Some(104)),
+            poll_once(&mut true_stream, &false_waker.waker())
+        );
+        // Nothing is dropped, so the other stream isn't woken
+        let wakes = false_waker.count();
+        assert_eq!(0, true_stream.retain_buffered(|_| true));
+        assert_eq!(wakes, false_waker.count());
+        assert_eq!(vec![108], true_stream.drain_buffered());
+        assert_eq!(
+            Poll::Ready(Some(12)),
+            poll_once(&mut true_stream, &false_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_max_buffered() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = source
+            .split_by_with_buffers(|&n: &i32| n % 2 == 0, VecDeque::new(), VecDeque::new())
+            .max_buffered(4)
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        for n in [1, 3, 5, 7, 9, 0] {
+            handle.push(n);
+        }
+        for _ in 0..4 {
+            assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        }
+        // The odd stream has the whole budget, even though its buffer has room
+        let polls = handle.poll_count();
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert_eq!(polls, handle.poll_count());
+        assert!(evens.would_block_on_other());
+        let wakes = even_waker.count();
+        assert_eq!(Some(1), odds.try_next());
+        assert!(even_waker.count() > wakes);
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert!(evens.would_block_on_other());
+        assert_eq!(vec![3, 5, 7, 9], odds.drain_buffered());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        // Now the even stream gets the whole budget
+        for n in [2, 4, 6, 8, 11] {
+            handle.push(n);
+        }
+        for _ in 0..4 {
+            assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        }
+        assert!(odds.would_block_on_other());
+        let wakes = odd_waker.count();
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        assert!(odd_waker.count() > wakes);
+        assert_eq!(
+            Poll::Ready(Some(11)),
+            poll_until_ready_with_waker(&mut odds, &odd_waker.waker(), 2)
+        );
+        assert_eq!(vec![4, 6, 8], evens.drain_buffered());
+    }
+
+    #[test]
+    fn test_resumable_source() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .resumable_source()
+            .into_tuple();
+        let even_waker = CountingWaker::new();
+        let odd_waker = CountingWaker::new();
+        handle.push(0);
+        handle.push(1);
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        // The odd stream pulls the end of the batch after its item, and neither stream ends
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert!(!evens.is_terminated());
+        assert!(!odds.is_terminated());
+        assert!(handle.is_empty());
+        // The next batch is pulled the next time either stream is polled
+        handle.push(3);
+        handle.push(2);
+        handle.end();
+        let polls = handle.poll_count();
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+        assert!(handle.poll_count() > polls);
+        assert!(odd_waker.count() > 0);
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut evens, &even_waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &even_waker.waker()));
+    }
+
+    #[test]
+    fn test_next_many() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = source
+            .split_by_buffered::<8>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let raw_waker = waker.waker();
+        let mut cx = std::task::Context::from_waker(&raw_waker);
+        let mut items = vec![-1];
+        assert_eq!(Poll::Pending, odds.poll_next_many(&mut cx, &mut items, 8));
+        for n in [0, 2, 4, 6, 1, 8, 10, 3] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut odds, &waker.waker(), 5)
+        );
+        // The buffered items come first, then the ones that are ready until one for the other
+        // stream, which is buffered
+        assert_eq!(Poll::Ready(6), evens.poll_next_many(&mut cx, &mut items, 8));
+        assert_eq!(vec![-1, 0, 2, 4, 6, 8, 10], items);
+        assert!(handle.is_empty());
+        items.clear();
+        for n in [5, 7, 9] {
+            handle.push(n);
+        }
+        // No more than `limit` items are taken
+        assert_eq!(Poll::Ready(2), odds.poll_next_many(&mut cx, &mut items, 2));
+        assert_eq!(vec![3, 5], items);
+        assert_eq!(Poll::Ready(0), odds.poll_next_many(&mut cx, &mut items, 0));
+        handle.end();
+        assert_eq!(Poll::Ready(2), odds.poll_next_many(&mut cx, &mut items, 8));
+        assert_eq!(vec![3, 5, 7, 9], items);
+        assert_eq!(Poll::Ready(0), odds.poll_next_many(&mut cx, &mut items, 8));
+        assert_eq!(Poll::Ready(0), evens.poll_next_many(&mut cx, &mut items, 8));
+    }
+
+    #[tokio::test]
+    async fn test_next_many_cancel_safe() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = source
+            .split_by_buffered::<4>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        for n in [1, 3] {
+            handle.push(n);
+        }
+        let mut items = Vec::new();
+        // Pulling the odd items buffers them without taking any, so dropping the future loses
+        // nothing
+        {
+            let next = evens.next_many(&mut items, 8);
+            futures::pin_mut!(next);
+            assert_eq!(Poll::Pending, futures::poll!(next));
+        }
+        assert!(items.is_empty());
+        assert_eq!(2, odds.next_many(&mut items, 8).await);
+        assert_eq!(vec![1, 3], items);
+    }
+
+    type Hooked = super::FalseSplitByBuffered<i32, ManualStream<i32>, Box<dyn Fn(&i32) -> bool>, 2>;
+
+    /// Splits the evens from the odds, and has the predicate poll the odd
+    /// stream and run `hook` when it classifies `at`, which is while the
+    /// even stream is classifying it without holding the lock
+    #[allow(clippy::type_complexity)]
+    fn split_with_hook(
+        at: i32,
+        hook: impl Fn(&mut Hooked) + 'static,
+    ) -> (
+        super::TrueSplitByBuffered<i32, ManualStream<i32>, Box<dyn Fn(&i32) -> bool>, 2>,
+        Rc<RefCell<Option<Hooked>>>,
+        ManualStreamHandle<i32>,
+    ) {
+        let (source, handle) = ManualStream::new();
+        let odds = Rc::new(RefCell::new(None::<Hooked>));
+        let predicate: Box<dyn Fn(&i32) -> bool> = Box::new({
+            let odds = odds.clone();
+            move |&n| {
+                if n == at {
+                    hook(odds.borrow_mut().as_mut().unwrap());
+                }
+                n % 2 == 0
+            }
+        });
+        let (evens, rest) = source.split_by_buffered::<2>(predicate).into_tuple();
+        *odds.borrow_mut() = Some(rest);
+        (evens, odds, handle)
+    }
+
+    #[test]
+    fn test_other_stream_polls_while_classifying() {
+        let waker = CountingWaker::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (mut evens, odds, handle) = split_with_hook(2, {
+            let seen = seen.clone();
+            let waker = waker.waker();
+            move |odds| {
+                // The buffered item can be taken, but nothing is pulled from the source while 2
+                // is classified
+                seen.borrow_mut().push(poll_once(odds, &waker));
+                seen.borrow_mut().push(poll_once(odds, &waker));
+            }
+        });
+        for n in [1, 2, 3] {
+            handle.push(n);
+        }
+        assert_eq!(Poll::Pending, poll_once(&mut evens, &waker.waker()));
+        let wakes = waker.count();
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut evens, &waker.waker()));
+        assert_eq!(vec![Poll::Ready(Some(1)), Poll::Pending], *seen.borrow());
+        assert_eq!(2, handle.poll_count());
+        // The odd stream is woken once 2 has been routed, so that it can pull again
+        assert!(waker.count() > wakes);
+        let mut odds = odds.borrow_mut().take().unwrap();
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut odds, &waker.waker()));
+    }
+
+    #[test]
+    fn test_source_ends_while_classifying() {
+        let waker = CountingWaker::new();
+        let (source_handle, ended) = (
+            Rc::new(RefCell::new(None::<ManualStreamHandle<i32>>)),
+            Rc::new(Cell::new(None)),
+        );
+        let (mut evens, odds, handle) = split_with_hook(4, {
+            let (source_handle, ended) = (source_handle.clone(), ended.clone());
+            let waker = waker.waker();
+            move |odds| {
+                if let Some(handle) = &*source_handle.borrow() {
+                    handle.end();
+                }
+                ended.set(Some(poll_once(odds, &waker)));
+            }
+        });
+        handle.push(4);
+        *source_handle.borrow_mut() = Some(handle);
+        assert_eq!(Poll::Ready(Some(4)), poll_once(&mut evens, &waker.waker()));
+        // The odd stream can't see the end of the source before 4 has been routed
+        assert_eq!(Some(Poll::Pending), ended.get());
+        let mut odds = odds.borrow_mut().take().unwrap();
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &waker.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut evens, &waker.waker()));
+    }
+
+    #[test]
+    fn test_predicate_panics() {
+        let (source, handle) = ManualStream::new();
+        let (mut evens, mut odds) = source
+            .split_by_buffered::<2>(|&n: &i32| {
+                assert!(n != 13, "unlucky");
+                n % 2 == 0
+            })
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [13, 15] {
+            handle.push(n);
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            poll_once(&mut evens, &waker.waker())
+        }));
+        assert!(result.is_err());
+        // The predicate is back, and the items after the one it panicked on keep flowing
+        assert_eq!(Poll::Ready(Some(15)), poll_once(&mut odds, &waker.waker()));
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_diagnose_other_side_never_polled() {
+        use crate::PendingReason;
+
+        let (source, handle) = ManualStream::new();
+        let (_true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 2, 4] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut false_stream, &waker.waker(), 3)
+        );
+        let diagnosis = false_stream.diagnose();
+        assert_eq!(
+            Some(PendingReason::OtherBufferFull),
+            diagnosis.rest().pending_reason()
+        );
+        assert_eq!(3, diagnosis.rest().polls());
+        assert!(!diagnosis.matches().waker_registered());
+        assert_eq!(2, diagnosis.matches().buffered());
+        let text = diagnosis.to_string();
+        assert!(text.starts_with("true side: never polled\nfalse side: waiting "));
+        assert!(text.contains("is the true stream being polled?"));
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_diagnose_source_pending() {
+        use crate::PendingReason;
+
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        // 1 goes to the other stream, which this one isn't waiting for
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(
+            Some(PendingReason::RoutedToOther),
+            true_stream.diagnose().matches().pending_reason()
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        let diagnosis = false_stream.diagnose();
+        assert_eq!(
+            Some(PendingReason::SourcePending),
+            diagnosis.matches().pending_reason()
+        );
+        assert_eq!(None, diagnosis.rest().pending_reason());
+        assert!(diagnosis.rest().since_last_delivery().is_some());
+        assert!(diagnosis.to_string().contains("on the underlying stream"));
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        let diagnosis = true_stream.diagnose();
+        assert!(diagnosis.matches().is_finished());
+        assert!(diagnosis.is_source_done());
+    }
+
+    #[test]
+    fn test_coalesce_storm() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .coalesce_buffered()
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for _ in 0..100 {
+            handle.push(1);
+        }
+        handle.push(2);
+        // Without coalescing the buffer of the false stream would fill up after two items
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 200)
+        );
+        assert_eq!(99, false_stream.coalesced());
+        assert_eq!(0, true_stream.coalesced());
+        handle.push(1);
+        handle.end();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_until_ready_with_waker(&mut true_stream, &waker.waker(), 10)
+        );
+        // The last item came after the buffer was emptied, but the storm is still in it
+        assert_eq!(100, false_stream.coalesced());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_coalesce_keeps_distinct_items() {
+        let items = [1, 1, 3, 1, 5, 5, 0, 7, 7, 7, 1];
+        let (true_stream, false_stream) = futures::stream::iter(items)
+            .split_by_buffered::<8>(|&n: &i32| n == 0)
+            .coalesce_buffered()
+            .into_tuple();
+        let (true_items, false_items) = futures::executor::block_on(futures::future::join(
+            true_stream.collect::<Vec<_>>(),
+            false_stream.collect::<Vec<_>>(),
+        ));
+        assert_eq!(vec![0], true_items);
+        // Only the false stream's own pulls skip coalescing, so some repeats may be kept, but
+        // items that differ from their neighbour are never dropped and the order stays the same
+        let mut distinct = false_items.clone();
+        distinct.dedup();
+        assert_eq!(vec![1, 3, 1, 5, 7, 1], distinct);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_diagnose_lock_contention() {
+        use crate::PendingReason;
+
+        let (source, _handle) = ManualStream::<i32>::new();
+        let (mut true_stream, false_stream) = source
+            .split_by_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let guard = false_stream.stream.lock();
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(1, waker.count());
+        drop(guard);
+        let diagnosis = false_stream.diagnose();
+        assert_eq!(
+            Some(PendingReason::LockContention),
+            diagnosis.matches().pending_reason()
+        );
+        // The stream was polled, but never got to register its waker
+        assert!(!diagnosis.matches().waker_registered());
+        assert_eq!(1, diagnosis.matches().polls());
     }
 }
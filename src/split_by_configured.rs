@@ -0,0 +1,1401 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, pin::Pin, task::Poll};
+
+use crate::{
+    bounded_buf::{BoundedBuf, Pushed},
+    buffer_age::BufferAge,
+    buffer_latency::{BufferLatency, Timers},
+    builder::RoutingHook,
+    completion::{Completion, CompletionTracker},
+    control::{Drain, SalvageHook, SplitControl},
+    debug::{self, Verbose},
+    offsets::{Advanced, Offsets},
+    parts::SplitParts,
+    reunite::ReuniteError,
+    routing_observer::RoutingSender,
+    sink_passthrough,
+    spill::Spill,
+    split_core::{ByPredicate, Extension, SplitCore},
+    sync::{Mutex, SharedState},
+    throttle::Throttle,
+    watermark::{Crossed, Watermarks},
+    Overflow, Side, Split,
+};
+use futures_core::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use futures_sink::Sink;
+
+/// The state of a split built with `SplitBuilder`, which is that of a split
+/// by a predicate with what the options of the builder add on top
+pub(crate) type SplitByConfigured<I, S, P> =
+    SplitCore<I, S, ByPredicate<P>, BoundedBuf<I>, Configured<I>>;
+
+/// What the options of `SplitBuilder` add to the state of a split
+pub(crate) struct Configured<I> {
+    overflow: Overflow,
+    /// The only stream that pulls from the underlying stream, if there is one
+    driver: Option<Side>,
+    /// Whether each stream only wants the latest item routed to it
+    conflate: bool,
+    replaced_true: usize,
+    replaced_false: usize,
+    inspect_routing: Option<RoutingHook<I>>,
+    observer: Option<RoutingSender>,
+    watermarks: Watermarks,
+    latency: BufferLatency,
+    ages: BufferAge,
+    offsets: Offsets,
+    drain: Drain<I>,
+    spill: Spill<I>,
+    /// Whether pulling from the underlying stream was paused by
+    /// `SplitControl::pause`
+    paused: bool,
+}
+
+impl<I> Extension<I, BoundedBuf<I>> for Configured<I> {
+    // A throttled stream waits without touching the shared state
+    const WAKE_ON_ROUTE: bool = true;
+
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn is_draining(&self) -> bool {
+        self.drain.is_draining()
+    }
+
+    fn waits_for_driver(&self, side: Side, other_closed: bool) -> bool {
+        self.driver == Some(Side::from(!side.is_true())) && !other_closed
+    }
+
+    fn waits_for_room(&self) -> bool {
+        self.overflow == Overflow::Wait && !self.spill.is_enabled()
+    }
+
+    fn held(&self, side: Side) -> usize {
+        self.spill.len(side)
+    }
+
+    fn pop(&mut self, side: Side, buf: &mut BoundedBuf<I>) -> Option<I> {
+        let age = buf.front_age();
+        let item = buf.pop_front()?;
+        self.ages.delivered(side, age);
+        self.offsets.popped(side);
+        self.spill.refill(side, buf);
+        Some(item)
+    }
+
+    fn push(&mut self, side: Side, buf: &mut BoundedBuf<I>, item: I, closed: bool) -> Pushed {
+        // If the buffer is full, the overflow policy decides which item is dropped
+        let item = if closed {
+            // Nothing would read spilled items back
+            Some(item)
+        } else {
+            self.spill.push(side, buf, item)
+        };
+        let pushed = match item {
+            Some(item) => {
+                if self.conflate && buf.remaining() == 0 {
+                    self.replaced(side);
+                }
+                buf.push_overflowing(item, self.overflow)
+            }
+            None => Pushed::Kept,
+        };
+        self.offsets.buffered(side, pushed);
+        pushed
+    }
+
+    fn routed(&mut self, item: &I, target: Side, polling: Side) {
+        if let Some(inspect_routing) = &mut self.inspect_routing {
+            inspect_routing(item, target);
+        }
+        if let Some(observer) = &self.observer {
+            observer.send(target);
+        }
+        if target == polling {
+            self.offsets.delivered();
+        }
+    }
+
+    fn conflates(&self) -> bool {
+        self.conflate
+    }
+
+    fn replaced(&mut self, side: Side) {
+        match side {
+            Side::True => self.replaced_true += 1,
+            Side::False => self.replaced_false += 1,
+        }
+    }
+
+    fn ended(&mut self) {
+        if let Some(observer) = &self.observer {
+            observer.close();
+        }
+    }
+}
+
+impl<I, S, P> SplitByConfigured<I, S, P> {
+    /// Stops pulling from the underlying stream, so that both streams only
+    /// return what's left in their buffers
+    pub(crate) fn start_drain(&mut self) {
+        self.extension.drain.start();
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    /// Returns `Ready` once each stream has emptied its buffer or been
+    /// dropped, or stores the waker to be woken when that might have changed
+    pub(crate) fn poll_drained(&mut self, cx: &mut core::task::Context<'_>) -> Poll<()> {
+        // A buffer is only empty once the items spilled for it were read back
+        let drained_true = self.buf_true.len() == 0 || self.closed_true;
+        let drained_false = self.buf_false.len() == 0 || self.closed_false;
+        if self.aborted || (drained_true && drained_false) {
+            return Poll::Ready(());
+        }
+        self.extension.drain.register(cx.waker());
+        Poll::Pending
+    }
+
+    /// Takes the items that are still buffered for either stream, along with
+    /// the report of the drain and the salvage hook
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn finish_drain(
+        &mut self,
+    ) -> (crate::DrainReport, Vec<(I, Side)>, Option<SalvageHook<I>>) {
+        let Configured {
+            spill,
+            offsets,
+            drain,
+            ..
+        } = &mut self.extension;
+        let mut abandoned = Vec::new();
+        while let Some(item) = self.buf_true.pop_front() {
+            abandoned.push((item, Side::True));
+        }
+        abandoned.extend(spill.drain(Side::True).map(|item| (item, Side::True)));
+        let abandoned_true = abandoned.len();
+        while let Some(item) = self.buf_false.pop_front() {
+            abandoned.push((item, Side::False));
+        }
+        abandoned.extend(spill.drain(Side::False).map(|item| (item, Side::False)));
+        let abandoned_false = abandoned.len() - abandoned_true;
+        offsets.abandoned(Side::True);
+        offsets.abandoned(Side::False);
+        let (report, salvage) = drain.finish(abandoned_true, abandoned_false);
+        (report, abandoned, salvage)
+    }
+
+    /// Stops or resumes pulling from the underlying stream. Both streams are
+    /// woken on resuming, since they may have returned `Pending` while paused
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        let resumed = self.extension.paused && !paused;
+        self.extension.paused = paused;
+        if resumed {
+            self.waker_true.wake();
+            self.waker_false.wake();
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.extension.paused
+    }
+
+    /// Takes the split apart like `into_predicate_parts`, with the items that
+    /// were spilled for each side after the ones in its buffer
+    fn into_spilled_parts(mut self) -> SplitParts<I, S, P> {
+        let spilled_true = self.extension.spill.drain(Side::True).collect::<Vec<_>>();
+        let spilled_false = self.extension.spill.drain(Side::False).collect::<Vec<_>>();
+        let mut parts = self.into_predicate_parts();
+        parts.buffered_true.extend(spilled_true);
+        parts.buffered_false.extend(spilled_false);
+        parts
+    }
+
+    /// The delivered watermark of the `side` stream, or of the whole split
+    /// without a side
+    pub(crate) fn delivered_watermark(&self, side: Option<Side>) -> Option<u64> {
+        self.extension.offsets.watermark(side)
+    }
+
+    /// The callback for the delivered watermark, if it advanced since the
+    /// last check
+    pub(crate) fn delivered_advanced(&mut self) -> Advanced {
+        self.extension.offsets.advanced()
+    }
+
+    /// Returns the callbacks of the watermarks that the buffers crossed since
+    /// they were last checked
+    fn crossed_watermarks(&mut self) -> Crossed {
+        let (len_true, len_false) = (self.buf_true.len(), self.buf_false.len());
+        self.extension.watermarks.check(len_true, len_false)
+    }
+
+    /// Returns the timers to start for the streams that have items buffered,
+    /// if a maximum buffer latency was set
+    fn buffer_timers(&self) -> Timers {
+        self.extension.latency.check(
+            self.buf_true.len(),
+            self.buf_false.len(),
+            &self.waker_true,
+            &self.waker_false,
+        )
+    }
+
+    fn debug_fields(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        let extension = &self.extension;
+        debug
+            .field("buffered_true", &self.buf_true.len())
+            .field("buffered_false", &self.buf_false.len())
+            .field("capacity_true", &self.buf_true.capacity())
+            .field("capacity_false", &self.buf_false.capacity())
+            .field("overflow", &extension.overflow)
+            .field("driver", &extension.driver)
+            .field("conflate", &extension.conflate)
+            .field("spilled_true", &extension.spill.len(Side::True))
+            .field("spilled_false", &extension.spill.len(Side::False))
+            .field("inspect_routing", &extension.inspect_routing.is_some())
+            .field("observer", &extension.observer.is_some())
+            .field("waker_true", &self.waker_true.is_registered())
+            .field("waker_false", &self.waker_false.is_registered())
+            .field("done", &self.done)
+            .field("draining", &extension.drain.is_draining())
+            .field("paused", &extension.paused)
+            .field("closed_true", &self.closed_true)
+            .field("closed_false", &self.closed_false)
+            .field("aborted", &self.aborted);
+    }
+
+    /// Marks the stream of `side` as dropped, and returns the callback for
+    /// the delivered watermark if that moved it
+    fn close(&mut self, side: Side) -> Advanced {
+        match side {
+            Side::True => self.close_true(),
+            Side::False => self.close_false(),
+        }
+        self.extension.offsets.abandoned(side);
+        if self.extension.driver == Some(side) {
+            // The other stream has to pull from the underlying stream itself from now on
+            match side {
+                Side::True => self.waker_false.wake(),
+                Side::False => self.waker_true.wake(),
+            }
+        }
+        self.extension.drain.closed();
+        self.delivered_advanced()
+    }
+}
+
+impl<I, S, P> SplitByConfigured<I, S, P>
+where
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate, and moves the buffered items that it puts on
+    /// the other side to the buffer of the other stream
+    fn reclassify_with(&mut self, predicate: P) {
+        self.router = Some(ByPredicate(predicate));
+        self.extension
+            .offsets
+            .rearranging(self.buf_true.len(), self.buf_false.len());
+        self.reclassify();
+        self.extension
+            .offsets
+            .rearranged(self.buf_true.len(), self.buf_false.len());
+        // Spilled items keep their side, but a buffer that gave up items has to be refilled
+        // before the spilled items behind them could be skipped
+        let Configured { spill, .. } = &mut self.extension;
+        spill.refill(Side::True, &mut self.buf_true);
+        spill.refill(Side::False, &mut self.buf_false);
+    }
+}
+
+impl<I, S, P> SplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        stream: S,
+        predicate: P,
+        capacity_true: usize,
+        capacity_false: usize,
+        boxed: bool,
+        overflow: Overflow,
+        driver: Option<Side>,
+        conflate: bool,
+        inspect_routing: Option<RoutingHook<I>>,
+        observer: Option<RoutingSender>,
+        watermarks: Watermarks,
+        latency: BufferLatency,
+        ages: BufferAge,
+        offsets: Offsets,
+        salvage: Option<SalvageHook<I>>,
+        spill: Spill<I>,
+    ) -> Arc<Mutex<Self>> {
+        let buffer = if boxed {
+            BoundedBuf::new_boxed
+        } else {
+            BoundedBuf::new
+        };
+        let (mut buf_true, mut buf_false) = (buffer(capacity_true), buffer(capacity_false));
+        ages.track(&mut buf_true);
+        ages.track(&mut buf_false);
+        let configured = Configured {
+            overflow,
+            driver,
+            conflate,
+            replaced_true: 0,
+            replaced_false: 0,
+            inspect_routing,
+            observer,
+            watermarks,
+            latency,
+            ages,
+            offsets,
+            drain: Drain::new(salvage),
+            spill,
+            paused: false,
+        };
+        Arc::new(Mutex::new(Self::with_extension(
+            stream,
+            ByPredicate(predicate),
+            buf_true,
+            buf_false,
+            "SplitBuilder::build",
+            ["TrueSplitByConfigured", "FalseSplitByConfigured"],
+            configured,
+        )))
+    }
+
+    /// Creates a split with a buffer size of `capacity` for both streams that
+    /// continues where the split `parts` came from left off. The first item
+    /// buffered for a stream goes in its peeked slot if the items don't all
+    /// fit in the buffer, since that slot comes first. If they still don't
+    /// fit, the buffer starts out over its capacity and shrinks as the
+    /// stream takes them
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn split_from_parts(
+        parts: SplitParts<I, S, P>,
+        capacity: usize,
+    ) -> Split<TrueSplitByConfigured<I, S, P>, FalseSplitByConfigured<I, S, P>> {
+        let stream = Self::new(
+            parts.stream,
+            parts.predicate,
+            capacity,
+            capacity,
+            false,
+            Overflow::Wait,
+            None,
+            false,
+            None,
+            None,
+            Watermarks::default(),
+            BufferLatency::default(),
+            BufferAge::default(),
+            Offsets::default(),
+            None,
+            Spill::default(),
+        );
+        let mut split = Split::share(
+            stream.clone(),
+            TrueSplitByConfigured::new,
+            FalseSplitByConfigured::new,
+        );
+        let mut state = stream.lock();
+        split.matches.peeked = state.buf_true.fill(parts.buffered_true);
+        split.rest.peeked = state.buf_false.fill(parts.buffered_false);
+        drop(state);
+        split
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`. Created by `SplitBuilder::build`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitByConfigured<I, S, P> {
+    stream: SharedState<Mutex<SplitByConfigured<I, S, P>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
+    throttle: Throttle,
+}
+
+impl<I, S, P> TrueSplitByConfigured<I, S, P> {
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByConfigured<I, S, P>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+            throttle: Throttle::default(),
+        }
+    }
+
+    pub(crate) fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = throttle;
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Returns a handle that controls the split without being one of its
+    /// streams, for example to close it gracefully with `close_graceful`
+    pub fn control(&self) -> SplitControl<I, S, P> {
+        SplitControl::new(self.stream.clone(), self.completion.clone())
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `false` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_false
+    }
+
+    /// How many items meant for this stream were replaced by newer ones
+    /// before it took them. Always 0 unless the split was built with
+    /// `SplitBuilder::conflate`
+    pub fn replaced(&self) -> usize {
+        self.stream.lock().extension.replaced_true
+    }
+
+    /// How long the oldest item buffered for this stream has been waiting,
+    /// or `None` if nothing is buffered for it or the split wasn't built with
+    /// `SplitBuilder::track_buffer_age`
+    pub fn oldest_buffered_age(&self) -> Option<core::time::Duration> {
+        self.stream.lock().buf_true.front_age()
+    }
+
+    /// How long the items this stream took from its buffer had been waiting
+    /// there. This stays empty unless the split was built with
+    /// `SplitBuilder::track_buffer_age`
+    pub fn buffer_age_summary(&self) -> crate::BufferAgeSummary {
+        self.stream.lock().extension.ages.summary(Side::True)
+    }
+
+    /// The highest offset such that every item with an offset up to it that
+    /// was routed to this stream has been returned by it, or given up on as
+    /// the split was built to, or `None` if there is no such item yet or
+    /// offsets aren't tracked. See `SplitBuilder::track_offsets`, and
+    /// `SplitControl::delivered_watermark` for both streams together
+    pub fn delivered_watermark(&self) -> Option<u64> {
+        self.stream.lock().delivered_watermark(Some(Side::True))
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `false` stream is
+    /// full or because the `false` stream is the driver set with
+    /// `SplitBuilder::driven_by`. In that case this stream can only make
+    /// progress once the `false` stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_true()
+    }
+
+    /// Polls for room in the buffer of the `false` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `false` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `false` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `false` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_false(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `false` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Changes how many items can be buffered for this stream. When the
+    /// buffer is shrunk below the number of items it holds, none of them are
+    /// dropped, but nothing more is buffered for this stream until it has
+    /// taken enough of them out
+    pub fn resize(&self, capacity: usize) {
+        let mut state = self.stream.lock();
+        let grew = capacity > state.buf_true.capacity();
+        state.buf_true.set_capacity(capacity);
+        if grew {
+            // The `false` stream might be waiting for room in this buffer
+            state.waker_false.wake();
+            state.room_true.wake();
+        }
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split and its control handles are the only references to its shared
+        // state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_spilled_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_true.insert(0, item);
+        }
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `true` and `false` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        self,
+        mut other: FalseSplitByConfigured<I, S, P>,
+    ) -> Result<(S, Vec<I>, Vec<I>), ReuniteError<Self, FalseSplitByConfigured<I, S, P>>> {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.stream.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.stream = SharedState::new(this.stream.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok((parts.stream, parts.buffered_true, parts.buffered_false))
+    }
+}
+
+impl<I, S, P> TrueSplitByConfigured<I, S, P>
+where
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.reclassify_with(predicate);
+        let crossed = state.crossed_watermarks();
+        let timers = state.buffer_timers();
+        drop(state);
+        crossed.call();
+        timers.start();
+    }
+}
+
+impl<I, S, P> TrueSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // A throttled stream waits without touching the shared state, so items for it keep being
+        // buffered while it does
+        if self.throttle.poll_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = guard.poll_true(cx);
+            if let Poll::Ready(Some(_)) = response {
+                guard.extension.latency.taken(Side::True);
+                guard.extension.drain.returned(Side::True);
+            }
+            // Remembers which underlying stream this stream might see the end of
+            self.generation = guard.generation;
+            let crossed = guard.crossed_watermarks();
+            let advanced = guard.delivered_advanced();
+            let timers = guard.buffer_timers();
+            drop(guard);
+            // The callbacks run outside of the lock, so they can do anything
+            crossed.call();
+            advanced.call();
+            timers.start();
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(Some(_)) = response {
+            self.throttle.returned();
+        }
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked item is never pinned
+impl<I, S, P> Unpin for TrueSplitByConfigured<I, S, P> {}
+
+impl<I, S, P> Stream for TrueSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        };
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P> core::async_iter::AsyncIterator for TrueSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P> FusedStream for TrueSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, T> Sink<T> for TrueSplitByConfigured<I, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P> fmt::Debug for TrueSplitByConfigured<I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByConfigured",
+            state.as_deref(),
+            self.terminated,
+            SplitByConfigured::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P> fmt::Debug for Verbose<'_, TrueSplitByConfigured<I, S, P>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByConfigured",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P> Drop for TrueSplitByConfigured<I, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            let advanced = stream.lock().close(Side::True);
+            advanced.call();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false`. Created by `SplitBuilder::build`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseSplitByConfigured<I, S, P> {
+    stream: SharedState<Mutex<SplitByConfigured<I, S, P>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<I>,
+    generation: usize,
+    throttle: Throttle,
+}
+
+impl<I, S, P> FalseSplitByConfigured<I, S, P> {
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByConfigured<I, S, P>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+            throttle: Throttle::default(),
+        }
+    }
+
+    pub(crate) fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = throttle;
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Returns a handle that controls the split without being one of its
+    /// streams, for example to close it gracefully with `close_graceful`
+    pub fn control(&self) -> SplitControl<I, S, P> {
+        SplitControl::new(self.stream.clone(), self.completion.clone())
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `true` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_true
+    }
+
+    /// How many items meant for this stream were replaced by newer ones
+    /// before it took them. Always 0 unless the split was built with
+    /// `SplitBuilder::conflate`
+    pub fn replaced(&self) -> usize {
+        self.stream.lock().extension.replaced_false
+    }
+
+    /// How long the oldest item buffered for this stream has been waiting,
+    /// or `None` if nothing is buffered for it or the split wasn't built with
+    /// `SplitBuilder::track_buffer_age`
+    pub fn oldest_buffered_age(&self) -> Option<core::time::Duration> {
+        self.stream.lock().buf_false.front_age()
+    }
+
+    /// How long the items this stream took from its buffer had been waiting
+    /// there. This stays empty unless the split was built with
+    /// `SplitBuilder::track_buffer_age`
+    pub fn buffer_age_summary(&self) -> crate::BufferAgeSummary {
+        self.stream.lock().extension.ages.summary(Side::False)
+    }
+
+    /// The highest offset such that every item with an offset up to it that
+    /// was routed to this stream has been returned by it, or given up on as
+    /// the split was built to, or `None` if there is no such item yet or
+    /// offsets aren't tracked. See `SplitBuilder::track_offsets`, and
+    /// `SplitControl::delivered_watermark` for both streams together
+    pub fn delivered_watermark(&self) -> Option<u64> {
+        self.stream.lock().delivered_watermark(Some(Side::False))
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `true` stream is
+    /// full or because the `true` stream is the driver set with
+    /// `SplitBuilder::driven_by`. In that case this stream can only make
+    /// progress once the `true` stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_false()
+    }
+
+    /// Polls for room in the buffer of the `true` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `true` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `true` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `true` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_true(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `true` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByPredicate(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Changes how many items can be buffered for this stream. When the
+    /// buffer is shrunk below the number of items it holds, none of them are
+    /// dropped, but nothing more is buffered for this stream until it has
+    /// taken enough of them out
+    pub fn resize(&self, capacity: usize) {
+        let mut state = self.stream.lock();
+        let grew = capacity > state.buf_false.capacity();
+        state.buf_false.set_capacity(capacity);
+        if grew {
+            // The `true` stream might be waiting for room in this buffer
+            state.waker_true.wake();
+            state.room_false.wake();
+        }
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitParts<I, S, P>, Self> {
+        // The streams of a split and its control handles are the only references to its shared
+        // state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_spilled_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_false.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, S, P> FalseSplitByConfigured<I, S, P>
+where
+    P: Fn(&I) -> bool,
+{
+    /// Replaces the predicate like `set_predicate`, and also checks the items
+    /// that are still buffered for either stream against the new predicate.
+    /// The ones that belong to the other stream now are moved to the back of
+    /// its buffer, keeping their order. If there isn't enough room, the first
+    /// ones move and the rest stay where they were in their old buffer. There
+    /// is always room for as many items as move the other way, so an item
+    /// never ends up in a buffer that neither predicate put it in. Items that
+    /// have been returned or peeked at aren't affected
+    pub fn set_predicate_reclassify(&self, predicate: P) {
+        let mut state = self.stream.lock();
+        state.reclassify_with(predicate);
+        let crossed = state.crossed_watermarks();
+        let timers = state.buffer_timers();
+        drop(state);
+        crossed.call();
+        timers.start();
+    }
+}
+
+impl<I, S, P> FalseSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, I>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, I>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // A throttled stream waits without touching the shared state, so items for it keep being
+        // buffered while it does
+        if self.throttle.poll_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = guard.poll_false(cx);
+            if let Poll::Ready(Some(_)) = response {
+                guard.extension.latency.taken(Side::False);
+                guard.extension.drain.returned(Side::False);
+            }
+            // Remembers which underlying stream this stream might see the end of
+            self.generation = guard.generation;
+            let crossed = guard.crossed_watermarks();
+            let advanced = guard.delivered_advanced();
+            let timers = guard.buffer_timers();
+            drop(guard);
+            // The callbacks run outside of the lock, so they can do anything
+            crossed.call();
+            advanced.call();
+            timers.start();
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(Some(_)) = response {
+            self.throttle.returned();
+        }
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&I>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&I> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked item is never pinned
+impl<I, S, P> Unpin for FalseSplitByConfigured<I, S, P> {}
+
+impl<I, S, P> Stream for FalseSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        };
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P> core::async_iter::AsyncIterator for FalseSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P> FusedStream for FalseSplitByConfigured<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, S, P, T> Sink<T> for FalseSplitByConfigured<I, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, S, P> fmt::Debug for FalseSplitByConfigured<I, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByConfigured",
+            state.as_deref(),
+            self.terminated,
+            SplitByConfigured::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P> fmt::Debug for Verbose<'_, FalseSplitByConfigured<I, S, P>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByConfigured",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P> Drop for FalseSplitByConfigured<I, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            let advanced = stream.lock().close(Side::False);
+            advanced.call();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
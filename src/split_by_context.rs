@@ -0,0 +1,727 @@
+use alloc::sync::Arc;
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{
+    completion::{Completion, CompletionTracker},
+    debug::{self, Items, Verbose},
+    ring::RingBuf,
+    side_waker::SideWaker,
+    sync::Mutex,
+};
+
+/// What the predicate of `split_by_with_context` knows about the split when
+/// it classifies an item. The values are a snapshot taken right before the
+/// call, while the shared state is locked, and don't count the item being
+/// classified. They can be out of date as soon as the predicate returns,
+/// since the other stream can take items out of its buffer right after
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitContext {
+    buffered_true: usize,
+    buffered_false: usize,
+    capacity_true: usize,
+    capacity_false: usize,
+    items_routed: u64,
+}
+
+impl SplitContext {
+    /// The number of items buffered for the `matches` stream
+    pub fn buffered_true(&self) -> usize {
+        self.buffered_true
+    }
+
+    /// The number of items buffered for the `rest` stream
+    pub fn buffered_false(&self) -> usize {
+        self.buffered_false
+    }
+
+    /// How many items the buffer of the `matches` stream holds once it's full
+    pub fn capacity_true(&self) -> usize {
+        self.capacity_true
+    }
+
+    /// How many items the buffer of the `rest` stream holds once it's full
+    pub fn capacity_false(&self) -> usize {
+        self.capacity_false
+    }
+
+    /// The number of items that were pulled from the underlying stream and
+    /// routed to either stream before this one
+    pub fn items_routed(&self) -> u64 {
+        self.items_routed
+    }
+}
+
+#[pin_project]
+pub(crate) struct SplitByContext<I, S, P, const N: usize> {
+    buf_true: RingBuf<I, N>,
+    buf_false: RingBuf<I, N>,
+    waker_true: SideWaker,
+    waker_false: SideWaker,
+    #[pin]
+    stream: S,
+    predicate: P,
+    items_routed: u64,
+    done: bool,
+    closed_true: bool,
+    closed_false: bool,
+    aborted: bool,
+}
+
+impl<I, S, P, const N: usize> SplitByContext<I, S, P, N> {
+    fn abort(&mut self) {
+        self.aborted = true;
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    fn debug_fields(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("buffered_true", &self.buf_true.len())
+            .field("buffered_false", &self.buf_false.len())
+            .field("capacity", &N)
+            .field("items_routed", &self.items_routed)
+            .field("waker_true", &self.waker_true.is_registered())
+            .field("waker_false", &self.waker_false.is_registered())
+            .field("done", &self.done)
+            .field("closed_true", &self.closed_true)
+            .field("closed_false", &self.closed_false)
+            .field("aborted", &self.aborted);
+    }
+}
+
+impl<I, S, P, const N: usize> SplitByContext<I, S, P, N>
+where
+    I: fmt::Debug,
+{
+    fn debug_items(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("items_true", &Items(self.buf_true.iter()))
+            .field("items_false", &Items(self.buf_false.iter()));
+    }
+}
+
+impl<I, S, P, const N: usize> SplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I>,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            buf_false: RingBuf::new(),
+            buf_true: RingBuf::new(),
+            waker_false: SideWaker::default(),
+            waker_true: SideWaker::default(),
+            stream,
+            predicate,
+            items_routed: 0,
+            done: false,
+            closed_true: false,
+            closed_false: false,
+            aborted: false,
+        }))
+    }
+
+    fn size_hint_true(&self) -> (usize, Option<usize>) {
+        if self.aborted {
+            return (0, Some(0));
+        }
+        let buffered = self.buf_true.len();
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+        // Any of the remaining items could end up in this stream
+        let (_, upper) = self.stream.size_hint();
+        (
+            buffered,
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+
+    fn size_hint_false(&self) -> (usize, Option<usize>) {
+        if self.aborted {
+            return (0, Some(0));
+        }
+        let buffered = self.buf_false.len();
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+        // Any of the remaining items could end up in this stream
+        let (_, upper) = self.stream.size_hint();
+        (
+            buffered,
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+
+    fn poll_next_true(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<I>> {
+        let this = self.project();
+        if *this.aborted {
+            return Poll::Ready(None);
+        }
+        this.waker_true.register(cx.waker(), "TrueSplitByContext");
+        let was_full = this.buf_true.remaining() == 0;
+        if let Some(item) = this.buf_true.pop_front() {
+            // There was already a value in the buffer. Return that value
+            if was_full {
+                // The `false` stream might be waiting for room in this buffer, and nothing else
+                // would wake it
+                this.waker_false.wake();
+            }
+            return Poll::Ready(Some(item));
+        }
+        if *this.done {
+            // The underlying stream has finished and there's nothing left in the buffer
+            return Poll::Ready(None);
+        }
+        if this.buf_false.remaining() == 0 {
+            // The other buffer is full, so notify that stream and return pending
+            this.waker_false.wake();
+            return Poll::Pending;
+        }
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                let context = SplitContext {
+                    buffered_true: this.buf_true.len(),
+                    buffered_false: this.buf_false.len(),
+                    capacity_true: N,
+                    capacity_false: N,
+                    items_routed: *this.items_routed,
+                };
+                *this.items_routed += 1;
+                if (this.predicate)(&context, &item) {
+                    Poll::Ready(Some(item))
+                } else {
+                    // This value is not what we wanted. Store it and notify other partition task if
+                    // it exists
+                    let _ = this.buf_false.push_back(item);
+                    this.waker_false.wake();
+                    Poll::Pending
+                }
+            }
+            Poll::Ready(None) => {
+                *this.done = true;
+                // If the underlying stream is finished, the `false` stream also must be
+                // finished, so wake it in case nothing else polls it
+                this.waker_false.wake();
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_next_false(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<I>> {
+        let this = self.project();
+        if *this.aborted {
+            return Poll::Ready(None);
+        }
+        this.waker_false.register(cx.waker(), "FalseSplitByContext");
+        let was_full = this.buf_false.remaining() == 0;
+        if let Some(item) = this.buf_false.pop_front() {
+            // There was already a value in the buffer. Return that value
+            if was_full {
+                // The `true` stream might be waiting for room in this buffer, and nothing else
+                // would wake it
+                this.waker_true.wake();
+            }
+            return Poll::Ready(Some(item));
+        }
+        if *this.done {
+            // The underlying stream has finished and there's nothing left in the buffer
+            return Poll::Ready(None);
+        }
+        if this.buf_true.remaining() == 0 {
+            // The other buffer is full, so notify that stream and return pending
+            this.waker_true.wake();
+            return Poll::Pending;
+        }
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                let context = SplitContext {
+                    buffered_true: this.buf_true.len(),
+                    buffered_false: this.buf_false.len(),
+                    capacity_true: N,
+                    capacity_false: N,
+                    items_routed: *this.items_routed,
+                };
+                *this.items_routed += 1;
+                if (this.predicate)(&context, &item) {
+                    // This value is not what we wanted. Store it and notify other stream if waker
+                    // it exists
+                    let _ = this.buf_true.push_back(item);
+                    this.waker_true.wake();
+                    Poll::Pending
+                } else {
+                    Poll::Ready(Some(item))
+                }
+            }
+            Poll::Ready(None) => {
+                *this.done = true;
+                // If the underlying stream is finished, the `true` stream also must be
+                // finished, so wake it in case nothing else polls it
+                this.waker_true.wake();
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`. Created by `split_by_with_context`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitByContext<I, S, P, const N: usize> {
+    stream: Arc<Mutex<SplitByContext<I, S, P, N>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+}
+
+impl<I, S, P, const N: usize> TrueSplitByContext<I, S, P, N> {
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByContext<I, S, P, N>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream,
+            completion,
+            terminated: false,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<I, S, P, const N: usize> TrueSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByContext::poll_next_true(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_true.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
+        }
+        response
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for TrueSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        }
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for TrueSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for TrueSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for TrueSplitByContext<I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByContext",
+            state.as_deref(),
+            self.terminated,
+            SplitByContext::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, TrueSplitByContext<I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByContext",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> Drop for TrueSplitByContext<I, S, P, N> {
+    fn drop(&mut self) {
+        self.stream.lock().closed_true = true;
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `false`. Created by `split_by_with_context`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseSplitByContext<I, S, P, const N: usize> {
+    stream: Arc<Mutex<SplitByContext<I, S, P, N>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+}
+
+impl<I, S, P, const N: usize> FalseSplitByContext<I, S, P, N> {
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByContext<I, S, P, N>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream,
+            completion,
+            terminated: false,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<I, S, P, const N: usize> FalseSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByContext::poll_next_false(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_false.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for FalseSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        }
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for FalseSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    type Item = I;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for FalseSplitByContext<I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: FnMut(&SplitContext, &I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for FalseSplitByContext<I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByContext",
+            state.as_deref(),
+            self.terminated,
+            SplitByContext::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, FalseSplitByContext<I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByContext",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, S, P, const N: usize> Drop for FalseSplitByContext<I, S, P, N> {
+    fn drop(&mut self) {
+        self.stream.lock().closed_false = true;
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use super::SplitContext;
+    use crate::{
+        testing::{poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// Routes everything to the `matches` stream, unless its buffer is more
+    /// than 80% full
+    fn premium(context: &SplitContext, _: &i32) -> bool {
+        context.buffered_true() * 10 <= context.capacity_true() * 8
+    }
+
+    #[test]
+    fn test_divert_when_backed_up() {
+        let (source, handle) = ManualStream::new();
+        let (mut premium, mut standard) = source
+            .split_by_with_context_buffered::<10>(premium)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in 0..12 {
+            handle.push(n);
+        }
+        // 9 items fill the buffer to 90%, so the next one goes to the other stream
+        assert_eq!(
+            Poll::Ready(Some(9)),
+            poll_until_ready_with_waker(&mut standard, &waker.waker(), 10)
+        );
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut premium, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_until_ready_with_waker(&mut standard, &waker.waker(), 1)
+        );
+        assert_eq!(
+            Poll::Ready(Some(11)),
+            poll_until_ready_with_waker(&mut standard, &waker.waker(), 1)
+        );
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(n)) = poll_once(&mut premium, &waker.waker()) {
+            items.push(n);
+        }
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8, 10], items);
+    }
+
+    #[test]
+    fn test_items_routed() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_with_context_buffered::<2>(|context: &SplitContext, &n: &u64| {
+                assert_eq!(n, context.items_routed());
+                n % 2 == 0
+            })
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in 0..4 {
+            handle.push(n);
+        }
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_split_by_with_context() {
+        let (evens, odds) = futures::stream::iter(0..100)
+            .split_by_with_context(|_: &SplitContext, &n| n % 2 == 0)
+            .into_tuple();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!((0..100).step_by(2).collect::<Vec<_>>(), evens);
+        assert_eq!((1..100).step_by(2).collect::<Vec<_>>(), odds);
+    }
+}
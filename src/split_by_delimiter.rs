@@ -0,0 +1,392 @@
+use alloc::sync::Arc;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{side_waker::SideWaker, sync::Mutex};
+
+/// What `split_by_delimiter` does with the items the predicate picks out as
+/// delimiters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Delimiter {
+    /// Drop the delimiter, so segments only hold the items between
+    /// delimiters
+    #[default]
+    Discard,
+    /// Return the delimiter as the last item of the segment it ends
+    EndsSegment,
+    /// Return the delimiter as the first item of the next segment
+    StartsSegment,
+}
+
+/// The state the segments of a `split_by_delimiter` share with the stream of
+/// segments
+pub(crate) struct SplitByDelimiter<I, S, P> {
+    stream: S,
+    predicate: P,
+    delimiter: Delimiter,
+    /// The number of the last segment that was returned. Older segments
+    /// have ended
+    segment: u64,
+    /// The item the last segment returns first, which was pulled when it
+    /// was created
+    first: Option<I>,
+    /// Whether the last segment pulls more items from the underlying stream
+    open: bool,
+    /// Whether the items up to the next delimiter are dropped, because the
+    /// next segment was asked for before the last one reached its end
+    skipping: bool,
+    /// A delimiter that starts the next segment, with `Delimiter::StartsSegment`
+    next_first: Option<I>,
+    segment_waker: SideWaker,
+    done: bool,
+}
+
+impl<I, S, P> SplitByDelimiter<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    pub(crate) fn new(stream: S, predicate: P, delimiter: Delimiter) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            stream,
+            predicate,
+            delimiter,
+            segment: 0,
+            first: None,
+            open: false,
+            skipping: false,
+            next_first: None,
+            segment_waker: SideWaker::default(),
+            done: false,
+        }))
+    }
+
+    /// Polls for the first item of the next segment, after dropping what's
+    /// left of the last one. Returns `None` once the underlying stream ends
+    fn poll_next_segment(&mut self, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        if self.open {
+            // The last segment ends here, and the items up to its delimiter are dropped
+            self.open = false;
+            self.first = None;
+            self.skipping = true;
+            self.segment_waker.wake();
+        }
+        while self.skipping {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if (self.predicate)(&item) {
+                        self.skipping = false;
+                        if self.delimiter == Delimiter::StartsSegment {
+                            self.next_first = Some(item);
+                        }
+                    }
+                }
+                Poll::Ready(None) => {
+                    self.skipping = false;
+                    self.done = true;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let (first, carried) = match self.next_first.take() {
+            Some(first) => (first, true),
+            None if self.done => return Poll::Ready(None),
+            None => match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(first)) => (first, false),
+                Poll::Ready(None) => {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+        self.segment += 1;
+        // A delimiter that ends a segment ends this one right away, which makes it empty with
+        // `Delimiter::Discard`
+        let ends = !carried && (self.predicate)(&first);
+        let (first, open) = match self.delimiter {
+            Delimiter::Discard if ends => (None, false),
+            Delimiter::EndsSegment if ends => (Some(first), false),
+            _ => (Some(first), true),
+        };
+        self.first = first;
+        self.open = open;
+        Poll::Ready(Some(()))
+    }
+
+    /// Polls for the next item of segment number `segment`
+    fn poll_segment(&mut self, segment: u64, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if segment != self.segment {
+            return Poll::Ready(None);
+        }
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(first));
+        }
+        if !self.open {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut self.stream).poll_next(cx) {
+            Poll::Ready(Some(item)) if (self.predicate)(&item) => {
+                self.open = false;
+                match self.delimiter {
+                    Delimiter::Discard => Poll::Ready(None),
+                    Delimiter::EndsSegment => Poll::Ready(Some(item)),
+                    Delimiter::StartsSegment => {
+                        self.next_first = Some(item);
+                        Poll::Ready(None)
+                    }
+                }
+            }
+            Poll::Ready(Some(item)) => Poll::Ready(Some(item)),
+            Poll::Ready(None) => {
+                self.open = false;
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => {
+                self.segment_waker.register(cx.waker(), "Segment");
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A stream of the segments of a stream, which are returned by
+/// `split_by_delimiter`
+#[must_use = "streams do nothing unless polled"]
+pub struct Segments<I, S, P> {
+    state: Arc<Mutex<SplitByDelimiter<I, S, P>>>,
+    terminated: bool,
+}
+
+impl<I, S, P> Segments<I, S, P> {
+    pub(crate) fn new(state: Arc<Mutex<SplitByDelimiter<I, S, P>>>) -> Self {
+        Self {
+            state,
+            terminated: false,
+        }
+    }
+}
+
+impl<I, S, P> Segments<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next segment without the stream having to be pinned.
+    /// This is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<Segment<I, S, P>>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let mut state = self.state.lock();
+        let response = state.poll_next_segment(cx).map(|segment| {
+            segment.map(|()| Segment {
+                state: self.state.clone(),
+                segment: state.segment,
+                terminated: false,
+            })
+        });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through the segments
+impl<I, S, P> Unpin for Segments<I, S, P> {}
+
+impl<I, S, P> Stream for Segments<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = Segment<I, S, P>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, P> FusedStream for Segments<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// A stream of the items of one segment, which is returned by `Segments`.
+/// It ends at the delimiter that ends the segment, or once the next segment
+/// has been asked for
+#[must_use = "streams do nothing unless polled"]
+pub struct Segment<I, S, P> {
+    state: Arc<Mutex<SplitByDelimiter<I, S, P>>>,
+    segment: u64,
+    terminated: bool,
+}
+
+impl<I, S, P> Segment<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned.
+    /// This is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = self.state.lock().poll_segment(self.segment, cx);
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through a segment
+impl<I, S, P> Unpin for Segment<I, S, P> {}
+
+impl<I, S, P> Stream for Segment<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, P> FusedStream for Segment<I, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        Delimiter, SplitStreamByExt,
+    };
+
+    async fn segments(items: &str, delimiter: Delimiter) -> Vec<String> {
+        let mut segments = futures::stream::iter(items.chars().collect::<Vec<_>>())
+            .split_by_delimiter(|&c| c == '|', delimiter);
+        let mut collected = Vec::new();
+        while let Some(segment) = segments.next().await {
+            collected.push(segment.collect::<String>().await);
+        }
+        collected
+    }
+
+    #[tokio::test]
+    async fn test_discard() {
+        assert_eq!(vec!["ab", "c"], segments("ab|c", Delimiter::Discard).await);
+    }
+
+    #[tokio::test]
+    async fn test_ends_segment() {
+        assert_eq!(
+            vec!["ab|", "c"],
+            segments("ab|c", Delimiter::EndsSegment).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_starts_segment() {
+        assert_eq!(
+            vec!["ab", "|c", "|"],
+            segments("ab|c|", Delimiter::StartsSegment).await
+        );
+        // A leading delimiter doesn't leave an empty segment before it
+        assert_eq!(vec!["|a"], segments("|a", Delimiter::StartsSegment).await);
+    }
+
+    #[tokio::test]
+    async fn test_empty_segments() {
+        assert_eq!(
+            vec!["", "a", "", "b"],
+            segments("|a||b", Delimiter::Discard).await
+        );
+        assert_eq!(
+            vec!["|", "a|", "|", "b"],
+            segments("|a||b", Delimiter::EndsSegment).await
+        );
+        assert!(segments("", Delimiter::Discard).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_trailing_delimiter() {
+        // There's no empty segment after the last delimiter
+        assert_eq!(vec!["a", "b"], segments("a|b|", Delimiter::Discard).await);
+        assert_eq!(
+            vec!["a|", "b|"],
+            segments("a|b|", Delimiter::EndsSegment).await
+        );
+        assert_eq!(vec![""], segments("|", Delimiter::Discard).await);
+    }
+
+    #[tokio::test]
+    async fn test_next_segment_drops_the_rest() {
+        let mut segments = futures::stream::iter("ab|cd|e".chars())
+            .split_by_delimiter(|&c| c == '|', Delimiter::Discard);
+        let mut first = segments.next().await.unwrap();
+        assert_eq!(Some('a'), first.next().await);
+        let second = segments.next().await.unwrap();
+        // `b` was dropped when the second segment was asked for
+        assert_eq!(None, first.next().await);
+        assert_eq!("cd", second.collect::<String>().await);
+        // A segment that's never polled is skipped whole
+        let third = segments.next().await.unwrap();
+        drop(third);
+        assert!(segments.next().await.is_none());
+    }
+
+    #[test]
+    fn test_waiting_segment_woken_when_skipped() {
+        let (source, handle) = ManualStream::new();
+        let mut segments = source.split_by_delimiter(|&n| n == 0, Delimiter::Discard);
+        let waker = CountingWaker::new();
+        handle.push(1);
+        let mut first = match poll_once(&mut segments, &waker.waker()) {
+            Poll::Ready(Some(segment)) => segment,
+            _ => panic!("the first segment starts with 1"),
+        };
+        let segment_waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut first, &segment_waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut first, &segment_waker.waker()));
+        assert!(poll_once(&mut segments, &waker.waker()).is_pending());
+        assert_eq!(1, segment_waker.count());
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut first, &segment_waker.waker())
+        );
+        handle.push(2);
+        handle.push(0);
+        handle.push(3);
+        assert!(matches!(
+            poll_once(&mut segments, &waker.waker()),
+            Poll::Ready(Some(_))
+        ));
+    }
+}
@@ -0,0 +1,826 @@
+use alloc::sync::Arc;
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{
+    completion::{Completion, CompletionTracker},
+    debug::{self, Items, Verbose},
+    ring::RingBuf,
+    side_waker::SideWaker,
+    sync::Mutex,
+};
+
+/// The items of the batches that the underlying stream yields
+type Item<S> = <<S as Stream>::Item as IntoIterator>::Item;
+type Batch<S> = <<S as Stream>::Item as IntoIterator>::IntoIter;
+
+#[pin_project]
+pub(crate) struct SplitByFlat<S, P, const N: usize>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    buf_true: RingBuf<Item<S>, N>,
+    buf_false: RingBuf<Item<S>, N>,
+    waker_true: SideWaker,
+    waker_false: SideWaker,
+    #[pin]
+    stream: S,
+    // The rest of the batch that is being routed, and the item of it that didn't fit in the
+    // buffer of its stream, along with whether that's the `true` stream
+    batch: Option<Batch<S>>,
+    held: Option<(bool, Item<S>)>,
+    predicate: P,
+    done: bool,
+    closed_true: bool,
+    closed_false: bool,
+    aborted: bool,
+}
+
+impl<S, P, const N: usize> SplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    fn abort(&mut self) {
+        self.aborted = true;
+        self.waker_true.wake();
+        self.waker_false.wake();
+    }
+
+    fn debug_fields(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("buffered_true", &self.buf_true.len())
+            .field("buffered_false", &self.buf_false.len())
+            .field("capacity", &N)
+            .field("waker_true", &self.waker_true.is_registered())
+            .field("waker_false", &self.waker_false.is_registered())
+            .field("batch", &(self.batch.is_some() || self.held.is_some()))
+            .field("done", &self.done)
+            .field("closed_true", &self.closed_true)
+            .field("closed_false", &self.closed_false)
+            .field("aborted", &self.aborted);
+    }
+}
+
+impl<S, P, const N: usize> SplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    Item<S>: fmt::Debug,
+{
+    fn debug_items(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("items_true", &Items(self.buf_true.iter()))
+            .field("items_false", &Items(self.buf_false.iter()));
+    }
+}
+
+impl<S, P, const N: usize> SplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            buf_false: RingBuf::new(),
+            buf_true: RingBuf::new(),
+            waker_false: SideWaker::default(),
+            waker_true: SideWaker::default(),
+            stream,
+            batch: None,
+            held: None,
+            predicate,
+            done: false,
+            closed_true: false,
+            closed_false: false,
+            aborted: false,
+        }))
+    }
+
+    fn size_hint_true(&self) -> (usize, Option<usize>) {
+        if self.aborted {
+            return (0, Some(0));
+        }
+        let held = usize::from(matches!(self.held, Some((true, _))));
+        let buffered = self.buf_true.len() + held;
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+        // The underlying stream counts batches, which say nothing about the number of items
+        (buffered, None)
+    }
+
+    fn size_hint_false(&self) -> (usize, Option<usize>) {
+        if self.aborted {
+            return (0, Some(0));
+        }
+        let held = usize::from(matches!(self.held, Some((false, _))));
+        let buffered = self.buf_false.len() + held;
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+        // The underlying stream counts batches, which say nothing about the number of items
+        (buffered, None)
+    }
+
+    fn poll_next_true(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Item<S>>> {
+        let mut this = self.project();
+        if *this.aborted {
+            return Poll::Ready(None);
+        }
+        this.waker_true.register(cx.waker(), "TrueSplitByFlat");
+        loop {
+            let was_full = this.buf_true.remaining() == 0;
+            if let Some(item) = this.buf_true.pop_front() {
+                // There was already a value in the buffer. Return that value
+                if was_full {
+                    // The `false` stream might be stuck behind an item for this buffer, and nothing
+                    // else would wake it
+                    this.waker_false.wake();
+                }
+                return Poll::Ready(Some(item));
+            }
+            if this.batch.is_some() || this.held.is_some() {
+                let (_, routed_false) = route(
+                    this.batch,
+                    this.held,
+                    this.predicate,
+                    this.buf_true,
+                    this.buf_false,
+                );
+                if routed_false {
+                    this.waker_false.wake();
+                }
+                if this.held.is_some() && this.buf_true.is_empty() {
+                    // The rest of the batch is stuck behind an item for the other stream, whose
+                    // buffer is full. Notify that stream and return pending
+                    this.waker_false.wake();
+                    return Poll::Pending;
+                }
+                continue;
+            }
+            if *this.done {
+                // The underlying stream has finished and there's nothing left in the buffer
+                return Poll::Ready(None);
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(batch)) => *this.batch = Some(batch.into_iter()),
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // If the underlying stream is finished, the `false` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    this.waker_false.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_next_false(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Item<S>>> {
+        let mut this = self.project();
+        if *this.aborted {
+            return Poll::Ready(None);
+        }
+        this.waker_false.register(cx.waker(), "FalseSplitByFlat");
+        loop {
+            let was_full = this.buf_false.remaining() == 0;
+            if let Some(item) = this.buf_false.pop_front() {
+                // There was already a value in the buffer. Return that value
+                if was_full {
+                    // The `true` stream might be stuck behind an item for this buffer, and nothing
+                    // else would wake it
+                    this.waker_true.wake();
+                }
+                return Poll::Ready(Some(item));
+            }
+            if this.batch.is_some() || this.held.is_some() {
+                let (routed_true, _) = route(
+                    this.batch,
+                    this.held,
+                    this.predicate,
+                    this.buf_true,
+                    this.buf_false,
+                );
+                if routed_true {
+                    this.waker_true.wake();
+                }
+                if this.held.is_some() && this.buf_false.is_empty() {
+                    // The rest of the batch is stuck behind an item for the other stream, whose
+                    // buffer is full. Notify that stream and return pending
+                    this.waker_true.wake();
+                    return Poll::Pending;
+                }
+                continue;
+            }
+            if *this.done {
+                // The underlying stream has finished and there's nothing left in the buffer
+                return Poll::Ready(None);
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(batch)) => *this.batch = Some(batch.into_iter()),
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // If the underlying stream is finished, the `true` stream also must be
+                    // finished, so wake it in case nothing else polls it
+                    this.waker_true.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Moves the items of `batch` to the buffers of their streams, starting with
+/// the `held` item that didn't fit last time, until one doesn't fit. That item
+/// becomes the `held` one. Returns whether any items went to the `true` and
+/// `false` stream
+fn route<I, B, P, const N: usize>(
+    batch: &mut Option<B>,
+    held: &mut Option<(bool, I)>,
+    predicate: &P,
+    buf_true: &mut RingBuf<I, N>,
+    buf_false: &mut RingBuf<I, N>,
+) -> (bool, bool)
+where
+    B: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    let mut routed = (false, false);
+    loop {
+        let (is_true, item) = match held.take() {
+            Some(held) => held,
+            None => match batch.as_mut().and_then(Iterator::next) {
+                Some(item) => (predicate(&item), item),
+                None => {
+                    *batch = None;
+                    return routed;
+                }
+            },
+        };
+        let buf = if is_true {
+            &mut *buf_true
+        } else {
+            &mut *buf_false
+        };
+        if let Some(item) = buf.push_back(item) {
+            *held = Some((is_true, item));
+            return routed;
+        }
+        if is_true {
+            routed.0 = true;
+        } else {
+            routed.1 = true;
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items of the batches
+/// where the predicate returns `true`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitByFlat<S, P, const N: usize>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    stream: Arc<Mutex<SplitByFlat<S, P, N>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+}
+
+impl<S, P, const N: usize> TrueSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByFlat<S, P, N>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream,
+            completion,
+            terminated: false,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<S, P, const N: usize> TrueSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream routes an item to this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<Item<S>>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByFlat::poll_next_true(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_true.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
+        }
+        response
+    }
+}
+
+impl<S, P, const N: usize> Stream for TrueSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    type Item = Item<S>;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        }
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<S, P, const N: usize> core::async_iter::AsyncIterator for TrueSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    type Item = Item<S>;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<S, P, const N: usize> FusedStream for TrueSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<S, P, const N: usize> fmt::Debug for TrueSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByFlat",
+            state.as_deref(),
+            self.terminated,
+            SplitByFlat::debug_fields,
+        )
+    }
+}
+
+impl<S, P, const N: usize> fmt::Debug for Verbose<'_, TrueSplitByFlat<S, P, N>>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    Item<S>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "TrueSplitByFlat",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<S, P, const N: usize> Drop for TrueSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    fn drop(&mut self) {
+        self.stream.lock().closed_true = true;
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
+    }
+}
+
+/// A struct that implements `Stream` which returns the items of the batches
+/// where the predicate returns `false`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseSplitByFlat<S, P, const N: usize>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    stream: Arc<Mutex<SplitByFlat<S, P, N>>>,
+    completion: CompletionTracker,
+    terminated: bool,
+}
+
+impl<S, P, const N: usize> FalseSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    pub(crate) fn new(
+        stream: Arc<Mutex<SplitByFlat<S, P, N>>>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream,
+            completion,
+            terminated: false,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<S, P, const N: usize> FalseSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream routes an item to this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<Item<S>>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByFlat::poll_next_false(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_false.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+}
+
+impl<S, P, const N: usize> Stream for FalseSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    type Item = Item<S>;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
+        }
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<S, P, const N: usize> core::async_iter::AsyncIterator for FalseSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    type Item = Item<S>;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<S, P, const N: usize> FusedStream for FalseSplitByFlat<S, P, N>
+where
+    S: Stream + Unpin,
+    S::Item: IntoIterator,
+    P: Fn(&Item<S>) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<S, P, const N: usize> fmt::Debug for FalseSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByFlat",
+            state.as_deref(),
+            self.terminated,
+            SplitByFlat::debug_fields,
+        )
+    }
+}
+
+impl<S, P, const N: usize> fmt::Debug for Verbose<'_, FalseSplitByFlat<S, P, N>>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+    Item<S>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "FalseSplitByFlat",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_items(debug);
+            },
+        )
+    }
+}
+
+impl<S, P, const N: usize> Drop for FalseSplitByFlat<S, P, N>
+where
+    S: Stream,
+    S::Item: IntoIterator,
+{
+    fn drop(&mut self) {
+        self.stream.lock().closed_false = true;
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    #[tokio::test]
+    async fn test_split_by_flat() {
+        let incoming_stream =
+            futures::stream::iter([vec![0, 1, 2], vec![], vec![3, 4, 5, 6, 8, 10], vec![7]]);
+        let (true_stream, false_stream) = incoming_stream
+            .split_by_flat_buffered::<2>(|&n| n % 2 == 0)
+            .into_tuple();
+        let (evens, odds) = futures::join!(
+            true_stream.collect::<Vec<_>>(),
+            false_stream.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![0, 2, 4, 6, 8, 10], evens);
+        assert_eq!(vec![1, 3, 5, 7], odds);
+    }
+
+    #[test]
+    fn test_batch_remainder() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_flat_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(vec![1, 3, 5, 0]);
+        handle.push(vec![7, 2]);
+        // 1 and 3 fill the buffer of the false stream, which holds up the rest of the batch
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(Poll::Pending, poll_once(&mut true_stream, &waker.waker()));
+        assert_eq!(1, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        // Now 5 fits, so 0 can be routed too, before the next batch is pulled
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(1, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(5)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(2, handle.poll_count());
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(7)),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut true_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut false_stream, &waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_order_across_batches() {
+        // A small LCG, so that the batches and polls are the same on every run
+        let mut seed = 7_u32;
+        let mut next = move |bound: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % bound
+        };
+        let mut items = 0..200;
+        let mut batches = Vec::new();
+        while !items.is_empty() {
+            let len = next(6) as usize;
+            batches.push(items.by_ref().take(len).collect::<Vec<u32>>());
+        }
+        let (mut true_stream, mut false_stream) = futures::stream::iter(batches)
+            .split_by_flat_buffered::<3>(|&n| n % 3 == 0)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        let (mut matches, mut rest) = (Vec::new(), Vec::new());
+        let (mut true_done, mut false_done) = (false, false);
+        while !true_done || !false_done {
+            if next(2) == 0 {
+                match poll_once(&mut true_stream, &waker.waker()) {
+                    Poll::Ready(Some(n)) => matches.push(n),
+                    Poll::Ready(None) => true_done = true,
+                    Poll::Pending => {}
+                }
+            } else {
+                match poll_once(&mut false_stream, &waker.waker()) {
+                    Poll::Ready(Some(n)) => rest.push(n),
+                    Poll::Ready(None) => false_done = true,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        assert_eq!((0..200).filter(|n| n % 3 == 0).collect::<Vec<_>>(), matches);
+        assert_eq!((0..200).filter(|n| n % 3 != 0).collect::<Vec<_>>(), rest);
+    }
+
+    #[test]
+    fn test_pop_wakes_full_buffer_waiter() {
+        let (source, handle) = ManualStream::new();
+        let (mut true_stream, mut false_stream) = source
+            .split_by_flat_buffered::<2>(|&n: &i32| n % 2 == 0)
+            .into_tuple();
+        for n in [vec![1, 3, 5, 7, 0]] {
+            handle.push(n);
+        }
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        while handle.poll_count() < 1 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut true_stream, &true_waker.waker())
+            );
+        }
+        // The buffer of the `false` stream is full, so the `true` stream is parked
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(0, true_waker.count());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert!(true_waker.count() > 0);
+    }
+}
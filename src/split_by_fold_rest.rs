@@ -0,0 +1,589 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{ring::RingBuf, side_waker::SideWaker, sync::Mutex};
+
+/// The state both streams of the split share
+type Shared<I, S, P, A, F, const N: usize> = Arc<Mutex<SplitByFoldRest<I, S, P, A, F, N>>>;
+
+#[pin_project]
+pub(crate) struct SplitByFoldRest<I, S, P, A, F, const N: usize> {
+    buf_true: RingBuf<I, N>,
+    waker_true: SideWaker,
+    waker_rest: SideWaker,
+    #[pin]
+    stream: S,
+    predicate: P,
+    fold: F,
+    init: A,
+    acc: A,
+    // How many items were folded into `acc` since the last summary
+    folded: usize,
+    // Emit a summary once this many items were folded, instead of whenever the summaries are
+    // polled
+    every: Option<usize>,
+    // The summaries that reached `every` items before they were taken
+    due: VecDeque<A>,
+    done: bool,
+    closed_true: bool,
+    closed_rest: bool,
+}
+
+impl<I, S, P, A, F, const N: usize> SplitByFoldRest<I, S, P, A, F, N> {
+    fn debug_fields(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field("buffered_true", &self.buf_true.len())
+            .field("capacity", &N)
+            .field("folded", &self.folded)
+            .field("every", &self.every)
+            .field("due", &self.due.len())
+            .field("waker_true", &self.waker_true.is_registered())
+            .field("waker_rest", &self.waker_rest.is_registered())
+            .field("done", &self.done)
+            .field("closed_true", &self.closed_true)
+            .field("closed_rest", &self.closed_rest);
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> SplitByFoldRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I>,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    pub(crate) fn new(stream: S, predicate: P, init: A, fold: F) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            buf_true: RingBuf::new(),
+            waker_true: SideWaker::default(),
+            waker_rest: SideWaker::default(),
+            stream,
+            predicate,
+            fold,
+            acc: init.clone(),
+            init,
+            folded: 0,
+            every: None,
+            due: VecDeque::new(),
+            done: false,
+            closed_true: false,
+            closed_rest: false,
+        }))
+    }
+
+    /// Folds an item the predicate rejected into the accumulator. Once it
+    /// holds `every` items, it's queued as a summary and starts over. Either
+    /// way the summaries are woken when there's something new for them
+    fn fold(self: Pin<&mut Self>, item: I) {
+        let this = self.project();
+        if *this.closed_rest {
+            return;
+        }
+        let acc = core::mem::replace(this.acc, this.init.clone());
+        *this.acc = (this.fold)(acc, item);
+        *this.folded += 1;
+        match *this.every {
+            Some(every) if *this.folded == every => {
+                *this.folded = 0;
+                this.due
+                    .push_back(core::mem::replace(this.acc, this.init.clone()));
+                this.waker_rest.wake();
+            }
+            Some(_) => {}
+            None if *this.folded == 1 => this.waker_rest.wake(),
+            None => {}
+        }
+    }
+
+    /// Takes the accumulator out, leaving a fresh one in its place
+    fn take_summary(self: Pin<&mut Self>) -> A {
+        let this = self.project();
+        *this.folded = 0;
+        core::mem::replace(this.acc, this.init.clone())
+    }
+
+    /// Returns the summary that is due, if any. With `every` set, that's
+    /// the oldest one that reached `every` items. Otherwise it's whatever was
+    /// folded since the last summary
+    fn due_summary(mut self: Pin<&mut Self>) -> Option<A> {
+        let this = self.as_mut().project();
+        if let Some(summary) = this.due.pop_front() {
+            return Some(summary);
+        }
+        if this.every.is_none() && *this.folded > 0 {
+            return Some(self.take_summary());
+        }
+        None
+    }
+
+    fn poll_next_true(
+        mut self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<I>> {
+        let this = self.as_mut().project();
+        this.waker_true.register(cx.waker(), "TrueSplitByFoldRest");
+        if let Some(item) = this.buf_true.pop_front() {
+            // There was already a value in the buffer. Return that value
+            return Poll::Ready(Some(item));
+        }
+        if *this.done {
+            // The underlying stream has finished and there's nothing left in the buffer
+            return Poll::Ready(None);
+        }
+        // Rejected items are folded right away, so nothing ever waits for the summaries
+        loop {
+            let this = self.as_mut().project();
+            match this.stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if (this.predicate)(&item) {
+                        return Poll::Ready(Some(item));
+                    }
+                    self.as_mut().fold(item);
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // The summaries still have to return what was folded last and end
+                    this.waker_rest.wake();
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_next_rest(
+        mut self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<A>> {
+        self.as_mut()
+            .project()
+            .waker_rest
+            .register(cx.waker(), "FoldedRest");
+        if let Some(summary) = self.as_mut().due_summary() {
+            return Poll::Ready(Some(summary));
+        }
+        loop {
+            let this = self.as_mut().project();
+            if *this.done {
+                // Whatever was folded since the last summary makes up the last one
+                return if *this.folded > 0 {
+                    Poll::Ready(Some(self.as_mut().take_summary()))
+                } else {
+                    Poll::Ready(None)
+                };
+            }
+            if this.buf_true.remaining() == 0 && !*this.closed_true {
+                // The buffer of the matches is full, so notify that stream and return pending
+                this.waker_true.wake();
+                return Poll::Pending;
+            }
+            match this.stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if (this.predicate)(&item) {
+                        if !*this.closed_true {
+                            let _ = this.buf_true.push_back(item);
+                            this.waker_true.wake();
+                        }
+                        continue;
+                    }
+                    self.as_mut().fold(item);
+                    if let Some(summary) = self.as_mut().project().due.pop_front() {
+                        return Poll::Ready(Some(summary));
+                    }
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // If the underlying stream is finished, the matches also must be finished,
+                    // so wake that stream in case nothing else polls it
+                    this.waker_true.wake();
+                }
+                Poll::Pending => {
+                    // Nothing more is available right now, so without a count to wait for, what
+                    // was folded so far is the summary
+                    return match self.due_summary() {
+                        Some(summary) => Poll::Ready(Some(summary)),
+                        None => Poll::Pending,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items where the
+/// predicate returns `true`. Created by `split_by_fold_rest`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueSplitByFoldRest<I, S, P, A, F, const N: usize> {
+    stream: Shared<I, S, P, A, F, N>,
+    terminated: bool,
+}
+
+impl<I, S, P, A, F, const N: usize> TrueSplitByFoldRest<I, S, P, A, F, N> {
+    pub(crate) fn new(stream: Shared<I, S, P, A, F, N>) -> Self {
+        Self {
+            stream,
+            terminated: false,
+        }
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for this stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> TrueSplitByFoldRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. Items the predicate rejects
+    /// are folded while this polls, so this only returns `Pending` when the
+    /// underlying stream does, or right away if the summaries were being
+    /// polled at the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByFoldRest::poll_next_true(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_true.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> Stream for TrueSplitByFoldRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> FusedStream for TrueSplitByFoldRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> fmt::Debug for TrueSplitByFoldRest<I, S, P, A, F, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        crate::debug::fmt_split(
+            f,
+            "TrueSplitByFoldRest",
+            state.as_deref(),
+            self.terminated,
+            SplitByFoldRest::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> Drop for TrueSplitByFoldRest<I, S, P, A, F, N> {
+    fn drop(&mut self) {
+        let mut state = self.stream.lock();
+        state.closed_true = true;
+        // The summaries may be waiting for room in the buffer, which nothing makes anymore
+        state.waker_rest.wake();
+    }
+}
+
+/// A struct that implements `Stream` which returns summaries of the items
+/// where the predicate returns `false`, folded into an accumulator. Created
+/// by `split_by_fold_rest`
+#[must_use = "streams do nothing unless polled"]
+pub struct FoldedRest<I, S, P, A, F, const N: usize> {
+    stream: Shared<I, S, P, A, F, N>,
+    terminated: bool,
+}
+
+impl<I, S, P, A, F, const N: usize> FoldedRest<I, S, P, A, F, N> {
+    pub(crate) fn new(stream: Shared<I, S, P, A, F, N>) -> Self {
+        Self {
+            stream,
+            terminated: false,
+        }
+    }
+
+    /// Only returns a summary once `items` items have been folded into it,
+    /// instead of whenever this stream is polled and something was folded.
+    /// Summaries that fill up while the `matches` stream is polled are queued
+    /// until this stream takes them. The last summary, returned once the
+    /// underlying stream ends, holds whatever is left. Panics if `items` is 0
+    pub fn every(self, items: usize) -> Self {
+        assert!(items > 0, "a summary has to hold at least one item");
+        self.stream.lock().every = Some(items);
+        self
+    }
+
+    /// Whether the underlying stream has ended. The last summary may not have
+    /// been returned yet
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> FoldedRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    /// Polls for the next summary without the stream having to be pinned.
+    /// This is what the `Stream` implementation calls. If a summary is due,
+    /// it's returned right away and the accumulator starts over. Otherwise
+    /// this pulls from the underlying stream, buffering the matches for the
+    /// other stream, until a summary is due or nothing more is available
+    pub fn poll_next_summary(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<A>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitByFoldRest::poll_next_rest(Pin::new(&mut guard), cx);
+            if response.is_ready() {
+                guard.waker_rest.ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> Stream for FoldedRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    type Item = A;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Option<A>> {
+        self.get_mut().poll_next_summary(cx)
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> FusedStream for FoldedRest<I, S, P, A, F, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+    A: Clone,
+    F: FnMut(A, I) -> A,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> fmt::Debug for FoldedRest<I, S, P, A, F, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        crate::debug::fmt_split(
+            f,
+            "FoldedRest",
+            state.as_deref(),
+            self.terminated,
+            SplitByFoldRest::debug_fields,
+        )
+    }
+}
+
+impl<I, S, P, A, F, const N: usize> Drop for FoldedRest<I, S, P, A, F, N> {
+    fn drop(&mut self) {
+        // Nothing reads the summaries anymore, so rejected items are dropped instead of folded
+        self.stream.lock().closed_rest = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::StreamExt;
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// Counts and sums the items
+    fn count_and_sum((count, sum): (usize, i32), n: i32) -> (usize, i32) {
+        (count + 1, sum + n)
+    }
+
+    #[tokio::test]
+    async fn test_every() {
+        let (matches, summaries) = futures::stream::iter(0..100)
+            .split_by_fold_rest(|&n| n % 3 == 0, (0, 0), count_and_sum)
+            .into_tuple();
+        let (matches, summaries) = futures::join!(
+            matches.collect::<Vec<_>>(),
+            summaries.every(7).collect::<Vec<_>>()
+        );
+        assert_eq!((0..100).step_by(3).collect::<Vec<_>>(), matches);
+        // 66 items don't divide into summaries of 7, so the last one holds the rest
+        let counts = summaries
+            .iter()
+            .map(|&(count, _)| count)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![7, 7, 7, 7, 7, 7, 7, 7, 7, 3], counts);
+        assert_eq!(
+            (0..100).filter(|n| n % 3 != 0).sum::<i32>(),
+            summaries.iter().map(|&(_, sum)| sum).sum::<i32>()
+        );
+    }
+
+    #[test]
+    fn test_on_demand() {
+        let (source, handle) = ManualStream::new();
+        let (mut matches, mut summaries) = source
+            .split_by_fold_rest(|&n: &i32| n % 3 == 0, (0, 0), count_and_sum)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 2, 3, 4] {
+            handle.push(n);
+        }
+        // The matches stream folds the items it skips over
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some((2, 3))),
+            poll_once(&mut summaries, &waker.waker())
+        );
+        // The summaries pull until nothing more is available
+        handle.push(5);
+        handle.push(6);
+        assert_eq!(
+            Poll::Ready(Some((2, 9))),
+            poll_once(&mut summaries, &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut summaries, &waker.waker()));
+        handle.push(7);
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(6)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut matches, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(Some((1, 7))),
+            poll_once(&mut summaries, &waker.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut summaries, &waker.waker()));
+    }
+
+    #[test]
+    fn test_rest_never_backs_up() {
+        let (source, handle) = ManualStream::new();
+        let (mut matches, mut summaries) = source
+            .split_by_fold_rest(|&n: &i32| n == 1000, (0, 0), count_and_sum)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in 0..500 {
+            handle.push(n);
+        }
+        handle.push(1000);
+        assert_eq!(
+            Poll::Ready(Some(1000)),
+            poll_once(&mut matches, &waker.waker())
+        );
+        assert_eq!(501, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some((500, (0..500).sum()))),
+            poll_once(&mut summaries, &waker.waker())
+        );
+    }
+
+    /// Polls both streams in a pseudo-random order until they end, returning
+    /// the matches and the summaries
+    fn interleave(every: Option<usize>) -> (Vec<i32>, Vec<(usize, i32)>) {
+        // A small LCG, so that the polls are the same on every run
+        let mut seed = 11_u32;
+        let mut next = move |bound: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % bound
+        };
+        let (mut matches, summaries) = futures::stream::iter(0..300)
+            .split_by_fold_rest(|&n| n % 4 == 0, (0, 0), count_and_sum)
+            .into_tuple();
+        let mut summaries = match every {
+            Some(every) => summaries.every(every),
+            None => summaries,
+        };
+        let waker = CountingWaker::new();
+        let (mut items, mut folded) = (Vec::new(), Vec::new());
+        let (mut matches_done, mut summaries_done) = (false, false);
+        while !matches_done || !summaries_done {
+            if next(3) == 0 {
+                match poll_once(&mut matches, &waker.waker()) {
+                    Poll::Ready(Some(n)) => items.push(n),
+                    Poll::Ready(None) => matches_done = true,
+                    Poll::Pending => {}
+                }
+            } else {
+                match poll_once(&mut summaries, &waker.waker()) {
+                    Poll::Ready(Some(summary)) => folded.push(summary),
+                    Poll::Ready(None) => summaries_done = true,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        (items, folded)
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let rest_sum = (0..300).filter(|n| n % 4 != 0).sum::<i32>();
+        for every in [None, Some(1), Some(5), Some(1000)] {
+            let (items, summaries) = interleave(every);
+            assert_eq!((0..300).step_by(4).collect::<Vec<_>>(), items);
+            assert_eq!(
+                225,
+                summaries.iter().map(|&(count, _)| count).sum::<usize>()
+            );
+            assert_eq!(rest_sum, summaries.iter().map(|&(_, sum)| sum).sum::<i32>());
+            assert!(summaries.iter().all(|&(count, _)| count > 0));
+            if let Some(every) = every {
+                let (last, full) = summaries.split_last().unwrap();
+                assert!(full.iter().all(|&(count, _)| count == every));
+                assert!(last.0 <= every);
+            }
+        }
+    }
+}
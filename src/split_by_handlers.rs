@@ -0,0 +1,267 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::stream::{Fuse, FuturesUnordered, StreamExt};
+use pin_project::pin_project;
+
+use crate::Side;
+
+/// A future that runs one of two async handlers on each item of a stream,
+/// depending on the side the predicate puts it on. Created by
+/// `SplitStreamByExt::split_by_handlers`, which runs one handler at a time,
+/// and `split_by_handlers_concurrent`
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SplitByHandlers<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+{
+    #[pin]
+    stream: Fuse<S>,
+    predicate: P,
+    on_true: FT,
+    on_false: FF,
+    running_true: FuturesUnordered<FutT>,
+    running_false: FuturesUnordered<FutF>,
+    limit_true: usize,
+    limit_false: usize,
+    /// How many handlers of both sides can run at the same time
+    limit: usize,
+    /// An item that was pulled for a side without a free slot, along with its
+    /// side. Nothing else is pulled until its handler has been started
+    held: Option<(S::Item, Side)>,
+}
+
+impl<S, P, FT, FF, FutT, FutF> SplitByHandlers<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+{
+    pub(crate) fn new(
+        stream: S,
+        predicate: P,
+        on_true: FT,
+        on_false: FF,
+        limit_true: usize,
+        limit_false: usize,
+        limit: usize,
+    ) -> Self {
+        assert!(
+            limit_true > 0 && limit_false > 0,
+            "each side has to be able to run at least one handler"
+        );
+        Self {
+            stream: stream.fuse(),
+            predicate,
+            on_true,
+            on_false,
+            running_true: FuturesUnordered::new(),
+            running_false: FuturesUnordered::new(),
+            limit_true,
+            limit_false,
+            limit,
+            held: None,
+        }
+    }
+
+    /// How many handlers are running
+    pub fn in_flight(&self) -> usize {
+        self.running_true.len() + self.running_false.len()
+    }
+}
+
+impl<S, P, FT, FF, FutT, FutF, E> Future for SplitByHandlers<S, P, FT, FF, FutT, FutF>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+    FT: FnMut(S::Item) -> FutT,
+    FF: FnMut(S::Item) -> FutF,
+    FutT: Future<Output = Result<(), E>>,
+    FutF: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            // Handlers that finish free up slots for the held item, so they're polled first
+            while let Poll::Ready(Some(result)) = this.running_true.poll_next_unpin(cx) {
+                result?;
+            }
+            while let Poll::Ready(Some(result)) = this.running_false.poll_next_unpin(cx) {
+                result?;
+            }
+            let in_flight = this.running_true.len() + this.running_false.len();
+            if let Some((item, side)) = this.held.take() {
+                let free = in_flight < *this.limit;
+                match side {
+                    Side::True if free && this.running_true.len() < *this.limit_true => {
+                        this.running_true.push((this.on_true)(item));
+                    }
+                    Side::False if free && this.running_false.len() < *this.limit_false => {
+                        this.running_false.push((this.on_false)(item));
+                    }
+                    // A running handler wakes this future when it's done
+                    _ => {
+                        *this.held = Some((item, side));
+                        return Poll::Pending;
+                    }
+                }
+                // The new handler is polled before anything else is pulled
+                continue;
+            }
+            if this.stream.is_terminated() {
+                return if in_flight == 0 {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                };
+            }
+            if in_flight >= *this.limit {
+                // No item could be handled before a running handler is done, so it isn't pulled
+                return Poll::Pending;
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let side = Side::from((this.predicate)(&item));
+                    *this.held = Some((item, side));
+                }
+                Poll::Ready(None) => {}
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures::{channel::oneshot, StreamExt};
+
+    use crate::{testing::CountingWaker, SplitStreamByExt};
+
+    #[tokio::test]
+    async fn test_handlers_get_their_side() {
+        let evens = Arc::new(Mutex::new(Vec::new()));
+        let odds = Arc::new(Mutex::new(Vec::new()));
+        futures::stream::iter(0..10)
+            .split_by_handlers(
+                |&n| n % 2 == 0,
+                |n| {
+                    let evens = evens.clone();
+                    async move {
+                        evens.lock().unwrap().push(n);
+                        Ok::<_, ()>(())
+                    }
+                },
+                |n| {
+                    let odds = odds.clone();
+                    async move {
+                        odds.lock().unwrap().push(n);
+                        Ok(())
+                    }
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 2, 4, 6, 8], *evens.lock().unwrap());
+        assert_eq!(vec![1, 3, 5, 7, 9], *odds.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_error_stops_driver() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let result = futures::stream::iter(0..10)
+            .split_by_handlers(
+                |&n| n < 5,
+                |_| {
+                    let handled = handled.clone();
+                    async move {
+                        handled.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    }
+                },
+                |n| async move { Err(n) },
+            )
+            .await;
+        assert_eq!(Err(5), result);
+        // Nothing after the item that failed was handled
+        assert_eq!(5, handled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_sequential_waits_for_handler() {
+        let (sender, receiver) = oneshot::channel::<()>();
+        let mut receiver = Some(receiver);
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let driver = futures::stream::iter(0..3)
+            .inspect({
+                let pulled = pulled.clone();
+                move |_| {
+                    pulled.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .split_by_handlers(
+                |_| true,
+                move |_| {
+                    let receiver = receiver.take();
+                    async move {
+                        if let Some(receiver) = receiver {
+                            receiver.await.unwrap();
+                        }
+                        Ok::<_, ()>(())
+                    }
+                },
+                |_| async { Ok(()) },
+            );
+        futures::pin_mut!(driver);
+        let waker = CountingWaker::new();
+        let waker_ref = waker.waker();
+        let mut cx = Context::from_waker(&waker_ref);
+        assert!(driver.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(1, pulled.load(Ordering::SeqCst));
+        assert_eq!(1, driver.in_flight());
+        let wakes = waker.count();
+        sender.send(()).unwrap();
+        assert!(waker.count() > wakes);
+        assert_eq!(Poll::Ready(Ok(())), driver.as_mut().poll(&mut cx));
+        assert_eq!(3, pulled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_bounded_per_side() {
+        let running = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let most = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let handler = |side: usize| {
+            let (running, most) = (running.clone(), most.clone());
+            move |_| {
+                let (running, most) = (running.clone(), most.clone());
+                async move {
+                    let now = running[side].fetch_add(1, Ordering::SeqCst) + 1;
+                    most[side].fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running[side].fetch_sub(1, Ordering::SeqCst);
+                    Ok::<_, ()>(())
+                }
+            }
+        };
+        futures::stream::iter(0..20)
+            .split_by_handlers_concurrent(|&n| n % 4 != 0, handler(0), handler(1), 3, 2)
+            .await
+            .unwrap();
+        assert_eq!(3, most[0].load(Ordering::SeqCst));
+        assert_eq!(2, most[1].load(Ordering::SeqCst));
+    }
+}
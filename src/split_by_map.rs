@@ -1,151 +1,298 @@
-use std::{
-    marker::PhantomData,
-    pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
+};
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use futures_sink::Sink;
+use futures_util::future::Either;
+
+use crate::{
+    completion::{Completion, CompletionTracker},
+    debug::{self, Verbose},
+    parts::SplitMapParts,
+    reunite::ReuniteError,
+    sink_passthrough,
+    split_by_map_buffered::{into_left, into_right},
+    split_core::{poll_unlocked, ByMap},
+    sync::{Mutex, SharedState},
+    Side, SplitByMapBuffered,
 };
 
-use futures::{future::Either, Stream};
-use pin_project::pin_project;
+type LockedSplitByMap<I, L, R, S, P> = Mutex<SplitByMap<I, L, R, S, P>>;
+type SharedSplitByMap<I, L, R, S, P> = Arc<LockedSplitByMap<I, L, R, S, P>>;
+
+/// The shared state of a split made with `split_by_map`, which is that of a
+/// buffered split whose buffers hold a single item
+pub(crate) type SplitByMap<I, L, R, S, P> = SplitByMapBuffered<I, L, R, S, P, 1>;
 
-#[pin_project]
-pub(crate) struct SplitByMap<I, L, R, S, P> {
-    buf_left: Option<L>,
-    buf_right: Option<R>,
-    waker_left: Option<Waker>,
-    waker_right: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
-    item: PhantomData<I>,
+/// What the streams of a split made with `split_by_map` are called
+pub(crate) const STREAMS: [&str; 2] = ["LeftSplitByMap", "RightSplitByMap"];
+
+/// A struct that implements `Stream` which returns the inner values where
+/// the predicate returns `Either::Left(..)` when using `split_by_map`
+#[must_use = "streams do nothing unless polled"]
+pub struct LeftSplitByMap<I, L, R, S, P> {
+    stream: SharedState<LockedSplitByMap<I, L, R, S, P>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<L>,
+    generation: usize,
 }
 
-impl<I, L, R, S, P> SplitByMap<I, L, R, S, P>
+impl<I, L, R, S, P> LeftSplitByMap<I, L, R, S, P> {
+    pub(crate) fn new(
+        stream: SharedSplitByMap<I, L, R, S, P>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `right` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_false
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `right` stream is
+    /// full. In that case this stream can only make progress once the `right`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_true()
+    }
+
+    /// Polls for room in the buffer of the `right` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `right` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `right` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `right` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_false(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `right` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedMapPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByMap::new(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitMapParts<L, R, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_map_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_left.insert(0, item);
+        }
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `left` and `right` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        self,
+        mut other: RightSplitByMap<I, L, R, S, P>,
+    ) -> Result<(S, Vec<L>, Vec<R>), ReuniteError<Self, RightSplitByMap<I, L, R, S, P>>> {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.stream.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.stream = SharedState::new(this.stream.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_right.insert(0, item);
+        }
+        Ok((parts.stream, parts.buffered_left, parts.buffered_right))
+    }
+}
+
+impl<I, L, R, S, P> LeftSplitByMap<I, L, R, S, P>
 where
-    S: Stream<Item = I>,
+    S: Stream<Item = I> + Unpin,
     P: Fn(I) -> Either<L, R>,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            buf_right: None,
-            buf_left: None,
-            waker_right: None,
-            waker_left: None,
-            stream,
-            predicate,
-            item: PhantomData,
-        }))
-    }
-
-    fn poll_next_left(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<L>> {
-        let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_left.is_none() {
-            *this.waker_left = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_left.take() {
-            // There was already a value in the buffer. Return that value
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, L>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, L>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<L>> {
+        if let Some(item) = self.peeked.take() {
             return Poll::Ready(Some(item));
         }
-        if this.buf_right.is_some() {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_right {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => Poll::Ready(Some(left_item)),
-                    Either::Right(right_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_right.replace(right_item);
-                        if let Some(waker) = this.waker_right {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
-                    }
-                }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `right` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_right {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
-            }
-            Poll::Pending => Poll::Pending,
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::True, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .map(|item| item.map(into_left));
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
         }
+        response
     }
 
-    fn poll_next_right(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<R>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_right.is_none() {
-            *this.waker_right = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_right.take() {
-            // There was already a value in the buffer. Return that value
-            return Poll::Ready(Some(item));
-        }
-        if this.buf_left.is_some() {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_left {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_left.replace(left_item);
-                        if let Some(waker) = this.waker_left {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
-                    }
-                    Either::Right(right_item) => Poll::Ready(Some(right_item)),
-                }
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&L>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
             }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `left` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_left {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
-            }
-            Poll::Pending => Poll::Pending,
         }
+        Poll::Ready(self.peeked.as_ref())
     }
-}
-
-/// A struct that implements `Stream` which returns the inner values where
-/// the predicate returns `Either::Left(..)` when using `split_by_map`
-pub struct LeftSplitByMap<I, L, R, S, P> {
-    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>,
-}
 
-impl<I, L, R, S, P> LeftSplitByMap<I, L, R, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>) -> Self {
-        Self { stream }
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&L> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
     }
 }
 
+// The peeked item is never pinned
+impl<I, L, R, S, P> Unpin for LeftSplitByMap<I, L, R, S, P> {}
+
 impl<I, L, R, S, P> Stream for LeftSplitByMap<I, L, R, S, P>
 where
     S: Stream<Item = I> + Unpin,
@@ -153,31 +300,374 @@ where
 {
     type Item = L;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMap::poll_next_left(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, L, R, S, P> core::async_iter::AsyncIterator for LeftSplitByMap<I, L, R, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    type Item = L;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, L, R, S, P> FusedStream for LeftSplitByMap<I, L, R, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, L, R, S, P, T> Sink<T> for LeftSplitByMap<I, L, R, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, L, R, S, P> fmt::Debug for LeftSplitByMap<I, L, R, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "LeftSplitByMap",
+            state.as_deref(),
+            self.terminated,
+            SplitByMap::debug_fields,
+        )
+    }
+}
+
+impl<I, L, R, S, P> fmt::Debug for Verbose<'_, LeftSplitByMap<I, L, R, S, P>>
+where
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "LeftSplitByMap",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_map_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, L, R, S, P> Drop for LeftSplitByMap<I, L, R, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_true();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
     }
 }
 
 /// A struct that implements `Stream` which returns the inner values where
 /// the predicate returns `Either::Right(..)` when using `split_by_map`
+#[must_use = "streams do nothing unless polled"]
 pub struct RightSplitByMap<I, L, R, S, P> {
-    stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>,
+    stream: SharedState<LockedSplitByMap<I, L, R, S, P>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<R>,
+    generation: usize,
 }
 
 impl<I, L, R, S, P> RightSplitByMap<I, L, R, S, P> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMap<I, L, R, S, P>>>) -> Self {
-        Self { stream }
+    pub(crate) fn new(
+        stream: SharedSplitByMap<I, L, R, S, P>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `left` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_true
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `left` stream is
+    /// full. In that case this stream can only make progress once the `left`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_false()
+    }
+
+    /// Polls for room in the buffer of the `left` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `left` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `left` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `left` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_true(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `left` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedMapPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByMap::new(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitMapParts<L, R, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_map_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_right.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, L, R, S, P> RightSplitByMap<I, L, R, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, R>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, R>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<R>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::False, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .map(|item| item.map(into_right));
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&R>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&R> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
     }
 }
 
+// The peeked item is never pinned
+impl<I, L, R, S, P> Unpin for RightSplitByMap<I, L, R, S, P> {}
+
 impl<I, L, R, S, P> Stream for RightSplitByMap<I, L, R, S, P>
 where
     S: Stream<Item = I> + Unpin,
@@ -185,15 +675,136 @@ where
 {
     type Item = R;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMap::poll_next_right(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, L, R, S, P> core::async_iter::AsyncIterator for RightSplitByMap<I, L, R, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    type Item = R;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, L, R, S, P> FusedStream for RightSplitByMap<I, L, R, S, P>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, L, R, S, P, T> Sink<T> for RightSplitByMap<I, L, R, S, P>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, L, R, S, P> fmt::Debug for RightSplitByMap<I, L, R, S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "RightSplitByMap",
+            state.as_deref(),
+            self.terminated,
+            SplitByMap::debug_fields,
+        )
+    }
+}
+
+impl<I, L, R, S, P> fmt::Debug for Verbose<'_, RightSplitByMap<I, L, R, S, P>>
+where
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "RightSplitByMap",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_map_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, L, R, S, P> Drop for RightSplitByMap<I, L, R, S, P> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_false();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
     }
 }
@@ -1,153 +1,491 @@
-use std::{
-    marker::PhantomData,
-    pin::Pin,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt, pin::Pin, task::Poll};
+
+use futures_core::{
+    stream::{BoxStream, FusedStream, LocalBoxStream},
+    Stream,
 };
+#[cfg(feature = "blocking")]
+use futures_executor::BlockingStream;
+use futures_sink::Sink;
+use futures_util::future::Either;
 
-use futures::{future::Either, Stream};
-use pin_project::pin_project;
+use crate::{
+    buffer::{self, Buffer},
+    completion::{Completion, CompletionTracker},
+    debug::{self, Items, Verbose},
+    parts::SplitMapParts,
+    reunite::ReuniteError,
+    ring::RingBuf,
+    sink_passthrough,
+    split_core::{poll_unlocked, ByMap, SplitCore},
+    sync::{Mutex, SharedState},
+    try_next::TryNext,
+    Error, Side,
+};
 
-use crate::ring_buf::RingBuf;
+type LockedSplitByMapBuffered<I, L, R, S, P, const N: usize> =
+    Mutex<SplitByMapBuffered<I, L, R, S, P, N>>;
+type SharedSplitByMapBuffered<I, L, R, S, P, const N: usize> =
+    Arc<LockedSplitByMapBuffered<I, L, R, S, P, N>>;
 
-#[pin_project]
-pub(crate) struct SplitByMapBuffered<I, L, R, S, P, const N: usize> {
-    buf_left: RingBuf<L, N>,
-    buf_right: RingBuf<R, N>,
-    waker_left: Option<Waker>,
-    waker_right: Option<Waker>,
-    #[pin]
-    stream: S,
-    predicate: P,
-    item: PhantomData<I>,
-}
+/// The shared state of a split made with `split_by_map_buffered`. The items
+/// of both streams are buffered as `Either`s, the left ones for the left
+/// stream, which is the `true` side of the state
+pub(crate) type SplitByMapBuffered<I, L, R, S, P, const N: usize> =
+    SplitCore<Either<L, R>, S, ByMap<I, P>, RingBuf<Either<L, R>, N>>;
 
-impl<I, L, R, S, P, const N: usize> SplitByMapBuffered<I, L, R, S, P, N>
+/// What the streams of a split made with `split_by_map_buffered` are called
+pub(crate) const STREAMS: [&str; 2] = ["LeftSplitByMapBuffered", "RightSplitByMapBuffered"];
+
+impl<I, L, R, S, P, B> SplitCore<Either<L, R>, S, ByMap<I, P>, B>
 where
-    S: Stream<Item = I>,
-    P: Fn(I) -> Either<L, R>,
+    B: Buffer<Either<L, R>>,
 {
-    pub(crate) fn new(stream: S, predicate: P) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            buf_right: RingBuf::new(),
-            buf_left: RingBuf::new(),
-            waker_right: None,
-            waker_left: None,
-            stream,
-            predicate,
-            item: PhantomData,
-        }))
-    }
-
-    fn poll_next_left(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<L>> {
-        let this = self.project();
-        // There should only ever be one waker calling the function
-        if this.waker_left.is_none() {
-            *this.waker_left = Some(cx.waker().clone());
-        }
-        if let Some(item) = this.buf_left.pop_front() {
-            // There was already a value in the buffer. Return that value
-            return Poll::Ready(Some(item));
-        }
-        if this.buf_right.remaining() == 0 {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_right {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => Poll::Ready(Some(left_item)),
-                    Either::Right(right_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_right.push_back(right_item);
-                        if let Some(waker) = this.waker_right {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
-                    }
-                }
-            }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `right` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_right {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
-            }
-            Poll::Pending => Poll::Pending,
-        }
+    /// Moves every item buffered for the left stream to the back of `items`,
+    /// without polling the underlying stream
+    pub(crate) fn drain_left(&mut self, items: &mut Vec<L>) {
+        let mut drained = Vec::new();
+        self.drain_true(&mut drained, usize::MAX);
+        items.extend(drained.into_iter().map(into_left));
     }
 
-    fn poll_next_right(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<R>> {
-        let this = self.project();
-        // I think there should only ever be one waker calling the function
-        if this.waker_right.is_none() {
-            *this.waker_right = Some(cx.waker().clone());
+    /// Pops an item buffered for the left stream without polling the
+    /// underlying stream
+    pub(crate) fn try_next_left(&mut self) -> TryNext<L> {
+        match self.try_next_true() {
+            TryNext::Item(item) => TryNext::Item(into_left(item)),
+            TryNext::Empty => TryNext::Empty,
+            TryNext::Ended => TryNext::Ended,
         }
-        if let Some(item) = this.buf_right.pop_front() {
-            // There was already a value in the buffer. Return that value
-            return Poll::Ready(Some(item));
+    }
+
+    /// Moves every item buffered for the right stream to the back of `items`,
+    /// without polling the underlying stream
+    pub(crate) fn drain_right(&mut self, items: &mut Vec<R>) {
+        let mut drained = Vec::new();
+        self.drain_false(&mut drained, usize::MAX);
+        items.extend(drained.into_iter().map(into_right));
+    }
+
+    /// Pops an item buffered for the right stream without polling the
+    /// underlying stream
+    pub(crate) fn try_next_right(&mut self) -> TryNext<R> {
+        match self.try_next_false() {
+            TryNext::Item(item) => TryNext::Item(into_right(item)),
+            TryNext::Empty => TryNext::Empty,
+            TryNext::Ended => TryNext::Ended,
         }
-        if this.buf_left.remaining() == 0 {
-            // There is a value available for the other stream. Wake that stream if possible
-            // and return pending since we can't store multiple values for a stream
-            if let Some(waker) = this.waker_left {
-                waker.wake_by_ref();
-            }
-            return Poll::Pending;
-        }
-        match this.stream.poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                match (this.predicate)(item) {
-                    Either::Left(left_item) => {
-                        // This value is not what we wanted. Store it and notify other partition
-                        // task if it exists
-                        let _ = this.buf_left.push_back(left_item);
-                        if let Some(waker) = this.waker_left {
-                            waker.wake_by_ref();
-                        }
-                        Poll::Pending
-                    }
-                    Either::Right(right_item) => Poll::Ready(Some(right_item)),
-                }
-            }
-            Poll::Ready(None) => {
-                // If the underlying stream is finished, the `left` stream also must be
-                // finished, so wake it in case nothing else polls it
-                if let Some(waker) = this.waker_left {
-                    waker.wake_by_ref();
-                }
-                Poll::Ready(None)
-            }
-            Poll::Pending => Poll::Pending,
+    }
+
+    /// Takes the split apart into what it was built from and the items that
+    /// were buffered for each side
+    pub(crate) fn into_map_parts(self) -> SplitMapParts<L, R, S, P> {
+        let parts = self.into_parts();
+        SplitMapParts {
+            stream: parts.stream,
+            predicate: parts.predicate.0,
+            buffered_left: parts.buffered_true.into_iter().map(into_left).collect(),
+            buffered_right: parts.buffered_false.into_iter().map(into_right).collect(),
         }
     }
 }
 
+impl<I, L, R, S, P, B> SplitCore<Either<L, R>, S, ByMap<I, P>, B>
+where
+    B: Buffer<Either<L, R>>,
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    pub(crate) fn debug_map_items(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field(
+                "items_left",
+                &Items(buffer::iter(&self.buf_true).map(inner_debug)),
+            )
+            .field(
+                "items_right",
+                &Items(buffer::iter(&self.buf_false).map(inner_debug)),
+            );
+    }
+}
+
+/// Unwraps an item that was buffered for the left stream
+pub(crate) fn into_left<L, R>(item: Either<L, R>) -> L {
+    match item {
+        Either::Left(item) => item,
+        Either::Right(_) => unreachable!("only left items are buffered for the left stream"),
+    }
+}
+
+/// Unwraps an item that was buffered for the right stream
+pub(crate) fn into_right<L, R>(item: Either<L, R>) -> R {
+    match item {
+        Either::Left(_) => unreachable!("only right items are buffered for the right stream"),
+        Either::Right(item) => item,
+    }
+}
+
+/// Formats a buffered item without the `Either` around it
+fn inner_debug<L, R>(item: &Either<L, R>) -> &dyn fmt::Debug
+where
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    match item {
+        Either::Left(item) => item,
+        Either::Right(item) => item,
+    }
+}
+
 /// A struct that implements `Stream` which returns the inner values where
 /// the predicate returns `Either::Left(..)` when using `split_by_map`
+#[must_use = "streams do nothing unless polled"]
 pub struct LeftSplitByMapBuffered<I, L, R, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByMapBuffered<I, L, R, S, P, N>>>,
+    stream: SharedState<LockedSplitByMapBuffered<I, L, R, S, P, N>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<L>,
+    generation: usize,
 }
 
 impl<I, L, R, S, P, const N: usize> LeftSplitByMapBuffered<I, L, R, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMapBuffered<I, L, R, S, P, N>>>) -> Self {
-        Self { stream }
+    pub(crate) fn new(
+        stream: SharedSplitByMapBuffered<I, L, R, S, P, N>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `right` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_false
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `right` stream is
+    /// full. In that case this stream can only make progress once the `right`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_true()
+    }
+
+    /// Polls for room in the buffer of the `right` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `right` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `right` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `right` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_false(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `right` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedMapPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByMap::new(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Returns an item that is already buffered for this stream, without
+    /// polling the underlying stream or registering a waker, so it never does
+    /// any work on behalf of the other stream. `None` means that nothing is
+    /// buffered right now, not that the stream has ended. Use
+    /// `try_next_or_ended` to tell those apart. This also returns `None` when
+    /// the other stream is being polled at the same time
+    pub fn try_next(&mut self) -> Option<L> {
+        self.try_next_or_ended().item()
+    }
+
+    /// Like `try_next`, but also tells whether the stream has ended
+    pub fn try_next_or_ended(&mut self) -> TryNext<L> {
+        if let Some(item) = self.peeked.take() {
+            return TryNext::Item(item);
+        }
+        if self.terminated {
+            return TryNext::Ended;
+        }
+        match self.stream.try_lock() {
+            Some(mut guard) => guard.try_next_left(),
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Takes every item buffered for this stream at once, oldest first. The
+    /// state is locked once, so nothing is routed in between, and the
+    /// underlying stream is never polled. If the buffer was full, the other
+    /// stream is woken, since it might have been waiting for room in it
+    pub fn drain_buffered(&mut self) -> Vec<L> {
+        let mut items = Vec::new();
+        self.drain_buffered_into(&mut items);
+        items
+    }
+
+    /// Like `drain_buffered`, but adds the items to the back of `items`, so
+    /// its allocation can be reused
+    pub fn drain_buffered_into(&mut self, items: &mut Vec<L>) {
+        items.extend(self.peeked.take());
+        self.stream.lock().drain_left(items);
+    }
+
+    /// Puts an item back at the front of this stream's buffer, so that it's
+    /// returned by the next poll before anything that was already buffered.
+    /// The item is never given to the predicate and never affects the other
+    /// stream, which only sees that this stream's buffer has less space left.
+    /// Fails with `Error::Overflow` if the buffer is full, or with
+    /// `Error::Ended` if the stream has already ended, handing back the item
+    pub fn push_front(&mut self, item: L) -> Result<(), Error<L>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        // An item taken by `poll_peek` comes before everything in the buffer, so it goes back in
+        // first
+        let needed = 1 + usize::from(self.peeked.is_some());
+        if state.buf_true.remaining() < needed {
+            return Err(Error::Overflow {
+                side: Side::True,
+                item: Some(item),
+            });
+        }
+        if let Some(peeked) = self.peeked.take() {
+            let _ = state.push_front(Side::True, Either::Left(peeked));
+        }
+        let _ = state.push_front(Side::True, Either::Left(item));
+        Ok(())
+    }
+
+    /// Adds an item to the back of this stream's buffer, after the items that
+    /// are already buffered but before any items that haven't been pulled
+    /// from the underlying stream yet. Like `push_front`, the item never
+    /// affects the other stream, and it fails the same way
+    pub fn push_back(&mut self, item: L) -> Result<(), Error<L>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        match state.push_back(Side::True, Either::Left(item)) {
+            Some(item) => Err(Error::Overflow {
+                side: Side::True,
+                item: Some(into_left(item)),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitMapParts<L, R, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_map_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_left.insert(0, item);
+        }
+        Ok(parts)
+    }
+
+    /// Puts the split back together, returning the underlying stream along
+    /// with the items that were buffered for the `left` and `right` streams, in
+    /// that order. Fails if `other` doesn't come from the same split
+    #[allow(clippy::type_complexity)]
+    pub fn reunite(
+        self,
+        mut other: RightSplitByMapBuffered<I, L, R, S, P, N>,
+    ) -> Result<(S, Vec<L>, Vec<R>), ReuniteError<Self, RightSplitByMapBuffered<I, L, R, S, P, N>>>
+    {
+        if !Arc::ptr_eq(&self.stream, &other.stream) {
+            return Err(ReuniteError(self, other));
+        }
+        // `other` lets go of the state so that `self` can take it, and gets it back if that fails
+        other.stream.release();
+        let mut parts = match self.into_parts() {
+            Ok(parts) => parts,
+            Err(this) => {
+                other.stream = SharedState::new(this.stream.clone());
+                return Err(ReuniteError(this, other));
+            }
+        };
+        // The item `other` has peeked at would be lost when it is dropped
+        if let Some(item) = other.peeked.take() {
+            parts.buffered_right.insert(0, item);
+        }
+        Ok((parts.stream, parts.buffered_left, parts.buffered_right))
     }
 }
 
+impl<I, L, R, S, P, const N: usize> LeftSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, L>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, L>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<L>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::True, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .map(|item| item.map(into_left));
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_true();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&L>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&L> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
+    }
+}
+
+// The peeked item is never pinned
+impl<I, L, R, S, P, const N: usize> Unpin for LeftSplitByMapBuffered<I, L, R, S, P, N> {}
+
 impl<I, L, R, S, P, const N: usize> Stream for LeftSplitByMapBuffered<I, L, R, S, P, N>
 where
     S: Stream<Item = I> + Unpin,
@@ -155,31 +493,462 @@ where
 {
     type Item = L;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMapBuffered::poll_next_left(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_true(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, L, R, S, P, const N: usize> core::async_iter::AsyncIterator
+    for LeftSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    type Item = L;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> FusedStream for LeftSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, L, R, S, P, const N: usize, T> Sink<T> for LeftSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> fmt::Debug for LeftSplitByMapBuffered<I, L, R, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "LeftSplitByMapBuffered",
+            state.as_deref(),
+            self.terminated,
+            SplitByMapBuffered::debug_fields,
+        )
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> fmt::Debug
+    for Verbose<'_, LeftSplitByMapBuffered<I, L, R, S, P, N>>
+where
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "LeftSplitByMapBuffered",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_map_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> Drop for LeftSplitByMapBuffered<I, L, R, S, P, N> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_true();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_true();
     }
 }
 
 /// A struct that implements `Stream` which returns the inner values where
 /// the predicate returns `Either::Right(..)` when using `split_by_map`
+#[must_use = "streams do nothing unless polled"]
 pub struct RightSplitByMapBuffered<I, L, R, S, P, const N: usize> {
-    stream: Arc<Mutex<SplitByMapBuffered<I, L, R, S, P, N>>>,
+    stream: SharedState<LockedSplitByMapBuffered<I, L, R, S, P, N>>,
+    completion: CompletionTracker,
+    terminated: bool,
+    peeked: Option<R>,
+    generation: usize,
 }
 
 impl<I, L, R, S, P, const N: usize> RightSplitByMapBuffered<I, L, R, S, P, N> {
-    pub(crate) fn new(stream: Arc<Mutex<SplitByMapBuffered<I, L, R, S, P, N>>>) -> Self {
-        Self { stream }
+    pub(crate) fn new(
+        stream: SharedSplitByMapBuffered<I, L, R, S, P, N>,
+        completion: CompletionTracker,
+    ) -> Self {
+        Self {
+            stream: SharedState::new(stream),
+            completion,
+            terminated: false,
+            peeked: None,
+            generation: 0,
+        }
+    }
+
+    /// Returns a future that resolves once the split is fully drained. See
+    /// `Completion` for details
+    pub fn completion(&self) -> Completion {
+        self.completion.completion()
+    }
+
+    /// Aborts the split. Both streams return `None` from then on, any items
+    /// still buffered are never delivered and all `Completion` futures resolve
+    pub fn abort(&self) {
+        self.stream.lock().abort();
+        self.completion.abort();
+    }
+
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+
+    /// Whether the underlying stream has ended. Items can still be buffered
+    /// for either stream
+    pub fn is_source_done(&self) -> bool {
+        self.stream.lock().done
+    }
+
+    /// Whether the `left` stream of the split has been dropped
+    pub fn other_side_closed(&self) -> bool {
+        self.stream.lock().closed_true
+    }
+
+    /// Whether polling this stream now would return `Pending` without pulling
+    /// from the underlying stream, because the buffer of the `left` stream is
+    /// full. In that case this stream can only make progress once the `left`
+    /// stream is polled
+    pub fn would_block_on_other(&self) -> bool {
+        self.peeked.is_none() && !self.terminated && self.stream.lock().would_block_false()
+    }
+
+    /// Polls for room in the buffer of the `left` stream, which is where this
+    /// stream puts the items it pulls for the other side. `Ready(n)` means
+    /// `n` more of those items fit before polling this stream stalls until
+    /// the `left` stream catches up. `Pending` means the buffer is full, and
+    /// the waker of `cx` is woken once the `left` stream takes an item out of
+    /// it. Only the waker of the latest call is kept. `Ready(0)` means no
+    /// room will free up anymore, because the split has been aborted or the
+    /// `left` stream has been dropped
+    pub fn poll_capacity(&self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        self.stream.lock().poll_room_true(cx)
+    }
+
+    /// Waits until there's room in the buffer of the `left` stream and
+    /// returns how much. See `poll_capacity`
+    pub async fn ready(&self) -> usize {
+        core::future::poll_fn(|cx| self.poll_capacity(cx)).await
+    }
+
+    /// Replaces the predicate of the split. The new predicate decides where
+    /// the next item pulled from the underlying stream goes, no matter which
+    /// stream pulls it. Items that were pulled before stay where they are,
+    /// whether they've been returned, peeked at or are still buffered. Every
+    /// closure has its own type, so this needs a predicate that is a function
+    /// pointer or boxed, like `BoxedMapPredicate`
+    pub fn set_predicate(&self, predicate: P) {
+        self.stream.lock().router = Some(ByMap::new(predicate));
+    }
+
+    /// Replaces the underlying stream of the split, for example to continue
+    /// with a new connection once the old one has ended. The items buffered
+    /// for both streams are kept and both streams are woken. The old stream
+    /// is returned, or `None` if it had already ended. In that case both
+    /// streams continue with the items of the new stream, even if they have
+    /// returned `None` already, although `Completion` futures that resolved
+    /// stay resolved. If the split has been aborted, nothing is replaced and
+    /// `stream` is returned as the error
+    pub fn replace_stream(&self, stream: S) -> Result<Option<S>, S> {
+        self.stream.lock().replace_stream(stream)
+    }
+
+    /// Calls `f` with the underlying stream, for example to look up the peer
+    /// address of a connection while it's split. The split is locked while
+    /// `f` runs, so the other stream waits for it to return, and `f` mustn't
+    /// use either stream of the split
+    pub fn with_stream_ref<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.stream.lock().stream)
+    }
+
+    /// Calls `f` with the underlying stream to change it, for example to set
+    /// an option of a socket or the state of a codec. The split is locked
+    /// like it is for `with_stream_ref`. What `f` does is up to the caller:
+    /// the items already pulled stay where they are, and nothing is pulled
+    /// again once the stream has ended, so use `replace_stream` for that
+    pub fn with_stream_mut<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.stream.lock().stream)
+    }
+
+    /// Whether the underlying stream has been replaced since this stream
+    /// last polled it
+    fn is_restarted(&self) -> bool {
+        self.stream
+            .try_lock()
+            .is_some_and(|guard| guard.generation != self.generation)
+    }
+
+    /// Returns an item that is already buffered for this stream, without
+    /// polling the underlying stream or registering a waker, so it never does
+    /// any work on behalf of the other stream. `None` means that nothing is
+    /// buffered right now, not that the stream has ended. Use
+    /// `try_next_or_ended` to tell those apart. This also returns `None` when
+    /// the other stream is being polled at the same time
+    pub fn try_next(&mut self) -> Option<R> {
+        self.try_next_or_ended().item()
+    }
+
+    /// Like `try_next`, but also tells whether the stream has ended
+    pub fn try_next_or_ended(&mut self) -> TryNext<R> {
+        if let Some(item) = self.peeked.take() {
+            return TryNext::Item(item);
+        }
+        if self.terminated {
+            return TryNext::Ended;
+        }
+        match self.stream.try_lock() {
+            Some(mut guard) => guard.try_next_right(),
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Takes every item buffered for this stream at once, oldest first. The
+    /// state is locked once, so nothing is routed in between, and the
+    /// underlying stream is never polled. If the buffer was full, the other
+    /// stream is woken, since it might have been waiting for room in it
+    pub fn drain_buffered(&mut self) -> Vec<R> {
+        let mut items = Vec::new();
+        self.drain_buffered_into(&mut items);
+        items
+    }
+
+    /// Like `drain_buffered`, but adds the items to the back of `items`, so
+    /// its allocation can be reused
+    pub fn drain_buffered_into(&mut self, items: &mut Vec<R>) {
+        items.extend(self.peeked.take());
+        self.stream.lock().drain_right(items);
+    }
+
+    /// Puts an item back at the front of this stream's buffer, so that it's
+    /// returned by the next poll before anything that was already buffered.
+    /// The item is never given to the predicate and never affects the other
+    /// stream, which only sees that this stream's buffer has less space left.
+    /// Fails with `Error::Overflow` if the buffer is full, or with
+    /// `Error::Ended` if the stream has already ended, handing back the item
+    pub fn push_front(&mut self, item: R) -> Result<(), Error<R>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        // An item taken by `poll_peek` comes before everything in the buffer, so it goes back in
+        // first
+        let needed = 1 + usize::from(self.peeked.is_some());
+        if state.buf_false.remaining() < needed {
+            return Err(Error::Overflow {
+                side: Side::False,
+                item: Some(item),
+            });
+        }
+        if let Some(peeked) = self.peeked.take() {
+            let _ = state.push_front(Side::False, Either::Right(peeked));
+        }
+        let _ = state.push_front(Side::False, Either::Right(item));
+        Ok(())
+    }
+
+    /// Adds an item to the back of this stream's buffer, after the items that
+    /// are already buffered but before any items that haven't been pulled
+    /// from the underlying stream yet. Like `push_front`, the item never
+    /// affects the other stream, and it fails the same way
+    pub fn push_back(&mut self, item: R) -> Result<(), Error<R>> {
+        if self.terminated {
+            return Err(Error::Ended { item: Some(item) });
+        }
+        let mut state = self.stream.lock();
+        match state.push_back(Side::False, Either::Right(item)) {
+            Some(item) => Err(Error::Overflow {
+                side: Side::False,
+                item: Some(into_right(item)),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the split apart into the underlying stream, the predicate and
+    /// the items that were buffered for both streams. This only works once
+    /// the other stream has been dropped, otherwise `self` is returned as the
+    /// error
+    pub fn into_parts(mut self) -> Result<SplitMapParts<L, R, S, P>, Self> {
+        // The streams of a split are the only references to its shared state
+        let state = match self.stream.try_take() {
+            Some(state) => state,
+            None => return Err(self),
+        };
+        let mut parts = state.into_inner().into_map_parts();
+        if let Some(item) = self.peeked.take() {
+            parts.buffered_right.insert(0, item);
+        }
+        Ok(parts)
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> RightSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    /// Boxes the stream, which erases the types of the underlying stream and
+    /// the predicate
+    pub fn boxed<'a>(self) -> BoxStream<'a, R>
+    where
+        Self: Send + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Boxes the stream like `boxed`, for streams that aren't `Send`
+    pub fn boxed_local<'a>(self) -> LocalBoxStream<'a, R>
+    where
+        Self: 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Turns the stream into an iterator that blocks the current thread while
+    /// it waits for each item, for consuming it from synchronous code. The
+    /// other stream can keep being consumed asynchronously, because it wakes
+    /// the blocked thread the same way it would wake a task. Requires the
+    /// `blocking` feature
+    #[cfg(feature = "blocking")]
+    pub fn into_blocking_iter(self) -> BlockingStream<Self> {
+        futures_executor::block_on_stream(self)
+    }
+
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls. `Ready(Some(item))` is the
+    /// next item of this stream and `Ready(None)` means the stream has ended,
+    /// which is then returned by every later call. `Pending` means there's no
+    /// item for this stream yet. The waker of `cx` replaces the one from the
+    /// previous call and is woken once there might be one: when the underlying
+    /// stream wakes it, when the other stream buffers an item for this one or
+    /// ends the split, or right away if the other stream was being polled at
+    /// the same time
+    pub fn poll_next_item(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<R>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if self.terminated {
+            if !self.is_restarted() {
+                return Poll::Ready(None);
+            }
+            self.terminated = false;
+        }
+        // Remembers which underlying stream this stream might see the end of
+        let response = poll_unlocked(&self.stream, Side::False, cx, &mut self.generation)
+            .unwrap_or_else(|| {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .map(|item| item.map(into_right));
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+            self.completion.finish_false();
+        }
+        response
+    }
+
+    /// Polls for the next item like `poll_next`, but keeps it so that it's
+    /// returned again by the next call to `poll_next` or `poll_peek`. Items
+    /// for the other stream are handled the same way `poll_next` handles them
+    pub fn poll_peek(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<&R>> {
+        if self.peeked.is_none() {
+            match self.poll_next_item(cx) {
+                Poll::Ready(Some(item)) => self.peeked = Some(item),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(self.peeked.as_ref())
+    }
+
+    /// Waits for the next item without consuming it. See `poll_peek`
+    pub async fn peek(&mut self) -> Option<&R> {
+        core::future::poll_fn(|cx| self.poll_peek(cx).map(|_| ())).await;
+        self.peeked.as_ref()
     }
 }
 
+// The peeked item is never pinned
+impl<I, L, R, S, P, const N: usize> Unpin for RightSplitByMapBuffered<I, L, R, S, P, N> {}
+
 impl<I, L, R, S, P, const N: usize> Stream for RightSplitByMapBuffered<I, L, R, S, P, N>
 where
     S: Stream<Item = I> + Unpin,
@@ -187,15 +956,397 @@ where
 {
     type Item = R;
     fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let response = if let Ok(mut guard) = self.stream.try_lock() {
-            SplitByMapBuffered::poll_next_right(Pin::new(&mut guard), cx)
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = match self.stream.try_lock() {
+            Some(guard) => guard.size_hint_false(),
+            // The lock is only held while the other stream is being polled, but there's no way
+            // to get a better estimate without waiting for it
+            None => (0, None),
         };
-        response
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, L, R, S, P, const N: usize> core::async_iter::AsyncIterator
+    for RightSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    type Item = R;
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> FusedStream for RightSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(I) -> Either<L, R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated && !self.is_restarted()
+    }
+}
+
+impl<I, L, R, S, P, const N: usize, T> Sink<T> for RightSplitByMapBuffered<I, L, R, S, P, N>
+where
+    S: Sink<T> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_ready(cx)
+        })
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        sink_passthrough::with_locked(&self.stream, |state| {
+            Pin::new(&mut state.stream).start_send(item)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_flush(cx)
+        })
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        sink_passthrough::poll_locked(&self.stream, cx, |state, cx| {
+            Pin::new(&mut state.stream).poll_close(cx)
+        })
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> fmt::Debug for RightSplitByMapBuffered<I, L, R, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "RightSplitByMapBuffered",
+            state.as_deref(),
+            self.terminated,
+            SplitByMapBuffered::debug_fields,
+        )
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> fmt::Debug
+    for Verbose<'_, RightSplitByMapBuffered<I, L, R, S, P, N>>
+where
+    L: fmt::Debug,
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.stream.try_lock();
+        debug::fmt_split(
+            f,
+            "RightSplitByMapBuffered",
+            state.as_deref(),
+            self.0.terminated,
+            |state, debug| {
+                state.debug_fields(debug);
+                state.debug_map_items(debug);
+            },
+        )
+    }
+}
+
+impl<I, L, R, S, P, const N: usize> Drop for RightSplitByMapBuffered<I, L, R, S, P, N> {
+    fn drop(&mut self) {
+        // A stream that was taken apart has nothing left to close
+        if let Some(stream) = self.stream.get() {
+            stream.lock().close_false();
+        }
+        // A stream that is dropped will never observe any more items, so it counts as finished
+        self.completion.finish_false();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{stream::FusedStream, Stream, StreamExt};
+
+    use crate::{
+        testing::{poll_once, poll_until_ready_with_waker, CountingWaker, ManualStream},
+        Either, Error, Side, SplitStreamByMapExt,
+    };
+
+    /// Sends the evens to the left as strings and the odds to the right as
+    /// bytes, so the two sides have different item types
+    fn evens_as_strings(n: i32) -> Either<String, u8> {
+        if n % 2 == 0 {
+            Either::Left(n.to_string())
+        } else {
+            Either::Right(n as u8)
+        }
+    }
+
+    #[test]
+    fn test_buffers_until_full() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, mut right_stream) = source
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        let left_waker = CountingWaker::new();
+        let right_waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut right_stream, &right_waker.waker())
+        );
+        for n in [0, 2, 4, 1] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut right_stream, &right_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut right_stream, &right_waker.waker())
+        );
+        // The left buffer is full now, so the right stream has to wait on the left stream
+        // without polling the source
+        let polls = handle.poll_count();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut right_stream, &right_waker.waker())
+        );
+        assert_eq!(polls, handle.poll_count());
+        for n in ["0", "2", "4"] {
+            assert_eq!(
+                Poll::Ready(Some(n.to_string())),
+                poll_once(&mut left_stream, &left_waker.waker())
+            );
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut left_stream, &left_waker.waker())
+        );
+        assert!(right_waker.count() > 0);
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut right_stream, &right_waker.waker())
+        );
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let (mut left_stream, mut right_stream) = futures::stream::iter([0, 1, 2, 3, 4, 5])
+            .split_by_map_buffered::<3>(evens_as_strings)
+            .into_tuple();
+        assert_eq!((0, Some(6)), left_stream.size_hint());
+        assert_eq!((0, Some(6)), right_stream.size_hint());
+        let waker = CountingWaker::new();
+        assert_eq!(
+            Poll::Ready(Some("0".to_string())),
+            poll_once(&mut left_stream, &waker.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut left_stream, &waker.waker()));
+        // 1 is buffered for the right stream
+        assert_eq!((0, Some(4)), left_stream.size_hint());
+        assert_eq!((1, Some(5)), right_stream.size_hint());
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut right_stream, &waker.waker())
+        );
+        assert_eq!((0, Some(4)), right_stream.size_hint());
+    }
+
+    #[test]
+    fn test_poll_after_termination() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, mut right_stream) = source
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        handle.end();
+        assert_eq!(Poll::Pending, poll_once(&mut left_stream, &waker.waker()));
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut left_stream, &waker.waker())
+        );
+        assert!(left_stream.is_terminated());
+        assert!(!right_stream.is_terminated());
+        let polls = handle.poll_count();
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut left_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut right_stream, &waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(None),
+            poll_once(&mut right_stream, &waker.waker())
+        );
+        assert!(right_stream.is_terminated());
+        // Neither stream polls the source again once it's finished
+        assert_eq!(polls, handle.poll_count());
+    }
+
+    #[tokio::test]
+    async fn test_select_without_fuse() {
+        let (mut left_stream, mut right_stream) = futures::stream::iter(0..10)
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        let mut left_items = Vec::new();
+        let mut right_items = Vec::new();
+        loop {
+            futures::select! {
+                item = left_stream.next() => left_items.extend(item),
+                item = right_stream.next() => right_items.extend(item),
+                complete => break,
+            }
+        }
+        assert_eq!(vec!["0", "2", "4", "6", "8"], left_items);
+        assert_eq!(vec![1, 3, 5, 7, 9], right_items);
+    }
+
+    #[test]
+    fn test_debug() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, right_stream) = source
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_once(&mut left_stream, &waker.waker()));
+        assert_eq!(
+            "LeftSplitByMapBuffered { buffered_left: 0, buffered_right: 1, capacity: 2, \
+             waker_left: true, waker_right: false, done: false, closed_left: false, \
+             closed_right: false, aborted: false, terminated: false }",
+            format!("{:?}", left_stream)
+        );
+        let verbose = format!("{:?}", right_stream.verbose());
+        assert!(verbose.contains("items_left: [], items_right: [1]"));
+    }
+
+    #[test]
+    fn test_reunite() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, right_stream) = source
+            .split_by_map_buffered::<3>(evens_as_strings)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [1, 3, 4, 5] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some("4".to_string())),
+            poll_until_ready_with_waker(&mut left_stream, &waker.waker(), 3)
+        );
+        let (mut source, buf_left, buf_right) = left_stream.reunite(right_stream).unwrap();
+        assert_eq!(Vec::<String>::new(), buf_left);
+        assert_eq!(vec![1, 3], buf_right);
+        assert_eq!(Poll::Ready(Some(5)), poll_once(&mut source, &waker.waker()));
+    }
+
+    #[test]
+    fn test_push_front() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, mut right_stream) = source
+            .split_by_map_buffered::<3>(evens_as_strings)
+            .into_tuple();
+        let waker = CountingWaker::new();
+        for n in [0, 2, 1] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_until_ready_with_waker(&mut right_stream, &waker.waker(), 3)
+        );
+        assert_eq!(Ok(()), right_stream.push_front(1));
+        assert_eq!(Ok(()), right_stream.push_back(3));
+        assert_eq!(Ok(()), right_stream.push_back(5));
+        assert_eq!(
+            Err(Error::Overflow {
+                side: Side::False,
+                item: Some(7)
+            }),
+            right_stream.push_back(7)
+        );
+        for n in ["0", "2"] {
+            assert_eq!(
+                Poll::Ready(Some(n.to_string())),
+                poll_once(&mut left_stream, &waker.waker())
+            );
+        }
+        handle.end();
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = poll_once(&mut right_stream, &waker.waker()) {
+            items.push(item);
+        }
+        assert_eq!(vec![1, 3, 5], items);
+    }
+
+    #[test]
+    fn test_other_side_closed() {
+        let (left_stream, right_stream) = futures::stream::iter(0..3)
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        assert!(!right_stream.other_side_closed());
+        drop(left_stream);
+        assert!(right_stream.other_side_closed());
+    }
+
+    #[test]
+    fn test_drain_buffered() {
+        let (source, handle) = ManualStream::new();
+        let (mut left_stream, mut right_stream) = source
+            .split_by_map_buffered::<2>(evens_as_strings)
+            .into_tuple();
+        let left_waker = CountingWaker::new();
+        for n in [1, 3, 0] {
+            handle.push(n);
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                Poll::Pending,
+                poll_once(&mut left_stream, &left_waker.waker())
+            );
+        }
+        let wakes = left_waker.count();
+        assert_eq!(vec![1, 3], right_stream.drain_buffered());
+        // The left stream was waiting for room in the right buffer
+        assert!(left_waker.count() > wakes);
+        assert_eq!(
+            Poll::Ready(Some("0".to_string())),
+            poll_once(&mut left_stream, &left_waker.waker())
+        );
+        assert!(right_stream.drain_buffered().is_empty());
     }
 }
@@ -0,0 +1,1259 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{fmt, marker::PhantomData, pin::Pin, task::Poll};
+
+use futures_core::Stream;
+use futures_util::future::Either;
+use pin_project::pin_project;
+
+use crate::{
+    bounded_buf::Pushed,
+    buffer::{self, Buffer},
+    debug::Items,
+    diagnostics::{Diagnostics, PendingReason},
+    idle::Activity,
+    metric::SplitMetrics,
+    order_check::OrderCheck,
+    parts::SplitParts,
+    side_waker::{RoomWaker, SideWaker},
+    sync::Mutex,
+    trace::SplitSpan,
+    try_next::TryNext,
+    Side,
+};
+
+/// Decides which stream of a split an item of the underlying stream goes to,
+/// and turns it into the `I` that stream returns
+pub(crate) trait Router<S, I> {
+    fn route(&self, item: S) -> (Side, I);
+
+    /// Turns an item into what the streams return without deciding where it
+    /// goes, for a stream that took over the other one. Hands the item back
+    /// if only `route` can do that
+    fn pass(item: S) -> Result<I, S> {
+        Err(item)
+    }
+}
+
+/// What the fields about each side of a split are called in `Debug` output,
+/// `true` side first
+pub(crate) trait SideNames {
+    const BUFFERED: [&'static str; 2];
+    const WAKER: [&'static str; 2];
+    const CLOSED: [&'static str; 2];
+    const ITEMS: [&'static str; 2];
+}
+
+/// Routes the items a predicate returns `true` for to the `true` stream, made
+/// by `split_by` and `split_by_buffered`
+pub(crate) struct ByPredicate<P>(pub(crate) P);
+
+impl<I, P> Router<I, I> for ByPredicate<P>
+where
+    P: Fn(&I) -> bool,
+{
+    fn route(&self, item: I) -> (Side, I) {
+        (Side::from((self.0)(&item)), item)
+    }
+
+    fn pass(item: I) -> Result<I, I> {
+        Ok(item)
+    }
+}
+
+impl<P> SideNames for ByPredicate<P> {
+    const BUFFERED: [&'static str; 2] = ["buffered_true", "buffered_false"];
+    const WAKER: [&'static str; 2] = ["waker_true", "waker_false"];
+    const CLOSED: [&'static str; 2] = ["closed_true", "closed_false"];
+    const ITEMS: [&'static str; 2] = ["items_true", "items_false"];
+}
+
+/// Routes the items a function maps to `Either::Left` to the `true` stream,
+/// which is the left one, made by `split_by_map` and `split_by_map_buffered`.
+/// Both buffers hold `Either`s, so the left stream unwraps what it takes out
+pub(crate) struct ByMap<I, P>(pub(crate) P, PhantomData<fn(I)>);
+
+impl<I, P> ByMap<I, P> {
+    pub(crate) fn new(map: P) -> Self {
+        Self(map, PhantomData)
+    }
+}
+
+impl<I, L, R, P> Router<I, Either<L, R>> for ByMap<I, P>
+where
+    P: Fn(I) -> Either<L, R>,
+{
+    fn route(&self, item: I) -> (Side, Either<L, R>) {
+        let item = (self.0)(item);
+        (Side::from(matches!(item, Either::Left(_))), item)
+    }
+}
+
+impl<I, P> SideNames for ByMap<I, P> {
+    const BUFFERED: [&'static str; 2] = ["buffered_left", "buffered_right"];
+    const WAKER: [&'static str; 2] = ["waker_left", "waker_right"];
+    const CLOSED: [&'static str; 2] = ["closed_left", "closed_right"];
+    const ITEMS: [&'static str; 2] = ["items_left", "items_right"];
+}
+
+/// What a split does on top of what every split does when it's polled, for
+/// the options of `SplitBuilder`. The unit type adds nothing, and is what the
+/// other splits use
+pub(crate) trait Extension<I, B>
+where
+    B: Buffer<I>,
+{
+    /// Whether the stream an item is routed to is woken even if its last
+    /// poll returned an item. It might be waiting for something other than
+    /// the split, like a throttle, in the same task as the stream that routed
+    /// the item, which returns `Pending` and needs the task polled again
+    const WAKE_ON_ROUTE: bool = false;
+
+    /// Whether nothing is pulled from the underlying stream for now
+    fn is_paused(&self) -> bool {
+        false
+    }
+
+    /// Whether the streams only return what's left in their buffers
+    fn is_draining(&self) -> bool {
+        false
+    }
+
+    /// Whether the stream of `side` leaves pulling from the underlying stream
+    /// to the other stream, which was dropped if `other_closed`
+    fn waits_for_driver(&self, _side: Side, _other_closed: bool) -> bool {
+        false
+    }
+
+    /// Whether a stream stops pulling once the buffer of the other stream is
+    /// full, rather than making room in it
+    fn waits_for_room(&self) -> bool {
+        true
+    }
+
+    /// How many items of the stream of `side` are kept outside of its
+    /// buffer, after the ones in it
+    fn held(&self, _side: Side) -> usize {
+        0
+    }
+
+    /// Takes the item at the front of `buf`, the buffer of `side`, out
+    fn pop(&mut self, _side: Side, buf: &mut B) -> Option<I> {
+        buf.pop_front()
+    }
+
+    /// Puts an item routed to the stream of `side` at the back of `buf`, its
+    /// buffer, which has room for it unless `waits_for_room` is `false`.
+    /// `closed` is whether that stream was dropped
+    fn push(&mut self, _side: Side, buf: &mut B, item: I, _closed: bool) -> Pushed {
+        // This can't fail because the buffer wasn't full when the item was pulled, and nothing
+        // else is pulled before it's routed
+        let _ = buf.push_back(item);
+        Pushed::Kept
+    }
+
+    /// Called with each item routed to the stream of `target` after the
+    /// stream of `polling` pulled it
+    fn routed(&mut self, _item: &I, _target: Side, _polling: Side) {}
+
+    /// Whether a stream that pulled an item for itself keeps pulling while
+    /// items are ready, and returns the newest one instead
+    fn conflates(&self) -> bool {
+        false
+    }
+
+    /// The stream of `side` pulled an item for itself that replaces the one
+    /// it pulled before, see `conflates`
+    fn replaced(&mut self, _side: Side) {}
+
+    /// Called once the underlying stream has ended
+    fn ended(&mut self) {}
+}
+
+impl<I, B> Extension<I, B> for () where B: Buffer<I> {}
+
+/// The state the two streams of a split share, whatever decides where items
+/// go and however they're buffered: `R` routes the items of the underlying
+/// stream `S`, each becoming an `I` that's returned right away to the stream
+/// that pulled it or kept in a `B` until the other stream asks for it. An
+/// unbuffered split is one whose buffers hold a single item. `E` adds what a
+/// split built with `SplitBuilder` does on top
+#[pin_project]
+pub(crate) struct SplitCore<I, S, R, B, E = ()> {
+    pub(crate) buf_true: B,
+    pub(crate) buf_false: B,
+    pub(crate) waker_true: SideWaker,
+    pub(crate) waker_false: SideWaker,
+    pub(crate) room_true: RoomWaker,
+    pub(crate) room_false: RoomWaker,
+    #[pin]
+    pub(crate) stream: S,
+    /// Taken out while a stream classifies an item without holding the lock
+    pub(crate) router: Option<R>,
+    /// The stream that pulled an item from the underlying stream and is
+    /// classifying it without holding the lock. Nothing else is pulled until
+    /// that item has been routed
+    classifying: Option<Side>,
+    /// Whether the other stream wanted to pull while an item was classified
+    waiting_on_classify: bool,
+    /// Compares an item with the one at the back of the buffer it's pushed
+    /// to, dropping it if they're equal. Set by `coalesce_buffered`
+    pub(crate) coalesce: Option<fn(&I, &I) -> bool>,
+    pub(crate) coalesced_true: usize,
+    pub(crate) coalesced_false: usize,
+    /// How many items both buffers may hold together, set by `max_buffered`
+    pub(crate) max_buffered: usize,
+    /// Whether a `None` from the underlying stream only means there's nothing
+    /// to pull for now, set by `resumable_source`
+    pub(crate) resumable_source: bool,
+    pub(crate) done: bool,
+    pub(crate) closed_true: bool,
+    pub(crate) closed_false: bool,
+    /// The stream that gets every item since the other one was dropped, set
+    /// by `take_over`
+    pub(crate) taken_over: Option<Side>,
+    pub(crate) aborted: bool,
+    pub(crate) generation: usize,
+    /// What the two streams are called when they're polled from two tasks
+    streams: [&'static str; 2],
+    span: SplitSpan,
+    pub(crate) metrics: SplitMetrics,
+    pub(crate) order: OrderCheck,
+    pub(crate) activity: Activity,
+    pub(crate) diagnostics: Diagnostics,
+    pub(crate) extension: E,
+    // The buffers hold the items, but `Buffer` doesn't say so
+    item: PhantomData<fn() -> I>,
+}
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E>
+where
+    B: Buffer<I>,
+    E: Extension<I, B>,
+{
+    pub(crate) fn abort(&mut self) {
+        self.aborted = true;
+        self.waker_true.wake();
+        self.waker_false.wake();
+        self.room_true.wake();
+        self.room_false.wake();
+    }
+
+    /// Swaps in a new underlying stream, returning the old one unless it had
+    /// already ended
+    pub(crate) fn replace_stream(&mut self, stream: S) -> Result<Option<S>, S> {
+        if self.aborted {
+            return Err(stream);
+        }
+        let old = core::mem::replace(&mut self.stream, stream);
+        let old = if self.done { None } else { Some(old) };
+        self.done = false;
+        self.generation = self.generation.wrapping_add(1);
+        self.waker_true.wake();
+        self.waker_false.wake();
+        Ok(old)
+    }
+
+    /// How many more items both buffers may hold together
+    fn total_room(&self) -> usize {
+        self.max_buffered
+            .saturating_sub(self.buf_true.len() + self.buf_false.len())
+    }
+
+    /// Returns how many more items fit in the buffer of the `true` stream,
+    /// or stores the waker to be woken once that stream takes one out
+    pub(crate) fn poll_room_true(&mut self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        if self.aborted {
+            return Poll::Ready(0);
+        }
+        let room = self.buf_true.remaining().min(self.total_room());
+        if room > 0 || self.closed_true {
+            return Poll::Ready(room);
+        }
+        self.room_true.register(cx.waker());
+        Poll::Pending
+    }
+
+    /// Returns how many more items fit in the buffer of the `false` stream,
+    /// or stores the waker to be woken once that stream takes one out
+    pub(crate) fn poll_room_false(&mut self, cx: &mut core::task::Context<'_>) -> Poll<usize> {
+        if self.aborted {
+            return Poll::Ready(0);
+        }
+        let room = self.buf_false.remaining().min(self.total_room());
+        if room > 0 || self.closed_false {
+            return Poll::Ready(room);
+        }
+        self.room_false.register(cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<I, S, R, B> SplitCore<I, S, R, B>
+where
+    B: Buffer<I>,
+{
+    /// Moves up to `max` of the items buffered for the `true` stream to the
+    /// back of `items`, oldest first, without polling the underlying stream
+    pub(crate) fn drain_true(&mut self, items: &mut Vec<I>, max: usize) {
+        if self.aborted || self.buf_true.is_empty() || max == 0 {
+            return;
+        }
+        let was_full = self.buf_true.remaining() == 0 || self.total_room() == 0;
+        let count = self.buf_true.len().min(max);
+        items.reserve(count);
+        for _ in 0..count {
+            let item = self
+                .buf_true
+                .pop_front()
+                .expect("the buffer holds at least `count` items");
+            self.metrics.popped_front(Side::True, self.buf_true.len());
+            self.order.popped_front(Side::True);
+            self.activity.delivered(Side::True);
+            items.push(item);
+        }
+        self.room_true.wake();
+        if was_full {
+            // The `false` stream might be waiting for room in this buffer
+            self.waker_false.wake_waiting();
+        }
+    }
+
+    /// Pops an item buffered for the `true` stream without polling the
+    /// underlying stream
+    pub(crate) fn try_next_true(&mut self) -> TryNext<I> {
+        if self.aborted {
+            return TryNext::Ended;
+        }
+        let was_full = self.buf_true.remaining() == 0 || self.total_room() == 0;
+        match self.buf_true.pop_front() {
+            Some(item) => {
+                self.metrics.popped_front(Side::True, self.buf_true.len());
+                self.order.popped_front(Side::True);
+                self.activity.delivered(Side::True);
+                self.room_true.wake();
+                if was_full {
+                    // The `false` stream might be waiting for room in this buffer
+                    self.waker_false.wake_waiting();
+                }
+                TryNext::Item(item)
+            }
+            None if self.done => TryNext::Ended,
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Moves up to `max` of the items buffered for the `false` stream to the
+    /// back of `items`, oldest first, without polling the underlying stream
+    pub(crate) fn drain_false(&mut self, items: &mut Vec<I>, max: usize) {
+        if self.aborted || self.buf_false.is_empty() || max == 0 {
+            return;
+        }
+        let was_full = self.buf_false.remaining() == 0 || self.total_room() == 0;
+        let count = self.buf_false.len().min(max);
+        items.reserve(count);
+        for _ in 0..count {
+            let item = self
+                .buf_false
+                .pop_front()
+                .expect("the buffer holds at least `count` items");
+            self.metrics.popped_front(Side::False, self.buf_false.len());
+            self.order.popped_front(Side::False);
+            self.activity.delivered(Side::False);
+            items.push(item);
+        }
+        self.room_false.wake();
+        if was_full {
+            // The `true` stream might be waiting for room in this buffer
+            self.waker_true.wake_waiting();
+        }
+    }
+
+    /// Pops an item buffered for the `false` stream without polling the
+    /// underlying stream
+    pub(crate) fn try_next_false(&mut self) -> TryNext<I> {
+        if self.aborted {
+            return TryNext::Ended;
+        }
+        let was_full = self.buf_false.remaining() == 0 || self.total_room() == 0;
+        match self.buf_false.pop_front() {
+            Some(item) => {
+                self.metrics.popped_front(Side::False, self.buf_false.len());
+                self.order.popped_front(Side::False);
+                self.activity.delivered(Side::False);
+                self.room_false.wake();
+                if was_full {
+                    // The `true` stream might be waiting for room in this buffer
+                    self.waker_true.wake_waiting();
+                }
+                TryNext::Item(item)
+            }
+            None if self.done => TryNext::Ended,
+            None => TryNext::Empty,
+        }
+    }
+
+    /// Puts an item of the stream of `side` at the front of its buffer, or
+    /// hands it back if the buffer is full
+    pub(crate) fn push_front(&mut self, side: Side, item: I) -> Option<I> {
+        let buf = match side {
+            Side::True => &mut self.buf_true,
+            Side::False => &mut self.buf_false,
+        };
+        let item = buf.push_front(item);
+        if item.is_none() {
+            self.metrics.pushed_front(side, buf.len());
+            self.order.pushed_front(side);
+        }
+        item
+    }
+
+    /// Puts an item of the stream of `side` at the back of its buffer, or
+    /// hands it back if the buffer is full
+    pub(crate) fn push_back(&mut self, side: Side, item: I) -> Option<I> {
+        let buf = match side {
+            Side::True => &mut self.buf_true,
+            Side::False => &mut self.buf_false,
+        };
+        let item = buf.push_back(item);
+        if item.is_none() {
+            self.metrics.pushed_back(side, buf.len());
+            self.order.pushed_back(side);
+        }
+        item
+    }
+
+    /// Keeps only the items buffered for the stream of `side` for which `f`
+    /// returns `true`, in the order they were in, and drops the rest. Returns
+    /// how many were dropped
+    pub(crate) fn retain(&mut self, side: Side, mut f: impl FnMut(&mut I) -> bool) -> usize {
+        let total = self.buf_true.len() + self.buf_false.len();
+        let (buf, room, waker_other) = match side {
+            Side::True => (
+                &mut self.buf_true,
+                &mut self.room_true,
+                &mut self.waker_false,
+            ),
+            Side::False => (
+                &mut self.buf_false,
+                &mut self.room_false,
+                &mut self.waker_true,
+            ),
+        };
+        let was_full = buf.remaining() == 0 || self.max_buffered <= total;
+        let len = buf.len();
+        let mut kept = 0;
+        // Every item goes around the ring once, and the ones that are kept end up in the same
+        // order
+        for _ in 0..len {
+            let mut item = buf
+                .pop_front()
+                .expect("the buffer holds at least `len` items");
+            if f(&mut item) {
+                let _ = buf.push_back(item);
+                kept += 1;
+            } else {
+                self.metrics.removed(side, kept);
+                self.order.removed(side, kept);
+            }
+        }
+        let removed = len - kept;
+        if removed > 0 {
+            self.metrics.resync(side, kept);
+            room.wake();
+            if was_full {
+                // The other stream might be waiting for room in this buffer
+                waker_other.wake_waiting();
+            }
+        }
+        removed
+    }
+}
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E>
+where
+    B: Buffer<I>,
+    E: Extension<I, B>,
+{
+    /// Takes the split apart into what it was built from and the items that
+    /// were buffered for each side
+    pub(crate) fn into_parts(mut self) -> SplitParts<I, S, R> {
+        // The buffered items are handed back rather than dropped
+        self.metrics.resync(Side::True, 0);
+        self.order.resync(Side::True, 0);
+        self.metrics.resync(Side::False, 0);
+        self.order.resync(Side::False, 0);
+        SplitParts {
+            stream: self.stream,
+            predicate: self
+                .router
+                .expect("the router is back once the stream classifying an item returns"),
+            buffered_true: buffer::into_vec(self.buf_true),
+            buffered_false: buffer::into_vec(self.buf_false),
+        }
+    }
+
+    /// Whether polling the `true` stream would return `Pending` right away
+    /// because the buffer of the `false` stream is full, or because the
+    /// `false` stream is the only one that pulls
+    pub(crate) fn would_block_true(&self) -> bool {
+        !self.aborted
+            && !self.done
+            && !self.extension.is_draining()
+            && self.taken_over != Some(Side::True)
+            && self.buf_true.is_empty()
+            && (self
+                .extension
+                .waits_for_driver(Side::True, self.closed_false)
+                || (self.extension.waits_for_room()
+                    && (self.buf_false.remaining() == 0 || self.total_room() == 0)))
+    }
+
+    /// Whether polling the `false` stream would return `Pending` right away
+    /// because the buffer of the `true` stream is full, or because the `true`
+    /// stream is the only one that pulls
+    pub(crate) fn would_block_false(&self) -> bool {
+        !self.aborted
+            && !self.done
+            && !self.extension.is_draining()
+            && self.taken_over != Some(Side::False)
+            && self.buf_false.is_empty()
+            && (self
+                .extension
+                .waits_for_driver(Side::False, self.closed_true)
+                || (self.extension.waits_for_room()
+                    && (self.buf_true.remaining() == 0 || self.total_room() == 0)))
+    }
+}
+
+impl<I, S, R, B> SplitCore<I, S, R, B>
+where
+    B: Buffer<I>,
+    R: SideNames,
+{
+    pub(crate) fn debug_fields(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field(R::BUFFERED[0], &self.buf_true.len())
+            .field(R::BUFFERED[1], &self.buf_false.len())
+            .field("capacity", &self.buf_true.capacity())
+            .field(R::WAKER[0], &self.waker_true.is_registered())
+            .field(R::WAKER[1], &self.waker_false.is_registered())
+            .field("done", &self.done)
+            .field(R::CLOSED[0], &self.closed_true)
+            .field(R::CLOSED[1], &self.closed_false)
+            .field("aborted", &self.aborted);
+    }
+}
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E> {
+    /// Marks both streams as open again, for a split whose streams are
+    /// handed out anew
+    #[cfg(any(feature = "static-split", feature = "embassy"))]
+    pub(crate) fn reopen(&mut self) {
+        self.closed_true = false;
+        self.closed_false = false;
+        self.taken_over = None;
+    }
+
+    /// Takes the router out, so that the stream of `side` can classify the
+    /// item it pulled without holding the lock
+    pub(crate) fn start_classifying(&mut self, side: Side) -> R {
+        self.classifying = Some(side);
+        self.router
+            .take()
+            .expect("only one item is classified at a time")
+    }
+
+    /// Puts the router back after an item was classified, unless it was
+    /// replaced in the meantime, and wakes the other stream if it wanted to
+    /// pull meanwhile
+    pub(crate) fn finish_classifying(&mut self, router: R) {
+        if self.router.is_none() {
+            self.router = Some(router);
+        }
+        let side = self
+            .classifying
+            .take()
+            .expect("an item was being classified");
+        if core::mem::take(&mut self.waiting_on_classify) {
+            match side {
+                Side::True => self.waker_false.wake(),
+                Side::False => self.waker_true.wake(),
+            }
+        }
+    }
+
+    /// Whether a stream is classifying the item it pulled without holding
+    /// the lock
+    #[cfg(feature = "async-lock")]
+    pub(crate) fn is_classifying(&self) -> bool {
+        self.classifying.is_some()
+    }
+
+    /// Marks the task of the stream of `side` as no longer waiting
+    fn ready(&mut self, side: Side) {
+        match side {
+            Side::True => self.waker_true.ready(),
+            Side::False => self.waker_false.ready(),
+        }
+    }
+
+    /// Marks the `true` stream as dropped
+    pub(crate) fn close_true(&mut self) {
+        self.closed_true = true;
+        self.span.abandoned(Side::True);
+        // Nothing takes items out of the buffer of this stream anymore
+        self.room_true.wake();
+    }
+
+    /// Marks the `false` stream as dropped
+    pub(crate) fn close_false(&mut self) {
+        self.closed_false = true;
+        self.span.abandoned(Side::False);
+        // Nothing takes items out of the buffer of this stream anymore
+        self.room_false.wake();
+    }
+}
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E>
+where
+    B: Buffer<I>,
+    I: fmt::Debug,
+    R: SideNames,
+{
+    pub(crate) fn debug_items(&self, debug: &mut fmt::DebugStruct<'_, '_>) {
+        debug
+            .field(R::ITEMS[0], &Items(buffer::iter(&self.buf_true)))
+            .field(R::ITEMS[1], &Items(buffer::iter(&self.buf_false)));
+    }
+}
+
+impl<I, S, R, B> SplitCore<I, S, R, B>
+where
+    B: Buffer<I>,
+    S: Stream,
+    R: Router<S::Item, I>,
+{
+    /// Creates the shared state of a split, where `kind` is the function that
+    /// made the split and `streams` are the names of its `true` and `false`
+    /// streams
+    pub(crate) fn new(
+        stream: S,
+        router: R,
+        buf_true: B,
+        buf_false: B,
+        kind: &'static str,
+        streams: [&'static str; 2],
+    ) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::with_kind(
+            stream, router, buf_true, buf_false, kind, streams,
+        )))
+    }
+
+    /// Creates the state of a split like `new`, without sharing it
+    pub(crate) fn with_kind(
+        stream: S,
+        router: R,
+        buf_true: B,
+        buf_false: B,
+        kind: &'static str,
+        streams: [&'static str; 2],
+    ) -> Self {
+        Self::with_extension(stream, router, buf_true, buf_false, kind, streams, ())
+    }
+}
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E>
+where
+    B: Buffer<I>,
+    E: Extension<I, B>,
+    S: Stream,
+    R: Router<S::Item, I>,
+{
+    /// Creates the state of a split like `with_kind`, with `extension` doing
+    /// what the split adds on top
+    pub(crate) fn with_extension(
+        stream: S,
+        router: R,
+        buf_true: B,
+        buf_false: B,
+        kind: &'static str,
+        streams: [&'static str; 2],
+        extension: E,
+    ) -> Self {
+        Self {
+            span: SplitSpan::new(kind, buf_true.capacity(), buf_false.capacity()),
+            buf_false,
+            buf_true,
+            waker_false: SideWaker::default(),
+            waker_true: SideWaker::default(),
+            room_true: RoomWaker::default(),
+            room_false: RoomWaker::default(),
+            stream,
+            router: Some(router),
+            classifying: None,
+            waiting_on_classify: false,
+            coalesce: None,
+            coalesced_true: 0,
+            coalesced_false: 0,
+            max_buffered: usize::MAX,
+            resumable_source: false,
+            done: false,
+            closed_true: false,
+            closed_false: false,
+            taken_over: None,
+            aborted: false,
+            generation: 0,
+            streams,
+            metrics: SplitMetrics::new(),
+            order: OrderCheck::default(),
+            activity: Activity::default(),
+            diagnostics: Diagnostics::default(),
+            extension,
+            item: PhantomData,
+        }
+    }
+
+    pub(crate) fn size_hint_true(&self) -> (usize, Option<usize>) {
+        self.size_hint_side(self.buf_true.len() + self.extension.held(Side::True))
+    }
+
+    pub(crate) fn size_hint_false(&self) -> (usize, Option<usize>) {
+        self.size_hint_side(self.buf_false.len() + self.extension.held(Side::False))
+    }
+
+    /// The size hint of a stream with `buffered` items in its buffer
+    fn size_hint_side(&self, buffered: usize) -> (usize, Option<usize>) {
+        if self.aborted {
+            return (0, Some(0));
+        }
+        if self.done || self.extension.is_draining() {
+            return (buffered, Some(buffered));
+        }
+        // Any of the remaining items could end up in this stream, and so could one that is being
+        // classified
+        let (_, upper) = self.stream.size_hint();
+        let classifying = usize::from(self.classifying.is_some());
+        (
+            buffered,
+            upper
+                .and_then(|upper| upper.checked_add(buffered))
+                .and_then(|upper| upper.checked_add(classifying)),
+        )
+    }
+
+    /// Returns the next item buffered for the stream of `side`, or pulls the
+    /// next item from the underlying stream. A pulled item still has to be
+    /// classified and passed to `route`
+    fn poll_pull(
+        mut self: Pin<&mut Self>,
+        side: Side,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Pulled<S::Item, I>> {
+        let this = self.as_mut().project();
+        if *this.aborted {
+            return Poll::Ready(Pulled::Ready(None));
+        }
+        let (buf, waker, room, other_buf, other_waker, other_closed) = match side {
+            Side::True => (
+                this.buf_true,
+                this.waker_true,
+                this.room_true,
+                this.buf_false,
+                this.waker_false,
+                *this.closed_false,
+            ),
+            Side::False => (
+                this.buf_false,
+                this.waker_false,
+                this.room_false,
+                this.buf_true,
+                this.waker_true,
+                *this.closed_true,
+            ),
+        };
+        waker.register(cx.waker(), this.streams[usize::from(side.is_false())]);
+        let was_full = buf.remaining() == 0 || buf.len() + other_buf.len() >= *this.max_buffered;
+        if let Some(item) = this.extension.pop(side, buf) {
+            // There was already a value in the buffer. Return that value
+            this.metrics.popped_front(side, buf.len());
+            this.order.popped_front(side);
+            this.activity.delivered(side);
+            room.wake();
+            if was_full {
+                // The other stream might be waiting for room in this buffer, and nothing else
+                // would wake it
+                other_waker.wake_waiting();
+            }
+            return Poll::Ready(Pulled::Ready(Some(item)));
+        }
+        let taken_over = *this.taken_over == Some(side);
+        if taken_over {
+            // The items routed to the other stream before it was dropped come after this stream's
+            // own, since nothing records how the two buffers interleave
+            let other = Side::from(!side.is_true());
+            if let Some(item) = this.extension.pop(other, other_buf) {
+                this.metrics.popped_front(other, other_buf.len());
+                this.order.popped_front(other);
+                this.activity.delivered(side);
+                return Poll::Ready(Pulled::Ready(Some(item)));
+            }
+        }
+        if *this.done || this.extension.is_draining() {
+            // The underlying stream has finished, or the split is closing, and there's nothing
+            // left in the buffer
+            return Poll::Ready(Pulled::Ready(None));
+        }
+        if this.classifying.is_some() {
+            // The other stream is classifying the item it pulled, which has to be routed before
+            // the next one is pulled. It wakes this stream once it's done
+            *this.waiting_on_classify = true;
+            this.diagnostics.pending(side, PendingReason::Classifying);
+            return Poll::Pending;
+        }
+        if this.extension.is_paused() {
+            // Nothing is pulled until the split is resumed, which wakes this stream. This comes
+            // before the check for room in the other buffer, so the streams don't keep waking
+            // each other meanwhile
+            return Poll::Pending;
+        }
+        if this.extension.waits_for_driver(side, other_closed) {
+            // Only the other stream pulls from the underlying stream. It wakes this one when it
+            // buffers an item for it or sees the end
+            return Poll::Pending;
+        }
+        if other_buf.remaining() == 0 && this.extension.waits_for_room() {
+            // The other buffer is full, so notify that stream and return pending
+            this.span.stalled(
+                Side::from(!side.is_true()),
+                other_buf.len(),
+                other_buf.capacity(),
+            );
+            other_waker.wake_waiting();
+            this.diagnostics
+                .pending(side, PendingReason::OtherBufferFull);
+            return Poll::Pending;
+        }
+        if other_buf.len() >= *this.max_buffered {
+            // This buffer is empty, so the other one holds as many items as both may together.
+            // Nothing is pulled until the other stream takes some of them
+            other_waker.wake_waiting();
+            this.diagnostics
+                .pending(side, PendingReason::OtherBufferFull);
+            return Poll::Pending;
+        }
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(item)) if taken_over => match R::pass(item) {
+                Ok(item) => {
+                    // Nothing would take items routed to the other stream, so this one returns all
+                    // of them without asking the router
+                    this.extension.routed(&item, side, side);
+                    this.metrics.routed(side);
+                    this.order.routed_to_stream(side);
+                    this.activity.delivered(side);
+                    Poll::Ready(Pulled::Ready(Some(item)))
+                }
+                Err(item) => Poll::Ready(Pulled::Item(item)),
+            },
+            Poll::Ready(Some(item)) => Poll::Ready(Pulled::Item(item)),
+            Poll::Ready(None) if *this.resumable_source => {
+                // The underlying stream is polled again the next time either stream is, since it
+                // doesn't wake anything when it has more
+                this.diagnostics.pending(side, PendingReason::SourcePending);
+                Poll::Pending
+            }
+            Poll::Ready(None) => {
+                self.end(side);
+                Poll::Ready(Pulled::Ready(None))
+            }
+            Poll::Pending => {
+                this.diagnostics.pending(side, PendingReason::SourcePending);
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Marks the underlying stream as ended after the stream of `side` saw
+    /// its end
+    fn end(self: Pin<&mut Self>, side: Side) {
+        let this = self.project();
+        *this.done = true;
+        this.span.ended();
+        this.extension.ended();
+        // If the underlying stream is finished, the other stream also must be finished, so wake it
+        // in case nothing else polls it
+        match side {
+            Side::True => this.waker_false.wake_waiting(),
+            Side::False => this.waker_true.wake_waiting(),
+        }
+    }
+
+    /// Sends an item that the stream of `polling` pulled to the stream of
+    /// `target`, returning it right away if that's the stream that pulled it
+    fn route(self: Pin<&mut Self>, polling: Side, item: I, target: Side) -> Poll<Option<I>> {
+        let this = self.project();
+        let (buf, waker, own_waker, coalesced, closed) = match target {
+            Side::True => (
+                this.buf_true,
+                this.waker_true,
+                this.waker_false,
+                this.coalesced_true,
+                *this.closed_true,
+            ),
+            Side::False => (
+                this.buf_false,
+                this.waker_false,
+                this.waker_true,
+                this.coalesced_false,
+                *this.closed_false,
+            ),
+        };
+        this.extension.routed(&item, target, polling);
+        if target == polling {
+            this.span.routed(target, 0, buf.capacity());
+            this.metrics.routed(target);
+            this.order.routed_to_stream(target);
+            this.activity.delivered(target);
+            return Poll::Ready(Some(item));
+        }
+        let duplicate = match (*this.coalesce, buf.back()) {
+            (Some(eq), Some(back)) => eq(back, &item),
+            _ => false,
+        };
+        if duplicate {
+            // The other stream still has the same item waiting, so this one adds nothing. The
+            // buffer didn't change, so nothing else would wake this stream to pull the next one
+            *coalesced += 1;
+            this.metrics.routed(target);
+            this.order.dropped();
+            own_waker.wake();
+            return Poll::Pending;
+        }
+        // This value is not what we wanted. Store it and notify the other stream
+        let pushed = this.extension.push(target, buf, item, closed);
+        this.span.routed(target, buf.len(), buf.capacity());
+        this.metrics.routed(target);
+        match pushed {
+            Pushed::Kept => {
+                this.metrics.pushed_back(target, buf.len());
+                this.order.routed_to_buffer(target);
+            }
+            Pushed::ReplacedOldest => {
+                this.metrics.removed(target, 0);
+                this.order.removed(target, 0);
+                this.metrics.pushed_back(target, buf.len());
+                this.order.routed_to_buffer(target);
+            }
+            Pushed::Dropped => this.order.dropped(),
+        }
+        this.diagnostics
+            .pending(polling, PendingReason::RoutedToOther);
+        if E::WAKE_ON_ROUTE {
+            waker.wake();
+        } else {
+            waker.wake_waiting();
+        }
+        Poll::Pending
+    }
+
+    /// Polls for the next item of the stream of `side`, classifying an item
+    /// pulled from the underlying stream while the lock is held
+    fn poll_next_side(
+        mut self: Pin<&mut Self>,
+        side: Side,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<I>> {
+        match self.as_mut().poll_pull(side, cx) {
+            Poll::Ready(Pulled::Item(item)) => {
+                let this = self.as_mut().project();
+                let router = this
+                    .router
+                    .as_ref()
+                    .expect("nothing is classified outside the lock here");
+                let (target, item) = router.route(item);
+                match self.as_mut().route(side, item, target) {
+                    Poll::Ready(Some(item)) if self.extension.conflates() => {
+                        self.poll_conflated(side, item, cx)
+                    }
+                    response => response,
+                }
+            }
+            Poll::Ready(Pulled::Ready(item)) => Poll::Ready(item),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Keeps pulling from the underlying stream after the stream of `side`
+    /// pulled `latest` for itself, for as long as items are ready, so that a
+    /// conflated stream returns the newest of them instead of the first. The
+    /// items for the other stream are routed as usual. Stops after
+    /// `CONFLATE_BUDGET` items, so that an underlying stream that is always
+    /// ready can't keep this poll from returning
+    fn poll_conflated(
+        mut self: Pin<&mut Self>,
+        side: Side,
+        mut latest: I,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<I>> {
+        for _ in 0..CONFLATE_BUDGET {
+            let this = self.as_mut().project();
+            let item = match this.stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                Poll::Ready(None) if *this.resumable_source => break,
+                Poll::Ready(None) => {
+                    self.as_mut().end(side);
+                    break;
+                }
+                Poll::Pending => break,
+            };
+            let router = this
+                .router
+                .as_ref()
+                .expect("nothing is classified outside the lock here");
+            let (target, item) = router.route(item);
+            if target == side {
+                // The stream never returned the item it pulled, so it's replaced like a buffered one
+                this.extension.routed(&item, target, side);
+                this.extension.replaced(side);
+                this.metrics.routed(target);
+                this.order.dropped();
+                latest = item;
+                continue;
+            }
+            let _ = self.as_mut().route(side, item, target);
+        }
+        Poll::Ready(Some(latest))
+    }
+}
+
+/// How many items a conflated stream pulls past the first one it pulled for
+/// itself in a single poll
+const CONFLATE_BUDGET: usize = 64;
+
+impl<I, S, R, B, E> SplitCore<I, S, R, B, E>
+where
+    B: Buffer<I>,
+    E: Extension<I, B>,
+    S: Stream + Unpin,
+    R: Router<S::Item, I>,
+{
+    /// Polls for the next item of the `true` stream, marking its task as no
+    /// longer waiting once there is one
+    pub(crate) fn poll_true(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        let response = Pin::new(&mut *self).poll_next_side(Side::True, cx);
+        if response.is_ready() {
+            self.waker_true.ready();
+        }
+        response
+    }
+
+    /// Polls for the next item of the `false` stream, marking its task as no
+    /// longer waiting once there is one
+    pub(crate) fn poll_false(&mut self, cx: &mut core::task::Context<'_>) -> Poll<Option<I>> {
+        let response = Pin::new(&mut *self).poll_next_side(Side::False, cx);
+        if response.is_ready() {
+            self.waker_false.ready();
+        }
+        response
+    }
+
+    /// Polls for the next item of the stream of `side` like `poll_true` and
+    /// `poll_false`, but hands out an item pulled from the underlying stream
+    /// instead of classifying it. That item is classified with the router
+    /// from `start_classifying` and passed to `route_classified`
+    pub(crate) fn poll_item(
+        &mut self,
+        side: Side,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Pulled<S::Item, I>> {
+        let response = Pin::new(&mut *self).poll_pull(side, cx);
+        if let Poll::Ready(Pulled::Ready(_)) = response {
+            self.ready(side);
+        }
+        response
+    }
+
+    /// Puts the router back after the stream of `side` classified `item` as
+    /// one for the stream of `target`, and routes it there
+    pub(crate) fn route_classified(
+        &mut self,
+        side: Side,
+        router: R,
+        item: I,
+        target: Side,
+    ) -> Poll<Option<I>> {
+        self.finish_classifying(router);
+        if self.aborted {
+            // The split was aborted while the item was classified
+            return Poll::Ready(None);
+        }
+        let response = Pin::new(&mut *self).route(side, item, target);
+        if response.is_ready() {
+            self.ready(side);
+        }
+        response
+    }
+}
+
+impl<I, S, P, B, E> SplitCore<I, S, ByPredicate<P>, B, E>
+where
+    B: Buffer<I>,
+    E: Extension<I, B>,
+{
+    /// Takes a split by a predicate apart like `into_parts`
+    pub(crate) fn into_predicate_parts(self) -> SplitParts<I, S, P> {
+        let parts = self.into_parts();
+        SplitParts {
+            stream: parts.stream,
+            predicate: parts.predicate.0,
+            buffered_true: parts.buffered_true,
+            buffered_false: parts.buffered_false,
+        }
+    }
+}
+
+impl<I, S, P, B, E> SplitCore<I, S, ByPredicate<P>, B, E>
+where
+    B: Buffer<I>,
+    P: Fn(&I) -> bool,
+{
+    /// Moves the buffered items that the predicate now puts on the other side
+    /// to the buffer of the other stream
+    pub(crate) fn reclassify(&mut self) {
+        let ByPredicate(predicate) = self
+            .router
+            .as_ref()
+            .expect("the predicate was just replaced");
+        let mut true_items = Vec::new();
+        while let Some(item) = self.buf_true.pop_front() {
+            true_items.push(item);
+        }
+        let mut false_items = Vec::new();
+        while let Some(item) = self.buf_false.pop_front() {
+            false_items.push(item);
+        }
+        let to_false = true_items.iter().filter(|item| !predicate(item)).count();
+        let to_true = false_items.iter().filter(|item| predicate(item)).count();
+        // An item moving one way can always trade places with one moving the other way, and
+        // on top of that as many more can move as there is room left in the buffer they move
+        // to. So every item either moves or stays where it was, and none is dropped
+        let room_true = self.buf_true.remaining() - true_items.len();
+        let room_false = self.buf_false.remaining() - false_items.len();
+        let moving_false = to_false.min(to_true.saturating_add(room_false));
+        let moving_true = to_true.min(to_false.saturating_add(room_true));
+        let moved = moving_true > 0 || moving_false > 0;
+        // The first items that belong to the other stream move, and the rest keep their place
+        let mut moved_false = Vec::new();
+        for item in true_items {
+            if !predicate(&item) && moved_false.len() < moving_false {
+                moved_false.push(item);
+            } else {
+                let _ = self.buf_true.push_back(item);
+            }
+        }
+        let mut moved_true = Vec::new();
+        for item in false_items {
+            if predicate(&item) && moved_true.len() < moving_true {
+                moved_true.push(item);
+            } else {
+                let _ = self.buf_false.push_back(item);
+            }
+        }
+        for item in moved_true {
+            let _ = self.buf_true.push_back(item);
+        }
+        for item in moved_false {
+            let _ = self.buf_false.push_back(item);
+        }
+        self.metrics.resync(Side::True, self.buf_true.len());
+        self.order.resync(Side::True, self.buf_true.len());
+        self.metrics.resync(Side::False, self.buf_false.len());
+        self.order.resync(Side::False, self.buf_false.len());
+        if moved {
+            self.waker_true.wake();
+            self.waker_false.wake();
+            self.room_true.wake();
+            self.room_false.wake();
+        }
+    }
+}
+
+/// What a stream found when it looked for its next item
+pub(crate) enum Pulled<T, I> {
+    /// The next item of the stream, or `None` if it has ended
+    Ready(Option<I>),
+    /// An item from the underlying stream, which still has to be classified
+    Item(T),
+}
+
+/// Polls for the next item of the stream of `side` like `poll_true` and
+/// `poll_false`, but classifies an item pulled from the underlying stream
+/// without holding the lock, so a slow predicate doesn't keep the other
+/// stream from taking the items in its buffer. Returns `None` if the other
+/// stream holds the lock. Sets `generation` to that of the underlying stream
+pub(crate) fn poll_unlocked<I, S, R, B>(
+    state: &Mutex<SplitCore<I, S, R, B>>,
+    side: Side,
+    cx: &mut core::task::Context<'_>,
+    generation: &mut usize,
+) -> Option<Poll<Option<I>>>
+where
+    B: Buffer<I>,
+    S: Stream + Unpin,
+    R: Router<S::Item, I>,
+{
+    let mut guard = state.try_lock()?;
+    *generation = guard.generation;
+    let item = match guard.poll_item(side, cx) {
+        Poll::Ready(Pulled::Item(item)) => item,
+        Poll::Ready(Pulled::Ready(item)) => return Some(Poll::Ready(item)),
+        Poll::Pending => return Some(Poll::Pending),
+    };
+    let mut classifying = Classifying {
+        router: Some(guard.start_classifying(side)),
+        state,
+    };
+    drop(guard);
+    let router = classifying
+        .router
+        .as_ref()
+        .expect("the router was just taken out");
+    let (target, item) = router.route(item);
+    let router = classifying.router.take().expect("the router didn't panic");
+    Some(state.lock().route_classified(side, router, item, target))
+}
+
+/// Puts the router back if it panics while an item is classified, so that
+/// the other stream doesn't wait for that item forever
+struct Classifying<'a, I, S, R, B> {
+    state: &'a Mutex<SplitCore<I, S, R, B>>,
+    router: Option<R>,
+}
+
+impl<I, S, R, B> Drop for Classifying<'_, I, S, R, B> {
+    fn drop(&mut self) {
+        if let Some(router) = self.router.take() {
+            self.state.lock().finish_classifying(router);
+        }
+    }
+}
+
+/// Puts `items` in `buf`, except for the first one if they don't all fit, which
+/// is returned instead
+pub(crate) fn fill<I, B>(buf: &mut B, items: Vec<I>) -> Option<I>
+where
+    B: Buffer<I>,
+{
+    let mut items = items.into_iter();
+    let first = if items.len() > buf.remaining() {
+        items.next()
+    } else {
+        None
+    };
+    for item in items {
+        assert!(
+            buf.push_back(item).is_none(),
+            "the buffered items fit in the buffer"
+        );
+    }
+    first
+}
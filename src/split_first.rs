@@ -0,0 +1,255 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{side_waker::SideWaker, sync::Mutex};
+
+/// The state the head and the rest of a `split_first` share
+struct SplitFirst<S> {
+    stream: S,
+    /// Whether the head has resolved or was dropped, after which the rest
+    /// can pull from the underlying stream
+    head_taken: bool,
+    rest_waker: SideWaker,
+    done: bool,
+}
+
+impl<S> SplitFirst<S> {
+    /// Lets the rest pull from the underlying stream
+    fn take_head(&mut self) {
+        self.head_taken = true;
+        self.rest_waker.wake();
+    }
+}
+
+/// Splits off the first item of a stream, like a header or a handshake that
+/// comes before the payload. The returned future resolves to the first item,
+/// or `None` if the stream is empty, and the returned stream returns the
+/// items after it.
+///
+/// The stream doesn't return anything until the future has resolved. If
+/// it's polled first, it returns `Pending` and is woken once the future has
+/// resolved. If the future is dropped before it resolves, the stream returns
+/// every item of the underlying stream, starting with the first one
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::split_first;
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let (header, payload) = split_first(futures::stream::iter(["v1", "a", "b"]));
+///     assert_eq!(Some("v1"), header.await);
+///     assert_eq!(vec!["a", "b"], payload.collect::<Vec<_>>().await);
+/// })
+/// ```
+pub fn split_first<S>(stream: S) -> (SplitFirstHead<S>, SplitFirstRest<S>)
+where
+    S: Stream + Unpin,
+{
+    let state = Arc::new(Mutex::new(SplitFirst {
+        stream,
+        head_taken: false,
+        rest_waker: SideWaker::default(),
+        done: false,
+    }));
+    let head = SplitFirstHead {
+        state: state.clone(),
+        resolved: false,
+    };
+    let rest = SplitFirstRest {
+        state,
+        terminated: false,
+    };
+    (head, rest)
+}
+
+/// A future that resolves to the first item of a stream. Created by
+/// `split_first`
+#[must_use = "futures do nothing unless polled"]
+pub struct SplitFirstHead<S> {
+    state: Arc<Mutex<SplitFirst<S>>>,
+    resolved: bool,
+}
+
+impl<S> Future for SplitFirstHead<S>
+where
+    S: Stream + Unpin,
+{
+    type Output = Option<S::Item>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.resolved, "`SplitFirstHead` polled after it resolved");
+        let mut state = self.state.lock();
+        let head = match Pin::new(&mut state.stream).poll_next(cx) {
+            Poll::Ready(head) => head,
+            Poll::Pending => return Poll::Pending,
+        };
+        state.done = head.is_none();
+        state.take_head();
+        drop(state);
+        self.resolved = true;
+        Poll::Ready(head)
+    }
+}
+
+impl<S> Drop for SplitFirstHead<S> {
+    fn drop(&mut self) {
+        if !self.resolved {
+            // Nothing was taken, so the rest starts with the first item
+            self.state.lock().take_head();
+        }
+    }
+}
+
+/// A stream that returns the items of a stream after the first one. Created
+/// by `split_first`
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitFirstRest<S> {
+    state: Arc<Mutex<SplitFirst<S>>>,
+    terminated: bool,
+}
+
+impl<S> SplitFirstRest<S> {
+    /// Whether the head has resolved or was dropped, so this stream returns
+    /// items once the underlying stream has them
+    pub fn head_taken(&self) -> bool {
+        self.state.lock().head_taken
+    }
+}
+
+impl<S> SplitFirstRest<S>
+where
+    S: Stream + Unpin,
+{
+    /// Polls for the next item without the stream having to be pinned.
+    /// This is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let mut state = self.state.lock();
+        if !state.head_taken {
+            state.rest_waker.register(cx.waker(), "SplitFirstRest");
+            return Poll::Pending;
+        }
+        state.rest_waker.ready();
+        let response = if state.done {
+            Poll::Ready(None)
+        } else {
+            Pin::new(&mut state.stream).poll_next(cx)
+        };
+        if let Poll::Ready(None) = response {
+            state.done = true;
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through the head or the rest
+impl<S> Unpin for SplitFirstHead<S> {}
+impl<S> Unpin for SplitFirstRest<S> {}
+
+impl<S> Stream for SplitFirstRest<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.terminated {
+            return (0, Some(0));
+        }
+        let state = self.state.lock();
+        if state.done {
+            return (0, Some(0));
+        }
+        let (lower, upper) = state.stream.size_hint();
+        if state.head_taken {
+            (lower, upper)
+        } else {
+            // The head takes the first item, unless it's dropped first
+            (lower.saturating_sub(1), upper)
+        }
+    }
+}
+
+impl<S> FusedStream for SplitFirstRest<S>
+where
+    S: Stream + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use futures::{FutureExt, StreamExt};
+
+    use super::*;
+    use crate::testing::{poll_once, CountingWaker, ManualStream};
+
+    #[tokio::test]
+    async fn test_head_and_rest() {
+        let (head, rest) = split_first(futures::stream::iter(0..4));
+        assert_eq!(Some(0), head.await);
+        assert_eq!(vec![1, 2, 3], rest.collect::<Vec<_>>().await);
+    }
+
+    #[tokio::test]
+    async fn test_empty_source() {
+        let (head, mut rest) = split_first(futures::stream::empty::<u32>());
+        assert_eq!(None, head.await);
+        assert_eq!(None, rest.next().await);
+        assert!(rest.is_terminated());
+    }
+
+    #[tokio::test]
+    async fn test_head_dropped_without_awaiting() {
+        let (head, rest) = split_first(futures::stream::iter(0..3));
+        drop(head);
+        assert!(rest.head_taken());
+        assert_eq!(vec![0, 1, 2], rest.collect::<Vec<_>>().await);
+    }
+
+    #[test]
+    fn test_rest_polled_first_waits_for_head() {
+        let (source, handle) = ManualStream::new();
+        let (mut head, mut rest) = split_first(source);
+        let waker = CountingWaker::new();
+        handle.push(0);
+        handle.push(1);
+        // The rest doesn't pull the first item, even though it's there
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &waker.waker()));
+        assert_eq!(0, handle.poll_count());
+        assert_eq!(0, waker.count());
+        assert_eq!(Some(Some(0)), (&mut head).now_or_never());
+        assert_eq!(1, waker.count());
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut rest, &waker.waker()));
+        handle.end();
+        assert_eq!(Poll::Ready(None), poll_once(&mut rest, &waker.waker()));
+    }
+
+    #[test]
+    fn test_head_dropped_while_pending() {
+        let (source, handle) = ManualStream::new();
+        let (mut head, mut rest) = split_first(source);
+        let waker = CountingWaker::new();
+        assert_eq!(None, (&mut head).now_or_never());
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &waker.waker()));
+        drop(head);
+        assert_eq!(1, waker.count());
+        handle.push(0);
+        assert_eq!(Poll::Ready(Some(0)), poll_once(&mut rest, &waker.waker()));
+    }
+}
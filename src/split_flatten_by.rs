@@ -0,0 +1,409 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::{side_waker::SideWaker, sync::Mutex, Side};
+
+/// The inner streams of one side of a `SplitFlattenBy` that are being
+/// flattened
+struct Pool<S2> {
+    streams: Vec<Pin<Box<S2>>>,
+    limit: usize,
+    // Where the next poll starts, so that a busy inner stream doesn't starve the others
+    next: usize,
+    waker: SideWaker,
+    closed: bool,
+}
+
+impl<S2> Pool<S2>
+where
+    S2: Stream,
+{
+    fn new(limit: usize) -> Self {
+        assert!(
+            limit > 0,
+            "a side has to be able to flatten at least one stream"
+        );
+        Self {
+            streams: Vec::new(),
+            limit,
+            next: 0,
+            waker: SideWaker::default(),
+            closed: false,
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.streams.len() < self.limit
+    }
+
+    /// Polls the inner streams, starting after the one that returned the
+    /// last item, and drops the ones that have ended. Returns the first item
+    /// any of them has, and whether any of them ended
+    fn poll_streams(&mut self, cx: &mut Context<'_>) -> (Option<S2::Item>, bool) {
+        let mut ended = false;
+        let mut polled = 0;
+        while polled < self.streams.len() {
+            let index = (self.next + polled) % self.streams.len();
+            match self.streams[index].as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    self.next = index + 1;
+                    return (Some(item), ended);
+                }
+                Poll::Ready(None) => {
+                    // The last stream takes its place, and is polled next
+                    self.streams.swap_remove(index);
+                    ended = true;
+                }
+                Poll::Pending => polled += 1,
+            }
+        }
+        (None, ended)
+    }
+}
+
+#[pin_project]
+pub(crate) struct SplitFlattenBy<S, P>
+where
+    S: Stream,
+{
+    pool_true: Pool<S::Item>,
+    pool_false: Pool<S::Item>,
+    /// An inner stream that was pulled for a side that was already
+    /// flattening as many as it can, along with that side. Nothing else is
+    /// pulled until it's been added
+    held: Option<(S::Item, Side)>,
+    #[pin]
+    stream: S,
+    classify: P,
+    done: bool,
+}
+
+/// The pool of `side`
+fn pool<'a, S2>(
+    pool_true: &'a mut Pool<S2>,
+    pool_false: &'a mut Pool<S2>,
+    side: Side,
+) -> &'a mut Pool<S2> {
+    match side {
+        Side::True => pool_true,
+        Side::False => pool_false,
+    }
+}
+
+impl<S, P> SplitFlattenBy<S, P>
+where
+    S: Stream,
+    S::Item: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    pub(crate) fn new(
+        stream: S,
+        classify: P,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            pool_true: Pool::new(limit_true),
+            pool_false: Pool::new(limit_false),
+            held: None,
+            stream,
+            classify,
+            done: false,
+        }))
+    }
+
+    /// Adds the held inner stream to its side and pulls more from the
+    /// underlying stream as long as the side of each one has room for it.
+    /// The stream of `polling` is the one doing this, so it doesn't need to be
+    /// woken
+    fn admit(self: Pin<&mut Self>, polling: Side, cx: &mut Context<'_>) {
+        let mut this = self.project();
+        loop {
+            if let Some((inner, side)) = this.held.take() {
+                let pool = pool(this.pool_true, this.pool_false, side);
+                if pool.closed {
+                    // Nothing takes the items of a dropped stream, so its inner streams are dropped
+                    drop(inner);
+                } else if pool.has_room() {
+                    pool.streams.push(Box::pin(inner));
+                    if side != polling {
+                        pool.waker.wake();
+                    }
+                } else {
+                    *this.held = Some((inner, side));
+                    return;
+                }
+            }
+            if *this.done {
+                return;
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(inner)) => {
+                    let side = Side::from((this.classify)(&inner));
+                    *this.held = Some((inner, side));
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // Either stream might be waiting for the underlying stream to end
+                    this.pool_true.waker.wake();
+                    this.pool_false.waker.wake();
+                    return;
+                }
+                Poll::Pending => return,
+            }
+        }
+    }
+
+    fn poll_next_side(
+        mut self: Pin<&mut Self>,
+        side: Side,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<S::Item as Stream>::Item>> {
+        let this = self.as_mut().project();
+        pool(this.pool_true, this.pool_false, side)
+            .waker
+            .register(cx.waker(), "SplitFlattenStream");
+        loop {
+            self.as_mut().admit(side, cx);
+            let this = self.as_mut().project();
+            let (item, ended) = pool(this.pool_true, this.pool_false, side).poll_streams(cx);
+            if let Some(item) = item {
+                return Poll::Ready(Some(item));
+            }
+            if !ended {
+                break;
+            }
+            // An inner stream that ended made room, so a held one might fit now
+        }
+        let this = self.project();
+        let held_here = matches!(this.held, Some((_, held)) if *held == side);
+        let empty = pool(this.pool_true, this.pool_false, side)
+            .streams
+            .is_empty();
+        if *this.done && empty && !held_here {
+            Poll::Ready(None)
+        } else {
+            // The inner streams wake this one once they have items, and so does the stream that
+            // adds an inner stream to this side
+            Poll::Pending
+        }
+    }
+}
+
+/// A struct that implements `Stream` which returns the items of the inner
+/// streams that `split_flatten_by` routed to one side, in the order they
+/// arrive
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitFlattenStream<S, P>
+where
+    S: Stream,
+{
+    stream: Arc<Mutex<SplitFlattenBy<S, P>>>,
+    side: Side,
+    terminated: bool,
+}
+
+impl<S, P> SplitFlattenStream<S, P>
+where
+    S: Stream,
+{
+    pub(crate) fn new(stream: Arc<Mutex<SplitFlattenBy<S, P>>>, side: Side) -> Self {
+        Self {
+            stream,
+            side,
+            terminated: false,
+        }
+    }
+
+    /// Which side of the split this stream returns the items of
+    pub fn side(&self) -> Side {
+        self.side
+    }
+}
+
+impl<S, P> SplitFlattenStream<S, P>
+where
+    S: Stream + Unpin,
+    S::Item: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<S::Item as Stream>::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitFlattenBy::poll_next_side(Pin::new(&mut guard), self.side, cx);
+            if response.is_ready() {
+                let this = Pin::new(&mut *guard).project();
+                pool(this.pool_true, this.pool_false, self.side)
+                    .waker
+                    .ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through one of its streams
+impl<S, P> Unpin for SplitFlattenStream<S, P> where S: Stream {}
+
+impl<S, P> Stream for SplitFlattenStream<S, P>
+where
+    S: Stream + Unpin,
+    S::Item: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    type Item = <S::Item as Stream>::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<S, P> core::async_iter::AsyncIterator for SplitFlattenStream<S, P>
+where
+    S: Stream + Unpin,
+    S::Item: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    type Item = <S::Item as Stream>::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<S, P> FusedStream for SplitFlattenStream<S, P>
+where
+    S: Stream + Unpin,
+    S::Item: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<S, P> Drop for SplitFlattenStream<S, P>
+where
+    S: Stream,
+{
+    fn drop(&mut self) {
+        let mut state = self.stream.lock();
+        let SplitFlattenBy {
+            pool_true,
+            pool_false,
+            held,
+            ..
+        } = &mut *state;
+        let side = self.side;
+        let closed = pool(pool_true, pool_false, side);
+        closed.closed = true;
+        // Only the inner streams of this side are cancelled
+        closed.streams.clear();
+        // The other stream might be waiting for an inner stream held for this one to be added
+        if matches!(held, Some((_, held)) if *held == side) {
+            *held = None;
+            pool(pool_true, pool_false, Side::from(!side.is_true()))
+                .waker
+                .wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{Stream, StreamExt};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// An inner stream tagged with the side it goes to
+    struct Tagged<St> {
+        admin: bool,
+        stream: St,
+    }
+
+    impl<St> Stream for Tagged<St>
+    where
+        St: Stream + Unpin,
+    {
+        type Item = St::Item;
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<St::Item>> {
+            self.stream.poll_next_unpin(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flatten_sides() {
+        let inner = (0..6).map(|n| Tagged {
+            admin: n % 3 == 0,
+            stream: futures::stream::iter(n * 10..n * 10 + 3),
+        });
+        let split = futures::stream::iter(inner).split_flatten_by(|inner| inner.admin, 1, 2);
+        let (mut admin, mut users) = futures::join!(
+            split.matches.collect::<Vec<_>>(),
+            split.rest.collect::<Vec<_>>()
+        );
+        admin.sort_unstable();
+        users.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 30, 31, 32], admin);
+        assert_eq!(vec![10, 11, 12, 20, 21, 22, 40, 41, 42, 50, 51, 52], users);
+    }
+
+    #[test]
+    fn test_held_until_side_has_room() {
+        let (source, handle) = ManualStream::new();
+        let split = source.split_flatten_by(|inner: &Tagged<ManualStream<i32>>| inner.admin, 1, 1);
+        let (mut admin, mut users) = (split.matches, split.rest);
+        let waker = CountingWaker::new();
+        let (first, first_handle) = ManualStream::new();
+        let (second, second_handle) = ManualStream::new();
+        let (user, user_handle) = ManualStream::new();
+        for (admin, stream) in [(true, first), (true, second), (false, user)] {
+            handle.push(Tagged { admin, stream });
+        }
+        second_handle.push(2);
+        user_handle.push(3);
+        // The second admin stream doesn't fit, so the user stream isn't pulled
+        assert_eq!(Poll::Pending, poll_once(&mut users, &waker.waker()));
+        assert_eq!(2, handle.poll_count());
+        first_handle.push(1);
+        first_handle.end();
+        assert_eq!(Poll::Ready(Some(1)), poll_once(&mut admin, &waker.waker()));
+        // The first admin stream ended, which made room for the second one
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut admin, &waker.waker()));
+        assert_eq!(Poll::Ready(Some(3)), poll_once(&mut users, &waker.waker()));
+        // Dropping a side leaves the other one alone
+        drop(admin);
+        user_handle.push(4);
+        assert_eq!(Poll::Ready(Some(4)), poll_once(&mut users, &waker.waker()));
+        handle.end();
+        user_handle.end();
+        assert_eq!(Poll::Ready(None), poll_once(&mut users, &waker.waker()));
+    }
+}
@@ -0,0 +1,395 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use pin_project::pin_project;
+
+use crate::{side_waker::SideWaker, sync::Mutex, Side};
+
+/// The futures of one side of a `SplitFuturesBy` that are running
+struct Pool<F> {
+    futures: FuturesUnordered<F>,
+    limit: usize,
+    waker: SideWaker,
+    closed: bool,
+}
+
+impl<F> Pool<F> {
+    fn new(limit: usize) -> Self {
+        assert!(
+            limit > 0,
+            "a pool has to be able to run at least one future"
+        );
+        Self {
+            futures: FuturesUnordered::new(),
+            limit,
+            waker: SideWaker::default(),
+            closed: false,
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.futures.len() < self.limit
+    }
+}
+
+#[pin_project]
+pub(crate) struct SplitFuturesBy<F, S, P> {
+    pool_true: Pool<F>,
+    pool_false: Pool<F>,
+    /// A future that was pulled for a pool that was full, along with its side.
+    /// Nothing else is pulled until it's been started
+    held: Option<(F, Side)>,
+    #[pin]
+    stream: S,
+    predicate: P,
+    done: bool,
+}
+
+impl<F, S, P> SplitFuturesBy<F, S, P>
+where
+    F: Future,
+    S: Stream<Item = F>,
+    P: Fn(&F) -> bool,
+{
+    pub(crate) fn new(
+        stream: S,
+        predicate: P,
+        limit_true: usize,
+        limit_false: usize,
+    ) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            pool_true: Pool::new(limit_true),
+            pool_false: Pool::new(limit_false),
+            held: None,
+            stream,
+            predicate,
+            done: false,
+        }))
+    }
+
+    /// Starts the held future and pulls more from the underlying stream as
+    /// long as the pool of each future has room for it. The stream of
+    /// `polling` is the one doing this, so it doesn't need to be woken
+    fn admit(self: Pin<&mut Self>, polling: Side, cx: &mut Context<'_>) {
+        let mut this = self.project();
+        loop {
+            if let Some((future, side)) = this.held.take() {
+                let pool = match side {
+                    Side::True => &mut *this.pool_true,
+                    Side::False => &mut *this.pool_false,
+                };
+                if pool.closed {
+                    // Nothing takes the outputs of a dropped stream, so its futures aren't run
+                    drop(future);
+                } else if pool.has_room() {
+                    pool.futures.push(future);
+                    if side != polling {
+                        pool.waker.wake();
+                    }
+                } else {
+                    *this.held = Some((future, side));
+                    return;
+                }
+            }
+            if *this.done {
+                return;
+            }
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(future)) => {
+                    let side = Side::from((this.predicate)(&future));
+                    *this.held = Some((future, side));
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    // Either stream might be waiting for the underlying stream to end
+                    this.pool_true.waker.wake();
+                    this.pool_false.waker.wake();
+                    return;
+                }
+                Poll::Pending => return,
+            }
+        }
+    }
+
+    fn poll_next_side(
+        mut self: Pin<&mut Self>,
+        side: Side,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<F::Output>> {
+        let this = self.as_mut().project();
+        pool(this.pool_true, this.pool_false, side)
+            .waker
+            .register(cx.waker(), "SplitFuturesStream");
+        self.as_mut().admit(side, cx);
+        let this = self.as_mut().project();
+        let held_here = matches!(this.held, Some((_, held)) if *held == side);
+        let done = *this.done;
+        match pool(this.pool_true, this.pool_false, side)
+            .futures
+            .poll_next_unpin(cx)
+        {
+            Poll::Ready(Some(output)) => {
+                if held_here {
+                    // The held future fits now, and once it's started the other stream might be
+                    // able to pull again
+                    self.as_mut().admit(side, cx);
+                    let this = self.project();
+                    let other = Side::from(!side.is_true());
+                    pool(this.pool_true, this.pool_false, other).waker.wake();
+                }
+                Poll::Ready(Some(output))
+            }
+            // The pool is empty, and nothing will be added to it anymore
+            Poll::Ready(None) if done && !held_here => Poll::Ready(None),
+            // The pool is empty or its futures are running. Whichever stream starts a future in
+            // it wakes this one, and so do the futures once they can make progress
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The pool of `side`
+fn pool<'a, F>(
+    pool_true: &'a mut Pool<F>,
+    pool_false: &'a mut Pool<F>,
+    side: Side,
+) -> &'a mut Pool<F> {
+    match side {
+        Side::True => pool_true,
+        Side::False => pool_false,
+    }
+}
+
+/// A struct that implements `Stream` which returns the outputs of the futures
+/// that `split_futures_by` routed to one side, in the order they complete
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitFuturesStream<F, S, P> {
+    stream: Arc<Mutex<SplitFuturesBy<F, S, P>>>,
+    side: Side,
+    terminated: bool,
+}
+
+impl<F, S, P> SplitFuturesStream<F, S, P> {
+    pub(crate) fn new(stream: Arc<Mutex<SplitFuturesBy<F, S, P>>>, side: Side) -> Self {
+        Self {
+            stream,
+            side,
+            terminated: false,
+        }
+    }
+
+    /// Which side of the split this stream returns the outputs of
+    pub fn side(&self) -> Side {
+        self.side
+    }
+}
+
+impl<F, S, P> SplitFuturesStream<F, S, P>
+where
+    F: Future,
+    S: Stream<Item = F> + Unpin,
+    P: Fn(&F) -> bool,
+{
+    /// Polls for the next output without the stream having to be pinned.
+    /// This is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<F::Output>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = if let Some(mut guard) = self.stream.try_lock() {
+            let response = SplitFuturesBy::poll_next_side(Pin::new(&mut guard), self.side, cx);
+            if response.is_ready() {
+                let this = Pin::new(&mut *guard).project();
+                pool(this.pool_true, this.pool_false, self.side)
+                    .waker
+                    .ready();
+            }
+            response
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+// The state is never pinned through one of its streams
+impl<F, S, P> Unpin for SplitFuturesStream<F, S, P> {}
+
+impl<F, S, P> Stream for SplitFuturesStream<F, S, P>
+where
+    F: Future,
+    S: Stream<Item = F> + Unpin,
+    P: Fn(&F) -> bool,
+{
+    type Item = F::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<F, S, P> core::async_iter::AsyncIterator for SplitFuturesStream<F, S, P>
+where
+    F: Future,
+    S: Stream<Item = F> + Unpin,
+    P: Fn(&F) -> bool,
+{
+    type Item = F::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+impl<F, S, P> FusedStream for SplitFuturesStream<F, S, P>
+where
+    F: Future,
+    S: Stream<Item = F> + Unpin,
+    P: Fn(&F) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<F, S, P> Drop for SplitFuturesStream<F, S, P> {
+    fn drop(&mut self) {
+        let mut state = self.stream.lock();
+        let SplitFuturesBy {
+            pool_true,
+            pool_false,
+            held,
+            ..
+        } = &mut *state;
+        let side = self.side;
+        let closed = pool(pool_true, pool_false, side);
+        closed.closed = true;
+        // Nothing takes the outputs of this stream anymore
+        closed.futures.clear();
+        // The other stream might be waiting for a future held for this one to be started
+        if matches!(held, Some((_, held)) if *held == side) {
+            *held = None;
+            pool(pool_true, pool_false, Side::from(!side.is_true()))
+                .waker
+                .wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures::{future::BoxFuture, FutureExt, StreamExt};
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    struct Job {
+        expensive: bool,
+        work: BoxFuture<'static, u32>,
+    }
+
+    impl Future for Job {
+        type Output = u32;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            self.work.poll_unpin(cx)
+        }
+    }
+
+    /// Counts how many jobs of one kind are running at once
+    #[derive(Clone, Default)]
+    struct Running {
+        now: Arc<AtomicUsize>,
+        max: Arc<AtomicUsize>,
+    }
+
+    impl Running {
+        fn job(&self, expensive: bool, n: u32) -> Job {
+            let running = self.clone();
+            Job {
+                expensive,
+                work: async move {
+                    let now = running.now.fetch_add(1, Ordering::SeqCst) + 1;
+                    running.max.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running.now.fetch_sub(1, Ordering::SeqCst);
+                    n
+                }
+                .boxed(),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_limits() {
+        let (expensive, cheap) = (Running::default(), Running::default());
+        let jobs = futures::stream::iter(0..40).map({
+            let (expensive, cheap) = (expensive.clone(), cheap.clone());
+            move |n| match n {
+                0..=29 => cheap.job(false, n),
+                _ => expensive.job(true, n),
+            }
+        });
+        let split = jobs.split_futures_by(|job| job.expensive, 2, 8);
+        let (mut expensive_outputs, mut cheap_outputs) = futures::join!(
+            split.matches.collect::<Vec<_>>(),
+            split.rest.collect::<Vec<_>>()
+        );
+        expensive_outputs.sort_unstable();
+        cheap_outputs.sort_unstable();
+        assert_eq!((30..40).collect::<Vec<_>>(), expensive_outputs);
+        assert_eq!((0..30).collect::<Vec<_>>(), cheap_outputs);
+        assert_eq!(2, expensive.max.load(Ordering::SeqCst));
+        assert_eq!(8, cheap.max.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_held_until_pool_has_room() {
+        let (source, handle) = ManualStream::new();
+        let split = source.split_futures_by(|job: &Job| job.expensive, 1, 1);
+        let (matches, mut rest) = (split.matches, split.rest);
+        let waker = CountingWaker::new();
+        handle.push(Job {
+            expensive: true,
+            work: futures::future::pending().boxed(),
+        });
+        handle.push(Job {
+            expensive: true,
+            work: async { 1 }.boxed(),
+        });
+        handle.push(Job {
+            expensive: false,
+            work: async { 2 }.boxed(),
+        });
+        // The second expensive job doesn't fit, so the cheap one isn't pulled
+        assert_eq!(Poll::Pending, poll_once(&mut rest, &waker.waker()));
+        assert_eq!(2, handle.poll_count());
+        // Dropping the other stream drops its jobs, so the cheap one can be pulled
+        drop(matches);
+        assert!(waker.count() > 0);
+        assert_eq!(Poll::Ready(Some(2)), poll_once(&mut rest, &waker.waker()));
+        handle.end();
+        assert_eq!(Poll::Ready(None), poll_once(&mut rest, &waker.waker()));
+    }
+}
@@ -0,0 +1,182 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::iter::FusedIterator;
+
+use crate::sync::{Mutex, MutexGuard};
+
+pub(crate) struct SplitIterBy<I, It, P> {
+    buf_true: VecDeque<I>,
+    buf_false: VecDeque<I>,
+    iter: It,
+    predicate: P,
+    done: bool,
+}
+
+impl<I, It, P> SplitIterBy<I, It, P>
+where
+    It: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    pub(crate) fn new(iter: It, predicate: P) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            buf_true: VecDeque::new(),
+            buf_false: VecDeque::new(),
+            iter,
+            predicate,
+            done: false,
+        }))
+    }
+
+    fn next_true(&mut self) -> Option<I> {
+        if let Some(item) = self.buf_true.pop_front() {
+            return Some(item);
+        }
+        while !self.done {
+            match self.iter.next() {
+                Some(item) if (self.predicate)(&item) => return Some(item),
+                // Park the item for the other iterator and keep looking
+                Some(item) => self.buf_false.push_back(item),
+                None => self.done = true,
+            }
+        }
+        None
+    }
+
+    fn next_false(&mut self) -> Option<I> {
+        if let Some(item) = self.buf_false.pop_front() {
+            return Some(item);
+        }
+        while !self.done {
+            match self.iter.next() {
+                // Park the item for the other iterator and keep looking
+                Some(item) if (self.predicate)(&item) => self.buf_true.push_back(item),
+                Some(item) => return Some(item),
+                None => self.done = true,
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self, buffered: usize) -> (usize, Option<usize>) {
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+        // Any of the remaining items could end up in this iterator
+        let (_, upper) = self.iter.size_hint();
+        (
+            buffered,
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock()
+}
+
+/// An iterator which returns the items where the predicate returns `true`.
+/// Created by `split_iter_by`
+pub struct TrueSplitIterBy<I, It, P> {
+    iter: Arc<Mutex<SplitIterBy<I, It, P>>>,
+}
+
+impl<I, It, P> TrueSplitIterBy<I, It, P> {
+    pub(crate) fn new(iter: Arc<Mutex<SplitIterBy<I, It, P>>>) -> Self {
+        Self { iter }
+    }
+}
+
+impl<I, It, P> Iterator for TrueSplitIterBy<I, It, P>
+where
+    It: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn next(&mut self) -> Option<I> {
+        lock(&self.iter).next_true()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let state = lock(&self.iter);
+        state.size_hint(state.buf_true.len())
+    }
+}
+
+impl<I, It, P> FusedIterator for TrueSplitIterBy<I, It, P>
+where
+    It: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+}
+
+/// An iterator which returns the items where the predicate returns `false`.
+/// Created by `split_iter_by`
+pub struct FalseSplitIterBy<I, It, P> {
+    iter: Arc<Mutex<SplitIterBy<I, It, P>>>,
+}
+
+impl<I, It, P> FalseSplitIterBy<I, It, P> {
+    pub(crate) fn new(iter: Arc<Mutex<SplitIterBy<I, It, P>>>) -> Self {
+        Self { iter }
+    }
+}
+
+impl<I, It, P> Iterator for FalseSplitIterBy<I, It, P>
+where
+    It: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn next(&mut self) -> Option<I> {
+        lock(&self.iter).next_false()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let state = lock(&self.iter);
+        state.size_hint(state.buf_false.len())
+    }
+}
+
+impl<I, It, P> FusedIterator for FalseSplitIterBy<I, It, P>
+where
+    It: Iterator<Item = I>,
+    P: Fn(&I) -> bool,
+{
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Split, SplitIterByExt};
+
+    #[test]
+    fn test_borrowed_items_and_predicate() {
+        let data = [1u8, 5, 2, 7];
+        let threshold = 4;
+        let Split { matches, rest } = data.iter().split_iter_by(|&&n| n > threshold);
+        assert_eq!(vec![&1, &2], rest.collect::<Vec<_>>());
+        assert_eq!(vec![&5, &7], matches.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let Split {
+            matches: mut even,
+            rest: mut odd,
+        } = (0..6).split_iter_by(|&n| n % 2 == 0);
+        assert_eq!(Some(1), odd.next());
+        assert_eq!(Some(3), odd.next());
+        assert_eq!((2, Some(4)), even.size_hint());
+        assert_eq!(Some(0), even.next());
+        assert_eq!(vec![2, 4], even.by_ref().collect::<Vec<_>>());
+        assert_eq!(None, even.next());
+        assert_eq!(vec![5], odd.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_threads() {
+        let (even, odd) = (0..1000).split_iter_by(|&n| n % 2 == 0).into_tuple();
+        let even = std::thread::spawn(move || even.sum::<i32>());
+        let odd = std::thread::spawn(move || odd.sum::<i32>());
+        assert_eq!(249500, even.join().unwrap());
+        assert_eq!(250000, odd.join().unwrap());
+    }
+}
@@ -0,0 +1,260 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::ready;
+use futures_sink::Sink;
+use pin_project::pin_project;
+
+use crate::{ForwardSplitError, Side};
+
+/// A sink that sends each item to one of two sinks, depending on a
+/// predicate. Created by `split_sink_by`
+#[pin_project]
+pub struct SplitSinkBy<ST, SF, P, I> {
+    #[pin]
+    sink_true: ST,
+    #[pin]
+    sink_false: SF,
+    predicate: P,
+    // The item that was accepted by `start_send`, but not yet by the sink it goes to
+    held: Option<(Side, I)>,
+}
+
+/// Combines two sinks into one, the opposite of splitting a stream. Items
+/// where `predicate` returns `true` are sent to `sink_true` and the rest to
+/// `sink_false`. Which sink an item goes to isn't known until `start_send`,
+/// so the item is held until its sink is ready, and `poll_ready` only
+/// returns `Ready` once the previous item has been passed on. That way only
+/// the sink an item goes to has to be ready for it, but an item for a busy
+/// sink holds up the items after it, even those for the other sink.
+/// Flushing and closing flush and close both sinks. An error says which of
+/// the sinks failed
+///
+///```rust
+/// use futures::{channel::mpsc, SinkExt, StreamExt};
+/// use split_stream_by::split_sink_by;
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let (evens, even_receiver) = mpsc::unbounded();
+///     let (odds, odd_receiver) = mpsc::unbounded();
+///     let mut sink = split_sink_by(evens, odds, |&n: &i32| n % 2 == 0);
+///     sink.send_all(&mut futures::stream::iter([0,1,2,3,4,5]).map(Ok)).await.unwrap();
+///     sink.close().await.unwrap();
+///     assert_eq!(vec![0,2,4], even_receiver.collect::<Vec<_>>().await);
+///     assert_eq!(vec![1,3,5], odd_receiver.collect::<Vec<_>>().await);
+/// })
+/// ```
+pub fn split_sink_by<ST, SF, P, I>(
+    sink_true: ST,
+    sink_false: SF,
+    predicate: P,
+) -> SplitSinkBy<ST, SF, P, I>
+where
+    ST: Sink<I>,
+    SF: Sink<I>,
+    P: Fn(&I) -> bool,
+{
+    SplitSinkBy {
+        sink_true,
+        sink_false,
+        predicate,
+        held: None,
+    }
+}
+
+impl<ST, SF, P, I> SplitSinkBy<ST, SF, P, I> {
+    /// Consumes this combinator, returning the two sinks. An item that was
+    /// accepted but not passed on yet is dropped
+    pub fn into_inner(self) -> (ST, SF) {
+        (self.sink_true, self.sink_false)
+    }
+}
+
+impl<ST, SF, P, I> SplitSinkBy<ST, SF, P, I>
+where
+    ST: Sink<I>,
+    SF: Sink<I>,
+{
+    /// Passes the held item on to its sink, once that sink is ready
+    #[allow(clippy::type_complexity)]
+    fn poll_send_held(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), ForwardSplitError<ST::Error, SF::Error>>> {
+        let mut this = self.project();
+        let side = match this.held {
+            Some((side, _)) => *side,
+            None => return Poll::Ready(Ok(())),
+        };
+        match side {
+            Side::True => {
+                ready!(this.sink_true.as_mut().poll_ready(cx)).map_err(ForwardSplitError::True)?
+            }
+            Side::False => {
+                ready!(this.sink_false.as_mut().poll_ready(cx)).map_err(ForwardSplitError::False)?
+            }
+        }
+        let (_, item) = this.held.take().expect("an item is held");
+        match side {
+            Side::True => this
+                .sink_true
+                .start_send(item)
+                .map_err(ForwardSplitError::True),
+            Side::False => this
+                .sink_false
+                .start_send(item)
+                .map_err(ForwardSplitError::False),
+        }
+        .into()
+    }
+}
+
+impl<ST, SF, P, I> Sink<I> for SplitSinkBy<ST, SF, P, I>
+where
+    ST: Sink<I>,
+    SF: Sink<I>,
+    P: Fn(&I) -> bool,
+{
+    type Error = ForwardSplitError<ST::Error, SF::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_send_held(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        let this = self.project();
+        debug_assert!(
+            this.held.is_none(),
+            "`start_send` called without `poll_ready`"
+        );
+        let side = Side::from((this.predicate)(&item));
+        *this.held = Some((side, item));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_send_held(cx))?;
+        let mut this = self.project();
+        // Both sinks are flushed every time, which is fine once they're done
+        let flushed_true = this
+            .sink_true
+            .as_mut()
+            .poll_flush(cx)
+            .map_err(ForwardSplitError::True)?;
+        let flushed_false = this
+            .sink_false
+            .as_mut()
+            .poll_flush(cx)
+            .map_err(ForwardSplitError::False)?;
+        ready!(flushed_true);
+        ready!(flushed_false);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_send_held(cx))?;
+        let mut this = self.project();
+        // Both sinks are closed every time, which is fine once they're done
+        let closed_true = this
+            .sink_true
+            .as_mut()
+            .poll_close(cx)
+            .map_err(ForwardSplitError::True)?;
+        let closed_false = this
+            .sink_false
+            .as_mut()
+            .poll_close(cx)
+            .map_err(ForwardSplitError::False)?;
+        ready!(closed_true);
+        ready!(closed_false);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{pin::Pin, task::Poll};
+
+    use futures::{channel::mpsc, Sink, SinkExt, StreamExt};
+
+    use super::*;
+    use crate::testing::{poll_once, CountingWaker};
+
+    #[tokio::test]
+    async fn test_split_sink_by() {
+        let (evens, even_receiver) = mpsc::channel(1);
+        let (odds, odd_receiver) = mpsc::channel(3);
+        let mut sink = split_sink_by(evens, odds, |&n: &i32| n % 2 == 0);
+        let (evens, odds, _) = futures::join!(
+            even_receiver.collect::<Vec<_>>(),
+            odd_receiver.collect::<Vec<_>>(),
+            async move {
+                for n in 0..10 {
+                    sink.send(n).await.unwrap();
+                }
+                sink.close().await.unwrap();
+            }
+        );
+        assert_eq!(vec![0, 2, 4, 6, 8], evens);
+        assert_eq!(vec![1, 3, 5, 7, 9], odds);
+    }
+
+    #[test]
+    fn test_waits_for_destination() {
+        // A channel with no buffer only has room for one message per sender
+        let (evens, mut even_receiver) = mpsc::channel(0);
+        let (odds, mut odd_receiver) = mpsc::unbounded();
+        let mut sink = split_sink_by(evens, odds, |&n: &i32| n % 2 == 0);
+        let waker = CountingWaker::new();
+        let cx_waker = waker.waker();
+        let mut cx = Context::from_waker(&cx_waker);
+        let send = |sink: &mut SplitSinkBy<_, _, _, i32>, n, cx: &mut Context<'_>| {
+            let ready = Pin::new(&mut *sink).poll_ready(cx);
+            if let Poll::Ready(Ok(())) = ready {
+                Pin::new(&mut *sink).start_send(n).unwrap();
+            }
+            ready
+        };
+        assert_eq!(Poll::Ready(Ok(())), send(&mut sink, 0, &mut cx));
+        assert_eq!(Poll::Ready(Ok(())), send(&mut sink, 1, &mut cx));
+        assert_eq!(Poll::Ready(Ok(())), send(&mut sink, 2, &mut cx));
+        // 2 is held, since the channel for even numbers is full
+        assert_eq!(Poll::Pending, send(&mut sink, 3, &mut cx));
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odd_receiver, &cx_waker)
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut odd_receiver, &cx_waker));
+        let wakes = waker.count();
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut even_receiver, &cx_waker)
+        );
+        assert!(waker.count() > wakes);
+        assert_eq!(Poll::Ready(Ok(())), send(&mut sink, 3, &mut cx));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut even_receiver, &cx_waker)
+        );
+        assert_eq!(Poll::Ready(Ok(())), Pin::new(&mut sink).poll_flush(&mut cx));
+        assert_eq!(
+            Poll::Ready(Some(3)),
+            poll_once(&mut odd_receiver, &cx_waker)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sink_error() {
+        let (evens, even_receiver) = mpsc::unbounded();
+        let (odds, odd_receiver) = mpsc::unbounded::<i32>();
+        let mut sink = split_sink_by(evens, odds, |&n: &i32| n % 2 == 0);
+        drop(odd_receiver);
+        sink.send(0).await.unwrap();
+        let err = sink.send(1).await.unwrap_err();
+        assert_eq!(Side::False, err.side());
+        drop(sink);
+        assert_eq!(vec![0], even_receiver.collect::<Vec<_>>().await);
+    }
+}
@@ -0,0 +1,398 @@
+use core::{
+    cell::RefCell,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use critical_section::Mutex;
+use futures_core::{stream::FusedStream, Stream};
+
+use crate::{
+    debug::{self, Verbose},
+    ring::RingBuf,
+    ByPredicate, Split, SplitByBuffered,
+};
+
+/// The shared state of a `StaticSplit`, which its streams borrow
+type State<I, S, P, const N: usize> = Mutex<RefCell<SplitByBuffered<I, S, P, RingBuf<I, N>>>>;
+
+/// A split that lives wherever the caller puts it, like in a `static` or on
+/// the stack, instead of behind an `Arc`. It works like `split_by_buffered`
+/// with a buffer of N items per stream, but never allocates, and its streams
+/// borrow it rather than owning it. The shared state is guarded by a
+/// `critical-section` mutex, so the underlying stream and the predicate run
+/// inside a critical section and should be cheap. Requires the
+/// `static-split` feature
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::StaticSplit;
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let mut split = StaticSplit::<_, _, _, 4>::new(futures::stream::iter(0..6), |&n: &i32| n % 2 == 0);
+///     let (evens, odds) = split.split().into_tuple();
+///     let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+///     assert_eq!(vec![0, 2, 4], evens);
+///     assert_eq!(vec![1, 3, 5], odds);
+/// })
+/// ```
+pub struct StaticSplit<I, S, P, const N: usize> {
+    state: State<I, S, P, N>,
+}
+
+impl<I, S, P, const N: usize> StaticSplit<I, S, P, N>
+where
+    S: Stream<Item = I>,
+    P: Fn(&I) -> bool,
+{
+    /// Creates the storage of a split of `stream`. Nothing is pulled from the
+    /// stream until one of the streams returned by `split` is polled
+    pub fn new(stream: S, predicate: P) -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(SplitByBuffered::with_kind(
+                stream,
+                ByPredicate(predicate),
+                RingBuf::new(),
+                RingBuf::new(),
+                "static_split",
+                ["TrueStaticSplit", "FalseStaticSplit"],
+            ))),
+        }
+    }
+}
+
+impl<I, S, P, const N: usize> StaticSplit<I, S, P, N> {
+    /// Returns the two streams of the split, which borrow it. Items where the
+    /// predicate returns `true` go to `matches` and the rest to `rest`. Once
+    /// both streams are dropped, this can be called again to get new ones
+    /// that continue where the old ones left off
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &mut self,
+    ) -> Split<TrueStaticSplit<'_, I, S, P, N>, FalseStaticSplit<'_, I, S, P, N>> {
+        let state = self.state.get_mut().get_mut();
+        state.reopen();
+        let state = &self.state;
+        Split {
+            matches: TrueStaticSplit {
+                state,
+                terminated: false,
+            },
+            rest: FalseStaticSplit {
+                state,
+                terminated: false,
+            },
+        }
+    }
+}
+
+/// Runs `f` on the state of a split in a critical section. Returns `None`
+/// without running `f` if the state is already in use, which happens when a
+/// stream is polled from inside the underlying stream or the predicate
+fn with_state<I, S, P, const N: usize, R>(
+    state: &State<I, S, P, N>,
+    f: impl FnOnce(&mut SplitByBuffered<I, S, P, RingBuf<I, N>>) -> R,
+) -> Option<R> {
+    critical_section::with(|cs| {
+        let mut state = state.borrow(cs).try_borrow_mut().ok()?;
+        Some(f(&mut state))
+    })
+}
+
+/// The stream of a `StaticSplit` which returns the items where the predicate
+/// returns `true`
+#[must_use = "streams do nothing unless polled"]
+pub struct TrueStaticSplit<'a, I, S, P, const N: usize> {
+    state: &'a State<I, S, P, N>,
+    terminated: bool,
+}
+
+impl<I, S, P, const N: usize> TrueStaticSplit<'_, I, S, P, N> {
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+}
+
+impl<I, S, P, const N: usize> TrueStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = with_state(self.state, |state| state.poll_true(cx)).unwrap_or_else(|| {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for TrueStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for TrueStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        Stream::poll_next(self, cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for TrueStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for TrueStaticSplit<'_, I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        critical_section::with(|cs| {
+            let state = self.state.borrow(cs).try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "TrueStaticSplit",
+                state.as_deref(),
+                self.terminated,
+                SplitByBuffered::debug_fields,
+            )
+        })
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, TrueStaticSplit<'_, I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        critical_section::with(|cs| {
+            let state = self.0.state.borrow(cs).try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "TrueStaticSplit",
+                state.as_deref(),
+                self.0.terminated,
+                |state, debug| {
+                    state.debug_fields(debug);
+                    state.debug_items(debug);
+                },
+            )
+        })
+    }
+}
+
+impl<I, S, P, const N: usize> Drop for TrueStaticSplit<'_, I, S, P, N> {
+    fn drop(&mut self) {
+        with_state(self.state, |state| state.close_true());
+    }
+}
+
+/// The stream of a `StaticSplit` which returns the items where the predicate
+/// returns `false`
+#[must_use = "streams do nothing unless polled"]
+pub struct FalseStaticSplit<'a, I, S, P, const N: usize> {
+    state: &'a State<I, S, P, N>,
+    terminated: bool,
+}
+
+impl<I, S, P, const N: usize> FalseStaticSplit<'_, I, S, P, N> {
+    /// Returns a wrapper whose `Debug` output also includes the buffered items
+    pub fn verbose(&self) -> Verbose<'_, Self> {
+        Verbose(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FalseStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    /// Polls for the next item without the stream having to be pinned. This
+    /// is what the `Stream` implementation calls
+    pub fn poll_next_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let response = with_state(self.state, |state| state.poll_false(cx)).unwrap_or_else(|| {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        });
+        if let Poll::Ready(None) = response {
+            self.terminated = true;
+        }
+        response
+    }
+}
+
+impl<I, S, P, const N: usize> Stream for FalseStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().poll_next_item(cx)
+    }
+}
+
+#[cfg(feature = "nightly-async-iterator")]
+impl<I, S, P, const N: usize> core::async_iter::AsyncIterator for FalseStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        Stream::poll_next(self, cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(self)
+    }
+}
+
+impl<I, S, P, const N: usize> FusedStream for FalseStaticSplit<'_, I, S, P, N>
+where
+    S: Stream<Item = I> + Unpin,
+    P: Fn(&I) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for FalseStaticSplit<'_, I, S, P, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        critical_section::with(|cs| {
+            let state = self.state.borrow(cs).try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "FalseStaticSplit",
+                state.as_deref(),
+                self.terminated,
+                SplitByBuffered::debug_fields,
+            )
+        })
+    }
+}
+
+impl<I, S, P, const N: usize> fmt::Debug for Verbose<'_, FalseStaticSplit<'_, I, S, P, N>>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        critical_section::with(|cs| {
+            let state = self.0.state.borrow(cs).try_borrow().ok();
+            debug::fmt_split(
+                f,
+                "FalseStaticSplit",
+                state.as_deref(),
+                self.0.terminated,
+                |state, debug| {
+                    state.debug_fields(debug);
+                    state.debug_items(debug);
+                },
+            )
+        })
+    }
+}
+
+impl<I, S, P, const N: usize> Drop for FalseStaticSplit<'_, I, S, P, N> {
+    fn drop(&mut self) {
+        with_state(self.state, |state| state.close_false());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::task::Poll;
+
+    use futures::StreamExt;
+
+    use super::StaticSplit;
+    use crate::testing::{poll_once, CountingWaker, ManualStream};
+
+    #[test]
+    fn test_buffers_until_full() {
+        let (source, handle) = ManualStream::new();
+        let mut split = StaticSplit::<_, _, _, 2>::new(source, |&n: &i32| n % 2 == 0);
+        let (mut true_stream, mut false_stream) = split.split().into_tuple();
+        let true_waker = CountingWaker::new();
+        let false_waker = CountingWaker::new();
+        for n in [0, 2, 1] {
+            handle.push(n);
+        }
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        // The true buffer is full, so the false stream waits without polling the source
+        let polls = handle.poll_count();
+        assert_eq!(
+            Poll::Pending,
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+        assert_eq!(polls, handle.poll_count());
+        assert_eq!(
+            Poll::Ready(Some(0)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut true_stream, &true_waker.waker())
+        );
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut false_stream, &false_waker.waker())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_split_again() {
+        let mut split =
+            StaticSplit::<_, _, _, 4>::new(futures::stream::iter(0..6), |&n: &i32| n % 2 == 0);
+        let (mut evens, mut odds) = split.split().into_tuple();
+        assert_eq!(
+            (Some(0), Some(1)),
+            futures::join!(evens.next(), odds.next())
+        );
+        drop((evens, odds));
+        let (evens, odds) = split.split().into_tuple();
+        let (evens, odds) = futures::join!(evens.collect::<Vec<_>>(), odds.collect::<Vec<_>>());
+        assert_eq!(vec![2, 4], evens);
+        assert_eq!(vec![3, 5], odds);
+    }
+}
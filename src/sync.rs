@@ -0,0 +1,156 @@
+//! The lock that guards the state the two streams of a split share. With the
+//! `std` feature this is a `std::sync::Mutex`, and otherwise a spin lock from
+//! the `spin` feature, which works anywhere atomics do. The `spin` crate is
+//! only a dependency when that feature is on, so builds with `std` don't
+//! pull it in
+
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+#[cfg(not(any(feature = "std", feature = "spin")))]
+compile_error!(
+    "without the `std` feature, the `spin` feature has to be turned on for the lock of a split"
+);
+
+#[cfg(feature = "std")]
+pub(crate) type MutexGuard<'a, T> = std::sync::MutexGuard<'a, T>;
+#[cfg(not(feature = "std"))]
+pub(crate) type MutexGuard<'a, T> = spin::MutexGuard<'a, T>;
+
+#[derive(Default)]
+pub(crate) struct Mutex<T> {
+    #[cfg(feature = "std")]
+    inner: std::sync::Mutex<T>,
+    #[cfg(not(feature = "std"))]
+    inner: spin::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            inner: std::sync::Mutex::new(value),
+            #[cfg(not(feature = "std"))]
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    /// Locks the state, waiting for the other stream if it holds the lock.
+    /// A panic while the lock was held, like in the predicate, doesn't leave
+    /// the state inconsistent, so poisoning is ignored
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(feature = "std")]
+        return self
+            .inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        #[cfg(not(feature = "std"))]
+        return self.inner.lock();
+    }
+
+    /// Locks the state if nothing else holds the lock right now
+    pub(crate) fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        #[cfg(feature = "std")]
+        return match self.inner.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(std::sync::TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(std::sync::TryLockError::WouldBlock) => None,
+        };
+        #[cfg(not(feature = "std"))]
+        return self.inner.try_lock();
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        #[cfg(feature = "std")]
+        return self
+            .inner
+            .into_inner()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        #[cfg(not(feature = "std"))]
+        return self.inner.into_inner();
+    }
+}
+
+/// The shared state of a split as one of its streams holds it. Taking a
+/// split apart takes the state out of the stream, which leaves nothing for
+/// the stream to close when it's dropped
+pub(crate) struct SharedState<T> {
+    inner: Option<Arc<T>>,
+}
+
+impl<T> SharedState<T> {
+    pub(crate) fn new(inner: Arc<T>) -> Self {
+        Self { inner: Some(inner) }
+    }
+
+    /// Takes the state out if nothing else refers to it anymore. Otherwise
+    /// the stream keeps it and `None` is returned
+    pub(crate) fn try_take(&mut self) -> Option<T> {
+        match Arc::try_unwrap(self.inner.take()?) {
+            Ok(state) => Some(state),
+            Err(inner) => {
+                self.inner = Some(inner);
+                None
+            }
+        }
+    }
+
+    /// Lets go of the state, so that the other stream of the split can take
+    /// it. `new` gives it back
+    pub(crate) fn release(&mut self) {
+        self.inner = None;
+    }
+
+    /// The state, unless it was taken out or let go of
+    pub(crate) fn get(&self) -> Option<&Arc<T>> {
+        self.inner.as_ref()
+    }
+}
+
+impl<T> Deref for SharedState<T> {
+    type Target = Arc<T>;
+
+    fn deref(&self) -> &Arc<T> {
+        self.inner
+            .as_ref()
+            .expect("only a stream that is being taken apart lets go of the state")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_lock_while_locked() {
+        let mutex = Mutex::new(1);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        *mutex.try_lock().unwrap() += 1;
+        assert_eq!(2, mutex.into_inner());
+    }
+
+    #[test]
+    fn test_lock_after_panic() {
+        let mutex = std::sync::Arc::new(Mutex::new(1));
+        let panicking = mutex.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = panicking.lock();
+            panic!("panics while the lock is held");
+        })
+        .join();
+        *mutex.lock() += 1;
+        assert_eq!(2, *mutex.try_lock().unwrap());
+    }
+
+    #[test]
+    fn test_try_take_shared_state() {
+        let mut state = SharedState::new(Arc::new(Mutex::new(1)));
+        let other = Arc::clone(&state);
+        assert!(state.try_take().is_none());
+        drop(other);
+        assert_eq!(1, state.try_take().unwrap().into_inner());
+        assert!(state.get().is_none());
+    }
+}
@@ -0,0 +1,360 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::side_waker::RoomWaker;
+
+/// What a lane shares with the thread that sends its items to the channel
+struct Slot<I> {
+    /// The item the thread is to send next
+    item: Option<I>,
+    /// Whether the thread is sending an item, which blocks while the channel
+    /// is full
+    sending: bool,
+    /// The driver, if it's waiting for the thread to take an item
+    waker: RoomWaker,
+    /// Whether the receiver was dropped
+    closed: bool,
+    /// Whether the lane won't hand over any more items
+    ended: bool,
+}
+
+/// The slot of a lane, along with what the thread waits on for an item
+struct Handoff<I> {
+    slot: Mutex<Slot<I>>,
+    filled: Condvar,
+}
+
+impl<I> Handoff<I> {
+    fn new() -> Self {
+        Self {
+            slot: Mutex::new(Slot {
+                item: None,
+                sending: false,
+                waker: RoomWaker::default(),
+                closed: false,
+                ended: false,
+            }),
+            filled: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot<I>> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Tells the thread that the lane is done, so it exits once it sent the
+    /// item it has, which ends the channel
+    fn end(&self) {
+        self.lock().ended = true;
+        self.filled.notify_one();
+    }
+}
+
+/// Sends the items a lane hands over to the channel, blocking while it's
+/// full, and wakes the driver each time it's ready for the next one. Runs on
+/// a thread of its own, since the receiver can't wake anything
+fn forward<I>(handoff: &Handoff<I>, sender: Sender<I>) {
+    let mut slot = handoff.lock();
+    loop {
+        match slot.item.take() {
+            Some(item) => {
+                slot.sending = true;
+                drop(slot);
+                let sent = sender.send(item).is_ok();
+                slot = handoff.lock();
+                slot.sending = false;
+                slot.closed = !sent;
+                slot.waker.wake();
+                if !sent {
+                    return;
+                }
+            }
+            None if slot.ended => return,
+            None => {
+                slot = handoff
+                    .filled
+                    .wait(slot)
+                    .unwrap_or_else(PoisonError::into_inner)
+            }
+        }
+    }
+}
+
+/// Hands the items of one stream of a split to the thread that sends them to
+/// its channel, holding on to an item while the thread is busy
+struct Lane<St>
+where
+    St: Stream,
+{
+    /// `None` once the stream ended
+    stream: Option<St>,
+    handoff: Arc<Handoff<St::Item>>,
+    /// Whether the receiver is still there. Once it isn't, the items that
+    /// are pulled are dropped
+    connected: bool,
+    // The item that was pulled from the stream but the thread can't take yet
+    pending: Option<St::Item>,
+}
+
+impl<St> Lane<St>
+where
+    St: Stream + Unpin,
+    St::Item: Send + 'static,
+{
+    fn new(stream: St, cap: usize) -> (Self, Receiver<St::Item>) {
+        let (sender, receiver) = crossbeam_channel::bounded(cap);
+        let handoff = Arc::new(Handoff::new());
+        thread::spawn({
+            let handoff = handoff.clone();
+            move || forward(&handoff, sender)
+        });
+        let lane = Self {
+            stream: Some(stream),
+            handoff,
+            connected: true,
+            pending: None,
+        };
+        (lane, receiver)
+    }
+
+    /// Hands an item to the thread, or holds on to it if the thread is still
+    /// sending the previous one, in which case the waker of `cx` is woken
+    /// once it's done. Returns `Ready(Err)` if the receiver was dropped
+    fn poll_send(&mut self, item: St::Item, cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        let mut slot = self.handoff.lock();
+        if slot.closed {
+            return Poll::Ready(Err(()));
+        }
+        if slot.item.is_some() || slot.sending {
+            slot.waker.register(cx.waker());
+            self.pending = Some(item);
+            return Poll::Pending;
+        }
+        slot.item = Some(item);
+        drop(slot);
+        self.handoff.filled.notify_one();
+        Poll::Ready(Ok(()))
+    }
+
+    /// Moves items from the stream to the thread until either of them makes
+    /// it wait. Returns `Ready` once the lane is done
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if let Some(item) = self.pending.take() {
+                match self.poll_send(item, cx) {
+                    Poll::Ready(Ok(())) => {}
+                    // The items pulled after this are dropped
+                    Poll::Ready(Err(())) => self.connected = false,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => return Poll::Ready(()),
+            };
+            match stream.poll_next_unpin(cx) {
+                // Without a receiver, the item is dropped right away
+                Poll::Ready(Some(item)) if self.connected => self.pending = Some(item),
+                Poll::Ready(Some(_)) => {}
+                Poll::Ready(None) => {
+                    self.stream = None;
+                    self.handoff.end();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<St> Drop for Lane<St>
+where
+    St: Stream,
+{
+    fn drop(&mut self) {
+        self.handoff.end();
+    }
+}
+
+/// A future that feeds the two streams of a split into the channels of two
+/// `crossbeam_channel::Receiver`s. Created by `Split::bridge_sync`
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct BridgeSync<T, F>
+where
+    T: Stream,
+    F: Stream,
+{
+    matches: Lane<T>,
+    rest: Lane<F>,
+}
+
+impl<T, F> BridgeSync<T, F>
+where
+    T: Stream + Unpin,
+    F: Stream + Unpin,
+    T::Item: Send + 'static,
+    F::Item: Send + 'static,
+{
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn new(
+        matches: T,
+        rest: F,
+        cap_true: usize,
+        cap_false: usize,
+    ) -> (Receiver<T::Item>, Receiver<F::Item>, Self) {
+        let (matches, receiver_true) = Lane::new(matches, cap_true);
+        let (rest, receiver_false) = Lane::new(rest, cap_false);
+        (receiver_true, receiver_false, Self { matches, rest })
+    }
+}
+
+impl<T, F> Unpin for BridgeSync<T, F>
+where
+    T: Stream,
+    F: Stream,
+{
+}
+
+impl<T, F> Future for BridgeSync<T, F>
+where
+    T: Stream + Unpin,
+    F: Stream + Unpin,
+    T::Item: Send + 'static,
+    F::Item: Send + 'static,
+{
+    type Output = ();
+
+    /// Resolves once both streams have ended
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Both lanes are polled every time, so one that waits for room doesn't hold up the other
+        let matches = self.matches.poll(cx);
+        let rest = self.rest.poll(cx);
+        if matches.is_ready() && rest.is_ready() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use futures::StreamExt;
+
+    use crate::SplitStreamByExt;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_threads_consume_while_task_drives() {
+        let split = futures::stream::iter(0..1000).split_by_buffered::<8>(|&n| n % 3 == 0);
+        let (threes, others, driver) = split.bridge_sync(4, 4);
+        let threes = thread::spawn(move || threes.iter().collect::<Vec<_>>());
+        // This thread is slow, so the driver keeps waiting for room in its channel
+        let others = thread::spawn(move || {
+            others
+                .iter()
+                .inspect(|_| thread::sleep(Duration::from_micros(50)))
+                .collect::<Vec<_>>()
+        });
+        tokio::spawn(driver).await.unwrap();
+        assert_eq!(
+            (0..1000).filter(|n| n % 3 == 0).collect::<Vec<_>>(),
+            threes.join().unwrap()
+        );
+        assert_eq!(
+            (0..1000).filter(|n| n % 3 != 0).collect::<Vec<_>>(),
+            others.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_full_channel_holds_back_stream() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let split = futures::stream::iter(0..100)
+            .inspect({
+                let pulled = pulled.clone();
+                move |_| {
+                    pulled.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .split_by_buffered::<2>(|&n| n % 2 == 0);
+        let (evens, odds, driver) = split.bridge_sync(2, 2);
+        let driver = thread::spawn(move || futures::executor::block_on(driver));
+        let start = Instant::now();
+        while evens.len() < 2 || odds.len() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(20));
+        // Both channels are full, so the driver waits for room and the rest of the items are
+        // left in the underlying stream. Each side has at most two items in its channel, one
+        // being sent, one waiting for that and two in the buffer of the split
+        assert_eq!((2, 2), (evens.len(), odds.len()));
+        assert!(pulled.load(Ordering::SeqCst) <= 12);
+        // The streams of the split wait for each other, so both are taken at once
+        let odds = thread::spawn(move || odds.iter().collect::<Vec<_>>());
+        assert_eq!(
+            (0..100).step_by(2).collect::<Vec<_>>(),
+            evens.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (1..100).step_by(2).collect::<Vec<_>>(),
+            odds.join().unwrap()
+        );
+        driver.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_dropped_receiver_doesnt_wedge_driver() {
+        let split = futures::stream::iter(0..100).split_by_buffered::<4>(|&n| n % 2 == 0);
+        let (evens, odds, driver) = split.bridge_sync(2, 2);
+        let driver = tokio::spawn(driver);
+        let evens = thread::spawn(move || evens.iter().take(3).collect::<Vec<_>>());
+        let odds = thread::spawn(move || odds.iter().collect::<Vec<_>>());
+        driver.await.unwrap();
+        assert_eq!(vec![0, 2, 4], evens.join().unwrap());
+        assert_eq!(
+            (1..100).step_by(2).collect::<Vec<_>>(),
+            odds.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ends_when_both_streams_end() {
+        let split = futures::stream::iter(0..4).split_by(|&n| n < 2);
+        let (small, big, driver) = split.bridge_sync(4, 4);
+        futures::executor::block_on(driver);
+        assert_eq!(vec![0, 1], small.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2, 3], big.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_zero_capacity_hands_items_over() {
+        let split = futures::stream::iter(0..20).split_by_buffered::<2>(|&n| n < 10);
+        let (small, big, driver) = split.bridge_sync(0, 0);
+        let small = thread::spawn(move || small.iter().collect::<Vec<_>>());
+        let big = thread::spawn(move || big.iter().collect::<Vec<_>>());
+        futures::executor::block_on(driver);
+        assert_eq!((0..10).collect::<Vec<_>>(), small.join().unwrap());
+        assert_eq!((10..20).collect::<Vec<_>>(), big.join().unwrap());
+    }
+}
@@ -0,0 +1,84 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use pin_project::pin_project;
+
+use crate::Side;
+
+/// A stream that returns each item of the underlying stream along with the
+/// side the predicate puts it on, in the order of the underlying stream.
+/// There's no shared state, so this is the cheapest option when a single
+/// consumer handles both sides. Created by `tag_by`
+#[pin_project]
+pub struct TagBy<S, P> {
+    #[pin]
+    stream: S,
+    predicate: P,
+}
+
+impl<S, P> TagBy<S, P> {
+    pub(crate) fn new(stream: S, predicate: P) -> Self {
+        Self { stream, predicate }
+    }
+
+    /// Returns the underlying stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, P> Stream for TagBy<S, P>
+where
+    S: Stream,
+    P: Fn(&S::Item) -> bool,
+{
+    type Item = (Side, S::Item);
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let predicate = this.predicate;
+        this.stream
+            .poll_next(cx)
+            .map(|item| item.map(|item| (Side::from(predicate(&item)), item)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+impl<S, P> FusedStream for TagBy<S, P>
+where
+    S: FusedStream,
+    P: Fn(&S::Item) -> bool,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use crate::{Side, SplitStreamByExt};
+
+    #[tokio::test]
+    async fn test_tag_by() {
+        let tagged = futures::stream::iter([0, 1, 2, 3])
+            .tag_by(|&n| n % 2 == 0)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            vec![
+                (Side::True, 0),
+                (Side::False, 1),
+                (Side::True, 2),
+                (Side::False, 3)
+            ],
+            tagged
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Utilities for writing tests against split streams without an executor.
+//! Requires the `test-util` feature
+//!
+//!```rust
+//! use std::task::Poll;
+//! use split_stream_by::{Split, SplitStreamByExt};
+//! use split_stream_by::testing::{poll_once, CountingWaker, ManualStream};
+//!
+//! let (source, handle) = ManualStream::new();
+//! let Split { matches: mut even_stream, rest: mut odd_stream } = source.split_by(|&n: &i32| n % 2 == 0);
+//! let even_waker = CountingWaker::new();
+//! let odd_waker = CountingWaker::new();
+//!
+//! // Poll the even stream so that its waker is registered
+//! assert_eq!(Poll::Pending, poll_once(&mut even_stream, &even_waker.waker()));
+//! handle.push(0);
+//! // The odd stream pulls the even number from the source, so it buffers it
+//! // and notifies the even stream
+//! assert_eq!(Poll::Pending, poll_once(&mut odd_stream, &odd_waker.waker()));
+//! assert_eq!(0, odd_waker.count());
+//! assert!(even_waker.count() > 0);
+//! assert_eq!(Poll::Ready(Some(0)), poll_once(&mut even_stream, &even_waker.waker()));
+//! ```
+use alloc::{collections::VecDeque, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_core::Stream;
+
+use crate::{
+    sync::{Mutex, MutexGuard},
+    timer::{Clock, Sleep},
+};
+
+enum Event<I> {
+    Item(I),
+    Pending,
+    End,
+}
+
+struct ManualState<I> {
+    events: VecDeque<Event<I>>,
+    ended: bool,
+    polls: usize,
+    waker: Option<Waker>,
+}
+
+/// A stream whose items are supplied by hand through a `ManualStreamHandle`.
+/// When there is nothing queued, polling it returns `Pending` until something
+/// is pushed. Once an end has been reached, polling with nothing queued
+/// returns `None`
+pub struct ManualStream<I> {
+    state: Arc<Mutex<ManualState<I>>>,
+}
+
+/// Controls a `ManualStream`. Each method queues an event which the stream
+/// will return, in order, and wakes the stream if it's waiting
+pub struct ManualStreamHandle<I> {
+    state: Arc<Mutex<ManualState<I>>>,
+}
+
+impl<I> ManualStream<I> {
+    /// Creates an empty stream and the handle used to feed it
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (ManualStream<I>, ManualStreamHandle<I>) {
+        let state = Arc::new(Mutex::new(ManualState {
+            events: VecDeque::new(),
+            ended: false,
+            polls: 0,
+            waker: None,
+        }));
+        (
+            ManualStream {
+                state: state.clone(),
+            },
+            ManualStreamHandle { state },
+        )
+    }
+}
+
+impl<I> ManualStreamHandle<I> {
+    /// Queues an item to be returned by the stream
+    pub fn push(&self, item: I) {
+        self.push_event(Event::Item(item));
+    }
+
+    /// Queues a `Pending` to be returned by the stream even if items are
+    /// queued after it
+    pub fn push_pending(&self) {
+        self.push_event(Event::Pending);
+    }
+
+    /// Queues the end of the stream. Items pushed afterwards will still be
+    /// returned, which allows scripting sources that resume after `None`
+    pub fn end(&self) {
+        self.push_event(Event::End);
+    }
+
+    /// The number of times the stream has been polled
+    pub fn poll_count(&self) -> usize {
+        self.lock().polls
+    }
+
+    /// Whether the stream has any events that haven't been returned yet
+    pub fn is_empty(&self) -> bool {
+        self.lock().events.is_empty()
+    }
+
+    fn push_event(&self, event: Event<I>) {
+        let waker = {
+            let mut state = self.lock();
+            state.events.push_back(event);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ManualState<I>> {
+        self.state.lock()
+    }
+}
+
+impl<I> Stream for ManualStream<I> {
+    type Item = I;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        let mut state = self.state.lock();
+        state.polls += 1;
+        match state.events.pop_front() {
+            Some(Event::Item(item)) => Poll::Ready(Some(item)),
+            Some(Event::End) => {
+                state.ended = true;
+                Poll::Ready(None)
+            }
+            Some(Event::Pending) => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            None if state.ended => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    wakes: AtomicUsize,
+}
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// A waker that doesn't schedule anything, but counts how many times it has
+/// been woken. Every `Waker` created from the same `CountingWaker` shares the
+/// count and `will_wake` each other
+#[derive(Clone, Default)]
+pub struct CountingWaker {
+    counter: Arc<Counter>,
+}
+
+impl CountingWaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a `Waker` which increments this counter when woken
+    pub fn waker(&self) -> Waker {
+        Waker::from(self.counter.clone())
+    }
+
+    /// The number of times any of this counter's wakers have been woken
+    pub fn count(&self) -> usize {
+        self.counter.wakes.load(Ordering::SeqCst)
+    }
+}
+
+/// Polls a stream once using `waker`, without needing an executor
+pub fn poll_once<St>(stream: &mut St, waker: &Waker) -> Poll<Option<St::Item>>
+where
+    St: Stream + Unpin + ?Sized,
+{
+    Pin::new(stream).poll_next(&mut Context::from_waker(waker))
+}
+
+/// Polls a stream with a waker that does nothing until it returns something
+/// other than `Pending` or has been polled `max_polls` times. This is useful
+/// for getting an item from a stream that a single poll might not be enough
+/// for, such as a split stream that had to buffer an item for the other side
+pub fn poll_until_ready<St>(stream: &mut St, max_polls: usize) -> Poll<Option<St::Item>>
+where
+    St: Stream + Unpin + ?Sized,
+{
+    poll_until_ready_with_waker(stream, Waker::noop(), max_polls)
+}
+
+/// Like `poll_until_ready`, but polls with `waker`, so a test can count the
+/// wakeups the polls cause or get
+pub fn poll_until_ready_with_waker<St>(
+    stream: &mut St,
+    waker: &Waker,
+    max_polls: usize,
+) -> Poll<Option<St::Item>>
+where
+    St: Stream + Unpin + ?Sized,
+{
+    for _ in 0..max_polls {
+        if let Poll::Ready(item) = poll_once(stream, waker) {
+            return Poll::Ready(item);
+        }
+    }
+    Poll::Pending
+}
+
+#[derive(Default)]
+struct MockClock {
+    now: Duration,
+    /// The wakers of the sleeps that are waiting, along with when they end
+    sleeping: Vec<(Duration, Waker)>,
+}
+
+/// A timer whose clock only moves when `advance` is called, for testing the
+/// time-based options of a split without waiting or a runtime. Clones share
+/// the clock
+///
+///```rust
+/// use std::{future::Future, pin::pin, task::{Context, Poll}, time::Duration};
+/// use split_stream_by::{testing::{CountingWaker, MockTimer}, timer::{Clock, Sleep}};
+///
+/// let timer = MockTimer::new();
+/// let waker = CountingWaker::new();
+/// let mut sleep = pin!(timer.sleep(Duration::from_secs(2)));
+/// let waker_ref = waker.waker();
+/// let mut cx = Context::from_waker(&waker_ref);
+/// assert_eq!(Poll::Pending, sleep.as_mut().poll(&mut cx));
+/// timer.advance(Duration::from_secs(1));
+/// assert_eq!(0, waker.count());
+/// timer.advance(Duration::from_secs(1));
+/// assert_eq!(1, waker.count());
+/// assert_eq!(Poll::Ready(()), sleep.as_mut().poll(&mut cx));
+/// assert_eq!(Duration::from_secs(2), timer.now());
+/// ```
+#[derive(Clone, Default)]
+pub struct MockTimer {
+    clock: Arc<Mutex<MockClock>>,
+}
+
+impl MockTimer {
+    /// Creates a timer whose clock starts at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward by `by`, waking the sleeps that end by then
+    pub fn advance(&self, by: Duration) {
+        let woken = {
+            let mut clock = self.clock.lock();
+            clock.now += by;
+            let now = clock.now;
+            let (woken, sleeping) = core::mem::take(&mut clock.sleeping)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(until, _)| *until <= now);
+            clock.sleeping = sleeping;
+            woken
+        };
+        // Wake outside of the lock in case a waker polls the sleep inline
+        for (_, waker) in woken {
+            waker.wake();
+        }
+    }
+
+    /// The number of sleeps that were polled and haven't ended yet
+    pub fn sleeping(&self) -> usize {
+        self.clock.lock().sleeping.len()
+    }
+}
+
+impl Sleep for MockTimer {
+    type Fut = MockSleep;
+    fn sleep(&self, duration: Duration) -> MockSleep {
+        MockSleep {
+            clock: self.clock.clone(),
+            until: self.now() + duration,
+        }
+    }
+}
+
+impl Clock for MockTimer {
+    fn now(&self) -> Duration {
+        self.clock.lock().now
+    }
+}
+
+/// A future that resolves once the clock of a `MockTimer` has been moved
+/// past its end. Created by `MockTimer::sleep`
+pub struct MockSleep {
+    clock: Arc<Mutex<MockClock>>,
+    until: Duration,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut clock = self.clock.lock();
+        if clock.now >= self.until {
+            return Poll::Ready(());
+        }
+        let until = self.until;
+        match clock
+            .sleeping
+            .iter_mut()
+            .find(|(end, waker)| *end == until && waker.will_wake(cx.waker()))
+        {
+            Some(_) => {}
+            None => clock.sleeping.push((until, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manual_stream() {
+        let (mut stream, handle) = ManualStream::new();
+        let counter = CountingWaker::new();
+        assert_eq!(Poll::Pending, poll_once(&mut stream, &counter.waker()));
+        handle.push(1);
+        assert_eq!(1, counter.count());
+        handle.push_pending();
+        handle.push(2);
+        handle.end();
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut stream, &counter.waker())
+        );
+        assert_eq!(Poll::Pending, poll_once(&mut stream, &counter.waker()));
+        assert_eq!(
+            Poll::Ready(Some(2)),
+            poll_once(&mut stream, &counter.waker())
+        );
+        assert_eq!(Poll::Ready(None), poll_once(&mut stream, &counter.waker()));
+        assert_eq!(Poll::Ready(None), poll_once(&mut stream, &counter.waker()));
+        assert_eq!(6, handle.poll_count());
+        assert!(handle.is_empty());
+    }
+
+    #[test]
+    fn test_counting_waker() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        assert!(waker.will_wake(&counter.waker()));
+        waker.wake_by_ref();
+        waker.wake();
+        assert_eq!(2, counter.count());
+    }
+
+    #[test]
+    fn test_poll_until_ready() {
+        let (mut stream, handle) = ManualStream::new();
+        handle.push_pending();
+        handle.push_pending();
+        handle.push(1);
+        assert_eq!(Poll::Pending, poll_until_ready(&mut stream, 2));
+        assert_eq!(Poll::Ready(Some(1)), poll_until_ready(&mut stream, 2));
+        assert_eq!(3, handle.poll_count());
+    }
+}
@@ -0,0 +1,203 @@
+use alloc::boxed::Box;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::timer::{Clock, DynTimer, Sleep};
+
+/// A sleep of the timer of a throttled stream
+type BoxSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Limits how fast one stream of a split returns items, by spacing them out
+/// evenly. Nothing is limited unless a rate was set
+#[derive(Default)]
+pub(crate) struct Throttle {
+    // Boxed so that the streams of a split don't grow much when they aren't throttled
+    limit: Option<Box<Limit>>,
+}
+
+struct Limit {
+    /// How long to wait between two items
+    interval: Duration,
+    /// When the stream can return its next item on the clock of `timer`, or
+    /// `None` if right away
+    next: Option<Duration>,
+    /// The sleep that's running, along with when it ends
+    sleep: Option<(Duration, BoxSleep)>,
+    timer: Box<dyn DynTimer>,
+}
+
+impl Throttle {
+    /// Lets the stream return at most `items` items every `per`, on the
+    /// clock of `timer`
+    pub(crate) fn new<T>(items: u32, per: Duration, timer: T) -> Self
+    where
+        T: Sleep + Clock + Send + 'static,
+    {
+        assert!(
+            items > 0,
+            "a throttled stream has to be able to return items"
+        );
+        Self {
+            limit: Some(Box::new(Limit {
+                interval: per / items,
+                next: None,
+                sleep: None,
+                timer: Box::new(timer),
+            })),
+        }
+    }
+
+    /// Returns `Ready` if the stream can return an item now. Otherwise it
+    /// returns `Pending` and the waker of `cx` is woken once it can, by a
+    /// sleep of the timer
+    #[inline]
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(limit) = &mut self.limit {
+            let now = limit.timer.now();
+            let next = match limit.next {
+                Some(next) if next > now => next,
+                _ => return Poll::Ready(()),
+            };
+            let sleep = match &mut limit.sleep {
+                Some((until, sleep)) if *until == next => sleep,
+                _ => {
+                    let sleep = limit.timer.sleep(next - now);
+                    &mut limit.sleep.insert((next, sleep)).1
+                }
+            };
+            if sleep.as_mut().poll(cx).is_ready() {
+                limit.sleep = None;
+                return Poll::Ready(());
+            }
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+
+    /// Records that the stream returned an item, which uses up its budget
+    /// until the next item is due
+    #[inline]
+    pub(crate) fn returned(&mut self) {
+        if let Some(limit) = &mut self.limit {
+            let now = limit.timer.now();
+            // A stream that was idle doesn't get to catch up on the items it didn't return
+            let from = limit.next.map_or(now, |next| next.max(now));
+            limit.next = Some(from + limit.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{task::Poll, time::Duration};
+
+    #[cfg(feature = "timer")]
+    use futures::StreamExt;
+    #[cfg(feature = "timer")]
+    use tokio::time::Instant;
+
+    use crate::{
+        testing::{poll_once, poll_until_ready_with_waker, CountingWaker, MockTimer},
+        SplitBuilder,
+    };
+
+    /// How many of `seconds` fall in each second from the start
+    #[cfg(feature = "timer")]
+    fn per_second(seconds: &[u64]) -> Vec<usize> {
+        let mut counts = Vec::new();
+        for &second in seconds {
+            let second = second as usize;
+            if counts.len() <= second {
+                counts.resize(second + 1, 0);
+            }
+            counts[second] += 1;
+        }
+        counts
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_false() {
+        let split = SplitBuilder::new(futures::stream::iter(0..100))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(50)
+            .throttle_false(10, Duration::from_secs(1))
+            .build();
+        let start = Instant::now();
+        let elapsed = |_| start.elapsed().as_secs();
+        let (evens, odds) = futures::join!(
+            split.matches.map(elapsed).collect::<Vec<_>>(),
+            split.rest.map(elapsed).collect::<Vec<_>>()
+        );
+        // The unthrottled stream gets all of its items right away, and the rest are buffered for
+        // the throttled one
+        assert_eq!(vec![50], per_second(&evens));
+        assert_eq!(vec![10, 10, 10, 10, 10], per_second(&odds));
+    }
+
+    #[cfg(feature = "timer")]
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_true_after_idle() {
+        let (mut evens, _odds) = SplitBuilder::new(futures::stream::iter((0..20).map(|n| n * 2)))
+            .predicate(|&n| n % 2 == 0)
+            .throttle_true(4, Duration::from_secs(1))
+            .build()
+            .into_tuple();
+        let start = Instant::now();
+        for _ in 0..4 {
+            evens.next().await;
+        }
+        assert_eq!(Duration::from_millis(750), start.elapsed());
+        // Being idle doesn't let the stream return a burst of items afterwards
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            evens.next().await;
+        }
+        assert_eq!(Duration::from_millis(500), start.elapsed());
+    }
+
+    #[test]
+    fn test_throttle_on_mock_timer() {
+        let timer = MockTimer::new();
+        let (mut evens, mut odds) = SplitBuilder::new(futures::stream::iter(0..10))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(5)
+            .throttle_false_with(2, Duration::from_secs(1), timer.clone())
+            .build()
+            .into_tuple();
+        let (even_waker, odd_waker) = (CountingWaker::new(), CountingWaker::new());
+        for n in [0, 2, 4, 6, 8] {
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_until_ready_with_waker(&mut evens, &even_waker.waker(), 2)
+            );
+        }
+        assert_eq!(
+            Poll::Ready(Some(1)),
+            poll_once(&mut odds, &odd_waker.waker())
+        );
+        for n in [3, 5, 7, 9] {
+            assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+            assert_eq!(1, timer.sleeping());
+            let wakes = odd_waker.count();
+            timer.advance(Duration::from_millis(499));
+            assert_eq!(wakes, odd_waker.count());
+            timer.advance(Duration::from_millis(1));
+            assert_eq!(wakes + 1, odd_waker.count());
+            assert_eq!(
+                Poll::Ready(Some(n)),
+                poll_once(&mut odds, &odd_waker.waker())
+            );
+        }
+        assert_eq!(0, timer.sleeping());
+        // Even the end of the stream is only returned once the next item would be due
+        assert_eq!(Poll::Pending, poll_once(&mut odds, &odd_waker.waker()));
+        timer.advance(Duration::from_millis(500));
+        assert_eq!(Poll::Ready(None), poll_once(&mut odds, &odd_waker.waker()));
+    }
+}
@@ -0,0 +1,175 @@
+//! The clock and the sleeps that the time-based options of a split run on,
+//! so they aren't tied to one async runtime. `TokioTimer` runs them on
+//! tokio and needs the `timer` feature, `FuturesTimer` runs them on the
+//! `futures-timer` crate, which works with any executor, and needs the
+//! `futures-timer` feature, and `testing::MockTimer` runs them on a clock
+//! that a test moves forward by hand. Any other runtime can be plugged in by
+//! implementing `Sleep` and `Clock` for a type of its own
+//!
+//! The `*_with` options of `SplitBuilder` take any timer, but the split
+//! keeps it boxed rather than as a type parameter. The timer is only used
+//! when a deadline is armed, not for every item, and a type parameter would
+//! have to be spelled out in the type of the split and of both of its
+//! streams, even by code that doesn't use any time-based option
+//!
+//!```rust
+//! use std::{future::Future, pin::Pin, time::{Duration, Instant}};
+//! use split_stream_by::timer::{Clock, Sleep};
+//!
+//! /// A timer for a runtime with a `sleep` that returns a boxed future
+//! #[derive(Clone)]
+//! struct MyTimer {
+//!     start: Instant,
+//! }
+//!
+//! impl Sleep for MyTimer {
+//!     type Fut = Pin<Box<dyn Future<Output = ()> + Send>>;
+//!     fn sleep(&self, duration: Duration) -> Self::Fut {
+//!         Box::pin(async move { /* my_runtime::sleep(duration).await */ })
+//!     }
+//! }
+//!
+//! impl Clock for MyTimer {
+//!     fn now(&self) -> Duration {
+//!         self.start.elapsed()
+//!     }
+//! }
+//! ```
+use alloc::boxed::Box;
+use core::{future::Future, pin::Pin, time::Duration};
+
+/// Makes futures that resolve once some time has passed
+pub trait Sleep {
+    /// The future returned by `sleep`
+    type Fut: Future<Output = ()> + Send + 'static;
+    /// Returns a future that resolves once `duration` has passed
+    fn sleep(&self, duration: Duration) -> Self::Fut;
+
+    /// Whether a sleep started now would end. Work that runs on the timer in
+    /// the background, like the timers of `SplitBuilder::max_buffer_latency`,
+    /// isn't started while this is `false`. Unless it's overridden, it's
+    /// always `true`
+    fn can_sleep(&self) -> bool {
+        true
+    }
+}
+
+/// A clock that only moves forward
+pub trait Clock {
+    /// How much time has passed since a point that stays the same for as
+    /// long as the clock is around, like when it was created
+    fn now(&self) -> Duration;
+}
+
+/// A `Sleep` and `Clock` whose sleep futures are boxed, so that the split
+/// can hold any timer without a type parameter for it. See the module docs
+/// for why
+pub(crate) trait DynTimer: Send {
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+    fn can_sleep(&self) -> bool;
+}
+
+impl<T> DynTimer for T
+where
+    T: Sleep + Clock + Send,
+{
+    fn now(&self) -> Duration {
+        Clock::now(self)
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(Sleep::sleep(self, duration))
+    }
+
+    fn can_sleep(&self) -> bool {
+        Sleep::can_sleep(self)
+    }
+}
+
+/// Sleeps on the tokio timer and reads the tokio clock, so it follows
+/// `tokio::time::pause` in tests. Sleeping needs a tokio runtime with the
+/// timer enabled. Requires the `timer` feature
+#[cfg(feature = "timer")]
+#[derive(Clone, Copy, Debug)]
+pub struct TokioTimer {
+    start: tokio::time::Instant,
+}
+
+#[cfg(feature = "timer")]
+impl TokioTimer {
+    /// Creates a timer whose clock counts from now
+    pub fn new() -> Self {
+        Self {
+            start: tokio::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "timer")]
+impl Default for TokioTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "timer")]
+impl Sleep for TokioTimer {
+    type Fut = tokio::time::Sleep;
+    fn sleep(&self, duration: Duration) -> Self::Fut {
+        tokio::time::sleep(duration)
+    }
+
+    /// Whether the current thread runs on a tokio runtime
+    fn can_sleep(&self) -> bool {
+        tokio::runtime::Handle::try_current().is_ok()
+    }
+}
+
+#[cfg(feature = "timer")]
+impl Clock for TokioTimer {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Sleeps on the timer thread of the `futures-timer` crate and reads
+/// `std::time::Instant`, so it works with any executor, or none at all.
+/// Requires the `futures-timer` feature
+#[cfg(feature = "futures-timer")]
+#[derive(Clone, Copy, Debug)]
+pub struct FuturesTimer {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "futures-timer")]
+impl FuturesTimer {
+    /// Creates a timer whose clock counts from now
+    pub fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "futures-timer")]
+impl Default for FuturesTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "futures-timer")]
+impl Sleep for FuturesTimer {
+    type Fut = futures_timer::Delay;
+    fn sleep(&self, duration: Duration) -> Self::Fut {
+        futures_timer::Delay::new(duration)
+    }
+}
+
+#[cfg(feature = "futures-timer")]
+impl Clock for FuturesTimer {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
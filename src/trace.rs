@@ -0,0 +1,200 @@
+use crate::Side;
+
+/// The target of the spans and events, so that they can be filtered with
+/// `split_stream_by=debug` no matter which module emits them
+#[cfg(feature = "tracing")]
+const TARGET: &str = "split_stream_by";
+
+/// The `tracing` span of a split, which the events about the split are
+/// emitted in. Without the `tracing` feature this is empty and the events
+/// compile to nothing
+pub(crate) struct SplitSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl SplitSpan {
+    /// Creates the span of a new split, where `kind` is the function that
+    /// made the split
+    #[inline]
+    pub(crate) fn new(kind: &'static str, capacity_true: usize, capacity_false: usize) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span =
+                tracing::debug_span!(target: TARGET, "split", kind, capacity_true, capacity_false);
+            span.in_scope(
+                || tracing::debug!(target: TARGET, kind, capacity_true, capacity_false, "created"),
+            );
+            Self { span }
+        }
+        #[cfg(not(feature = "tracing"))]
+        Self {}
+    }
+
+    /// An item from the underlying stream went to `side`, where the buffer
+    /// holds `buffered_len` items now
+    #[inline]
+    pub(crate) fn routed(&self, side: Side, buffered_len: usize, capacity: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.in_scope(|| {
+            tracing::trace!(target: TARGET, side = ?side, buffered_len, capacity, "routed");
+        });
+    }
+
+    /// The other stream can't pull from the underlying stream, because the
+    /// buffer of `side` is full
+    #[inline]
+    pub(crate) fn stalled(&self, side: Side, buffered_len: usize, capacity: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.in_scope(|| {
+            tracing::debug!(target: TARGET, side = ?side, buffered_len, capacity, "buffer full");
+        });
+    }
+
+    /// The stream of `side` was dropped
+    #[inline]
+    pub(crate) fn abandoned(&self, side: Side) {
+        #[cfg(feature = "tracing")]
+        self.span
+            .in_scope(|| tracing::debug!(target: TARGET, side = ?side, "abandoned"));
+    }
+
+    /// The underlying stream has ended
+    #[inline]
+    pub(crate) fn ended(&self) {
+        #[cfg(feature = "tracing")]
+        self.span
+            .in_scope(|| tracing::debug!(target: TARGET, "terminated"));
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::{
+        fmt,
+        sync::{Arc, Mutex, PoisonError},
+        task::Poll,
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use crate::{
+        testing::{poll_once, CountingWaker, ManualStream},
+        SplitStreamByExt,
+    };
+
+    /// A subscriber that records the message and the fields of every event
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Capture {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+        }
+    }
+
+    #[derive(Default)]
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if !self.0.is_empty() {
+                self.0.push(' ');
+            }
+            if field.name() == "message" {
+                self.0.push_str(&format!("{:?}", value));
+            } else {
+                self.0.push_str(&format!("{}={:?}", field.name(), value));
+            }
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "split_stream_by"
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(fields.0);
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn test_events() {
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            let (source, handle) = ManualStream::new();
+            let (mut true_stream, mut false_stream) = source
+                .split_by_buffered::<1>(|&n: &i32| n % 2 == 0)
+                .into_tuple();
+            assert_eq!(
+                vec!["created kind=\"split_by_buffered\" capacity_true=1 capacity_false=1"],
+                capture.take()
+            );
+            let waker = CountingWaker::new();
+            for n in [0, 2, 1] {
+                handle.push(n);
+            }
+            handle.end();
+            assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+            assert_eq!(Poll::Pending, poll_once(&mut false_stream, &waker.waker()));
+            assert_eq!(
+                vec![
+                    "routed side=True buffered_len=1 capacity=1",
+                    "buffer full side=True buffered_len=1 capacity=1",
+                ],
+                capture.take()
+            );
+            assert_eq!(
+                Poll::Ready(Some(0)),
+                poll_once(&mut true_stream, &waker.waker())
+            );
+            assert_eq!(
+                Poll::Ready(Some(2)),
+                poll_once(&mut true_stream, &waker.waker())
+            );
+            assert_eq!(
+                vec!["routed side=True buffered_len=0 capacity=1"],
+                capture.take()
+            );
+            drop(true_stream);
+            assert_eq!(
+                Poll::Ready(Some(1)),
+                poll_once(&mut false_stream, &waker.waker())
+            );
+            assert_eq!(
+                Poll::Ready(None),
+                poll_once(&mut false_stream, &waker.waker())
+            );
+            assert_eq!(
+                vec![
+                    "abandoned side=True",
+                    "routed side=False buffered_len=0 capacity=1",
+                    "terminated",
+                ],
+                capture.take()
+            );
+        });
+    }
+}
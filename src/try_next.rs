@@ -0,0 +1,20 @@
+/// What a stream of a split had ready when `try_next_or_ended` was called
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TryNext<T> {
+    /// An item that was buffered for the stream
+    Item(T),
+    /// Nothing is buffered for the stream right now, but it hasn't ended
+    Empty,
+    /// The stream has ended, so it won't return any more items
+    Ended,
+}
+
+impl<T> TryNext<T> {
+    /// Returns the item, if there was one
+    pub fn item(self) -> Option<T> {
+        match self {
+            TryNext::Item(item) => Some(item),
+            TryNext::Empty | TryNext::Ended => None,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::Side;
+
+/// A callback that is called when a buffer crosses a watermark
+type WatermarkCallback = Arc<dyn Fn() + Send + Sync>;
+
+struct Watermark {
+    side: Side,
+    threshold: usize,
+    // Whether this fires when the buffer fills up to the threshold, rather than when it drains
+    // down to it
+    rising: bool,
+    callback: WatermarkCallback,
+}
+
+impl Watermark {
+    fn is_crossed(&self, before: usize, after: usize) -> bool {
+        if self.rising {
+            before < self.threshold && after >= self.threshold
+        } else {
+            before > self.threshold && after <= self.threshold
+        }
+    }
+}
+
+/// The watermarks of both buffers of a split, along with the number of
+/// items each buffer held when they were last checked
+#[derive(Default)]
+pub(crate) struct Watermarks {
+    marks: Vec<Watermark>,
+    len_true: usize,
+    len_false: usize,
+}
+
+impl Watermarks {
+    pub(crate) fn add_high(
+        &mut self,
+        side: Side,
+        threshold: usize,
+        callback: impl Fn() + Send + Sync + 'static,
+    ) {
+        self.add(side, threshold, true, Arc::new(callback));
+    }
+
+    pub(crate) fn add_low(
+        &mut self,
+        side: Side,
+        threshold: usize,
+        callback: impl Fn() + Send + Sync + 'static,
+    ) {
+        self.add(side, threshold, false, Arc::new(callback));
+    }
+
+    fn add(&mut self, side: Side, threshold: usize, rising: bool, callback: WatermarkCallback) {
+        self.marks.push(Watermark {
+            side,
+            threshold,
+            rising,
+            callback,
+        });
+    }
+
+    /// Returns the callbacks of the watermarks that were crossed since the
+    /// last check, given how many items the buffers hold now
+    pub(crate) fn check(&mut self, len_true: usize, len_false: usize) -> Crossed {
+        let (before_true, before_false) = (self.len_true, self.len_false);
+        self.len_true = len_true;
+        self.len_false = len_false;
+        if self.marks.is_empty() || (before_true, before_false) == (len_true, len_false) {
+            return Crossed(Vec::new());
+        }
+        let crossed = self
+            .marks
+            .iter()
+            .filter(|mark| match mark.side {
+                Side::True => mark.is_crossed(before_true, len_true),
+                Side::False => mark.is_crossed(before_false, len_false),
+            })
+            .map(|mark| mark.callback.clone())
+            .collect();
+        Crossed(crossed)
+    }
+}
+
+/// The callbacks of crossed watermarks, which are called once the shared
+/// state of the split has been unlocked
+#[must_use]
+pub(crate) struct Crossed(Vec<WatermarkCallback>);
+
+impl Crossed {
+    pub(crate) fn call(self) {
+        for callback in self.0 {
+            callback();
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use alloc::collections::VecDeque;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::{stream::FusedStream, Stream};
+use futures_util::{future::Either, stream::Fuse, StreamExt};
+use pin_project::pin_project;
+
+/// Pairs up the items of the two streams of a split. Created by
+/// `zip_partitions`
+#[pin_project]
+pub struct ZipPartitions<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    #[pin]
+    a: Fuse<A>,
+    #[pin]
+    b: Fuse<B>,
+    queue_a: VecDeque<A::Item>,
+    queue_b: VecDeque<B::Item>,
+    cap: usize,
+}
+
+/// Pairs up the items of the two streams of a split, the first item of `a`
+/// with the first item of `b`, the second with the second and so on, and
+/// ends once either stream has ended. Use `into_leftovers` to get the items
+/// of the longer stream after that.
+///
+/// A plain `zip` can hang on the streams of a split. Once it has the next
+/// item of one stream, it only polls the other one, which stalls as soon as
+/// the buffer of the first stream is full, because nothing takes items out of
+/// it. This keeps pulling items from the stream that's ahead into a queue of
+/// up to `cap` items, which makes room in its buffer. The streams still stall
+/// once one of them gets `cap` items plus the size of its buffer ahead of the
+/// other, so `cap` should cover the largest expected imbalance. At least one
+/// item is always queued, even if `cap` is 0
+///
+///```rust
+/// use futures::StreamExt;
+/// use split_stream_by::{zip_partitions, SplitStreamByExt};
+///
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     let messages = futures::stream::iter(["req 1", "req 2", "res 1", "req 3", "res 2"]);
+///     let split = messages.split_by(|message| message.starts_with("req"));
+///     let pairs = zip_partitions(split.matches, split.rest, 4);
+///     assert_eq!(
+///         vec![("req 1", "res 1"), ("req 2", "res 2")],
+///         pairs.collect::<Vec<_>>().await
+///     );
+/// })
+/// ```
+pub fn zip_partitions<A, B>(a: A, b: B, cap: usize) -> ZipPartitions<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    ZipPartitions {
+        a: a.fuse(),
+        b: b.fuse(),
+        queue_a: VecDeque::new(),
+        queue_b: VecDeque::new(),
+        cap: cap.max(1),
+    }
+}
+
+impl<A, B> ZipPartitions<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    /// Whether one of the streams has ended and all of its items were paired
+    fn is_done(&self) -> bool {
+        (self.a.is_done() && self.queue_a.is_empty())
+            || (self.b.is_done() && self.queue_b.is_empty())
+    }
+
+    /// Turns this into a stream of the items that weren't paired: first the
+    /// ones of `a`, queued or not, and then the ones of `b`. After the pairs
+    /// have ended, these are the remaining items of the longer stream
+    pub fn into_leftovers(self) -> Leftovers<A, B> {
+        Leftovers {
+            a: self.a,
+            b: self.b,
+            queue_a: self.queue_a,
+            queue_b: self.queue_b,
+        }
+    }
+}
+
+impl<A, B> Stream for ZipPartitions<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.is_done() {
+                return Poll::Ready(None);
+            }
+            let mut this = self.as_mut().project();
+            if !this.queue_a.is_empty() && !this.queue_b.is_empty() {
+                let pair = (this.queue_a.pop_front(), this.queue_b.pop_front());
+                return Poll::Ready(pair.0.zip(pair.1));
+            }
+            let mut progress = false;
+            // The stream that's ahead keeps being polled, so that the other one doesn't stall on
+            // its full buffer
+            if this.queue_a.len() < *this.cap && !this.a.is_done() {
+                if let Poll::Ready(item) = this.a.as_mut().poll_next(cx) {
+                    this.queue_a.extend(item);
+                    progress = true;
+                }
+            }
+            if this.queue_b.len() < *this.cap && !this.b.is_done() {
+                if let Poll::Ready(item) = this.b.as_mut().poll_next(cx) {
+                    this.queue_b.extend(item);
+                    progress = true;
+                }
+            }
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_done() {
+            return (0, Some(0));
+        }
+        let (lower_a, upper_a) = self.a.size_hint();
+        let (lower_b, upper_b) = self.b.size_hint();
+        let lower = lower_a
+            .saturating_add(self.queue_a.len())
+            .min(lower_b.saturating_add(self.queue_b.len()));
+        let upper_a = upper_a.and_then(|upper| upper.checked_add(self.queue_a.len()));
+        let upper_b = upper_b.and_then(|upper| upper.checked_add(self.queue_b.len()));
+        let upper = match (upper_a, upper_b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (upper, None) | (None, upper) => upper,
+        };
+        (lower, upper)
+    }
+}
+
+impl<A, B> FusedStream for ZipPartitions<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    fn is_terminated(&self) -> bool {
+        self.is_done()
+    }
+}
+
+/// The items that `ZipPartitions` didn't pair up. Created by
+/// `ZipPartitions::into_leftovers`
+#[pin_project]
+pub struct Leftovers<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    #[pin]
+    a: Fuse<A>,
+    #[pin]
+    b: Fuse<B>,
+    queue_a: VecDeque<A::Item>,
+    queue_b: VecDeque<B::Item>,
+}
+
+impl<A, B> Stream for Leftovers<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    type Item = Either<A::Item, B::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some(item) = this.queue_a.pop_front() {
+            return Poll::Ready(Some(Either::Left(item)));
+        }
+        if !this.a.is_done() {
+            match this.a.poll_next(cx) {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(Either::Left(item))),
+                Poll::Ready(None) => {}
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if let Some(item) = this.queue_b.pop_front() {
+            return Poll::Ready(Some(Either::Right(item)));
+        }
+        this.b.poll_next(cx).map(|item| item.map(Either::Right))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let queued = self.queue_a.len() + self.queue_b.len();
+        let (lower_a, upper_a) = self.a.size_hint();
+        let (lower_b, upper_b) = self.b.size_hint();
+        let lower = lower_a.saturating_add(lower_b).saturating_add(queued);
+        let upper = upper_a
+            .zip(upper_b)
+            .and_then(|(a, b)| a.checked_add(b))
+            .and_then(|upper| upper.checked_add(queued));
+        (lower, upper)
+    }
+}
+
+impl<A, B> FusedStream for Leftovers<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    fn is_terminated(&self) -> bool {
+        self.a.is_done() && self.b.is_done() && self.queue_a.is_empty() && self.queue_b.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{SplitBuilder, SplitStreamByExt};
+
+    #[tokio::test]
+    async fn test_imbalance_beyond_split_buffer() {
+        // All of the evens come before the odds, so `zip` would stall once the buffer of the even
+        // stream was full
+        let split = futures::stream::iter([0, 2, 4, 6, 8, 10, 1, 3, 5, 7, 9, 11])
+            .split_by_buffered::<2>(|&n| n % 2 == 0);
+        let pairs = zip_partitions(split.matches, split.rest, 5);
+        assert_eq!(
+            vec![(0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11)],
+            pairs.collect::<Vec<_>>().await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leftovers() {
+        let split = SplitBuilder::new(futures::stream::iter([0, 1, 2, 4, 3, 6, 8]))
+            .predicate(|&n| n % 2 == 0)
+            .buffer(4)
+            .build();
+        let mut pairs = zip_partitions(split.matches, split.rest, 4);
+        assert_eq!(Some((0, 1)), pairs.next().await);
+        assert_eq!(Some((2, 3)), pairs.next().await);
+        assert_eq!(None, pairs.next().await);
+        assert!(pairs.is_terminated());
+        // The evens are the longer side, and odds would be negated
+        let leftovers = pairs
+            .into_leftovers()
+            .map(|item| match item {
+                Either::Left(n) => n,
+                Either::Right(n) => -n,
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(vec![4, 6, 8], leftovers);
+    }
+}